@@ -0,0 +1,226 @@
+use chrono::{DateTime, Duration, FixedOffset};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::Serialize;
+
+use crate::{
+    config::{Config, Strategy, TradingPair},
+    firestore::{PriceHistory, PriceTrend},
+    indicators,
+    trading::{should_make_trade, should_trade_rsi, should_trade_sma_crossover, Position, TradingState},
+};
+
+pub struct BacktestConfig {
+    // Strategy thresholds (stop loss, take profit, SMA windows, RSI levels) are read from here
+    pub trading: Config,
+    pub pair: TradingPair,
+    // Fee charged on each fill, as a fraction of the traded amount
+    pub fee_pct: Decimal,
+    // Adverse price movement applied to each fill
+    pub slippage_bps: u16,
+    // Quote tokens held at the start of the replay
+    pub initial_quote: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BacktestResult {
+    pub total_trades: i64,
+    pub winning_trades: i64,
+    pub losing_trades: i64,
+    pub win_rate: Decimal,
+    // Largest peak-to-trough decline of the marked-to-market equity, in quote tokens
+    pub max_drawdown: Decimal,
+    pub final_equity: Decimal,
+    pub profit_loss: Decimal,
+}
+
+// Replay the price series through the strategy, simulating fills at each price point.
+// The first price point seeds the last trade price, as if the bot had just traded there.
+pub fn backtest(prices: &[PriceHistory], strategy: &Strategy, config: &BacktestConfig) -> BacktestResult {
+    // Strategies expect the history newest first, so walk it from the oldest end
+    let mut newest_first = prices.to_vec();
+    newest_first.sort_by_key(|p| std::cmp::Reverse(p.timestamp));
+
+    let slippage = Decimal::from(config.slippage_bps) / dec!(10000);
+    let mut state = TradingState::new(config.pair.clone());
+    let mut quote_balance = config.initial_quote;
+    let mut base_balance = dec!(0);
+    // Quote tokens spent on the open position, to score the round trip when it closes
+    let mut cost_basis = dec!(0);
+    let mut equity_curve = Vec::with_capacity(newest_first.len());
+
+    if let Some(first) = newest_first.last() {
+        state.last_trade_price = Some(first.sol_price_usdc);
+        state.last_trade_timestamp = Some(first.timestamp);
+    }
+
+    for i in (0..newest_first.len()).rev() {
+        // History up to and including the current price point
+        let history = &newest_first[i..];
+        let point = &history[0];
+        let price = point.sol_price_usdc;
+        let now = point.timestamp;
+
+        if state.position == Position::Base {
+            state.peak_price = Some(state.peak_price.map_or(price, |peak| peak.max(price)));
+        }
+        
+        let should_trade = history.len() > 1 && match strategy {
+            Strategy::ThresholdPct => {
+                let trend = trend_at(history, now);
+                should_make_trade(&state.position, &trend, price, point.usdc_price_sol, now, &state, &config.trading)
+            }
+            Strategy::SmaCrossover => {
+                let window = within_hours(history, now, config.trading.sma_long_hours);
+                should_trade_sma_crossover(&state.position, window, price, &state, &config.trading)
+            }
+            Strategy::Rsi => {
+                let window = within_hours(history, now, (config.trading.rsi_period * 3) as u32);
+                should_trade_rsi(&state.position, window, price, &state, &config.trading)
+            }
+        };
+
+        if should_trade {
+            match state.position {
+                Position::Quote => {
+                    let fill_price = price * (dec!(1) + slippage);
+                    base_balance = quote_balance * (dec!(1) - config.fee_pct) / fill_price;
+                    cost_basis = quote_balance;
+                    quote_balance = dec!(0);
+                    state.position = Position::Base;
+                    state.peak_price = Some(price);
+                }
+                Position::Base => {
+                    let fill_price = price * (dec!(1) - slippage);
+                    quote_balance = base_balance * fill_price * (dec!(1) - config.fee_pct);
+                    base_balance = dec!(0);
+
+                    match (quote_balance - cost_basis).cmp(&dec!(0)) {
+                        std::cmp::Ordering::Greater => state.winning_trades += 1,
+                        std::cmp::Ordering::Less => state.losing_trades += 1,
+                        std::cmp::Ordering::Equal => {}
+                    }
+                    state.position = Position::Quote;
+                    state.peak_price = None;
+                }
+            }
+
+            state.total_trades += 1;
+            state.last_trade_price = Some(price);
+            state.last_trade_timestamp = Some(now);
+        }
+
+        equity_curve.push(quote_balance + base_balance * price);
+    }
+
+    let final_equity = equity_curve.last().copied().unwrap_or(config.initial_quote);
+    let closed_trades = state.winning_trades + state.losing_trades;
+    let win_rate = if closed_trades > 0 {
+        Decimal::from(state.winning_trades) / Decimal::from(closed_trades) * dec!(100)
+    } else {
+        dec!(0)
+    };
+
+    BacktestResult {
+        total_trades: state.total_trades,
+        winning_trades: state.winning_trades,
+        losing_trades: state.losing_trades,
+        win_rate,
+        max_drawdown: indicators::max_drawdown(&equity_curve),
+        final_equity,
+        profit_loss: final_equity - config.initial_quote,
+    }
+}
+
+// Prices within `hours` of `now`, from a history ordered newest first
+fn within_hours(history: &[PriceHistory], now: DateTime<FixedOffset>, hours: u32) -> &[PriceHistory] {
+    let cutoff = now - Duration::hours(hours as i64);
+    let len = history.iter().take_while(|p| p.timestamp > cutoff).count();
+    &history[..len]
+}
+
+// Rebuild the part of the price trend the threshold strategy uses, as of `now`
+fn trend_at(history: &[PriceHistory], now: DateTime<FixedOffset>) -> PriceTrend {
+    let price_at = |ago: Duration| history.iter()
+        .find(|p| p.timestamp <= now - ago)
+        .map(|p| p.sol_price_usdc);
+
+    PriceTrend {
+        timestamp: now,
+        price_1h_ago: price_at(Duration::hours(1)),
+        price_24h_ago: price_at(Duration::hours(24)),
+        price_7d_ago: price_at(Duration::days(7)),
+        trend_1h: None,
+        trend_24h: None,
+        trend_7d: None,
+        volatility_1h: None,
+        volatility_24h: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    
+    // Hourly prices oldest first, all within a day so the 24h rule never applies
+    fn hourly_prices(prices: &[Decimal]) -> Vec<PriceHistory> {
+        let start = DateTime::parse_from_rfc3339("2024-05-01T00:00:00+09:00").unwrap();
+        prices.iter().enumerate().map(|(i, price)| PriceHistory {
+            id: i.to_string(),
+            pair: "SOL/USDC".to_string(),
+            timestamp: start + Duration::hours(i as i64),
+            sol_price_usdc: *price,
+            usdc_price_sol: dec!(1) / *price,
+            data_source: "test".to_string(),
+            trading_session_id: String::new(),
+            spread: None,
+        }).collect()
+    }
+    
+    #[test]
+    fn up_then_down_series_buys_the_dip_and_stops_out() {
+        let trading = test_config();
+        let config = BacktestConfig {
+            pair: trading.pairs[0].clone(),
+            trading: Config { stop_loss_pct: dec!(0.05), take_profit_pct: dec!(0.01), buy_dip_pct: dec!(0.01), trailing_stop_pct: None, ..trading },
+            fee_pct: dec!(0),
+            slippage_bps: 0,
+            initial_quote: dec!(1000),
+        };
+        // Rising prices never dip 1% below the seeded 100, then the fall buys at 99 and stops out at 94
+        let prices = hourly_prices(&[dec!(100), dec!(101), dec!(102), dec!(103), dec!(101), dec!(99), dec!(97), dec!(94)]);
+        
+        let result = backtest(&prices, &Strategy::ThresholdPct, &config);
+        
+        let expected_equity = dec!(1000) / dec!(99) * dec!(94);
+        assert_eq!(result.total_trades, 2);
+        assert_eq!(result.winning_trades, 0);
+        assert_eq!(result.losing_trades, 1);
+        assert_eq!(result.win_rate, dec!(0));
+        assert_eq!(result.final_equity.round_dp(6), expected_equity.round_dp(6));
+        assert_eq!(result.profit_loss.round_dp(6), (expected_equity - dec!(1000)).round_dp(6));
+        assert_eq!(result.max_drawdown.round_dp(6), (dec!(1000) - expected_equity).round_dp(6));
+    }
+    
+    #[test]
+    fn input_order_does_not_matter() {
+        let trading = test_config();
+        let config = BacktestConfig {
+            pair: trading.pairs[0].clone(),
+            trading,
+            fee_pct: dec!(0.001),
+            slippage_bps: 10,
+            initial_quote: dec!(1000),
+        };
+        let prices = hourly_prices(&[dec!(100), dec!(98), dec!(100), dec!(97), dec!(99)]);
+        let mut reversed = prices.clone();
+        reversed.reverse();
+        
+        let forward = backtest(&prices, &Strategy::ThresholdPct, &config);
+        let backward = backtest(&reversed, &Strategy::ThresholdPct, &config);
+        
+        assert_eq!(forward.total_trades, backward.total_trades);
+        assert_eq!(forward.final_equity, backward.final_equity);
+    }
+}
@@ -0,0 +1,82 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime, Offset, TimeZone};
+use chrono_tz::Tz;
+use std::sync::OnceLock;
+use tracing::warn;
+
+static TIMEZONE: OnceLock<Tz> = OnceLock::new();
+
+// Set once at startup from TIMEZONE
+pub fn set_timezone(timezone: Tz) {
+    if TIMEZONE.set(timezone).is_err() {
+        warn!("Timezone is already set to {}, ignoring {}", self::timezone(), timezone);
+    }
+}
+
+// Asia/Tokyo until set_timezone is called, matching timestamps stored before TIMEZONE existed
+pub fn timezone() -> Tz {
+    TIMEZONE.get().copied().unwrap_or(chrono_tz::Asia::Tokyo)
+}
+
+// Current time in the configured timezone, as the fixed-offset timestamps stored in Firestore
+pub fn now_local() -> DateTime<FixedOffset> {
+    to_local(&chrono::Utc::now())
+}
+
+pub fn to_local<T: TimeZone>(time: &DateTime<T>) -> DateTime<FixedOffset> {
+    to_zone(time, timezone())
+}
+
+fn to_zone<T: TimeZone>(time: &DateTime<T>, timezone: Tz) -> DateTime<FixedOffset> {
+    let local = time.with_timezone(&timezone);
+    local.with_timezone(&local.offset().fix())
+}
+
+// e.g. "2024-05-01 09:30:00 JST", for notifications
+pub fn format_local<T: TimeZone>(time: &DateTime<T>) -> String {
+    format_in_zone(time, timezone())
+}
+
+fn format_in_zone<T: TimeZone>(time: &DateTime<T>, timezone: Tz) -> String {
+    time.with_timezone(&timezone).format("%Y-%m-%d %H:%M:%S %Z").to_string()
+}
+
+// Local midnight at the start of `date`, or the UTC midnight in the rare zones where DST skips it
+pub fn start_of_day(date: NaiveDate) -> DateTime<FixedOffset> {
+    start_of_day_in_zone(date, timezone())
+}
+
+fn start_of_day_in_zone(date: NaiveDate, timezone: Tz) -> DateTime<FixedOffset> {
+    let midnight = date.and_time(NaiveTime::MIN);
+    let start = timezone.from_local_datetime(&midnight)
+        .earliest()
+        .unwrap_or_else(|| timezone.from_utc_datetime(&midnight));
+    to_zone(&start, timezone)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    
+    #[test]
+    fn times_follow_the_configured_timezone() {
+        let time = Utc.with_ymd_and_hms(2024, 7, 1, 0, 30, 0).unwrap();
+        
+        assert_eq!(to_zone(&time, chrono_tz::Asia::Tokyo).to_rfc3339(), "2024-07-01T09:30:00+09:00");
+        assert_eq!(format_in_zone(&time, chrono_tz::Asia::Tokyo), "2024-07-01 09:30:00 JST");
+        // New York is on daylight saving time in July
+        assert_eq!(to_zone(&time, chrono_tz::America::New_York).to_rfc3339(), "2024-06-30T20:30:00-04:00");
+        assert_eq!(format_in_zone(&time, chrono_tz::America::New_York), "2024-06-30 20:30:00 EDT");
+        
+        let date = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+        assert_eq!(start_of_day_in_zone(date, chrono_tz::Asia::Tokyo).to_rfc3339(), "2024-07-01T00:00:00+09:00");
+        assert_eq!(start_of_day_in_zone(date, chrono_tz::America::New_York).to_rfc3339(), "2024-07-01T00:00:00-04:00");
+    }
+    
+    #[test]
+    fn timezone_defaults_to_tokyo() {
+        // No test calls set_timezone
+        assert_eq!(timezone(), chrono_tz::Asia::Tokyo);
+        assert_eq!(now_local().offset().local_minus_utc(), 9 * 3600);
+    }
+}
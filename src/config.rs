@@ -1,75 +1,1001 @@
 use anyhow::{Result, Context};
+use rust_decimal::Decimal;
+use solana_sdk::pubkey::Pubkey;
 use std::env;
+use std::str::FromStr;
+
+use crate::line_bot::MAX_MULTICAST_RECIPIENTS;
+use crate::notifier::NotificationEvent;
+
+// Most compute units a single transaction may request
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Strategy {
+    // Trade when the price moves a fixed percentage from the last trade price
+    #[default]
+    ThresholdPct,
+    // Trade when the short SMA crosses the long SMA
+    SmaCrossover,
+    // Buy when RSI is oversold, sell when it is overbought
+    Rsi,
+}
+
+impl std::str::FromStr for Strategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "threshold_pct" => Ok(Strategy::ThresholdPct),
+            "sma_crossover" => Ok(Strategy::SmaCrossover),
+            "rsi" => Ok(Strategy::Rsi),
+            _ => anyhow::bail!("Unknown strategy: {}", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OrderMode {
+    // Swap at the market price once the strategy signals
+    #[default]
+    Market,
+    // Keep a Jupiter limit order resting at the next threshold price
+    Limit,
+}
+
+impl std::str::FromStr for OrderMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "market" => Ok(OrderMode::Market),
+            "limit" => Ok(OrderMode::Limit),
+            _ => anyhow::bail!("Unknown order mode: {}", s),
+        }
+    }
+}
+
+// Which events a trigger sends to the notification channels
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum NotificationLevel {
+    Errors,
+    // Trades and errors
+    #[default]
+    Trades,
+    // Also a ping when a trigger finds no trade, and the daily high/low prices
+    Verbose,
+}
+
+impl NotificationLevel {
+    pub fn notifies(self, event: NotificationEvent) -> bool {
+        match event {
+            NotificationEvent::Error => true,
+            NotificationEvent::Trade => self != NotificationLevel::Errors,
+            NotificationEvent::NoTrade | NotificationEvent::DailySummary => self == NotificationLevel::Verbose,
+        }
+    }
+}
+
+impl std::str::FromStr for NotificationLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "errors" => Ok(NotificationLevel::Errors),
+            "trades" => Ok(NotificationLevel::Trades),
+            "verbose" => Ok(NotificationLevel::Verbose),
+            _ => anyhow::bail!("Unknown notification level: {}", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cluster {
+    Mainnet,
+    Devnet,
+    Testnet,
+    Localnet,
+}
+
+impl Cluster {
+    // Anything that doesn't look like a test cluster is treated as mainnet, so an unknown
+    // RPC provider still has to be confirmed
+    pub fn from_rpc_url(rpc_url: &str) -> Self {
+        let rpc_url = rpc_url.to_lowercase();
+        if rpc_url.contains("devnet") {
+            Cluster::Devnet
+        } else if rpc_url.contains("testnet") {
+            Cluster::Testnet
+        } else if rpc_url.contains("localhost") || rpc_url.contains("127.0.0.1") {
+            Cluster::Localnet
+        } else {
+            Cluster::Mainnet
+        }
+    }
+}
+
+// Test clusters need no confirmation; mainnet only runs with CONFIRM_MAINNET=true
+fn cluster_confirmed(cluster: Cluster, confirm_mainnet: Option<&str>) -> bool {
+    cluster != Cluster::Mainnet || confirm_mainnet == Some("true")
+}
+
+impl std::str::FromStr for Cluster {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "mainnet" | "mainnet-beta" => Ok(Cluster::Mainnet),
+            "devnet" => Ok(Cluster::Devnet),
+            "testnet" => Ok(Cluster::Testnet),
+            "localnet" => Ok(Cluster::Localnet),
+            _ => anyhow::bail!("Unknown cluster: {}", s),
+        }
+    }
+}
+
+impl std::fmt::Display for Cluster {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cluster::Mainnet => write!(f, "mainnet"),
+            Cluster::Devnet => write!(f, "devnet"),
+            Cluster::Testnet => write!(f, "testnet"),
+            Cluster::Localnet => write!(f, "localnet"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradingPair {
+    // Name such as "SOL/USDC", stored as the pair field of Firestore documents
+    pub name: String,
+    pub base_mint: String,
+    pub quote_mint: String,
+    pub base_decimals: u8,
+    pub quote_decimals: u8,
+}
+
+impl TradingPair {
+    pub fn base_symbol(&self) -> &str {
+        self.name.split('/').next().unwrap_or(&self.name)
+    }
+    
+    pub fn quote_symbol(&self) -> &str {
+        self.name.split('/').nth(1).unwrap_or(&self.name)
+    }
+}
+
+// Parses "NAME:base_mint:quote_mint:base_decimals:quote_decimals", e.g. "SOL/USDC:So111...:EPjF...:9:6"
+impl std::str::FromStr for TradingPair {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.trim().split(':').collect();
+        let [name, base_mint, quote_mint, base_decimals, quote_decimals] = parts.as_slice() else {
+            anyhow::bail!("Expected NAME:BASE_MINT:QUOTE_MINT:BASE_DECIMALS:QUOTE_DECIMALS, got {}", s);
+        };
+        if !name.contains('/') {
+            anyhow::bail!("Pair name must look like BASE/QUOTE, got {}", name);
+        }
+        
+        Ok(TradingPair {
+            name: name.to_string(),
+            base_mint: base_mint.to_string(),
+            quote_mint: quote_mint.to_string(),
+            base_decimals: base_decimals.parse().context("Invalid base decimals")?,
+            quote_decimals: quote_decimals.parse().context("Invalid quote decimals")?,
+        })
+    }
+}
+
+// Sell `fraction` of the position size at entry once the price is `target_pct` above the entry price
+#[derive(Debug, Clone, PartialEq)]
+pub struct LadderRung {
+    pub target_pct: Decimal,
+    pub fraction: Decimal,
+}
+
+// Parses "target_pct:fraction", e.g. "0.02:0.25"
+impl std::str::FromStr for LadderRung {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let Some((target_pct, fraction)) = s.trim().split_once(':') else {
+            anyhow::bail!("Expected TARGET_PCT:FRACTION, got {}", s);
+        };
+        
+        Ok(LadderRung {
+            target_pct: target_pct.parse().context("Invalid ladder target")?,
+            fraction: fraction.parse().context("Invalid ladder fraction")?,
+        })
+    }
+}
+
+// Configuration problems found while loading, reported together
+#[derive(Debug, Default)]
+struct ConfigErrors(Vec<String>);
+
+impl ConfigErrors {
+    fn push(&mut self, message: String) {
+        self.0.push(message);
+    }
+    
+    fn require(&mut self, name: &str) -> String {
+        env::var(name).unwrap_or_else(|_| {
+            self.push(format!("{} must be set", name));
+            String::new()
+        })
+    }
+    
+    fn parse<T: FromStr>(&mut self, name: &str, value: &str) -> Option<T>
+    where
+        T::Err: std::fmt::Display,
+    {
+        match value.parse() {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.push(format!("Invalid {}: {:#}", name, e));
+                None
+            }
+        }
+    }
+    
+    // An invalid value is reported and replaced with T's default so loading can continue
+    fn parse_or<T: FromStr + Default>(&mut self, name: &str, default: &str) -> T
+    where
+        T::Err: std::fmt::Display,
+    {
+        let value = env::var(name).unwrap_or_else(|_| default.to_string());
+        self.parse(name, &value).unwrap_or_default()
+    }
+    
+    fn parse_optional<T: FromStr>(&mut self, name: &str) -> Option<T>
+    where
+        T::Err: std::fmt::Display,
+    {
+        let value = env::var(name).ok()?;
+        self.parse(name, &value)
+    }
+    
+    fn check_pubkey(&mut self, name: &str, value: &str) {
+        if Pubkey::from_str(value).is_err() {
+            self.push(format!("{} must be a valid public key, got {}", name, value));
+        }
+    }
+    
+    fn reported(&self, name: &str) -> bool {
+        let prefix = format!("Invalid {}:", name);
+        self.0.iter().any(|message| message.starts_with(&prefix))
+    }
+    
+    fn into_result(self) -> Result<()> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        anyhow::bail!("Invalid configuration:\n- {}", self.0.join("\n- "))
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Config {
     // Solana configuration
-    pub rpc_url: String,
+    // Tried in order, moving on when an endpoint is unhealthy
+    pub rpc_urls: Vec<String>,
     pub private_key: String,
+    // From SOLANA_CLUSTER, or guessed from the RPC URL
+    pub cluster: Cluster,
     
     // Jupiter configuration
     pub jupiter_api_url: String,
+    pub jupiter_api_key: Option<String>,
+    pub jupiter_quote_path: String,
+    pub jupiter_swap_path: String,
+    pub jupiter_trigger_api_url: String,
     pub slippage_bps: u16,
+    // Scale slippage with 24h volatility between the bounds below instead of using slippage_bps
+    pub adaptive_slippage: bool,
+    pub min_slippage_bps: u16,
+    pub max_slippage_bps: u16,
+    // 24h volatility, as a fraction of the price, at and above which max_slippage_bps is used
+    pub adaptive_slippage_volatility_pct: Decimal,
+    // Average request rate allowed towards Jupiter, the free host allows about one per second
+    pub jupiter_requests_per_second: f64,
+    pub quote_cache_ttl_secs: u64,
+    // None means "auto" (75th percentile of recent prioritization fees)
+    pub priority_fee_micro_lamports: Option<u64>,
+    // Compute unit limit set on swap transactions, None lets Jupiter estimate it per swap
+    pub compute_unit_limit: Option<u32>,
+    pub max_price_impact_pct: Decimal,
+    // Simulate each signed swap and refuse to send it if the simulation fails
+    pub simulate_before_send: bool,
+    // Refuse to trade SOL when the Jupiter price is further than this fraction from Pyth, unset disables
+    pub max_price_divergence_pct: Option<Decimal>,
+    // Skip trading when the buy and sell quotes are further apart than this fraction of the price, unset disables
+    pub max_spread_pct: Option<Decimal>,
+    // Pyth SOL/USD price feed account read for the divergence check
+    pub pyth_price_account: String,
+    // CoinGecko API used by /admin/backfill, the key is sent as a demo API key when set
+    pub coingecko_api_url: String,
+    pub coingecko_api_key: Option<String>,
+    // Flag swaps that receive less than this many bps above the slippage minimum
+    pub suspicious_fill_margin_bps: u16,
+    // Token account that collects Jupiter platform fees, set together with platform_fee_bps
+    pub fee_account: Option<String>,
+    pub platform_fee_bps: Option<u16>,
+    
+    // Trading configuration
+    pub strategy: Strategy,
+    pub order_mode: OrderMode,
+    // Limit orders left unfilled this long are cancelled by Jupiter, unset keeps them open
+    pub limit_order_expiry_secs: Option<u64>,
+    pub sma_short_hours: u32,
+    pub sma_long_hours: u32,
+    pub rsi_period: usize,
+    pub rsi_oversold: Decimal,
+    pub rsi_overbought: Decimal,
+    pub stop_loss_pct: Decimal,
+    pub take_profit_pct: Decimal,
+    pub buy_dip_pct: Decimal,
+    // Sell once the price falls this far below its peak since buying, unset disables
+    pub trailing_stop_pct: Option<Decimal>,
+    // Sell the base token once it has been held this long, whatever the price, unset disables
+    pub max_hold_hours: Option<u32>,
+    // Partial take-profit exits, sorted by target and fired once each while holding the base token
+    pub take_profit_ladder: Vec<LadderRung>,
+    // Prices are rounded to this many decimal places before they are stored or compared
+    pub price_decimals: u32,
+    pub dry_run: bool,
+    pub trade_fraction: Decimal,
+    pub max_trade_usdc: Option<Decimal>,
+    pub min_trade_usdc: Option<Decimal>,
+    // Skip trades whose estimated profit after fees is below this, in units of the quote token
+    pub min_profit_usdc: Option<Decimal>,
+    // Native SOL kept back from swaps to pay transaction fees
+    pub sol_fee_reserve: Decimal,
+    // Close a leftover wrapped SOL account before trading, returning its balance and rent to native SOL
+    pub close_wrapped_sol: bool,
+    // Holdings worth less than this (in quote tokens) don't count when checking the position against the wallet
+    pub position_reconcile_threshold_usdc: Decimal,
+    // Minimum time between trades of a pair, 0 disables
+    pub trade_cooldown_secs: u64,
+    // Halt trading after this many consecutive losing trades until reset
+    pub max_consecutive_losses: Option<u32>,
     
-    // LINE bot configuration
-    pub line_channel_token: String,
-    pub line_user_id: String,
+    // Notification configuration (each channel is optional)
+    pub line_channel_token: Option<String>,
+    // Notifications go to all of these users, and any of them can use the chat commands
+    pub line_user_ids: Vec<String>,
+    // Verifies /webhook/line requests, unset disables the webhook
+    pub line_channel_secret: Option<String>,
+    // Per-attempt limit on LINE API requests
+    pub line_timeout_secs: u64,
+    pub notification_level: NotificationLevel,
+    pub telegram_bot_token: Option<String>,
+    pub telegram_chat_id: Option<String>,
+    pub discord_webhook_url: Option<String>,
+    // Each completed trade's session is POSTed here as JSON, signed with the secret when one is set
+    pub result_webhook_url: Option<String>,
+    pub result_webhook_secret: Option<String>,
+    pub send_startup_notification: bool,
+    // Transaction link template for notifications, `{signature}` is replaced with the swap signature
+    pub explorer_tx_url: String,
+    // Timezone of stored timestamps, notification times and the midnight price update
+    pub timezone: chrono_tz::Tz,
     
-    // Token addresses
-    pub sol_mint: String,
-    pub usdc_mint: String,
+    // Pairs to trade, each with its own position and history
+    pub pairs: Vec<TradingPair>,
+    // Symbol of a configured token that profits are reported in, defaults to the first pair's quote token
+    pub reporting_currency: String,
     
     // Server configuration
     pub port: u16,
+    pub trigger_secret: Option<String>,
+    // Alert when no /trigger has arrived for this long, unset disables
+    pub heartbeat_timeout_secs: Option<u64>,
     
     // Firestore configuration
     pub gcp_project_id: String,
+    // e.g. "staging_", empty for the original collection names
+    pub firestore_collection_prefix: String,
     pub data_retention_days: u32,
 }
 
 impl Config {
     pub fn from_env() -> Result<Self> {
         dotenvy::dotenv().ok();
+        // Every problem is collected so they can all be fixed in one go
+        let mut errors = ConfigErrors::default();
 
-        Ok(Config {
-            rpc_url: env::var("SOLANA_RPC_URL")
-                .unwrap_or_else(|_| "https://api.mainnet-beta.solana.com".to_string()),
+        let mut config = Config {
+            rpc_urls: env::var("SOLANA_RPC_URL")
+                .unwrap_or_else(|_| "https://api.mainnet-beta.solana.com".to_string())
+                .split(',')
+                .map(|url| url.trim().to_string())
+                .filter(|url| !url.is_empty())
+                .collect(),
             
-            private_key: env::var("WALLET_PRIVATE_KEY")
-                .context("WALLET_PRIVATE_KEY must be set")?,
+            private_key: errors.require("WALLET_PRIVATE_KEY"),
+            
+            // Every endpoint should serve the same cluster, so the first is enough to tell which
+            cluster: errors.parse_optional("SOLANA_CLUSTER").unwrap_or_else(|| Cluster::from_rpc_url(
+                env::var("SOLANA_RPC_URL").unwrap_or_default().split(',').next().unwrap_or_default(),
+            )),
             
             jupiter_api_url: env::var("JUPITER_API_URL")
                 .unwrap_or_else(|_| "https://lite-api.jup.ag/swap/v1".to_string()),
             
-            slippage_bps: env::var("SLIPPAGE_BPS")
-                .unwrap_or_else(|_| "50".to_string())
-                .parse()
-                .context("Invalid SLIPPAGE_BPS")?,
+            jupiter_api_key: env::var("JUPITER_API_KEY").ok(),
+            
+            jupiter_quote_path: env::var("JUPITER_QUOTE_PATH")
+                .unwrap_or_else(|_| "/quote".to_string()),
+            
+            jupiter_swap_path: env::var("JUPITER_SWAP_PATH")
+                .unwrap_or_else(|_| "/swap".to_string()),
+            
+            jupiter_trigger_api_url: env::var("JUPITER_TRIGGER_API_URL")
+                .unwrap_or_else(|_| "https://lite-api.jup.ag/trigger/v1".to_string()),
+            
+            slippage_bps: errors.parse_or("SLIPPAGE_BPS", "50"),
             
-            line_channel_token: env::var("LINE_CHANNEL_TOKEN")
-                .context("LINE_CHANNEL_TOKEN must be set")?,
+            adaptive_slippage: errors.parse_or("ADAPTIVE_SLIPPAGE", "false"),
             
-            line_user_id: env::var("LINE_USER_ID")
-                .context("LINE_USER_ID must be set")?,
+            min_slippage_bps: errors.parse_or("MIN_SLIPPAGE_BPS", "30"),
             
-            sol_mint: env::var("SOL_MINT")
-                .unwrap_or_else(|_| "So11111111111111111111111111111111111111112".to_string()),
+            max_slippage_bps: errors.parse_or("MAX_SLIPPAGE_BPS", "300"),
             
-            usdc_mint: env::var("USDC_MINT")
-                .unwrap_or_else(|_| "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string()),
+            adaptive_slippage_volatility_pct: errors.parse_or("ADAPTIVE_SLIPPAGE_VOLATILITY_PCT", "0.05"),
             
-            port: env::var("PORT")
-                .unwrap_or_else(|_| "8080".to_string())
-                .parse()
-                .context("Invalid PORT")?,
+            jupiter_requests_per_second: errors.parse_or("JUPITER_REQUESTS_PER_SECOND", "1"),
             
-            gcp_project_id: env::var("GCP_PROJECT_ID")
-                .context("GCP_PROJECT_ID must be set")?,
+            quote_cache_ttl_secs: errors.parse_or("QUOTE_CACHE_TTL_SECS", "5"),
             
-            data_retention_days: env::var("DATA_RETENTION_DAYS")
-                .unwrap_or_else(|_| "365".to_string())
-                .parse()
-                .context("Invalid DATA_RETENTION_DAYS")?,
+            priority_fee_micro_lamports: match env::var("PRIORITY_FEE_MICRO_LAMPORTS")
+                .unwrap_or_else(|_| "1000".to_string())
+                .as_str()
+            {
+                "auto" => None,
+                value => errors.parse("PRIORITY_FEE_MICRO_LAMPORTS", value),
+            },
+            
+            compute_unit_limit: errors.parse_optional("COMPUTE_UNIT_LIMIT"),
+            
+            max_price_impact_pct: errors.parse_or("MAX_PRICE_IMPACT_PCT", "1.0"),
+            
+            simulate_before_send: errors.parse_or("SIMULATE_BEFORE_SEND", "true"),
+            
+            max_price_divergence_pct: errors.parse_optional("MAX_PRICE_DIVERGENCE_PCT"),
+            
+            max_spread_pct: errors.parse_optional("MAX_SPREAD_PCT"),
+            
+            pyth_price_account: env::var("PYTH_PRICE_ACCOUNT")
+                .unwrap_or_else(|_| "7UVimffxr9ow1uXYxsr4LHAcV58mLzhmwaeKvJ1pjLiE".to_string()),
+            
+            coingecko_api_url: env::var("COINGECKO_API_URL")
+                .unwrap_or_else(|_| "https://api.coingecko.com/api/v3".to_string()),
+            
+            coingecko_api_key: env::var("COINGECKO_API_KEY").ok(),
+            
+            suspicious_fill_margin_bps: errors.parse_or("SUSPICIOUS_FILL_MARGIN_BPS", "10"),
+            
+            fee_account: env::var("FEE_ACCOUNT").ok(),
+            
+            platform_fee_bps: errors.parse_optional("PLATFORM_FEE_BPS"),
+            
+            strategy: errors.parse_or("STRATEGY", "threshold_pct"),
+            
+            order_mode: errors.parse_or("ORDER_MODE", "market"),
+            
+            limit_order_expiry_secs: errors.parse_optional("LIMIT_ORDER_EXPIRY_SECS"),
+            
+            sma_short_hours: errors.parse_or("SMA_SHORT_HOURS", "6"),
+            
+            sma_long_hours: errors.parse_or("SMA_LONG_HOURS", "24"),
+            
+            rsi_period: errors.parse_or("RSI_PERIOD", "14"),
+            
+            rsi_oversold: errors.parse_or("RSI_OVERSOLD", "30"),
+            
+            rsi_overbought: errors.parse_or("RSI_OVERBOUGHT", "70"),
+            
+            stop_loss_pct: errors.parse_or("STOP_LOSS_PCT", "0.05"),
+            
+            take_profit_pct: errors.parse_or("TAKE_PROFIT_PCT", "0.01"),
+            
+            buy_dip_pct: errors.parse_or("BUY_DIP_PCT", "0.01"),
+            
+            trailing_stop_pct: errors.parse_optional("TRAILING_STOP_PCT"),
+            
+            max_hold_hours: errors.parse_optional("MAX_HOLD_HOURS"),
+            
+            take_profit_ladder: match env::var("TAKE_PROFIT_LADDER") {
+                Ok(ladder) if !ladder.trim().is_empty() => ladder
+                    .split(',')
+                    .filter_map(|rung| errors.parse("TAKE_PROFIT_LADDER", rung))
+                    .collect(),
+                _ => Vec::new(),
+            },
+            
+            price_decimals: errors.parse_or("PRICE_DECIMALS", "6"),
+            
+            dry_run: errors.parse_or("DRY_RUN", "false"),
+            
+            trade_fraction: errors.parse_or("TRADE_FRACTION", "1.0"),
+            
+            max_trade_usdc: errors.parse_optional("MAX_TRADE_USDC"),
+            
+            min_trade_usdc: errors.parse_optional("MIN_TRADE_USDC"),
+            
+            min_profit_usdc: errors.parse_optional("MIN_PROFIT_USDC"),
+            
+            sol_fee_reserve: errors.parse_or("SOL_FEE_RESERVE", "0.01"),
+            
+            close_wrapped_sol: errors.parse_or("CLOSE_WRAPPED_SOL", "false"),
+            
+            position_reconcile_threshold_usdc: errors.parse_or("POSITION_RECONCILE_THRESHOLD_USDC", "1"),
+            
+            trade_cooldown_secs: errors.parse_or("TRADE_COOLDOWN_SECS", "0"),
+            
+            max_consecutive_losses: errors.parse_optional("MAX_CONSECUTIVE_LOSSES"),
+            
+            line_channel_token: env::var("LINE_CHANNEL_TOKEN").ok(),
+            
+            // A comma-separated LINE_USER_IDS, or the single LINE_USER_ID
+            line_user_ids: env::var("LINE_USER_IDS")
+                .or_else(|_| env::var("LINE_USER_ID"))
+                .unwrap_or_default()
+                .split(',')
+                .map(|user_id| user_id.trim().to_string())
+                .filter(|user_id| !user_id.is_empty())
+                .collect(),
+            
+            line_channel_secret: env::var("LINE_CHANNEL_SECRET").ok(),
+            
+            line_timeout_secs: errors.parse_or("LINE_TIMEOUT_SECS", "10"),
+            
+            notification_level: errors.parse_or("NOTIFICATION_LEVEL", "trades"),
+            
+            telegram_bot_token: env::var("TELEGRAM_BOT_TOKEN").ok(),
+            
+            telegram_chat_id: env::var("TELEGRAM_CHAT_ID").ok(),
+            
+            discord_webhook_url: env::var("DISCORD_WEBHOOK_URL").ok(),
+            
+            result_webhook_url: env::var("RESULT_WEBHOOK_URL").ok(),
+            
+            result_webhook_secret: env::var("RESULT_WEBHOOK_SECRET").ok(),
+            
+            send_startup_notification: errors.parse_or("SEND_STARTUP_NOTIFICATION", "true"),
+            
+            explorer_tx_url: env::var("EXPLORER_TX_URL")
+                .unwrap_or_else(|_| "https://solscan.io/tx/{signature}".to_string()),
+            
+            timezone: errors.parse_optional("TIMEZONE").unwrap_or(chrono_tz::Asia::Tokyo),
+            
+            pairs: match env::var("TRADING_PAIRS") {
+                Ok(pairs) => pairs
+                    .split(',')
+                    .filter_map(|pair| errors.parse("TRADING_PAIRS", pair))
+                    .collect(),
+                // Default to the single SOL/USDC pair
+                Err(_) => vec![TradingPair {
+                    name: "SOL/USDC".to_string(),
+                    base_mint: env::var("SOL_MINT")
+                        .unwrap_or_else(|_| "So11111111111111111111111111111111111111112".to_string()),
+                    quote_mint: env::var("USDC_MINT")
+                        .unwrap_or_else(|_| "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string()),
+                    base_decimals: 9,
+                    quote_decimals: 6,
+                }],
+            },
+            
+            reporting_currency: env::var("REPORTING_CURRENCY").unwrap_or_default(),
+            
+            port: errors.parse_or("PORT", "8080"),
+            
+            trigger_secret: env::var("TRIGGER_SECRET").ok(),
+            
+            heartbeat_timeout_secs: errors.parse_optional("HEARTBEAT_TIMEOUT_SECS"),
+            
+            gcp_project_id: errors.require("GCP_PROJECT_ID"),
+            
+            firestore_collection_prefix: env::var("FIRESTORE_COLLECTION_PREFIX").unwrap_or_default(),
+            
+            data_retention_days: errors.parse_or("DATA_RETENTION_DAYS", "365"),
+        };
+        
+        if config.rpc_urls.is_empty() {
+            errors.push("SOLANA_RPC_URL must contain at least one URL".to_string());
+        }
+        
+        // Real funds are at stake on mainnet, so it has to be opted into explicitly
+        if !cluster_confirmed(config.cluster, env::var("CONFIRM_MAINNET").ok().as_deref()) {
+            // The RPC URL is left out since provider URLs often embed an API key
+            errors.push(
+                "Refusing to start on mainnet without CONFIRM_MAINNET=true. \
+                Set SOLANA_CLUSTER or SOLANA_RPC_URL to a devnet/testnet endpoint for testing".to_string()
+            );
+        }
+        
+        if config.sma_short_hours == 0 || config.sma_short_hours >= config.sma_long_hours {
+            errors.push("SMA_SHORT_HOURS must be greater than 0 and less than SMA_LONG_HOURS".to_string());
+        }
+        
+        if config.rsi_period == 0 || config.rsi_oversold >= config.rsi_overbought {
+            errors.push("RSI_PERIOD must be greater than 0 and RSI_OVERSOLD must be below RSI_OVERBOUGHT".to_string());
+        }
+        
+        if config.trade_fraction <= Decimal::ZERO || config.trade_fraction > Decimal::ONE {
+            errors.push(format!("TRADE_FRACTION must be greater than 0 and at most 1, got {}", config.trade_fraction));
+        }
+        
+        if !config.jupiter_requests_per_second.is_finite() || config.jupiter_requests_per_second <= 0.0 {
+            errors.push(format!("JUPITER_REQUESTS_PER_SECOND must be greater than 0, got {}", config.jupiter_requests_per_second));
+        }
+        
+        if let Some(max_price_divergence_pct) = config.max_price_divergence_pct {
+            if max_price_divergence_pct <= Decimal::ZERO {
+                errors.push(format!("MAX_PRICE_DIVERGENCE_PCT must be greater than 0, got {}", max_price_divergence_pct));
+            }
+            errors.check_pubkey("PYTH_PRICE_ACCOUNT", &config.pyth_price_account);
+        }
+        
+        match (&config.fee_account, config.platform_fee_bps) {
+            (Some(fee_account), Some(platform_fee_bps)) => {
+                errors.check_pubkey("FEE_ACCOUNT", fee_account);
+                if platform_fee_bps == 0 || platform_fee_bps > 10_000 {
+                    errors.push(format!("PLATFORM_FEE_BPS must be between 1 and 10000, got {}", platform_fee_bps));
+                }
+            }
+            (None, None) => {}
+            _ => errors.push("FEE_ACCOUNT and PLATFORM_FEE_BPS must be set together".to_string()),
+        }
+        
+        if config.slippage_bps > 10_000 {
+            errors.push(format!("SLIPPAGE_BPS must be at most 10000, got {}", config.slippage_bps));
+        }
+        
+        if config.adaptive_slippage {
+            if config.max_slippage_bps > 10_000 {
+                errors.push(format!("MAX_SLIPPAGE_BPS must be at most 10000, got {}", config.max_slippage_bps));
+            }
+            if config.min_slippage_bps > config.max_slippage_bps {
+                errors.push(format!("MIN_SLIPPAGE_BPS ({}) must not exceed MAX_SLIPPAGE_BPS ({})", config.min_slippage_bps, config.max_slippage_bps));
+            }
+            if config.adaptive_slippage_volatility_pct <= Decimal::ZERO {
+                errors.push(format!("ADAPTIVE_SLIPPAGE_VOLATILITY_PCT must be greater than 0, got {}", config.adaptive_slippage_volatility_pct));
+            }
+        }
+        
+        if let Some(trailing_stop_pct) = config.trailing_stop_pct {
+            if trailing_stop_pct <= Decimal::ZERO || trailing_stop_pct >= Decimal::ONE {
+                errors.push(format!("TRAILING_STOP_PCT must be greater than 0 and less than 1, got {}", trailing_stop_pct));
+            }
+        }
+        
+        if config.line_user_ids.len() > MAX_MULTICAST_RECIPIENTS {
+            errors.push(format!("LINE_USER_IDS can list at most {} users, got {}", MAX_MULTICAST_RECIPIENTS, config.line_user_ids.len()));
+        }
+        
+        if config.max_hold_hours == Some(0) {
+            errors.push("MAX_HOLD_HOURS must be greater than 0".to_string());
+        }
+        
+        if config.heartbeat_timeout_secs == Some(0) {
+            errors.push("HEARTBEAT_TIMEOUT_SECS must be greater than 0".to_string());
+        }
+        
+        for rung in &config.take_profit_ladder {
+            if rung.target_pct <= Decimal::ZERO {
+                errors.push(format!("TAKE_PROFIT_LADDER targets must be greater than 0, got {}", rung.target_pct));
+            }
+            if rung.fraction <= Decimal::ZERO || rung.fraction > Decimal::ONE {
+                errors.push(format!("TAKE_PROFIT_LADDER fractions must be greater than 0 and at most 1, got {}", rung.fraction));
+            }
+        }
+        config.take_profit_ladder.sort_by_key(|rung| rung.target_pct);
+        
+        if let Some(limit) = config.compute_unit_limit {
+            if limit == 0 || limit > MAX_COMPUTE_UNIT_LIMIT {
+                errors.push(format!("COMPUTE_UNIT_LIMIT must be between 1 and {}, got {}", MAX_COMPUTE_UNIT_LIMIT, limit));
+            }
+        }
+        
+        if config.port == 0 {
+            errors.push("PORT must be greater than 0".to_string());
+        }
+        
+        if config.price_decimals > 28 {
+            errors.push(format!("PRICE_DECIMALS must be at most 28, got {}", config.price_decimals));
+        }
+        
+        if config.sol_fee_reserve < Decimal::ZERO {
+            errors.push(format!("SOL_FEE_RESERVE must not be negative, got {}", config.sol_fee_reserve));
+        }
+        
+        if !config.explorer_tx_url.contains("{signature}") {
+            errors.push(format!("EXPLORER_TX_URL must contain {{signature}}, got {}", config.explorer_tx_url));
+        }
+        
+        // A TRADING_PAIRS entry that failed to parse is already reported
+        if config.pairs.is_empty() && !errors.reported("TRADING_PAIRS") {
+            errors.push("TRADING_PAIRS must contain at least one pair".to_string());
+        }
+        
+        for pair in &config.pairs {
+            errors.check_pubkey(&format!("{} base mint", pair.name), &pair.base_mint);
+            errors.check_pubkey(&format!("{} quote mint", pair.name), &pair.quote_mint);
+        }
+        
+        // Use the symbol as spelled in TRADING_PAIRS so it can be compared with pair symbols
+        let reporting_currency = if config.reporting_currency.is_empty() {
+            config.pairs.first().map(|pair| pair.quote_symbol().to_string())
+        } else {
+            config.pairs.iter()
+                .flat_map(|pair| [pair.base_symbol(), pair.quote_symbol()])
+                .find(|symbol| symbol.eq_ignore_ascii_case(&config.reporting_currency))
+                .map(str::to_string)
+        };
+        match reporting_currency {
+            Some(reporting_currency) => config.reporting_currency = reporting_currency,
+            None if !config.pairs.is_empty() => errors.push(format!(
+                "REPORTING_CURRENCY must be the symbol of a token in TRADING_PAIRS, got {}", config.reporting_currency
+            )),
+            None => {}
+        }
+        
+        let mut names: Vec<&str> = config.pairs.iter().map(|pair| pair.name.as_str()).collect();
+        names.sort();
+        names.dedup();
+        if names.len() != config.pairs.len() {
+            errors.push("TRADING_PAIRS must not contain the same pair name twice".to_string());
+        }
+        
+        errors.into_result()?;
+        Ok(config)
+    }
+    
+    // Non-secret settings for the startup notification. Keys, tokens and URLs that may embed credentials are left out.
+    pub fn startup_summary(&self) -> String {
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "off".to_string());
+        let pairs: Vec<&str> = self.pairs.iter().map(|pair| pair.name.as_str()).collect();
+        
+        format!(
+            "🚀 Trading bot started\n\
+            Cluster: {}\n\
+            Pairs: {}\n\
+            Reporting currency: {}\n\
+            Timezone: {}\n\
+            Strategy: {:?}\n\
+            Order mode: {:?}\n\
+            Dry run: {}\n\
+            Slippage: {}\n\
+            Stop loss: {}%\n\
+            Take profit: {}%\n\
+            Buy dip: {}%\n\
+            Trailing stop: {}\n\
+            Max hold: {}\n\
+            Take profit ladder: {}\n\
+            Trade fraction: {}\n\
+            Max trade: {}\n\
+            Min trade: {}\n\
+            Min profit: {}\n\
+            Cooldown: {}s\n\
+            Max consecutive losses: {}\n\
+            Heartbeat timeout: {}\n\
+            Notifications: {:?}\n\
+            Data retention: {} days",
+            self.cluster,
+            pairs.join(", "),
+            self.reporting_currency,
+            self.timezone,
+            self.strategy,
+            self.order_mode,
+            self.dry_run,
+            if self.adaptive_slippage {
+                format!("adaptive {}-{} bps", self.min_slippage_bps, self.max_slippage_bps)
+            } else {
+                format!("{} bps", self.slippage_bps)
+            },
+            self.stop_loss_pct * Decimal::ONE_HUNDRED,
+            self.take_profit_pct * Decimal::ONE_HUNDRED,
+            self.buy_dip_pct * Decimal::ONE_HUNDRED,
+            optional(self.trailing_stop_pct.map(|pct| format!("{}%", pct * Decimal::ONE_HUNDRED))),
+            optional(self.max_hold_hours.map(|hours| format!("{}h", hours))),
+            if self.take_profit_ladder.is_empty() {
+                "off".to_string()
+            } else {
+                self.take_profit_ladder.iter()
+                    .map(|rung| format!("{}% at +{}%", rung.fraction * Decimal::ONE_HUNDRED, rung.target_pct * Decimal::ONE_HUNDRED))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            },
+            self.trade_fraction,
+            optional(self.max_trade_usdc.map(|v| v.to_string())),
+            optional(self.min_trade_usdc.map(|v| v.to_string())),
+            optional(self.min_profit_usdc.map(|v| v.to_string())),
+            self.trade_cooldown_secs,
+            optional(self.max_consecutive_losses.map(|v| v.to_string())),
+            optional(self.heartbeat_timeout_secs.map(|secs| format!("{}s", secs))),
+            self.notification_level,
+            self.data_retention_days,
+        )
+    }
+    
+    // Mint and decimals of a configured pair's token, looked up by symbol or mint
+    pub fn find_token(&self, token: &str) -> Option<(String, u8)> {
+        self.pairs.iter().find_map(|pair| {
+            if pair.base_mint == token || pair.base_symbol().eq_ignore_ascii_case(token) {
+                Some((pair.base_mint.clone(), pair.base_decimals))
+            } else if pair.quote_mint == token || pair.quote_symbol().eq_ignore_ascii_case(token) {
+                Some((pair.quote_mint.clone(), pair.quote_decimals))
+            } else {
+                None
+            }
         })
     }
+    
+    pub fn explorer_url(&self, signature: &str) -> String {
+        self.explorer_tx_url.replace("{signature}", signature)
+    }
+}
+
+// from_env reads the process environment, so tests that change it hold this while they do
+#[cfg(test)]
+static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+// Configuration for unit tests: the defaults, with the required variables set to placeholders
+#[cfg(test)]
+pub(crate) fn test_config() -> Config {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| {
+        let keypair = solana_sdk::signature::Keypair::new();
+        env::set_var("WALLET_PRIVATE_KEY", bs58::encode(keypair.to_bytes()).into_string());
+        env::set_var("GCP_PROJECT_ID", "test-project");
+        env::set_var("SOLANA_CLUSTER", "devnet");
+    });
+    let _env = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    Config::from_env().expect("test configuration should be valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn every_bad_variable_is_reported_at_once() {
+        test_config();
+        let _env = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let bad = [
+            ("SLIPPAGE_BPS", "fifty"),
+            ("STRATEGY", "moon"),
+            ("TRADE_FRACTION", "1.5"),
+            ("PORT", "0"),
+            ("TAKE_PROFIT_LADDER", "0.02"),
+        ];
+        for (name, value) in bad {
+            env::set_var(name, value);
+        }
+        let result = Config::from_env();
+        for (name, _) in bad {
+            env::remove_var(name);
+        }
+        
+        let message = result.unwrap_err().to_string();
+        for expected in [
+            "Invalid SLIPPAGE_BPS:",
+            "Invalid STRATEGY: Unknown strategy: moon",
+            "TRADE_FRACTION must be greater than 0 and at most 1, got 1.5",
+            "PORT must be greater than 0",
+            "Invalid TAKE_PROFIT_LADDER: Expected TARGET_PCT:FRACTION, got 0.02",
+        ] {
+            assert!(message.contains(expected), "{:?} missing from:\n{}", expected, message);
+        }
+        assert_eq!(message.lines().count(), 1 + bad.len(), "{}", message);
+    }
+    
+    #[test]
+    fn cluster_is_inferred_from_the_rpc_url() {
+        let cases = [
+            ("https://api.mainnet-beta.solana.com", Cluster::Mainnet),
+            ("https://mainnet.helius-rpc.com/?api-key=abc", Cluster::Mainnet),
+            ("https://api.devnet.solana.com", Cluster::Devnet),
+            ("https://API.DEVNET.SOLANA.COM", Cluster::Devnet),
+            ("https://api.testnet.solana.com", Cluster::Testnet),
+            ("http://localhost:8899", Cluster::Localnet),
+            ("http://127.0.0.1:8899", Cluster::Localnet),
+        ];
+        for (url, expected) in cases {
+            assert_eq!(Cluster::from_rpc_url(url), expected, "{}", url);
+        }
+    }
+    
+    #[test]
+    fn mainnet_needs_confirm_mainnet() {
+        assert!(!cluster_confirmed(Cluster::Mainnet, None));
+        assert!(!cluster_confirmed(Cluster::Mainnet, Some("yes")));
+        assert!(!cluster_confirmed(Cluster::Mainnet, Some("")));
+        assert!(cluster_confirmed(Cluster::Mainnet, Some("true")));
+        
+        for cluster in [Cluster::Devnet, Cluster::Testnet, Cluster::Localnet] {
+            assert!(cluster_confirmed(cluster, None), "{}", cluster);
+        }
+    }
+    
+    #[test]
+    fn notification_level_filters_events() {
+        use NotificationEvent::*;
+        use NotificationLevel::*;
+        
+        // Error, Trade, NoTrade, DailySummary
+        let cases = [
+            (Errors, [true, false, false, false]),
+            (Trades, [true, true, false, false]),
+            (Verbose, [true, true, true, true]),
+        ];
+        for (level, expected) in cases {
+            for (event, notifies) in [Error, Trade, NoTrade, DailySummary].into_iter().zip(expected) {
+                assert_eq!(level.notifies(event), notifies, "{:?} / {:?}", level, event);
+            }
+        }
+    }
+    
+    #[test]
+    fn startup_summary_leaves_out_secrets() {
+        let config = Config {
+            jupiter_api_key: Some("jup-secret-key".to_string()),
+            coingecko_api_key: Some("cg-secret-key".to_string()),
+            line_channel_token: Some("line-secret-token".to_string()),
+            line_channel_secret: Some("line-channel-secret".to_string()),
+            telegram_bot_token: Some("123456:telegram-secret".to_string()),
+            discord_webhook_url: Some("https://discord.com/api/webhooks/1/discord-secret".to_string()),
+            result_webhook_url: Some("https://example.com/hook?key=hook-secret".to_string()),
+            result_webhook_secret: Some("hmac-secret".to_string()),
+            trigger_secret: Some("trigger-secret".to_string()),
+            rpc_urls: vec!["https://devnet.helius-rpc.com/?api-key=rpc-secret".to_string()],
+            ..test_config()
+        };
+        let summary = config.startup_summary();
+        
+        for field in [
+            "Cluster: devnet",
+            "Pairs: ",
+            "Reporting currency: ",
+            "Timezone: ",
+            "Strategy: ",
+            "Dry run: ",
+            "Slippage: ",
+            "Stop loss: ",
+            "Trade fraction: ",
+            "Notifications: ",
+            "Data retention: ",
+        ] {
+            assert!(summary.contains(field), "{:?} missing from:\n{}", field, summary);
+        }
+        for secret in [
+            config.private_key.as_str(),
+            "jup-secret-key",
+            "cg-secret-key",
+            "line-secret-token",
+            "line-channel-secret",
+            "telegram-secret",
+            "discord-secret",
+            "hook-secret",
+            "hmac-secret",
+            "trigger-secret",
+            "rpc-secret",
+        ] {
+            assert!(!summary.contains(secret), "{:?} leaked into:\n{}", secret, summary);
+        }
+    }
+    
+    #[test]
+    fn explorer_url_links_the_signature() {
+        let config = test_config();
+        assert_eq!(config.explorer_url("5sig"), "https://solscan.io/tx/5sig");
+        
+        let config = Config {
+            explorer_tx_url: "https://explorer.solana.com/tx/{signature}?cluster=devnet".to_string(),
+            ..test_config()
+        };
+        assert_eq!(config.explorer_url("5sig"), "https://explorer.solana.com/tx/5sig?cluster=devnet");
+    }
 }
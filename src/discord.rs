@@ -0,0 +1,126 @@
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use crate::clock::format_local;
+use crate::error::notification_title;
+use crate::service::{redact, retry_as_exponential_back_off};
+use crate::trading::TradingState;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::{info, error, warn};
+
+#[derive(Debug, Serialize)]
+struct WebhookMessage {
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RateLimitResponse {
+    // Seconds to wait before the next request
+    retry_after: f64,
+}
+
+pub struct DiscordClient {
+    client: reqwest::Client,
+    webhook_url: String,
+    dry_run: bool,
+}
+
+impl DiscordClient {
+    pub fn new(webhook_url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            webhook_url: webhook_url.to_string(),
+            dry_run: false,
+        }
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub async fn send_message(&self, text: &str) -> Result<()> {
+        let message = WebhookMessage {
+            content: if self.dry_run { format!("[DRY RUN] {}", text) } else { text.to_string() },
+        };
+
+        retry_as_exponential_back_off(
+            || async {
+                let response = self.client
+                    .post(&self.webhook_url)
+                    .json(&message)
+                    .send()
+                    .await?;
+
+                // Discord rate-limits webhooks with 429 and tells us how long to wait
+                if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                    let retry_after = response.json::<RateLimitResponse>().await
+                        .map(|r| r.retry_after)
+                        .unwrap_or(1.0);
+                    warn!("Discord rate limited, waiting {}s before retrying", retry_after);
+                    tokio::time::sleep(Duration::from_secs_f64(retry_after.max(0.0))).await;
+                    anyhow::bail!("Discord rate limited");
+                }
+
+                if !response.status().is_success() {
+                    let error_text = redact(&response.text().await?);
+                    error!("Discord API error: {}", error_text);
+                    anyhow::bail!("Failed to send Discord message: {}", error_text);
+                }
+
+                Ok::<(), anyhow::Error>(())
+            },
+            "Send Discord message",
+            3,
+            500,
+            Some(Duration::from_secs(30)),
+            None,
+        )
+        .await?;
+
+        info!("Discord message sent successfully");
+        Ok(())
+    }
+
+    pub async fn send_success_notification(
+        &self,
+        state: &TradingState,
+        profit: Decimal,
+    ) -> Result<()> {
+        let trade_price = state.last_trade_price.unwrap_or(dec!(0));
+        let message = format!(
+            "😎 **Trade executed!**\n\
+            Pair: {}\n\
+            Position: {}\n\
+            Trade Price: {:.4} {}\n\
+            Profit: **{}**\n\
+            {}Time: {}",
+            state.pair.name,
+            state.position_symbol(),
+            trade_price,
+            state.pair.quote_symbol(),
+            state.format_profit(profit),
+            state.last_explorer_url.as_ref().map(|url| format!("[View transaction]({})\n", url)).unwrap_or_default(),
+            format_local(&chrono::Utc::now())
+        );
+        self.send_message(&message).await
+    }
+
+    pub async fn send_error_notification(
+        &self,
+        e: &anyhow::Error,
+    ) -> Result<()> {
+        let title = notification_title(e);
+        let message = format!(
+            "**{}**\n\
+            ```\n{}\n```\n\
+            Time: {}",
+            title,
+            e,
+            format_local(&chrono::Utc::now())
+        );
+        self.send_message(&message).await
+    }
+}
@@ -0,0 +1,48 @@
+use rust_decimal::Decimal;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TradingError {
+    #[error("Quote failed: {0}")]
+    QuoteFailed(String),
+    #[error("Swap failed: {0}")]
+    SwapFailed(String),
+    #[error("Swap simulation failed: {error}\nProgram logs:\n{logs}")]
+    SimulationFailed { error: String, logs: String },
+    #[error("Price impact {actual}% exceeds the maximum of {max}%")]
+    PriceImpactTooHigh { actual: Decimal, max: Decimal },
+    #[error("Insufficient {token} balance: {available}")]
+    InsufficientBalance { token: String, available: Decimal },
+    #[error("Insufficient SOL after reserve: balance {available} does not exceed the {reserve} SOL fee reserve")]
+    InsufficientSolAfterReserve { available: Decimal, reserve: Decimal },
+    #[error("Invalid token amount: {0}")]
+    InvalidAmount(String),
+    #[error("RPC error: {0}")]
+    RpcError(String),
+    #[error("Firestore error: {0}")]
+    FirestoreError(String),
+    #[error("{pair} price {price} diverges {divergence_pct}% from the Pyth price {reference}, more than the maximum of {max_pct}%")]
+    PriceDivergence { pair: String, price: Decimal, reference: Decimal, divergence_pct: Decimal, max_pct: Decimal },
+    #[error("Trading halted after {consecutive_losses} consecutive losses, reset the circuit breaker to resume")]
+    CircuitBroken { consecutive_losses: u32 },
+    #[error("Cannot {action} {pair} while already holding {position}")]
+    PositionMismatch { pair: String, action: String, position: String },
+}
+
+// Notification title for an error, picked by error class
+pub fn notification_title(e: &anyhow::Error) -> &'static str {
+    match e.downcast_ref::<TradingError>() {
+        Some(TradingError::QuoteFailed(_)) => "📉 Quote failed",
+        Some(TradingError::SwapFailed(_))
+        | Some(TradingError::SimulationFailed { .. }) => "💥 Swap failed",
+        Some(TradingError::PriceImpactTooHigh { .. })
+        | Some(TradingError::PriceDivergence { .. })
+        | Some(TradingError::PositionMismatch { .. }) => "🚧 Swap skipped",
+        Some(TradingError::InsufficientBalance { .. })
+        | Some(TradingError::InsufficientSolAfterReserve { .. }) => "🪫 Insufficient balance",
+        Some(TradingError::InvalidAmount(_)) => "🔢 Invalid amount",
+        Some(TradingError::RpcError(_)) => "📡 RPC error",
+        Some(TradingError::FirestoreError(_)) => "🗄️ Firestore error",
+        Some(TradingError::CircuitBroken { .. }) => "🛑 Circuit breaker tripped",
+        None => "🥺 Trading error...",
+    }
+}
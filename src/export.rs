@@ -0,0 +1,137 @@
+use rust_decimal::Decimal;
+
+use crate::firestore::TradingSession;
+
+const CSV_HEADER: &[&str] = &[
+    "timestamp",
+    "pair",
+    "action",
+    "position_before",
+    "position_after",
+    "price",
+    "base_balance_before",
+    "quote_balance_before",
+    "base_balance_after",
+    "quote_balance_after",
+    "slippage_bps",
+    "suspicious_fill",
+    "gas_fee",
+    "profit_loss",
+    "cumulative_profit",
+    "signature",
+    "id",
+];
+
+// One row per trading session, in the given order. Balances and prices use the legacy
+// sol_*/usdc_* fields, which hold the base and quote token of the session's pair.
+pub fn trading_sessions_to_csv(sessions: &[TradingSession]) -> String {
+    let mut csv = csv_row(CSV_HEADER.iter().map(|column| column.to_string()));
+    
+    for session in sessions {
+        csv.push_str(&csv_row([
+            session.timestamp.to_rfc3339(),
+            session.pair.clone(),
+            session.action.clone(),
+            session.position_before.clone(),
+            session.position_after.clone(),
+            session.price_at_trade.to_string(),
+            session.sol_balance_before.to_string(),
+            session.usdc_balance_before.to_string(),
+            session.sol_balance_after.to_string(),
+            session.usdc_balance_after.to_string(),
+            optional(session.slippage),
+            session.suspicious_fill.to_string(),
+            optional(session.gas_fee),
+            optional(session.profit_loss),
+            optional(session.cumulative_profit),
+            session.signature.clone().unwrap_or_default(),
+            session.id.clone(),
+        ]));
+    }
+    
+    csv
+}
+
+fn optional(value: Option<Decimal>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn csv_row(fields: impl IntoIterator<Item = String>) -> String {
+    let fields: Vec<String> = fields.into_iter().map(|field| csv_field(&field)).collect();
+    format!("{}\r\n", fields.join(","))
+}
+
+// RFC 4180: quote fields containing a delimiter, quote or line break, doubling any quotes
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+    use rust_decimal_macros::dec;
+    
+    fn session(id: &str, timestamp: &str, action: &str) -> TradingSession {
+        TradingSession {
+            id: id.to_string(),
+            pair: "SOL/USDC".to_string(),
+            timestamp: DateTime::parse_from_rfc3339(timestamp).unwrap(),
+            position_before: "USDC".to_string(),
+            position_after: "SOL".to_string(),
+            action: action.to_string(),
+            sol_balance_before: dec!(0),
+            usdc_balance_before: dec!(150),
+            sol_balance_after: dec!(1),
+            usdc_balance_after: dec!(0),
+            price_at_trade: dec!(150),
+            slippage: None,
+            gas_fee: None,
+            signature: None,
+            profit_loss: None,
+            cumulative_profit: None,
+            suspicious_fill: false,
+            holding_duration_secs: None,
+        }
+    }
+    
+    #[test]
+    fn sessions_render_to_csv_rows() {
+        let buy = TradingSession {
+            gas_fee: Some(dec!(0.000005)),
+            signature: Some("5sig".to_string()),
+            ..session("a1", "2026-01-01T09:00:00+09:00", "BUY_SOL")
+        };
+        let sell = TradingSession {
+            position_before: "SOL".to_string(),
+            position_after: "USDC".to_string(),
+            sol_balance_before: dec!(1),
+            usdc_balance_before: dec!(0),
+            sol_balance_after: dec!(0),
+            usdc_balance_after: dec!(155),
+            price_at_trade: dec!(155),
+            slippage: Some(dec!(12)),
+            profit_loss: Some(dec!(5)),
+            cumulative_profit: Some(dec!(5)),
+            suspicious_fill: true,
+            ..session("b2", "2026-01-02T09:00:00+09:00", "SELL, \"manual\"")
+        };
+        
+        assert_eq!(
+            trading_sessions_to_csv(&[buy, sell]),
+            "timestamp,pair,action,position_before,position_after,price,base_balance_before,quote_balance_before,\
+            base_balance_after,quote_balance_after,slippage_bps,suspicious_fill,gas_fee,profit_loss,cumulative_profit,signature,id\r\n\
+            2026-01-01T09:00:00+09:00,SOL/USDC,BUY_SOL,USDC,SOL,150,0,150,1,0,,false,0.000005,,,5sig,a1\r\n\
+            2026-01-02T09:00:00+09:00,SOL/USDC,\"SELL, \"\"manual\"\"\",SOL,USDC,155,1,0,0,155,12,true,,5,5,,b2\r\n"
+        );
+    }
+    
+    #[test]
+    fn no_sessions_is_just_the_header() {
+        assert_eq!(trading_sessions_to_csv(&[]).lines().count(), 1);
+    }
+}
@@ -1,28 +1,60 @@
 use anyhow::Result;
-use chrono::{DateTime, FixedOffset, TimeZone};
-use chrono_tz::Asia::Tokyo;
-use rust_decimal::Decimal;
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, Timelike, Utc};
+use rust_decimal::prelude::*;
 use serde::{Deserialize, Serialize};
 use tracing::{error, info, warn};
 use uuid::Uuid;
-use gcp_auth::{AuthenticationManager, CustomServiceAccount};
+use gcp_auth::{AuthenticationManager, CustomServiceAccount, Token};
 use reqwest::{Client, header::{AUTHORIZATION, CONTENT_TYPE}};
 use serde_json::Value as JsonValue;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use tokio::sync::{broadcast, Mutex};
+use crate::clock::{self, now_local, to_local};
+use crate::config::TradingPair;
+use crate::error::TradingError;
+use crate::indicators;
+use crate::price_source::CoinGeckoClient;
+use crate::service::retry_as_exponential_back_off;
+use crate::trading::Position;
 
+// Refresh the cached auth token once it is this close to expiring
+const TOKEN_REFRESH_MARGIN_SECS: i64 = 60;
+
+// A backfilled price is only written when no stored price is closer than this, half of CoinGecko's hourly spacing
+const BACKFILL_MIN_GAP_MINUTES: i64 = 30;
+
+// Documents written before multi-pair support have no pair field and were all SOL/USDC
+fn default_pair() -> String {
+    "SOL/USDC".to_string()
+}
+
+// Profits were reported in USDC before the reporting currency was configurable
+fn default_currency() -> String {
+    "USDC".to_string()
+}
+
+// Field names keep the original SOL/USDC wording so existing documents still load;
+// sol_* fields hold the base token and usdc_* fields the quote token of the pair.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PriceHistory {
     pub id: String,
+    #[serde(default = "default_pair")]
+    pub pair: String,
     pub timestamp: DateTime<FixedOffset>,
     pub sol_price_usdc: Decimal,
     pub usdc_price_sol: Decimal,
     pub data_source: String,
     pub trading_session_id: String,
+    // Gap between the two quote directions as a fraction of the price, None for backfilled prices
+    #[serde(default)]
+    pub spread: Option<Decimal>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TradingSession {
     pub id: String,
+    #[serde(default = "default_pair")]
+    pub pair: String,
     pub timestamp: DateTime<FixedOffset>,
     pub position_before: String,
     pub position_after: String,
@@ -34,23 +66,120 @@ pub struct TradingSession {
     pub price_at_trade: Decimal,
     pub slippage: Option<Decimal>,
     pub gas_fee: Option<Decimal>,
+    // Swap transaction signature, None for dry runs and documents written before it was stored
+    #[serde(default)]
+    pub signature: Option<String>,
+    // In the reporting currency configured when the trade was made
     pub profit_loss: Option<Decimal>,
     pub cumulative_profit: Option<Decimal>,
+    // Received amount was within SUSPICIOUS_FILL_MARGIN_BPS of the slippage minimum, a possible sandwich
+    #[serde(default)]
+    pub suspicious_fill: bool,
+    // Time the base token was held, on sells whose entry time is known
+    #[serde(default)]
+    pub holding_duration_secs: Option<i64>,
+}
+
+impl TradingSession {
+    // A sell leaves the pair's quote token (after the slash in the pair name) held, realizing the round trip
+    pub fn is_sell(&self) -> bool {
+        self.pair.split('/').nth(1) == Some(self.position_after.as_str())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProfitTracking {
     pub id: String,
+    #[serde(default = "default_pair")]
+    pub pair: String,
     pub timestamp: DateTime<FixedOffset>,
     pub trading_session_id: String,
+    // In `currency` despite the names, which are kept so existing documents still load
     pub profit_loss_usdc: Decimal,
     pub cumulative_profit_usdc: Decimal,
+    #[serde(default = "default_currency")]
+    pub currency: String,
     pub roi_percentage: Decimal,
     pub total_trades: i64,
     pub winning_trades: i64,
     pub losing_trades: i64,
 }
 
+// Per-pair loss streak and halt flag, kept in one document so it survives restarts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitBreakerState {
+    pub pair: String,
+    pub consecutive_losses: u32,
+    pub circuit_broken: bool,
+    pub updated_at: DateTime<FixedOffset>,
+}
+
+// Full per-pair TradingState, rewritten after every run so it can be restored from one document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradingStateSnapshot {
+    pub pair: String,
+    pub position: Position,
+    pub last_base_price: Option<Decimal>,
+    pub last_observed_price: Option<Decimal>,
+    pub last_quote_price: Option<Decimal>,
+    pub last_trade_price: Option<Decimal>,
+    pub last_trade_timestamp: Option<DateTime<FixedOffset>>,
+    pub peak_price: Option<Decimal>,
+    // Take profit ladder rungs already sold since entering the base position
+    #[serde(default)]
+    pub ladder_rungs_fired: usize,
+    #[serde(default)]
+    pub entered_at: Option<DateTime<FixedOffset>>,
+    #[serde(default)]
+    pub average_entry_price: Option<Decimal>,
+    #[serde(default)]
+    pub position_size: Decimal,
+    #[serde(default)]
+    pub entry_fees: Decimal,
+    pub total_profit: Decimal,
+    pub total_trades: i64,
+    pub winning_trades: i64,
+    pub losing_trades: i64,
+    pub consecutive_losses: u32,
+    pub circuit_broken: bool,
+    pub gas_fee: Option<Decimal>,
+    pub updated_at: DateTime<FixedOffset>,
+}
+
+// Highest base price seen since entering the base position, None while holding the quote token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrailingStopState {
+    pub pair: String,
+    pub peak_price: Option<Decimal>,
+    pub updated_at: DateTime<FixedOffset>,
+}
+
+// Time of the last /trigger, so the heartbeat check survives restarts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeartbeatState {
+    pub last_trigger_at: DateTime<FixedOffset>,
+}
+
+// The pair's resting limit order in limit order mode, None when there is none
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LimitOrderState {
+    pub pair: String,
+    pub open_order: Option<OpenLimitOrder>,
+    pub updated_at: DateTime<FixedOffset>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenLimitOrder {
+    pub order: String,
+    // Held when the order was placed, the order swaps it for the other token
+    pub position: Position,
+    // Base token amount bought or sold when the order fills
+    pub base_amount: Decimal,
+    pub target_price: Decimal,
+    pub signature: String,
+    pub placed_at: DateTime<FixedOffset>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PriceTrend {
     pub timestamp: DateTime<FixedOffset>,
@@ -64,17 +193,76 @@ pub struct PriceTrend {
     pub volatility_24h: Option<Decimal>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Volatility {
+    // Standard deviation of the price over the window, in quote tokens
+    pub volatility: Decimal,
+    pub sample_count: usize,
+    pub hours: u32,
+    // Fewer than two prices in the window, when volatility is reported as 0
+    pub insufficient_data: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Candle {
+    // Local time at the start of the interval
+    pub period_start: DateTime<FixedOffset>,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub point_count: usize,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TradingPerformance {
     pub total_trades: i64,
     pub winning_trades: i64,
     pub losing_trades: i64,
     pub total_profit_loss: Decimal,
+    // Reporting currency the profit figures are in
+    pub currency: String,
     pub total_gas_fees: Decimal,
     pub win_rate: Decimal,
+    // Largest peak-to-trough decline of the cumulative profit over the period
+    pub max_drawdown: Decimal,
+    // Mean time the base token was held, over sells with a recorded holding duration
+    pub average_holding_secs: Option<i64>,
+    // Sessions without a profit (a pair's first trade) and break-even ones don't affect streaks
+    pub longest_winning_streak: u32,
+    pub longest_losing_streak: u32,
+    // Positive for consecutive wins up to the latest trade, negative for consecutive losses
+    pub current_streak: i64,
     pub period_days: u32,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bucket {
+    Day,
+    // Weeks start on Monday
+    Week,
+}
+
+impl std::str::FromStr for Bucket {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "day" => Ok(Bucket::Day),
+            "week" => Ok(Bucket::Week),
+            _ => anyhow::bail!("Unknown bucket: {}", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfitBucket {
+    // Local midnight at the start of the day or week
+    pub period_start: DateTime<FixedOffset>,
+    pub profit_loss: Decimal,
+    pub trade_count: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FirestoreDocument {
     name: Option<String>,
@@ -140,12 +328,35 @@ struct ListDocumentsResponse {
     next_page_token: Option<String>,
 }
 
+// One per matching document, or a single entry without one when nothing matched
+#[derive(Debug, Deserialize)]
+struct RunQueryResult {
+    document: Option<FirestoreDocument>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchWriteResponse {
+    #[serde(default)]
+    status: Vec<BatchWriteStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchWriteStatus {
+    #[serde(default)]
+    code: i32,
+}
+
 pub struct FirestoreDb {
     client: Client,
     auth_manager: AuthenticationManager,
+    // Reused until it is about to expire, since every request needs one
+    auth_token: Mutex<Option<Token>>,
     pub project_id: String,
     pub database_id: String,
+    // Prepended to every collection name, so several bots can share one database
+    pub collection_prefix: String,
     retry_count: u32,
+    price_tx: Option<broadcast::Sender<PriceHistory>>,
 }
 
 impl FirestoreDb {
@@ -167,36 +378,60 @@ impl FirestoreDb {
         Ok(Self {
             client,
             auth_manager,
+            auth_token: Mutex::new(None),
             project_id,
             database_id: "(default)".to_string(),
+            collection_prefix: String::new(),
             retry_count: 3,
+            price_tx: None,
         })
     }
     
+    pub async fn from_config(config: &crate::config::Config) -> Result<Self> {
+        Ok(Self::new(config.gcp_project_id.clone()).await?
+            .with_collection_prefix(&config.firestore_collection_prefix))
+    }
+    
+    pub fn with_collection_prefix(mut self, collection_prefix: &str) -> Self {
+        if !collection_prefix.is_empty() {
+            info!("Using Firestore collection prefix: {}", collection_prefix);
+        }
+        self.collection_prefix = collection_prefix.to_string();
+        self
+    }
+    
+    // Broadcast each stored price point to live subscribers
+    pub fn with_price_broadcast(mut self, price_tx: broadcast::Sender<PriceHistory>) -> Self {
+        self.price_tx = Some(price_tx);
+        self
+    }
+    
     fn get_document_url(&self, collection: &str, document_id: &str) -> String {
         format!(
-            "https://firestore.googleapis.com/v1/projects/{}/databases/{}/documents/{}/{}",
-            self.project_id, self.database_id, collection, document_id
+            "https://firestore.googleapis.com/v1/projects/{}/databases/{}/documents/{}{}/{}",
+            self.project_id, self.database_id, self.collection_prefix, collection, document_id
         )
     }
     
     fn get_collection_url(&self, collection: &str) -> String {
         format!(
-            "https://firestore.googleapis.com/v1/projects/{}/databases/{}/documents/{}",
-            self.project_id, self.database_id, collection
+            "https://firestore.googleapis.com/v1/projects/{}/databases/{}/documents/{}{}",
+            self.project_id, self.database_id, self.collection_prefix, collection
         )
     }
     
     pub async fn get_auth_token(&self) -> Result<String> {
-        let token = self.auth_manager
-            .get_token(&["https://www.googleapis.com/auth/datastore"])
-            .await?;
+        let token = cached_token(&self.auth_token, || async {
+            Ok(self.auth_manager
+                .get_token(&["https://www.googleapis.com/auth/datastore"])
+                .await?)
+        }).await?;
         Ok(format!("Bearer {}", token.as_str()))
     }
     
-    fn serialize_to_firestore_document<T: Serialize>(&self, data: &T) -> Result<FirestoreDocument> {
+    fn serialize_to_firestore_document<T: Serialize>(data: &T) -> Result<FirestoreDocument> {
         let json_value = serde_json::to_value(data)?;
-        let fields = self.json_to_firestore_fields(json_value)?;
+        let fields = Self::json_to_firestore_fields(json_value)?;
         
         Ok(FirestoreDocument {
             fields,
@@ -206,12 +441,12 @@ impl FirestoreDb {
         })
     }
     
-    fn json_to_firestore_fields(&self, value: JsonValue) -> Result<HashMap<String, FirestoreValue>> {
+    fn json_to_firestore_fields(value: JsonValue) -> Result<HashMap<String, FirestoreValue>> {
         match value {
             JsonValue::Object(map) => {
                 let mut fields = HashMap::new();
                 for (key, val) in map {
-                    fields.insert(key, self.json_to_firestore_value(val)?);
+                    fields.insert(key, Self::json_to_firestore_value(val)?);
                 }
                 Ok(fields)
             }
@@ -219,7 +454,7 @@ impl FirestoreDb {
         }
     }
     
-    fn json_to_firestore_value(&self, value: JsonValue) -> Result<FirestoreValue> {
+    fn json_to_firestore_value(value: JsonValue) -> Result<FirestoreValue> {
         Ok(match value {
             JsonValue::Null => FirestoreValue::NullValue { null_value: "NULL_VALUE".to_string() },
             JsonValue::Bool(b) => FirestoreValue::BooleanValue { boolean_value: b },
@@ -243,19 +478,19 @@ impl FirestoreDb {
             },
             JsonValue::Array(arr) => {
                 let values = arr.into_iter()
-                    .map(|v| self.json_to_firestore_value(v))
+                    .map(Self::json_to_firestore_value)
                     .collect::<Result<Vec<_>>>()?;
                 FirestoreValue::ArrayValue { array_value: FirestoreArrayValue { values } }
             },
             JsonValue::Object(map) => {
-                let fields = self.json_to_firestore_fields(JsonValue::Object(map))?;
+                let fields = Self::json_to_firestore_fields(JsonValue::Object(map))?;
                 FirestoreValue::MapValue { map_value: FirestoreMapValue { fields } }
             },
         })
     }
     
-    pub fn firestore_document_to_json<T: for<'de> Deserialize<'de>>(&self, doc: FirestoreDocument) -> Result<T> {
-        let json_value = self.firestore_fields_to_json(doc.fields.clone())?;
+    pub fn firestore_document_to_json<T: for<'de> Deserialize<'de>>(doc: FirestoreDocument) -> Result<T> {
+        let json_value = Self::firestore_fields_to_json(doc.fields.clone())?;
         
         serde_json::from_value(json_value).map_err(|e| {
             error!("Failed to deserialize document. Raw fields: {:?}", doc.fields);
@@ -263,15 +498,15 @@ impl FirestoreDb {
         })
     }
     
-    fn firestore_fields_to_json(&self, fields: HashMap<String, FirestoreValue>) -> Result<JsonValue> {
+    fn firestore_fields_to_json(fields: HashMap<String, FirestoreValue>) -> Result<JsonValue> {
         let mut map = serde_json::Map::new();
         for (key, value) in fields {
-            map.insert(key, self.firestore_value_to_json(value)?);
+            map.insert(key, Self::firestore_value_to_json(value)?);
         }
         Ok(JsonValue::Object(map))
     }
     
-    fn firestore_value_to_json(&self, value: FirestoreValue) -> Result<JsonValue> {
+    fn firestore_value_to_json(value: FirestoreValue) -> Result<JsonValue> {
         Ok(match value {
             FirestoreValue::NullValue { .. } => JsonValue::Null,
             FirestoreValue::BooleanValue { boolean_value } => JsonValue::Bool(boolean_value),
@@ -286,7 +521,7 @@ impl FirestoreDb {
             FirestoreValue::TimestampValue { timestamp_value } => JsonValue::String(timestamp_value),
             FirestoreValue::ArrayValue { array_value } => {
                 let values = array_value.values.into_iter()
-                    .map(|v| self.firestore_value_to_json(v))
+                    .map(Self::firestore_value_to_json)
                     .collect::<Result<Vec<_>>>()?;
                 JsonValue::Array(values)
             },
@@ -315,7 +550,7 @@ impl FirestoreDb {
                                     // Handle nested map (possibly double-wrapped)
                                     if nested_map.fields.len() == 1 {
                                         if let Some((_, nested_val)) = nested_map.fields.iter().next() {
-                                            return self.firestore_value_to_json(nested_val.clone());
+                                            return Self::firestore_value_to_json(nested_val.clone());
                                         }
                                     }
                                 }
@@ -335,7 +570,7 @@ impl FirestoreDb {
                     return Ok(JsonValue::String("0".to_string()));
                 }
                 
-                self.firestore_fields_to_json(map_value.fields)?
+                Self::firestore_fields_to_json(map_value.fields)?
             },
             FirestoreValue::Other(val) => {
                 // Handle null values that come as {"nullValue": null}
@@ -350,6 +585,50 @@ impl FirestoreDb {
         })
     }
     
+    async fn list_all_documents(&self, collection: &str, query: &str) -> Result<Vec<FirestoreDocument>> {
+        const MAX_PAGES: u32 = 100;
+        
+        let auth_token = self.get_auth_token().await?;
+        let mut documents = Vec::new();
+        let mut page_token: Option<String> = None;
+        let mut pages = 0;
+        
+        loop {
+            let url = format!("{}?{}", self.get_collection_url(collection), query);
+            let mut request = self.client
+                .get(&url)
+                .header(AUTHORIZATION, &auth_token);
+            // Page tokens are opaque and may contain characters that need escaping
+            if let Some(token) = &page_token {
+                request = request.query(&[("pageToken", token)]);
+            }
+            
+            let response = request
+                .send()
+                .await?
+                .error_for_status()?;
+            
+            let result: ListDocumentsResponse = response.json().await?;
+            pages += 1;
+            
+            if let Some(page) = result.documents {
+                documents.extend(page);
+            }
+            
+            match result.next_page_token {
+                Some(token) if pages < MAX_PAGES => page_token = Some(token),
+                Some(_) => {
+                    warn!("Reached page limit ({}) while listing {}, results are truncated", MAX_PAGES, collection);
+                    break;
+                }
+                None => break,
+            }
+        }
+        
+        info!("Fetched {} documents from {} in {} pages", documents.len(), collection, pages);
+        Ok(documents)
+    }
+    
     pub async fn store_price_history(&self, price_data: &PriceHistory) -> Result<()> {
         let mut attempts = 0;
         
@@ -357,6 +636,10 @@ impl FirestoreDb {
             match self._store_price_history_internal(price_data).await {
                 Ok(_) => {
                     info!("Successfully stored price history: {}", price_data.id);
+                    if let Some(price_tx) = &self.price_tx {
+                        // Sending only fails when nobody is subscribed
+                        let _ = price_tx.send(price_data.clone());
+                    }
                     return Ok(());
                 }
                 Err(e) => {
@@ -370,11 +653,11 @@ impl FirestoreDb {
             }
         }
         
-        Err(anyhow::anyhow!("Failed to store price history after {} attempts", self.retry_count))
+        Err(TradingError::FirestoreError(format!("Failed to store price history after {} attempts", self.retry_count)).into())
     }
     
     async fn _store_price_history_internal(&self, price_data: &PriceHistory) -> Result<()> {
-        let document = self.serialize_to_firestore_document(price_data)?;
+        let document = Self::serialize_to_firestore_document(price_data)?;
         let url = self.get_document_url("price_history", &price_data.id);
         let auth_token = self.get_auth_token().await?;
         
@@ -410,11 +693,11 @@ impl FirestoreDb {
             }
         }
         
-        Err(anyhow::anyhow!("Failed to store trading session after {} attempts", self.retry_count))
+        Err(TradingError::FirestoreError(format!("Failed to store trading session after {} attempts", self.retry_count)).into())
     }
     
     async fn _store_trading_session_internal(&self, session: &TradingSession) -> Result<()> {
-        let document = self.serialize_to_firestore_document(session)?;
+        let document = Self::serialize_to_firestore_document(session)?;
         let url = self.get_document_url("trading_sessions", &session.id);
         let auth_token = self.get_auth_token().await?;
         
@@ -431,7 +714,7 @@ impl FirestoreDb {
     }
     
     pub async fn store_profit_tracking(&self, profit: &ProfitTracking) -> Result<()> {
-        let document = self.serialize_to_firestore_document(profit)?;
+        let document = Self::serialize_to_firestore_document(profit)?;
         let url = self.get_document_url("profit_tracking", &profit.id);
         let auth_token = self.get_auth_token().await?;
         
@@ -447,61 +730,188 @@ impl FirestoreDb {
         Ok(())
     }
     
-    pub async fn get_latest_price(&self) -> Result<Option<PriceHistory>> {
-        let url = format!("{}{}", self.get_collection_url("price_history"), "?pageSize=1&orderBy=timestamp%20desc");
+    // Newest document of the pair in the collection
+    async fn get_latest_for_pair<T: for<'de> Deserialize<'de>>(
+        &self,
+        collection: &str,
+        pair: &str,
+        pair_of: fn(&T) -> &str,
+    ) -> Result<Option<T>> {
+        let documents = self.list_all_documents(collection, "pageSize=300&orderBy=timestamp%20desc").await?;
+        
+        for doc in documents {
+            let item: T = Self::firestore_document_to_json(doc)?;
+            if pair_of(&item) == pair {
+                return Ok(Some(item));
+            }
+        }
+        
+        Ok(None)
+    }
+    
+    // Cheapest authenticated read: list at most one document name from a collection
+    pub async fn ping(&self) -> Result<()> {
+        let url = self.get_collection_url("price_history");
         let auth_token = self.get_auth_token().await?;
         
-        let response = self.client
+        self.client
             .get(&url)
             .header(AUTHORIZATION, auth_token)
+            .query(&[("pageSize", "1"), ("mask.fieldPaths", "__name__")])
             .send()
             .await?
             .error_for_status()?;
         
-        let result: ListDocumentsResponse = response.json().await?;
+        Ok(())
+    }
+    
+    // Pair names contain '/', which is not allowed in a document ID
+    fn pair_document_id(pair: &str) -> String {
+        pair.replace('/', "-")
+    }
+    
+    // Per-pair state kept in one document per pair, None if it has never been stored
+    async fn get_pair_document<T: for<'de> Deserialize<'de>>(&self, collection: &str, pair: &str) -> Result<Option<T>> {
+        let url = self.get_document_url(collection, &Self::pair_document_id(pair));
+        let auth_token = self.get_auth_token().await?;
         
-        if let Some(documents) = result.documents {
-            if let Some(doc) = documents.into_iter().next() {
-                return Ok(Some(self.firestore_document_to_json(doc)?));
-            }
+        let response = self.client
+            .get(&url)
+            .header(AUTHORIZATION, auth_token)
+            .send()
+            .await?;
+        
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
         }
         
-        Ok(None)
+        let document: FirestoreDocument = response.error_for_status()?.json().await?;
+        Ok(Some(Self::firestore_document_to_json(document)?))
     }
     
-    pub async fn get_price_history(&self, hours: u32) -> Result<Vec<PriceHistory>> {
-        let cutoff_time = Tokyo.from_utc_datetime(&chrono::Utc::now().naive_utc()).with_timezone(&FixedOffset::east_opt(9 * 3600).unwrap()) - chrono::Duration::hours(hours as i64);
-        let url = format!("{}{}", self.get_collection_url("price_history"), "?orderBy=timestamp%20desc");
+    async fn store_pair_document<T: Serialize>(&self, collection: &str, pair: &str, data: &T) -> Result<()> {
+        let document = Self::serialize_to_firestore_document(data)?;
+        let url = self.get_document_url(collection, &Self::pair_document_id(pair));
         let auth_token = self.get_auth_token().await?;
         
-        let response = self.client
-            .get(&url)
+        self.client
+            .patch(&url)
             .header(AUTHORIZATION, auth_token)
+            .header(CONTENT_TYPE, "application/json")
+            .json(&document)
             .send()
             .await?
             .error_for_status()?;
         
-        let result: ListDocumentsResponse = response.json().await?;
+        Ok(())
+    }
+    
+    pub async fn get_circuit_breaker(&self, pair: &str) -> Result<Option<CircuitBreakerState>> {
+        self.get_pair_document("circuit_breakers", pair).await
+    }
+    
+    pub async fn store_circuit_breaker(&self, circuit_breaker: &CircuitBreakerState) -> Result<()> {
+        self.store_pair_document("circuit_breakers", &circuit_breaker.pair, circuit_breaker).await
+    }
+    
+    pub async fn load_trading_state(&self, pair: &str) -> Result<Option<TradingStateSnapshot>> {
+        self.get_pair_document("trading_state", pair).await
+    }
+    
+    pub async fn save_trading_state(&self, snapshot: &TradingStateSnapshot) -> Result<()> {
+        self.store_pair_document("trading_state", &snapshot.pair, snapshot).await
+    }
+    
+    pub async fn get_trailing_stop(&self, pair: &str) -> Result<Option<TrailingStopState>> {
+        self.get_pair_document("trailing_stops", pair).await
+    }
+    
+    pub async fn store_trailing_stop(&self, trailing_stop: &TrailingStopState) -> Result<()> {
+        self.store_pair_document("trailing_stops", &trailing_stop.pair, trailing_stop).await
+    }
+    
+    pub async fn get_limit_order(&self, pair: &str) -> Result<Option<OpenLimitOrder>> {
+        let state: Option<LimitOrderState> = self.get_pair_document("limit_orders", pair).await?;
+        Ok(state.and_then(|state| state.open_order))
+    }
+    
+    pub async fn store_limit_order(&self, limit_order: &LimitOrderState) -> Result<()> {
+        self.store_pair_document("limit_orders", &limit_order.pair, limit_order).await
+    }
+    
+    // A single document rather than one per pair
+    pub async fn get_heartbeat(&self) -> Result<Option<HeartbeatState>> {
+        self.get_pair_document("heartbeats", "trigger").await
+    }
+    
+    pub async fn store_heartbeat(&self, heartbeat: &HeartbeatState) -> Result<()> {
+        self.store_pair_document("heartbeats", "trigger", heartbeat).await
+    }
+    
+    pub async fn get_latest_price(&self, pair: &str) -> Result<Option<PriceHistory>> {
+        self.get_latest_for_pair("price_history", pair, |price: &PriceHistory| &price.pair).await
+    }
+    
+    pub async fn get_price_history(&self, pair: &str, hours: u32) -> Result<Vec<PriceHistory>> {
+        let cutoff_time = now_local() - chrono::Duration::hours(hours as i64);
+        let documents = self.list_all_documents("price_history", "pageSize=300&orderBy=timestamp%20desc").await?;
         let mut prices = Vec::new();
         
-        if let Some(documents) = result.documents {
-            for doc in documents {
-                let price: PriceHistory = self.firestore_document_to_json(doc)?;
-                if price.timestamp > cutoff_time {
-                    prices.push(price);
-                }
+        for doc in documents {
+            let price: PriceHistory = Self::firestore_document_to_json(doc)?;
+            if price.pair == pair && price.timestamp > cutoff_time {
+                prices.push(price);
             }
         }
         
         Ok(prices)
     }
     
-    pub async fn get_price_trend(&self, current_price: Decimal) -> Result<PriceTrend> {
-        let now = Tokyo.from_utc_datetime(&chrono::Utc::now().naive_utc()).with_timezone(&FixedOffset::east_opt(9 * 3600).unwrap());
+    // Fill gaps between `from` and `to` with CoinGecko prices, e.g. after the scheduler was paused.
+    // Returns how many price points were written
+    pub async fn backfill_prices(
+        &self,
+        source: &CoinGeckoClient,
+        pair: &TradingPair,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        price_decimals: u32,
+    ) -> Result<usize> {
+        let hours = (Utc::now() - from).num_hours().max(0) + 1;
+        let stored: Vec<DateTime<FixedOffset>> = self.get_price_history(&pair.name, hours as u32).await?
+            .into_iter()
+            .map(|price| price.timestamp)
+            .collect();
+        
+        let points = source.get_price_range(pair, from, to).await?;
+        let fetched = points.len();
+        let missing = missing_price_points(&stored, points, chrono::Duration::minutes(BACKFILL_MIN_GAP_MINUTES));
+        info!("Backfilling {} of {} CoinGecko {} prices from {} to {}", missing.len(), fetched, pair.name, from, to);
+        
+        for (timestamp, price) in &missing {
+            let price = price.round_dp(price_decimals);
+            self.store_price_history(&PriceHistory {
+                // Derived from the timestamp so a retried backfill overwrites instead of duplicating
+                id: format!("coingecko-{}-{}", Self::pair_document_id(&pair.name), timestamp.timestamp()),
+                pair: pair.name.clone(),
+                timestamp: to_local(timestamp),
+                sol_price_usdc: price,
+                usdc_price_sol: (Decimal::ONE / price).round_dp(price_decimals),
+                data_source: "CoinGecko".to_string(),
+                trading_session_id: String::new(),
+                spread: None,
+            }).await?;
+        }
         
-        let price_1h = self.get_price_at_time(now - chrono::Duration::hours(1)).await?;
-        let price_24h = self.get_price_at_time(now - chrono::Duration::hours(24)).await?;
-        let price_7d = self.get_price_at_time(now - chrono::Duration::days(7)).await?;
+        Ok(missing.len())
+    }
+    
+    pub async fn get_price_trend(&self, pair: &str, current_price: Decimal) -> Result<PriceTrend> {
+        let now = now_local();
+        
+        let price_1h = self.get_price_at_time(pair, now - chrono::Duration::hours(1)).await?;
+        let price_24h = self.get_price_at_time(pair, now - chrono::Duration::hours(24)).await?;
+        let price_7d = self.get_price_at_time(pair, now - chrono::Duration::days(7)).await?;
         
         let trend_1h = price_1h.map(|p| {
             match current_price.cmp(&p) {
@@ -527,8 +937,8 @@ impl FirestoreDb {
             }
         });
         
-        let volatility_1h = self.calculate_volatility(1).await.ok();
-        let volatility_24h = self.calculate_volatility(24).await.ok();
+        let volatility_1h = self.calculate_volatility(pair, 1).await.ok();
+        let volatility_24h = self.calculate_volatility(pair, 24).await.ok();
         
         Ok(PriceTrend {
             timestamp: now,
@@ -543,89 +953,75 @@ impl FirestoreDb {
         })
     }
     
-    async fn get_price_at_time(&self, time: DateTime<FixedOffset>) -> Result<Option<Decimal>> {
-        let url = format!("{}{}", self.get_collection_url("price_history"), "?pageSize=300&orderBy=timestamp%20desc");
-        let auth_token = self.get_auth_token().await?;
+    async fn get_price_at_time(&self, pair: &str, time: DateTime<FixedOffset>) -> Result<Option<Decimal>> {
+        let documents = self.list_all_documents("price_history", "pageSize=300&orderBy=timestamp%20desc").await?;
         
-        let response = self.client
-            .get(&url)
-            .header(AUTHORIZATION, auth_token)
-            .send()
-            .await?
-            .error_for_status()?;
-        
-        let result: ListDocumentsResponse = response.json().await?;
-        
-        if let Some(documents) = result.documents {
-            for doc in documents {
-                let price: PriceHistory = self.firestore_document_to_json(doc)?;
-                if price.timestamp <= time {
-                    return Ok(Some(price.sol_price_usdc));
-                }
+        for doc in documents {
+            let price: PriceHistory = Self::firestore_document_to_json(doc)?;
+            if price.pair == pair && price.timestamp <= time {
+                return Ok(Some(price.sol_price_usdc));
             }
         }
         
         Ok(None)
     }
     
-    async fn calculate_volatility(&self, hours: u32) -> Result<Decimal> {
-        let prices = self.get_price_history(hours).await?;
-        
-        if prices.len() < 2 {
-            return Ok(Decimal::ZERO);
-        }
-        
-        let prices_vec: Vec<Decimal> = prices.iter().map(|p| p.sol_price_usdc).collect();
-        let mean = prices_vec.iter().sum::<Decimal>() / Decimal::from(prices_vec.len());
-        
-        let variance = prices_vec
+    async fn calculate_volatility(&self, pair: &str, hours: u32) -> Result<Decimal> {
+        Ok(self.get_volatility(pair, hours).await?.volatility)
+    }
+    
+    pub async fn get_volatility(&self, pair: &str, hours: u32) -> Result<Volatility> {
+        let prices: Vec<Decimal> = self.get_price_history(pair, hours).await?
             .iter()
-            .map(|p| (*p - mean) * (*p - mean))
-            .sum::<Decimal>() / Decimal::from(prices_vec.len());
-        
-        // Since Decimal doesn't have sqrt, we'll approximate it or return the variance
-        // For trading bot purposes, variance is sufficient for volatility measure
-        Ok(variance)
+            .map(|p| p.sol_price_usdc)
+            .collect();
+        Ok(volatility(&prices, hours))
     }
     
-    pub async fn get_trading_performance(&self, days: u32) -> Result<TradingPerformance> {
-        let cutoff_time = Tokyo.from_utc_datetime(&chrono::Utc::now().naive_utc()).with_timezone(&FixedOffset::east_opt(9 * 3600).unwrap()) - chrono::Duration::days(days as i64);
-        let url = self.get_collection_url("trading_sessions");
-        let auth_token = self.get_auth_token().await?;
-        
-        let response = self.client
-            .get(&url)
-            .header(AUTHORIZATION, auth_token)
-            .send()
-            .await?
-            .error_for_status()?;
-        
-        let result: ListDocumentsResponse = response.json().await?;
+    pub async fn get_ohlc(&self, pair: &str, hours: u32, interval_minutes: u32) -> Result<Vec<Candle>> {
+        let prices = self.get_price_history(pair, hours).await?;
+        Ok(ohlc_candles(&prices, interval_minutes))
+    }
+    
+    pub async fn get_trading_performance(&self, days: u32, currency: &str) -> Result<TradingPerformance> {
+        let cutoff_time = now_local() - chrono::Duration::days(days as i64);
+        let documents = self.list_all_documents("trading_sessions", "pageSize=300&orderBy=timestamp%20desc").await?;
         let mut total_trades = 0;
         let mut winning_trades = 0;
         let mut losing_trades = 0;
         let mut total_profit_loss = Decimal::ZERO;
         let mut total_gas_fees = Decimal::ZERO;
-        
-        if let Some(documents) = result.documents {
-            for doc in documents {
-                let session: TradingSession = self.firestore_document_to_json(doc)?;
-                if session.timestamp > cutoff_time {
-                    total_trades += 1;
-                    
-                    if let Some(profit_loss) = session.profit_loss {
-                        total_profit_loss += profit_loss;
-                        match profit_loss.cmp(&Decimal::ZERO) {
-                            std::cmp::Ordering::Greater => winning_trades += 1,
-                            std::cmp::Ordering::Less => losing_trades += 1,
-                            std::cmp::Ordering::Equal => {},
-                        }
+        // Newest first like the documents, so sell_profits is reversed before computing streaks
+        let mut profit_curve = Vec::new();
+        // Buys are scored against the previous sell price, so only sells make or break a streak
+        let mut sell_profits = Vec::new();
+        let mut holding_durations = Vec::new();
+        
+        for doc in documents {
+            let session: TradingSession = Self::firestore_document_to_json(doc)?;
+            if session.timestamp > cutoff_time {
+                total_trades += 1;
+                
+                if let Some(profit_loss) = session.profit_loss {
+                    total_profit_loss += profit_loss;
+                    profit_curve.push(profit_loss);
+                    if session.is_sell() {
+                        sell_profits.push(profit_loss);
                     }
-                    
-                    if let Some(gas_fee) = session.gas_fee {
-                        total_gas_fees += gas_fee;
+                    match profit_loss.cmp(&Decimal::ZERO) {
+                        std::cmp::Ordering::Greater => winning_trades += 1,
+                        std::cmp::Ordering::Less => losing_trades += 1,
+                        std::cmp::Ordering::Equal => {},
                     }
                 }
+                
+                if let Some(gas_fee) = session.gas_fee {
+                    total_gas_fees += gas_fee;
+                }
+                
+                if let Some(holding_duration_secs) = session.holding_duration_secs {
+                    holding_durations.push(holding_duration_secs);
+                }
             }
         }
         
@@ -635,168 +1031,334 @@ impl FirestoreDb {
             Decimal::ZERO
         };
         
+        sell_profits.reverse();
+        let streaks = indicators::streaks(&sell_profits);
+        let max_drawdown = profit_drawdown(&profit_curve);
+        
         Ok(TradingPerformance {
             total_trades,
             winning_trades,
             losing_trades,
             total_profit_loss,
+            currency: currency.to_string(),
             total_gas_fees,
             win_rate,
+            max_drawdown,
+            average_holding_secs: (!holding_durations.is_empty())
+                .then(|| holding_durations.iter().sum::<i64>() / holding_durations.len() as i64),
+            longest_winning_streak: streaks.longest_winning,
+            longest_losing_streak: streaks.longest_losing,
+            current_streak: streaks.current,
             period_days: days,
         })
     }
     
-    pub async fn get_latest_profit_tracking(&self) -> Result<Option<ProfitTracking>> {
-        let url = format!("{}{}", self.get_collection_url("profit_tracking"), "?pageSize=1&orderBy=timestamp%20desc");
-        let auth_token = self.get_auth_token().await?;
-        
-        let response = self.client
-            .get(&url)
-            .header(AUTHORIZATION, auth_token)
-            .send()
-            .await?
-            .error_for_status()?;
-        
-        let result: ListDocumentsResponse = response.json().await?;
-        
-        if let Some(documents) = result.documents {
-            if let Some(doc) = documents.into_iter().next() {
-                return Ok(Some(self.firestore_document_to_json(doc)?));
+    // Profit of the trading sessions in the last `days`, grouped by local day or week, oldest first
+    pub async fn get_profit_by_period(&self, days: u32, bucket: Bucket) -> Result<Vec<ProfitBucket>> {
+        let cutoff_time = now_local() - chrono::Duration::days(days as i64);
+        let documents = self.list_all_documents("trading_sessions", "pageSize=300&orderBy=timestamp%20desc").await?;
+        let mut sessions = Vec::new();
+        
+        for doc in documents {
+            let session: TradingSession = Self::firestore_document_to_json(doc)?;
+            if session.timestamp > cutoff_time {
+                sessions.push(session);
             }
         }
         
-        Ok(None)
+        Ok(profit_buckets(&sessions, bucket))
     }
     
-    pub async fn get_latest_trading_session(&self) -> Result<Option<TradingSession>> {
-        let url = format!("{}{}", self.get_collection_url("trading_sessions"), "?pageSize=1&orderBy=timestamp%20desc");
+    // Newest first, filtered, ordered and paged by Firestore rather than by listing the whole collection
+    pub async fn get_trading_sessions(
+        &self,
+        limit: u32,
+        offset: u32,
+        since: Option<DateTime<FixedOffset>>,
+    ) -> Result<Vec<TradingSession>> {
+        let url = format!(
+            "https://firestore.googleapis.com/v1/projects/{}/databases/{}/documents:runQuery",
+            self.project_id, self.database_id
+        );
+        let collection = format!("{}trading_sessions", self.collection_prefix);
+        let body = serde_json::json!({
+            "structuredQuery": trading_sessions_query(&collection, limit, offset, since)?,
+        });
         let auth_token = self.get_auth_token().await?;
         
-        let response = self.client
-            .get(&url)
+        let results: Vec<RunQueryResult> = self.client
+            .post(&url)
             .header(AUTHORIZATION, auth_token)
+            .header(CONTENT_TYPE, "application/json")
+            .json(&body)
             .send()
             .await?
-            .error_for_status()?;
+            .error_for_status()?
+            .json()
+            .await?;
         
-        let result: ListDocumentsResponse = response.json().await?;
+        results
+            .into_iter()
+            .filter_map(|result| result.document)
+            .map(Self::firestore_document_to_json)
+            .collect()
+    }
+    
+    pub async fn get_latest_profit_tracking(&self, pair: &str) -> Result<Option<ProfitTracking>> {
+        self.get_latest_for_pair("profit_tracking", pair, |profit: &ProfitTracking| &profit.pair).await
+    }
+    
+    pub async fn get_latest_trading_session(&self, pair: &str) -> Result<Option<TradingSession>> {
+        self.get_latest_for_pair("trading_sessions", pair, |session: &TradingSession| &session.pair).await
+    }
+    
+    // Delete documents via :batchWrite, at most 500 writes per request
+    async fn batch_delete_documents(&self, names: &[String], auth_token: &str) -> usize {
+        const MAX_WRITES_PER_BATCH: usize = 500;
+        
+        let url = format!(
+            "https://firestore.googleapis.com/v1/projects/{}/databases/{}/documents:batchWrite",
+            self.project_id, self.database_id
+        );
+        let mut deleted_count = 0;
         
-        if let Some(documents) = result.documents {
-            if let Some(doc) = documents.into_iter().next() {
-                return Ok(Some(self.firestore_document_to_json(doc)?));
+        for chunk in names.chunks(MAX_WRITES_PER_BATCH) {
+            let body = serde_json::json!({
+                "writes": chunk.iter().map(|name| serde_json::json!({ "delete": name })).collect::<Vec<_>>(),
+            });
+            
+            // batchWrite is not atomic, so retry the whole chunk if any write failed (deletes are idempotent)
+            let result = retry_as_exponential_back_off(
+                || async {
+                    let response: BatchWriteResponse = self.client
+                        .post(&url)
+                        .header(AUTHORIZATION, auth_token)
+                        .header(CONTENT_TYPE, "application/json")
+                        .json(&body)
+                        .send()
+                        .await?
+                        .error_for_status()?
+                        .json()
+                        .await?;
+                    
+                    let failed = response.status.iter().filter(|status| status.code != 0).count();
+                    if failed > 0 {
+                        anyhow::bail!("{} of {} deletes failed", failed, chunk.len());
+                    }
+                    Ok::<(), anyhow::Error>(())
+                },
+                "Batch delete",
+                3,
+                500,
+                Some(std::time::Duration::from_secs(30)),
+                None,
+            )
+            .await;
+            
+            match result {
+                Ok(_) => deleted_count += chunk.len(),
+                Err(e) => error!("Failed to delete batch of {} documents: {}", chunk.len(), e),
             }
         }
         
-        Ok(None)
+        deleted_count
     }
     
     pub async fn cleanup_old_data(&self, retention_days: u32) -> Result<()> {
-        let cutoff_time = Tokyo.from_utc_datetime(&chrono::Utc::now().naive_utc()).with_timezone(&FixedOffset::east_opt(9 * 3600).unwrap()) - chrono::Duration::days(retention_days as i64);
+        let cutoff_time = now_local() - chrono::Duration::days(retention_days as i64);
         
         info!("Cleaning up data older than {} days", retention_days);
         
-        // Clean up price history
-        let url = self.get_collection_url("price_history");
         let auth_token = self.get_auth_token().await?;
         
-        let response = self.client
-            .get(&url)
-            .header(AUTHORIZATION, &auth_token)
-            .send()
-            .await?
-            .error_for_status()?;
-        
-        let result: ListDocumentsResponse = response.json().await?;
-        let mut deleted_count = 0;
-        
-        if let Some(documents) = result.documents {
-            for doc in documents {
-                let price: PriceHistory = self.firestore_document_to_json(doc.clone())?;
-                if price.timestamp < cutoff_time {
-                    if let Some(name) = doc.name {
-                        let delete_url = format!("https://firestore.googleapis.com/v1/{}", name);
-                        self.client
-                            .delete(&delete_url)
-                            .header(AUTHORIZATION, &auth_token)
-                            .send()
-                            .await?
-                            .error_for_status()?;
-                        deleted_count += 1;
-                    }
+        // Clean up price history
+        let documents = self.list_all_documents("price_history", "pageSize=300").await?;
+        let mut expired_names = Vec::new();
+        
+        for doc in documents {
+            let price: PriceHistory = Self::firestore_document_to_json(doc.clone())?;
+            if price.timestamp < cutoff_time {
+                if let Some(name) = doc.name {
+                    expired_names.push(name);
                 }
             }
         }
         
+        let deleted_count = self.batch_delete_documents(&expired_names, &auth_token).await;
+        
         info!("Deleted {} old documents from price_history", deleted_count);
         
         // Clean up trading sessions
-        let url = self.get_collection_url("trading_sessions");
-        let response = self.client
-            .get(&url)
-            .header(AUTHORIZATION, &auth_token)
-            .send()
-            .await?
-            .error_for_status()?;
-        
-        let result: ListDocumentsResponse = response.json().await?;
-        let mut deleted_count = 0;
-        
-        if let Some(documents) = result.documents {
-            for doc in documents {
-                let session: TradingSession = self.firestore_document_to_json(doc.clone())?;
-                if session.timestamp < cutoff_time {
-                    if let Some(name) = doc.name {
-                        let delete_url = format!("https://firestore.googleapis.com/v1/{}", name);
-                        self.client
-                            .delete(&delete_url)
-                            .header(AUTHORIZATION, &auth_token)
-                            .send()
-                            .await?
-                            .error_for_status()?;
-                        deleted_count += 1;
-                    }
+        let documents = self.list_all_documents("trading_sessions", "pageSize=300").await?;
+        let mut expired_names = Vec::new();
+        
+        for doc in documents {
+            let session: TradingSession = Self::firestore_document_to_json(doc.clone())?;
+            if session.timestamp < cutoff_time {
+                if let Some(name) = doc.name {
+                    expired_names.push(name);
                 }
             }
         }
         
+        let deleted_count = self.batch_delete_documents(&expired_names, &auth_token).await;
+        
         info!("Deleted {} old documents from trading_sessions", deleted_count);
         
         // Clean up profit tracking
-        let url = self.get_collection_url("profit_tracking");
-        let response = self.client
-            .get(&url)
-            .header(AUTHORIZATION, &auth_token)
-            .send()
-            .await?
-            .error_for_status()?;
-        
-        let result: ListDocumentsResponse = response.json().await?;
-        let mut deleted_count = 0;
-        
-        if let Some(documents) = result.documents {
-            for doc in documents {
-                let profit: ProfitTracking = self.firestore_document_to_json(doc.clone())?;
-                if profit.timestamp < cutoff_time {
-                    if let Some(name) = doc.name {
-                        let delete_url = format!("https://firestore.googleapis.com/v1/{}", name);
-                        self.client
-                            .delete(&delete_url)
-                            .header(AUTHORIZATION, &auth_token)
-                            .send()
-                            .await?
-                            .error_for_status()?;
-                        deleted_count += 1;
-                    }
+        let documents = self.list_all_documents("profit_tracking", "pageSize=300").await?;
+        let mut expired_names = Vec::new();
+        
+        for doc in documents {
+            let profit: ProfitTracking = Self::firestore_document_to_json(doc.clone())?;
+            if profit.timestamp < cutoff_time {
+                if let Some(name) = doc.name {
+                    expired_names.push(name);
                 }
             }
         }
         
+        let deleted_count = self.batch_delete_documents(&expired_names, &auth_token).await;
+        
         info!("Deleted {} old documents from profit_tracking", deleted_count);
         
         Ok(())
     }
 }
 
+// The cached token, or a new one from `refresh` once it is about to expire.
+// Holding the lock while refreshing keeps concurrent requests from all fetching a new token
+async fn cached_token<F, Fut>(cache: &Mutex<Option<Token>>, refresh: F) -> Result<Token>
+where
+    F: FnOnce() -> Fut,
+    Fut: std::future::Future<Output = Result<Token>>,
+{
+    let mut cached = cache.lock().await;
+    if let Some(token) = cached.as_ref().filter(|token| !expires_soon(token, Utc::now())) {
+        return Ok(token.clone());
+    }
+    
+    let token = refresh().await?;
+    *cached = Some(token.clone());
+    Ok(token)
+}
+
+fn expires_soon(token: &Token, now: DateTime<Utc>) -> bool {
+    token.expires_at() - chrono::Duration::seconds(TOKEN_REFRESH_MARGIN_SECS) <= now
+}
+
+// Population standard deviation of `prices`, 0 with fewer than two prices
+pub fn volatility(prices: &[Decimal], hours: u32) -> Volatility {
+    let insufficient_data = prices.len() < 2;
+    let volatility = if insufficient_data {
+        Decimal::ZERO
+    } else {
+        let mean = prices.iter().sum::<Decimal>() / Decimal::from(prices.len());
+        let variance = prices
+            .iter()
+            .map(|p| (*p - mean) * (*p - mean))
+            .sum::<Decimal>() / Decimal::from(prices.len());
+        
+        // Decimal has no sqrt, so take the square root in f64 to get the standard deviation in price units
+        variance.to_f64().map(f64::sqrt).and_then(Decimal::from_f64).unwrap_or(Decimal::ZERO)
+    };
+    
+    Volatility {
+        volatility,
+        sample_count: prices.len(),
+        hours,
+        insufficient_data,
+    }
+}
+
+// Largest fall in cumulative profit over trade profits given newest first, as sessions are listed.
+// The curve starts from zero so an initial loss counts.
+fn profit_drawdown(profits: &[Decimal]) -> Decimal {
+    let equity_curve: Vec<Decimal> = std::iter::once(Decimal::ZERO)
+        .chain(profits.iter().rev().scan(Decimal::ZERO, |cumulative_profit, profit_loss| {
+            *cumulative_profit += *profit_loss;
+            Some(*cumulative_profit)
+        }))
+        .collect();
+    indicators::max_drawdown(&equity_curve)
+}
+
+// Structured query for sessions no older than `since`, newest first. Timestamps are stored as
+// local RFC 3339 strings, so `since` is compared in the same form.
+fn trading_sessions_query(
+    collection: &str,
+    limit: u32,
+    offset: u32,
+    since: Option<DateTime<FixedOffset>>,
+) -> Result<JsonValue> {
+    // Firestore takes 32-bit limits and offsets
+    let mut query = serde_json::json!({
+        "from": [{ "collectionId": collection }],
+        "orderBy": [{ "field": { "fieldPath": "timestamp" }, "direction": "DESCENDING" }],
+        "offset": offset.min(i32::MAX as u32),
+        "limit": limit.min(i32::MAX as u32),
+    });
+    if let Some(since) = since {
+        let value = FirestoreDb::json_to_firestore_value(serde_json::to_value(to_local(&since))?)?;
+        query["where"] = serde_json::json!({
+            "fieldFilter": {
+                "field": { "fieldPath": "timestamp" },
+                "op": "GREATER_THAN_OR_EQUAL",
+                "value": value,
+            },
+        });
+    }
+    Ok(query)
+}
+
+// Profit and trade count per local day or week, oldest first
+fn profit_buckets(sessions: &[TradingSession], bucket: Bucket) -> Vec<ProfitBucket> {
+    let mut buckets: BTreeMap<NaiveDate, (Decimal, i64)> = BTreeMap::new();
+    
+    for session in sessions {
+        // Bucket on the local calendar date regardless of the offset the timestamp was stored with
+        let date = session.timestamp.with_timezone(&clock::timezone()).date_naive();
+        let period_start = match bucket {
+            Bucket::Day => date,
+            Bucket::Week => date - chrono::Duration::days(date.weekday().num_days_from_monday() as i64),
+        };
+        
+        let entry = buckets.entry(period_start).or_insert((Decimal::ZERO, 0));
+        entry.0 += session.profit_loss.unwrap_or(Decimal::ZERO);
+        entry.1 += 1;
+    }
+    
+    buckets
+        .into_iter()
+        .map(|(date, (profit_loss, trade_count))| ProfitBucket {
+            period_start: clock::start_of_day(date),
+            profit_loss,
+            trade_count,
+        })
+        .collect()
+}
+
+// Points with no stored price within `min_gap`, so overlapping or repeated backfills don't write duplicates
+pub fn missing_price_points(
+    stored: &[DateTime<FixedOffset>],
+    points: Vec<(DateTime<Utc>, Decimal)>,
+    min_gap: chrono::Duration,
+) -> Vec<(DateTime<Utc>, Decimal)> {
+    let mut stored: Vec<i64> = stored.iter().map(|timestamp| timestamp.timestamp()).collect();
+    stored.sort_unstable();
+    let min_gap = min_gap.num_seconds();
+    
+    points.into_iter()
+        .filter(|(timestamp, _)| {
+            let timestamp = timestamp.timestamp();
+            // Only the stored prices either side of the point can be the closest
+            let next = stored.partition_point(|stored| *stored < timestamp);
+            let near = |index: usize| stored.get(index).is_some_and(|stored| (stored - timestamp).abs() < min_gap);
+            !(near(next) || next.checked_sub(1).is_some_and(near))
+        })
+        .collect()
+}
+
 pub fn generate_session_id() -> String {
     Uuid::new_v4().to_string()
 }
@@ -812,3 +1374,326 @@ pub fn validate_price_data(price: Decimal) -> Result<()> {
     
     Ok(())
 }
+
+// Buckets prices into candles of `interval_minutes`, counted from local midnight so boundaries don't shift
+// with the offset a price was stored with. Intervals that don't divide a day end early at midnight.
+// Oldest first, and intervals without prices are left out
+pub fn ohlc_candles(prices: &[PriceHistory], interval_minutes: u32) -> Vec<Candle> {
+    let interval_minutes = interval_minutes.max(1);
+    let mut sorted: Vec<&PriceHistory> = prices.iter().collect();
+    sorted.sort_by_key(|price| price.timestamp);
+    
+    let mut candles: BTreeMap<(NaiveDate, u32), Candle> = BTreeMap::new();
+    for price in sorted {
+        let local = price.timestamp.with_timezone(&clock::timezone());
+        let minute_of_day = local.hour() * 60 + local.minute();
+        let key = (local.date_naive(), minute_of_day - minute_of_day % interval_minutes);
+        let value = price.sol_price_usdc;
+        
+        candles
+            .entry(key)
+            .and_modify(|candle| {
+                candle.high = candle.high.max(value);
+                candle.low = candle.low.min(value);
+                candle.close = value;
+                candle.point_count += 1;
+            })
+            .or_insert_with(|| Candle {
+                period_start: clock::start_of_day(key.0) + chrono::Duration::minutes(key.1 as i64),
+                open: value,
+                high: value,
+                low: value,
+                close: value,
+                point_count: 1,
+            });
+    }
+    
+    candles.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    
+    fn token(expires_in_secs: i64) -> Token {
+        serde_json::from_value(serde_json::json!({
+            "access_token": "test-token",
+            "expires_in": expires_in_secs,
+        }))
+        .unwrap()
+    }
+    
+    fn session(position_after: &str, profit_loss: Decimal) -> TradingSession {
+        TradingSession {
+            id: String::new(),
+            pair: "SOL/USDC".to_string(),
+            timestamp: now_local(),
+            position_before: if position_after == "SOL" { "USDC" } else { "SOL" }.to_string(),
+            position_after: position_after.to_string(),
+            action: String::new(),
+            sol_balance_before: Decimal::ZERO,
+            usdc_balance_before: Decimal::ZERO,
+            sol_balance_after: Decimal::ZERO,
+            usdc_balance_after: Decimal::ZERO,
+            price_at_trade: Decimal::ONE,
+            slippage: None,
+            gas_fee: None,
+            signature: None,
+            profit_loss: Some(profit_loss),
+            cumulative_profit: None,
+            suspicious_fill: false,
+            holding_duration_secs: None,
+        }
+    }
+    
+    #[test]
+    fn only_sells_leave_the_quote_token_held() {
+        assert!(session("USDC", Decimal::ONE).is_sell());
+        assert!(!session("SOL", -Decimal::ONE).is_sell());
+    }
+    
+    #[tokio::test]
+    async fn cached_token_is_reused_until_it_expires() {
+        let cache = Mutex::new(None);
+        let refreshes = AtomicUsize::new(0);
+        let refresh = || async {
+            refreshes.fetch_add(1, Ordering::SeqCst);
+            Ok(token(3600))
+        };
+        
+        let first = cached_token(&cache, refresh).await.unwrap();
+        let second = cached_token(&cache, refresh).await.unwrap();
+        
+        assert_eq!(refreshes.load(Ordering::SeqCst), 1);
+        assert_eq!(first.as_str(), second.as_str());
+    }
+    
+    #[tokio::test]
+    async fn cached_token_is_refreshed_within_the_margin() {
+        let cache = Mutex::new(Some(token(TOKEN_REFRESH_MARGIN_SECS - 1)));
+        let refreshes = AtomicUsize::new(0);
+        
+        cached_token(&cache, || async {
+            refreshes.fetch_add(1, Ordering::SeqCst);
+            Ok(token(3600))
+        }).await.unwrap();
+        
+        assert_eq!(refreshes.load(Ordering::SeqCst), 1);
+    }
+    
+    #[test]
+    fn volatility_is_the_standard_deviation_in_price_units() {
+        let prices = [2, 4, 4, 4, 5, 5, 7, 9].map(Decimal::from);
+        let result = volatility(&prices, 1);
+        assert!((result.volatility - dec!(2)).abs() < dec!(0.000000001), "got {}", result.volatility);
+        assert_eq!(result.sample_count, 8);
+        assert!(!result.insufficient_data);
+        
+        // Variance 2.1875, so the standard deviation is its square root rather than the variance itself
+        let result = volatility(&[dec!(100), dec!(102), dec!(98), dec!(101)], 24);
+        assert!((result.volatility - dec!(1.479019945774904)).abs() < dec!(0.000000001), "got {}", result.volatility);
+    }
+    
+    #[test]
+    fn volatility_needs_two_prices() {
+        let result = volatility(&[dec!(100)], 1);
+        assert_eq!(result.volatility, Decimal::ZERO);
+        assert!(result.insufficient_data);
+    }
+    
+    #[test]
+    fn backfill_skips_points_near_stored_prices() {
+        let at = |time: &str| DateTime::parse_from_rfc3339(time).unwrap();
+        let stored = [at("2026-01-01T10:00:00+09:00"), at("2026-01-01T12:10:00+09:00")];
+        let points: Vec<(DateTime<Utc>, Decimal)> = [
+            "2026-01-01T09:00:00+09:00",
+            "2026-01-01T10:20:00+09:00",
+            "2026-01-01T11:00:00+09:00",
+            "2026-01-01T12:00:00+09:00",
+            "2026-01-01T13:00:00+09:00",
+        ]
+        .into_iter()
+        .map(|time| (at(time).with_timezone(&Utc), dec!(100)))
+        .collect();
+        
+        // 10:20 and 12:00 are within 30 minutes of a stored price
+        let missing = missing_price_points(&stored, points.clone(), chrono::Duration::minutes(30));
+        let missing_times: Vec<DateTime<FixedOffset>> = missing.iter().map(|(timestamp, _)| to_local(timestamp)).collect();
+        assert_eq!(missing_times, [
+            at("2026-01-01T09:00:00+09:00"),
+            at("2026-01-01T11:00:00+09:00"),
+            at("2026-01-01T13:00:00+09:00"),
+        ]);
+        
+        // Running the same backfill again once those are written adds nothing
+        let stored: Vec<DateTime<FixedOffset>> = stored.into_iter().chain(missing_times).collect();
+        assert!(missing_price_points(&stored, points, chrono::Duration::minutes(30)).is_empty());
+    }
+    
+    fn price_at(time: &str, price: Decimal) -> PriceHistory {
+        PriceHistory {
+            id: String::new(),
+            pair: "SOL/USDC".to_string(),
+            timestamp: DateTime::parse_from_rfc3339(time).unwrap(),
+            sol_price_usdc: price,
+            usdc_price_sol: Decimal::ONE / price,
+            data_source: "Jupiter".to_string(),
+            trading_session_id: String::new(),
+            spread: None,
+        }
+    }
+    
+    #[test]
+    fn candles_take_open_high_low_close_per_interval() {
+        // Out of order, and one stored in UTC, which is 09:30 JST
+        let prices = [
+            price_at("2026-01-01T09:55:00+09:00", dec!(101)),
+            price_at("2026-01-01T09:05:00+09:00", dec!(100)),
+            price_at("2026-01-01T10:10:00+09:00", dec!(102)),
+            price_at("2026-01-01T00:30:00+00:00", dec!(104)),
+            price_at("2026-01-01T09:40:00+09:00", dec!(98)),
+        ];
+        let candles = ohlc_candles(&prices, 60);
+        
+        assert_eq!(candles.len(), 2);
+        let candle = &candles[0];
+        assert_eq!(candle.period_start, DateTime::parse_from_rfc3339("2026-01-01T09:00:00+09:00").unwrap());
+        assert_eq!(
+            (candle.open, candle.high, candle.low, candle.close, candle.point_count),
+            (dec!(100), dec!(104), dec!(98), dec!(101), 4)
+        );
+        let candle = &candles[1];
+        assert_eq!(candle.period_start, DateTime::parse_from_rfc3339("2026-01-01T10:00:00+09:00").unwrap());
+        assert_eq!((candle.open, candle.high, candle.low, candle.close, candle.point_count), (dec!(102), dec!(102), dec!(102), dec!(102), 1));
+    }
+    
+    #[test]
+    fn drawdown_follows_the_cumulative_profit() {
+        // Oldest first the curve is 0, 10, 15, 7, 3, 9, 12: down 12 from the peak of 15
+        let mut profits = vec![dec!(10), dec!(5), dec!(-8), dec!(-4), dec!(6), dec!(3)];
+        profits.reverse();
+        assert_eq!(profit_drawdown(&profits), dec!(12));
+        
+        // A loss on the first trade is a drawdown from zero
+        assert_eq!(profit_drawdown(&[dec!(2), dec!(-5)]), dec!(5));
+        assert_eq!(profit_drawdown(&[dec!(3), dec!(2), dec!(1)]), dec!(0));
+        assert_eq!(profit_drawdown(&[]), dec!(0));
+    }
+    
+    #[test]
+    fn profit_is_bucketed_on_jst_days() {
+        let at = |time: &str, profit_loss: Decimal| TradingSession {
+            timestamp: DateTime::parse_from_rfc3339(time).unwrap(),
+            ..session("USDC", profit_loss)
+        };
+        // The first is 01:30 on January 1st in JST, though still December 31st in UTC
+        let sessions = [
+            at("2025-12-31T16:30:00+00:00", dec!(1.5)),
+            at("2026-01-01T23:59:59+09:00", dec!(-0.5)),
+            at("2026-01-02T00:00:01+09:00", dec!(2)),
+        ];
+        
+        let buckets = profit_buckets(&sessions, Bucket::Day);
+        let summary: Vec<_> = buckets.iter().map(|bucket| (bucket.period_start, bucket.profit_loss, bucket.trade_count)).collect();
+        assert_eq!(summary, [
+            (DateTime::parse_from_rfc3339("2026-01-01T00:00:00+09:00").unwrap(), dec!(1), 2),
+            (DateTime::parse_from_rfc3339("2026-01-02T00:00:00+09:00").unwrap(), dec!(2), 1),
+        ]);
+        
+        // Thursday and Friday of the same week
+        let buckets = profit_buckets(&sessions, Bucket::Week);
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].period_start, DateTime::parse_from_rfc3339("2025-12-29T00:00:00+09:00").unwrap());
+        assert_eq!(buckets[0].trade_count, 3);
+    }
+    
+    #[test]
+    fn sessions_stored_before_signatures_still_load() {
+        // A document from before pair, signature and the later fields were stored
+        let document: FirestoreDocument = serde_json::from_value(serde_json::json!({
+            "name": "projects/test-project/databases/(default)/documents/trading_sessions/abc",
+            "fields": {
+                "id": { "stringValue": "abc" },
+                "timestamp": { "stringValue": "2024-05-01T09:30:00+09:00" },
+                "position_before": { "stringValue": "USDC" },
+                "position_after": { "stringValue": "SOL" },
+                "action": { "stringValue": "BUY_SOL" },
+                "sol_balance_before": { "stringValue": "0" },
+                "usdc_balance_before": { "stringValue": "150" },
+                "sol_balance_after": { "stringValue": "1" },
+                "usdc_balance_after": { "stringValue": "0" },
+                "price_at_trade": { "stringValue": "150" },
+                "gas_fee": { "stringValue": "0.000005" },
+                "profit_loss": { "nullValue": null }
+            }
+        }))
+        .unwrap();
+        
+        let session: TradingSession = FirestoreDb::firestore_document_to_json(document).unwrap();
+        assert_eq!(session.id, "abc");
+        assert_eq!(session.pair, "SOL/USDC");
+        assert_eq!(session.signature, None);
+        assert_eq!(session.gas_fee, Some(dec!(0.000005)));
+        assert_eq!(session.profit_loss, None);
+        assert!(!session.suspicious_fill);
+        
+        // New documents keep the signature through a round trip
+        let stored = TradingSession { signature: Some("5sig".to_string()), ..session };
+        let document = FirestoreDb::serialize_to_firestore_document(&stored).unwrap();
+        let loaded: TradingSession = FirestoreDb::firestore_document_to_json(document).unwrap();
+        assert_eq!(loaded.signature.as_deref(), Some("5sig"));
+    }
+    
+    #[test]
+    fn trading_sessions_are_queried_newest_first_since_a_time() {
+        let since = DateTime::parse_from_rfc3339("2026-01-01T00:00:00+00:00").unwrap();
+        let query = trading_sessions_query("bot1_trading_sessions", 50, 100, Some(since)).unwrap();
+        
+        assert_eq!(query["from"], serde_json::json!([{ "collectionId": "bot1_trading_sessions" }]));
+        assert_eq!(query["orderBy"], serde_json::json!([{ "field": { "fieldPath": "timestamp" }, "direction": "DESCENDING" }]));
+        assert_eq!(query["limit"], 50);
+        assert_eq!(query["offset"], 100);
+        // Compared as stored, in local time
+        assert_eq!(query["where"]["fieldFilter"]["op"], "GREATER_THAN_OR_EQUAL");
+        assert_eq!(query["where"]["fieldFilter"]["value"], serde_json::json!({ "stringValue": "2026-01-01T09:00:00+09:00" }));
+        
+        let query = trading_sessions_query("trading_sessions", u32::MAX, 0, None).unwrap();
+        assert!(query.get("where").is_none());
+        assert_eq!(query["limit"], i32::MAX);
+    }
+    
+    #[test]
+    fn trading_state_snapshot_round_trips_through_a_document() {
+        let entered_at = DateTime::parse_from_rfc3339("2024-05-01T09:00:00+09:00").unwrap();
+        let snapshot = TradingStateSnapshot {
+            pair: "SOL/USDC".to_string(),
+            position: Position::Base,
+            last_base_price: Some(dec!(150.25)),
+            last_observed_price: Some(dec!(151.5)),
+            last_quote_price: Some(dec!(0.0066)),
+            last_trade_price: Some(dec!(150.25)),
+            last_trade_timestamp: Some(entered_at),
+            peak_price: Some(dec!(152)),
+            ladder_rungs_fired: 1,
+            entered_at: Some(entered_at),
+            average_entry_price: Some(dec!(149.8)),
+            position_size: dec!(2.5),
+            entry_fees: dec!(0.01),
+            total_profit: dec!(-3.75),
+            total_trades: 12,
+            winning_trades: 7,
+            losing_trades: 5,
+            consecutive_losses: 2,
+            circuit_broken: false,
+            gas_fee: None,
+            updated_at: entered_at,
+        };
+        
+        let document = FirestoreDb::serialize_to_firestore_document(&snapshot).unwrap();
+        let loaded: TradingStateSnapshot = FirestoreDb::firestore_document_to_json(document).unwrap();
+        
+        assert_eq!(serde_json::to_value(&loaded).unwrap(), serde_json::to_value(&snapshot).unwrap());
+    }
+}
@@ -0,0 +1,139 @@
+use chrono::{DateTime, FixedOffset};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::clock::{format_local, now_local};
+use crate::config::Config;
+use crate::firestore::{FirestoreDb, HeartbeatState};
+use crate::notifier;
+
+// How often the watchdog compares the last trigger time against the timeout
+const HEARTBEAT_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+// Time of the last /trigger, shared between the handler and the watchdog task
+#[derive(Clone)]
+pub struct Heartbeat {
+    last_trigger: Arc<Mutex<DateTime<FixedOffset>>>,
+    firestore: Option<Arc<FirestoreDb>>,
+}
+
+impl Heartbeat {
+    // Starts from the stored trigger time, or from now if none was stored, so a restart doesn't alert straight away
+    pub async fn load(config: &Config) -> Self {
+        let firestore = match FirestoreDb::from_config(config).await {
+            Ok(db) => Some(Arc::new(db)),
+            Err(e) => {
+                warn!("Failed to connect to Firestore, the last trigger time won't survive restarts: {}", e);
+                None
+            }
+        };
+
+        let stored = match &firestore {
+            Some(db) => db.get_heartbeat().await.unwrap_or_else(|e| {
+                error!("Failed to load heartbeat state: {}", e);
+                None
+            }),
+            None => None,
+        };
+        let last_trigger = stored.map_or_else(now_local, |heartbeat| heartbeat.last_trigger_at);
+
+        Self {
+            last_trigger: Arc::new(Mutex::new(last_trigger)),
+            firestore,
+        }
+    }
+
+    // Without Firestore, for handler tests
+    #[cfg(test)]
+    pub fn in_memory() -> Self {
+        Self {
+            last_trigger: Arc::new(Mutex::new(now_local())),
+            firestore: None,
+        }
+    }
+
+    pub async fn record_trigger(&self) {
+        let now = now_local();
+        *self.last_trigger.lock().await = now;
+
+        // Stored in the background so the trigger response isn't held up by Firestore
+        if let Some(db) = self.firestore.clone() {
+            tokio::spawn(async move {
+                if let Err(e) = db.store_heartbeat(&HeartbeatState { last_trigger_at: now }).await {
+                    error!("Failed to store heartbeat state: {}", e);
+                }
+            });
+        }
+    }
+
+    // Sends one alert per stretch without triggers, and logs once they resume
+    pub async fn watch(self, config: Config, timeout: Duration) {
+        let notifiers = notifier::build_notifiers(&config);
+        let mut alerted = false;
+        let mut interval = tokio::time::interval(HEARTBEAT_CHECK_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            let last_trigger = *self.last_trigger.lock().await;
+
+            if !is_heartbeat_missing(last_trigger, now_local(), timeout) {
+                if alerted {
+                    info!("Triggers resumed, last received at {}", last_trigger);
+                }
+                alerted = false;
+                continue;
+            }
+            if alerted {
+                continue;
+            }
+
+            warn!("No trigger received since {}", last_trigger);
+            let message = format!(
+                "💔 Heartbeat missing\n\
+                No /trigger received for over {}s\n\
+                Last trigger: {}",
+                timeout.as_secs(),
+                format_local(&last_trigger),
+            );
+            for notifier in &notifiers {
+                if let Err(e) = notifier.send_message(&message).await {
+                    error!("Failed to send heartbeat alert: {}", e);
+                }
+            }
+            alerted = true;
+        }
+    }
+}
+
+// Whether more than `timeout` has passed since the last trigger
+pub fn is_heartbeat_missing(last_trigger: DateTime<FixedOffset>, now: DateTime<FixedOffset>, timeout: Duration) -> bool {
+    match (now - last_trigger).to_std() {
+        Ok(elapsed) => elapsed > timeout,
+        // A trigger time in the future (clock skew) isn't stale
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heartbeat_is_missing_only_past_the_timeout() {
+        let now = DateTime::parse_from_rfc3339("2024-05-01T09:00:00+09:00").unwrap();
+        let timeout = Duration::from_secs(30 * 60);
+        let cases = [
+            (chrono::Duration::minutes(5), false),
+            (chrono::Duration::minutes(30), false),
+            (chrono::Duration::minutes(31), true),
+            (chrono::Duration::days(2), true),
+            // Clock skew put the trigger in the future
+            (chrono::Duration::minutes(-5), false),
+        ];
+        for (ago, missing) in cases {
+            assert_eq!(is_heartbeat_missing(now - ago, now, timeout), missing, "last trigger {} ago", ago);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+// Wilder's relative strength index over prices ordered oldest first.
+// Returns None if there are not more than `period` prices.
+pub fn rsi(prices: &[Decimal], period: usize) -> Option<Decimal> {
+    if period == 0 || prices.len() <= period {
+        return None;
+    }
+    
+    let changes: Vec<Decimal> = prices.windows(2).map(|w| w[1] - w[0]).collect();
+    let period_dec = Decimal::from(period);
+    
+    // Seed the averages with a simple mean over the first period
+    let mut avg_gain = changes[..period].iter().map(|c| (*c).max(dec!(0))).sum::<Decimal>() / period_dec;
+    let mut avg_loss = changes[..period].iter().map(|c| (-*c).max(dec!(0))).sum::<Decimal>() / period_dec;
+    
+    // Then apply Wilder's smoothing to the remaining changes
+    for change in &changes[period..] {
+        avg_gain = (avg_gain * (period_dec - dec!(1)) + (*change).max(dec!(0))) / period_dec;
+        avg_loss = (avg_loss * (period_dec - dec!(1)) + (-*change).max(dec!(0))) / period_dec;
+    }
+    
+    if avg_loss == dec!(0) {
+        return Some(dec!(100));
+    }
+    
+    let rs = avg_gain / avg_loss;
+    Some(dec!(100) - dec!(100) / (dec!(1) + rs))
+}
+
+// Largest peak-to-trough decline over a series of equity or cumulative profit values, oldest first.
+// Returns zero for an empty or never-declining series.
+pub fn max_drawdown(values: &[Decimal]) -> Decimal {
+    let mut peak: Option<Decimal> = None;
+    let mut max_drawdown = dec!(0);
+    
+    for value in values {
+        let current_peak = peak.map_or(*value, |p| p.max(*value));
+        peak = Some(current_peak);
+        max_drawdown = max_drawdown.max(current_peak - *value);
+    }
+    
+    max_drawdown
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Streaks {
+    pub longest_winning: u32,
+    pub longest_losing: u32,
+    // Positive while on a winning streak, negative while on a losing one
+    pub current: i64,
+}
+
+// Winning and losing streaks over trade profits, oldest first. Break-even trades are neutral and
+// neither extend nor break a streak, the same as for the circuit breaker's loss count.
+pub fn streaks(profits: &[Decimal]) -> Streaks {
+    let mut streaks = Streaks::default();
+    
+    for profit in profits {
+        match profit.cmp(&dec!(0)) {
+            std::cmp::Ordering::Greater => streaks.current = streaks.current.max(0) + 1,
+            std::cmp::Ordering::Less => streaks.current = streaks.current.min(0) - 1,
+            std::cmp::Ordering::Equal => continue,
+        }
+        if streaks.current > 0 {
+            streaks.longest_winning = streaks.longest_winning.max(streaks.current as u32);
+        } else {
+            streaks.longest_losing = streaks.longest_losing.max(streaks.current.unsigned_abs() as u32);
+        }
+    }
+    
+    streaks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    // Wilder's 14-period example series from StockCharts. Their table rounds the averages at each
+    // step, so it shows values a few hundredths higher (70.53, 66.32, ...) than the unrounded ones here
+    fn example_closes() -> Vec<Decimal> {
+        [
+            dec!(44.34), dec!(44.09), dec!(44.15), dec!(43.61), dec!(44.33), dec!(44.83), dec!(45.10), dec!(45.42),
+            dec!(45.84), dec!(46.08), dec!(45.89), dec!(46.03), dec!(45.61), dec!(46.28), dec!(46.28), dec!(46.00),
+            dec!(46.03), dec!(46.41), dec!(46.22), dec!(45.64),
+        ]
+        .to_vec()
+    }
+    
+    #[test]
+    fn rsi_matches_the_canonical_example() {
+        let closes = example_closes();
+        let expected = [dec!(70.46), dec!(66.25), dec!(66.48), dec!(69.35), dec!(66.29), dec!(57.92)];
+        
+        for (i, expected) in expected.iter().enumerate() {
+            let rsi = rsi(&closes[..15 + i], 14).unwrap();
+            assert_eq!(rsi.round_dp(2), *expected, "RSI after {} prices", 15 + i);
+        }
+    }
+    
+    #[test]
+    fn rsi_needs_more_prices_than_the_period() {
+        assert_eq!(rsi(&example_closes()[..14], 14), None);
+        assert_eq!(rsi(&example_closes(), 0), None);
+    }
+    
+    #[test]
+    fn rsi_is_100_without_losses() {
+        let rising: Vec<Decimal> = (1..=20).map(Decimal::from).collect();
+        assert_eq!(rsi(&rising, 14), Some(dec!(100)));
+    }
+    
+    #[test]
+    fn streaks_skip_break_even_trades() {
+        let profits = [dec!(1), dec!(2), dec!(0), dec!(3), dec!(-1), dec!(-1), dec!(0), dec!(-2)];
+        
+        assert_eq!(streaks(&profits), Streaks { longest_winning: 3, longest_losing: 3, current: -3 });
+        assert_eq!(streaks(&[]), Streaks::default());
+    }
+}
@@ -1,12 +1,42 @@
 use anyhow::{Result, Context};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
 use serde::{Deserialize, Serialize};
 use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    compute_budget::{self, ComputeBudgetInstruction},
+    hash::Hash,
+    instruction::CompiledInstruction,
+    message::VersionedMessage,
     pubkey::Pubkey,
+    signature::Signature,
     transaction::{Transaction, VersionedTransaction},
 };
-use solana_client::rpc_client::RpcClient;
-use tracing::{info, error};
-use crate::service::retry_as_exponential_back_off;
+use solana_client::{client_error::ClientError, rpc_client::{RpcClient, SerializableTransaction}};
+use solana_transaction_status::TransactionStatus;
+use tracing::{info, error, warn};
+use crate::config::Config;
+use crate::error::TradingError;
+use crate::metrics::metrics;
+use crate::service::{parse_retry_after, redact, retry_as_exponential_back_off, RateLimiter};
+use crate::wallet::lamports_to_sol;
+
+const BASE_SIGNATURE_FEE_LAMPORTS: u64 = 5_000;
+// Jupiter routes usually consume a few hundred thousand compute units
+const ESTIMATED_SWAP_COMPUTE_UNITS: u64 = 300_000;
+// How many 429 responses to wait out before giving up
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+// Used when a 429 response has no usable Retry-After header
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+// Overall time allowed for sending a swap, so a stuck RPC can't hold the trade task for minutes
+const SEND_TRANSACTION_BUDGET: Duration = Duration::from_secs(120);
+// Program logs kept in a simulation error, the failing instruction logs last
+const MAX_SIMULATION_LOG_LINES: usize = 20;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct QuoteRequest {
@@ -17,9 +47,12 @@ pub struct QuoteRequest {
     pub amount: String,
     #[serde(rename = "slippageBps")]
     pub slippage_bps: u16,
+    // Only sent for swaps when a platform fee is configured
+    #[serde(rename = "platformFeeBps", skip_serializing_if = "Option::is_none")]
+    pub platform_fee_bps: Option<u16>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QuoteResponse {
     #[serde(rename = "inputMint")]
     pub input_mint: String,
@@ -41,14 +74,14 @@ pub struct QuoteResponse {
     pub route_plan: Vec<RoutePlanStep>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoutePlanStep {
     #[serde(rename = "swapInfo")]
     pub swap_info: SwapInfo,
     pub percent: u8,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SwapInfo {
     #[serde(rename = "ammKey")]
     pub amm_key: String,
@@ -75,7 +108,7 @@ pub struct SwapRequest {
     pub wrap_and_unwrap_sol: bool,
     #[serde(rename = "useSharedAccounts")]
     pub use_shared_accounts: bool,
-    #[serde(rename = "feeAccount")]
+    #[serde(rename = "feeAccount", skip_serializing_if = "Option::is_none")]
     pub fee_account: Option<String>,
     #[serde(rename = "trackingAccount")]
     pub tracking_account: Option<String>,
@@ -101,9 +134,149 @@ pub struct SwapResponse {
     pub swap_transaction: String,
 }
 
+// Trigger (limit order) API: createOrder and cancelOrder return an unsigned transaction,
+// which is signed and sent back through execute
+#[derive(Debug, Serialize)]
+pub struct CreateOrderRequest {
+    #[serde(rename = "inputMint")]
+    pub input_mint: String,
+    #[serde(rename = "outputMint")]
+    pub output_mint: String,
+    pub maker: String,
+    pub payer: String,
+    pub params: CreateOrderParams,
+    #[serde(rename = "computeUnitPrice")]
+    pub compute_unit_price: String,
+    #[serde(rename = "wrapAndUnwrapSol")]
+    pub wrap_and_unwrap_sol: bool,
+}
+
+impl CreateOrderRequest {
+    // The maker pays for the order account, and the priority fee is left to Jupiter
+    pub fn new(
+        maker: &Pubkey,
+        input_mint: &str,
+        output_mint: &str,
+        making_amount: u64,
+        taking_amount: u64,
+        expired_at: Option<i64>,
+    ) -> Self {
+        Self {
+            input_mint: input_mint.to_string(),
+            output_mint: output_mint.to_string(),
+            maker: maker.to_string(),
+            payer: maker.to_string(),
+            params: CreateOrderParams {
+                making_amount: making_amount.to_string(),
+                taking_amount: taking_amount.to_string(),
+                expired_at: expired_at.map(|timestamp| timestamp.to_string()),
+            },
+            compute_unit_price: "auto".to_string(),
+            wrap_and_unwrap_sol: true,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateOrderParams {
+    // Raw amounts: sell making_amount of the input token for taking_amount of the output token
+    #[serde(rename = "makingAmount")]
+    pub making_amount: String,
+    #[serde(rename = "takingAmount")]
+    pub taking_amount: String,
+    // Unix timestamp in seconds, the order never expires when unset
+    #[serde(rename = "expiredAt", skip_serializing_if = "Option::is_none")]
+    pub expired_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateOrderResponse {
+    pub order: String,
+    pub transaction: String,
+    #[serde(rename = "requestId")]
+    pub request_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CancelOrderRequest {
+    pub maker: String,
+    pub order: String,
+    #[serde(rename = "computeUnitPrice")]
+    pub compute_unit_price: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CancelOrderResponse {
+    pub transaction: String,
+    #[serde(rename = "requestId")]
+    pub request_id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ExecuteRequest {
+    #[serde(rename = "signedTransaction")]
+    signed_transaction: String,
+    #[serde(rename = "requestId")]
+    request_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExecuteResponse {
+    status: String,
+    signature: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TriggerOrdersResponse {
+    orders: Vec<TriggerOrder>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TriggerOrder {
+    #[serde(rename = "orderKey")]
+    order_key: String,
+}
+
+pub struct LimitOrderResult {
+    // Order account, used to cancel the order and check whether it is still open
+    pub order: String,
+    pub signature: String,
+}
+
+pub struct SwapResult {
+    pub signature: String,
+    pub gas_fee: Option<Decimal>,
+    // Quoted output amount in the output token's smallest unit
+    pub quoted_out_amount: u64,
+    // Least output the swap accepts at the slippage used (otherAmountThreshold), in the same unit
+    pub min_out_amount: u64,
+}
+
+// (input_mint, output_mint, amount, slippage_bps)
+type QuoteCacheKey = (String, String, u64, u16);
+
 pub struct JupiterClient {
     client: reqwest::Client,
     api_url: String,
+    // Path segments appended to api_url, which differ between the free and paid hosts
+    quote_path: String,
+    swap_path: String,
+    // Base URL of the trigger (limit order) API
+    trigger_api_url: String,
+    // Sent as x-api-key for paid hosts
+    api_key: Option<String>,
+    quote_cache: Mutex<HashMap<QuoteCacheKey, (Instant, QuoteResponse)>>,
+    quote_cache_ttl: Duration,
+    rate_limiter: RateLimiter,
+    platform_fee: Option<PlatformFee>,
+}
+
+// Jupiter referral fee, charged on swaps and paid into fee_account
+#[derive(Debug, Clone)]
+pub struct PlatformFee {
+    pub fee_account: String,
+    pub fee_bps: u16,
 }
 
 impl JupiterClient {
@@ -119,10 +292,134 @@ impl JupiterClient {
             
         Self {
             client,
-            api_url: api_url.to_string(),
+            api_url: api_url.trim_end_matches('/').to_string(),
+            quote_path: "/quote".to_string(),
+            swap_path: "/swap".to_string(),
+            trigger_api_url: "https://lite-api.jup.ag/trigger/v1".to_string(),
+            api_key: None,
+            quote_cache: Mutex::new(HashMap::new()),
+            quote_cache_ttl: Duration::from_secs(5),
+            rate_limiter: RateLimiter::new(1.0),
+            platform_fee: None,
         }
     }
     
+    // Private so every client built from the configuration is the shared one, with a single rate limiter
+    fn from_config(config: &Config) -> Self {
+        info!(
+            "Using Jupiter API at {} ({})",
+            config.jupiter_api_url,
+            if config.jupiter_api_key.is_some() { "API key: [REDACTED]" } else { "no API key" }
+        );
+        
+        let mut client = Self::new(&config.jupiter_api_url)
+            .with_paths(&config.jupiter_quote_path, &config.jupiter_swap_path)
+            .with_quote_cache_ttl(Duration::from_secs(config.quote_cache_ttl_secs))
+            .with_rate_limit(config.jupiter_requests_per_second)
+            .with_trigger_api_url(&config.jupiter_trigger_api_url);
+        if let Some(api_key) = &config.jupiter_api_key {
+            client = client.with_api_key(api_key);
+        }
+        if let (Some(fee_account), Some(fee_bps)) = (&config.fee_account, config.platform_fee_bps) {
+            client = client.with_platform_fee(fee_account, fee_bps);
+        }
+        client
+    }
+    
+    // The client every caller shares, so the quote cache and rate limit span the whole process.
+    // Configuration is read once, on first use.
+    pub fn shared(config: &Config) -> &'static Self {
+        static CLIENT: OnceLock<JupiterClient> = OnceLock::new();
+        CLIENT.get_or_init(|| Self::from_config(config))
+    }
+    
+    pub fn with_quote_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.quote_cache_ttl = ttl;
+        self
+    }
+    
+    pub fn with_rate_limit(mut self, requests_per_second: f64) -> Self {
+        self.rate_limiter = RateLimiter::new(requests_per_second);
+        self
+    }
+    
+    pub fn with_paths(mut self, quote_path: &str, swap_path: &str) -> Self {
+        self.quote_path = normalize_path(quote_path);
+        self.swap_path = normalize_path(swap_path);
+        self
+    }
+    
+    pub fn with_trigger_api_url(mut self, trigger_api_url: &str) -> Self {
+        self.trigger_api_url = trigger_api_url.trim_end_matches('/').to_string();
+        self
+    }
+    
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+    
+    pub fn with_platform_fee(mut self, fee_account: &str, fee_bps: u16) -> Self {
+        self.platform_fee = Some(PlatformFee {
+            fee_account: fee_account.to_string(),
+            fee_bps,
+        });
+        self
+    }
+    
+    // Attach the API key header only when one is configured
+    fn with_auth(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.api_key {
+            Some(api_key) => request.header("x-api-key", api_key),
+            None => request,
+        }
+    }
+    
+    // Send a request within the rate limit. Connection errors are retried with exponential back off,
+    // while 429 responses wait for Retry-After instead. The last 429 is returned if they persist.
+    async fn send<F>(&self, build_request: F, operation_name: &str) -> Result<reqwest::Response>
+    where
+        F: Fn() -> reqwest::RequestBuilder,
+    {
+        let mut rate_limit_retries = 0;
+        loop {
+            self.rate_limiter.acquire().await;
+            
+            let response = retry_as_exponential_back_off(
+                || async {
+                    self.with_auth(build_request())
+                        .send()
+                        .await
+                        .map_err(|e| anyhow::anyhow!("Failed to send {}: {}", operation_name.to_lowercase(), e))
+                },
+                operation_name,
+                5,
+                500,
+                None,
+                None,
+            )
+            .await?;
+            
+            if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS || rate_limit_retries >= MAX_RATE_LIMIT_RETRIES {
+                return Ok(response);
+            }
+            rate_limit_retries += 1;
+            
+            let retry_after = response.headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| parse_retry_after(value, chrono::Utc::now()))
+                .unwrap_or(DEFAULT_RETRY_AFTER)
+                .min(MAX_RETRY_AFTER);
+            warn!(
+                "{} rate limited by Jupiter (attempt {}/{}), retrying in {:?}",
+                operation_name, rate_limit_retries, MAX_RATE_LIMIT_RETRIES, retry_after
+            );
+            tokio::time::sleep(retry_after).await;
+        }
+    }
+    
+    // Quotes for price reads, reused for quote_cache_ttl to cut Jupiter API calls
     pub async fn get_quote(
         &self,
         input_mint: &str,
@@ -130,46 +427,67 @@ impl JupiterClient {
         amount: u64,
         slippage_bps: u16,
     ) -> Result<QuoteResponse> {
-        let url = format!("{}/quote", self.api_url);
+        let key = (input_mint.to_string(), output_mint.to_string(), amount, slippage_bps);
+        
+        if let Some((fetched_at, quote)) = self.quote_cache.lock().await.get(&key) {
+            if fetched_at.elapsed() < self.quote_cache_ttl {
+                info!("Using cached quote: {} {} -> {}", amount, input_mint, output_mint);
+                return Ok(quote.clone());
+            }
+        }
+        
+        let quote = self.fetch_quote(input_mint, output_mint, amount, slippage_bps, None).await?;
+        
+        let mut cache = self.quote_cache.lock().await;
+        cache.retain(|_, (fetched_at, _)| fetched_at.elapsed() < self.quote_cache_ttl);
+        cache.insert(key, (Instant::now(), quote.clone()));
+        
+        Ok(quote)
+    }
+    
+    // Always requests a fresh quote, used when the quote will be swapped against.
+    // The platform fee is deducted from the quoted output, so price reads leave it unset.
+    async fn fetch_quote(
+        &self,
+        input_mint: &str,
+        output_mint: &str,
+        amount: u64,
+        slippage_bps: u16,
+        platform_fee_bps: Option<u16>,
+    ) -> Result<QuoteResponse> {
+        let url = format!("{}{}", self.api_url, self.quote_path);
         
         info!(
             "Requesting quote: {} {} -> {} (slippage: {} bps)",
             amount, input_mint, output_mint, slippage_bps
         );
         
-        let client = self.client.clone();
-        let input_mint = input_mint.to_string();
-        let output_mint = output_mint.to_string();
-        let amount_str = amount.to_string();
-        let slippage_str = slippage_bps.to_string();
-        
-        let response = retry_as_exponential_back_off(
-            || async {
-                client
-                    .get(&url)
-                    .query(&[
-                        ("inputMint", &input_mint),
-                        ("outputMint", &output_mint),
-                        ("amount", &amount_str),
-                        ("slippageBps", &slippage_str),
-                    ])
-                    .header("Accept", "application/json")
-                    .send()
-                    .await
-                    .map_err(|e| anyhow::anyhow!("Failed to send quote request: {}", e))
-            },
+        let quote_request = QuoteRequest {
+            input_mint: input_mint.to_string(),
+            output_mint: output_mint.to_string(),
+            amount: amount.to_string(),
+            slippage_bps,
+            platform_fee_bps,
+        };
+        
+        let timer = metrics().quote_latency_seconds.start_timer();
+        let response = self.send(
+            || self.client.get(&url)
+                .query(&quote_request)
+                .header("Accept", "application/json"),
             "Quote request",
-            5,
-            500,
-            None,
         )
-        .await?;
+        .await
+        .map_err(|e| TradingError::QuoteFailed(e.to_string()))?;
+        timer.observe_duration();
         
         let status = response.status();
         if !status.is_success() {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unable to read error response".to_string());
+            let error_text = response.text().await
+                .map(|text| redact(&text))
+                .unwrap_or_else(|_| "Unable to read error response".to_string());
             error!("Quote request failed with status {}: {}", status, error_text);
-            anyhow::bail!("Quote request failed (status {}): {}", status, error_text)
+            return Err(TradingError::QuoteFailed(format!("status {}: {}", status, error_text)).into());
         }
         
         let response_text = response.text().await
@@ -178,8 +496,8 @@ impl JupiterClient {
         let quote: QuoteResponse = serde_json::from_str(&response_text)
             .map_err(|e| {
                 error!("Failed to parse quote response: {}", e);
-                error!("Response text: {}", response_text);
-                anyhow::anyhow!("Failed to parse quote response: {}", e)
+                error!("Response text: {}", redact(&response_text));
+                TradingError::QuoteFailed(format!("Failed to parse quote response: {}", e))
             })?;
         
         info!(
@@ -196,47 +514,39 @@ impl JupiterClient {
         &self,
         user_public_key: &Pubkey,
         quote: QuoteResponse,
+        priority_fee_micro_lamports: u64,
+        compute_unit_limit: Option<u32>,
     ) -> Result<SwapResponse> {
-        let url = format!("{}/swap", self.api_url);
+        let url = format!("{}{}", self.api_url, self.swap_path);
         
         let swap_request = SwapRequest {
             user_public_key: user_public_key.to_string(),
             wrap_and_unwrap_sol: true,
             use_shared_accounts: true,
-            fee_account: None,
+            fee_account: self.platform_fee.as_ref().map(|fee| fee.fee_account.clone()),
             tracking_account: None,
-            compute_unit_price_micro_lamports: Some(1000),
-            as_legacy_transaction: true,
+            compute_unit_price_micro_lamports: Some(priority_fee_micro_lamports),
+            as_legacy_transaction: false,
             use_token_ledger: false,
             destination_token_account: None,
-            dynamic_compute_unit_limit: true,
+            // Jupiter's request has no field for the limit itself, execute_swap sets it in the transaction
+            dynamic_compute_unit_limit: compute_unit_limit.is_none(),
             skip_user_accounts_rpc_calls: false,
             quote_response: quote,
         };
         
-        let client = self.client.clone();
-        
-        let response = retry_as_exponential_back_off(
-            || async {
-                client
-                    .post(&url)
-                    .json(&swap_request)
-                    .send()
-                    .await
-                    .map_err(|e| anyhow::anyhow!("Failed to send swap request: {}", e))
-            },
+        let response = self.send(
+            || self.client.post(&url).json(&swap_request),
             "Swap request",
-            5,
-            500,
-            None,
         )
-        .await?;
+        .await
+        .map_err(|e| TradingError::SwapFailed(e.to_string()))?;
         
         let status = response.status();
         if !status.is_success() {
-            let error_text = response.text().await?;
+            let error_text = redact(&response.text().await?);
             error!("Swap request failed with status {}: {}", status, error_text);
-            anyhow::bail!("Swap request failed: {}", error_text);
+            return Err(TradingError::SwapFailed(format!("status {}: {}", status, error_text)).into());
         }
         
         let response_text = response.text().await
@@ -245,8 +555,8 @@ impl JupiterClient {
         let swap: SwapResponse = serde_json::from_str(&response_text)
             .map_err(|e| {
                 error!("Failed to parse swap response: {}", e);
-                error!("Response text: {}", response_text);
-                anyhow::anyhow!("Failed to parse swap response: {}", e)
+                error!("Response text: {}", redact(&response_text));
+                TradingError::SwapFailed(format!("Failed to parse swap response: {}", e))
             })?;
         
         Ok(swap)
@@ -259,13 +569,38 @@ impl JupiterClient {
         input_mint: &str,
         output_mint: &str,
         amount: u64,
-        slippage_bps: u16,
-    ) -> Result<String> {
+        config: &Config,
+    ) -> Result<SwapResult> {
         // Get quote
-        let quote = self.get_quote(input_mint, output_mint, amount, slippage_bps).await?;
+        let platform_fee_bps = self.platform_fee.as_ref().map(|fee| fee.fee_bps);
+        let quote = self.fetch_quote(input_mint, output_mint, amount, config.slippage_bps, platform_fee_bps).await?;
+        let quoted_out_amount = quote.out_amount.parse::<u64>()
+            .context("Failed to parse quoted output amount")?;
+        let min_out_amount = quote.other_amount_threshold.parse::<u64>()
+            .context("Failed to parse minimum output amount")?;
+        
+        // Refuse to trade into thin liquidity
+        let price_impact = parse_price_impact_pct(&quote.price_impact_pct)?;
+        if price_impact > config.max_price_impact_pct {
+            return Err(TradingError::PriceImpactTooHigh {
+                actual: price_impact,
+                max: config.max_price_impact_pct,
+            }.into());
+        }
+        
+        // Resolve priority fee
+        let priority_fee = match config.priority_fee_micro_lamports {
+            Some(fee) => fee,
+            None => get_auto_priority_fee(rpc_client)?,
+        };
+        info!("Using priority fee: {} micro-lamports per compute unit", priority_fee);
+        match config.compute_unit_limit {
+            Some(limit) => info!("Using compute unit limit: {}", limit),
+            None => info!("Using compute unit limit: dynamic"),
+        }
         
         // Get swap transaction
-        let swap_response = self.get_swap_transaction(wallet.pubkey(), quote).await?;
+        let swap_response = self.get_swap_transaction(wallet.pubkey(), quote, priority_fee, config.compute_unit_limit).await?;
         
         // Deserialize and sign transaction
         info!("Swap transaction base64 length: {}", swap_response.swap_transaction.len());
@@ -275,66 +610,399 @@ impl JupiterClient {
         
         info!("Transaction bytes length: {}", tx_bytes.len());
         
-        // Try to deserialize as versioned transaction first
-        let (mut transaction, _is_versioned) = match bincode::deserialize::<VersionedTransaction>(&tx_bytes) {
-            Ok(versioned_tx) => {
+        // Get recent blockhash
+        let recent_blockhash = rpc_client.get_latest_blockhash()
+            .map_err(|e| TradingError::RpcError(format!("Failed to get recent blockhash: {}", e)))?;
+        
+        let signature = match bincode::deserialize::<VersionedTransaction>(&tx_bytes) {
+            Ok(mut versioned_tx) => {
                 info!("Successfully deserialized as versioned transaction");
-                // Convert versioned transaction to legacy if possible
-                match versioned_tx.into_legacy_transaction() {
-                    Some(legacy_tx) => (legacy_tx, false),
-                    None => {
-                        // If we can't convert to legacy, try to handle it differently
-                        error!("Cannot convert versioned transaction to legacy format");
-                        return Err(anyhow::anyhow!("Jupiter returned a versioned transaction that cannot be converted to legacy format"));
-                    }
+                versioned_tx.message.set_recent_blockhash(recent_blockhash);
+                if let Some(limit) = config.compute_unit_limit {
+                    let (account_keys, instructions) = match &mut versioned_tx.message {
+                        VersionedMessage::Legacy(message) => (&message.account_keys, &mut message.instructions),
+                        VersionedMessage::V0(message) => (&message.account_keys, &mut message.instructions),
+                    };
+                    set_compute_unit_limit(account_keys, instructions, limit);
+                }
+                
+                // Sign transaction
+                wallet.sign_versioned_transaction(&mut versioned_tx)?;
+                if config.simulate_before_send {
+                    simulate_transaction(rpc_client, &versioned_tx)?;
                 }
+                let versioned_tx = Mutex::new(versioned_tx);
+                
+                // Send and confirm transaction
+                retry_as_exponential_back_off(
+                    || async {
+                        let mut versioned_tx = versioned_tx.lock().await;
+                        let signature = versioned_tx.signatures[0];
+                        match prepare_send(rpc_client, &signature, versioned_tx.message.recent_blockhash())? {
+                            SendStep::Landed => return Ok(signature),
+                            SendStep::Send => {}
+                            SendStep::Resign => {
+                                versioned_tx.message.set_recent_blockhash(latest_blockhash(rpc_client)?);
+                                wallet.sign_versioned_transaction(&mut versioned_tx)?;
+                            }
+                        }
+                        
+                        rpc_client
+                            .send_and_confirm_transaction(&*versioned_tx)
+                            .map_err(|e| anyhow::anyhow!("Failed to send and confirm transaction: {}", e))
+                    },
+                    "Send and confirm transaction",
+                    5,
+                    500,
+                    Some(std::time::Duration::from_secs(60)),
+                    Some(SEND_TRANSACTION_BUDGET),
+                )
+                .await
+                .map_err(|e| TradingError::SwapFailed(e.to_string()))?
             }
             Err(_) => {
-                // Try deserializing as legacy transaction
-                match bincode::deserialize::<Transaction>(&tx_bytes) {
+                // Fall back to a legacy transaction
+                let mut transaction = match bincode::deserialize::<Transaction>(&tx_bytes) {
                     Ok(tx) => {
                         info!("Successfully deserialized as legacy transaction");
-                        (tx, false)
+                        tx
                     }
                     Err(e) => {
                         error!("Failed to deserialize as both versioned and legacy transaction");
                         error!("Bincode deserialization error: {:?}", e);
                         error!("First 100 bytes of tx_bytes: {:?}", &tx_bytes[..tx_bytes.len().min(100)]);
-                        return Err(anyhow::anyhow!("Failed to deserialize transaction: {}", e));
+                        return Err(TradingError::SwapFailed(format!("Failed to deserialize transaction: {}", e)).into());
                     }
+                };
+                
+                transaction.message.recent_blockhash = recent_blockhash;
+                if let Some(limit) = config.compute_unit_limit {
+                    set_compute_unit_limit(&transaction.message.account_keys, &mut transaction.message.instructions, limit);
                 }
+                
+                // Sign transaction
+                wallet.sign_transaction(&mut transaction)?;
+                if config.simulate_before_send {
+                    simulate_transaction(rpc_client, &transaction)?;
+                }
+                let transaction = Mutex::new(transaction);
+                
+                // Send and confirm transaction
+                retry_as_exponential_back_off(
+                    || async {
+                        let mut transaction = transaction.lock().await;
+                        let signature = transaction.signatures[0];
+                        match prepare_send(rpc_client, &signature, &transaction.message.recent_blockhash)? {
+                            SendStep::Landed => return Ok(signature),
+                            SendStep::Send => {}
+                            SendStep::Resign => {
+                                transaction.message.recent_blockhash = latest_blockhash(rpc_client)?;
+                                wallet.sign_transaction(&mut transaction)?;
+                            }
+                        }
+                        
+                        rpc_client
+                            .send_and_confirm_transaction(&*transaction)
+                            .map_err(|e| anyhow::anyhow!("Failed to send and confirm transaction: {}", e))
+                    },
+                    "Send and confirm transaction",
+                    5,
+                    500,
+                    Some(std::time::Duration::from_secs(60)),
+                    Some(SEND_TRANSACTION_BUDGET),
+                )
+                .await
+                .map_err(|e| TradingError::SwapFailed(e.to_string()))?
             }
         };
         
-        // Get recent blockhash
-        let recent_blockhash = rpc_client.get_latest_blockhash()
-            .context("Failed to get recent blockhash")?;
+        info!("Swap executed successfully: {}", signature);
+        
+        // A missing fee should not fail an already confirmed swap
+        let gas_fee = match wallet.get_gas_fee(rpc_client, signature.to_string()).await {
+            Ok(fee) => Some(fee),
+            Err(e) => {
+                error!("Failed to get gas fee for {}: {}", signature, e);
+                None
+            }
+        };
         
-        transaction.message.recent_blockhash = recent_blockhash;
+        Ok(SwapResult {
+            signature: signature.to_string(),
+            gas_fee,
+            quoted_out_amount,
+            min_out_amount,
+        })
+    }
+    
+    // Place a limit order selling making_amount of input_mint for at least taking_amount of output_mint
+    pub async fn place_limit_order(
+        &self,
+        wallet: &crate::wallet::Wallet,
+        input_mint: &str,
+        output_mint: &str,
+        making_amount: u64,
+        taking_amount: u64,
+        expired_at: Option<i64>,
+    ) -> Result<LimitOrderResult> {
+        let url = format!("{}/createOrder", self.trigger_api_url);
+        let request = CreateOrderRequest::new(wallet.pubkey(), input_mint, output_mint, making_amount, taking_amount, expired_at);
         
-        // Sign transaction
-        wallet.sign_transaction(&mut transaction)?;
+        let response = self.send(|| self.client.post(&url).json(&request), "Create order request")
+            .await
+            .map_err(|e| TradingError::SwapFailed(e.to_string()))?;
+        let created: CreateOrderResponse = trigger_response(response, "Create order").await?;
         
-        // Send and confirm transaction
-        let signature = retry_as_exponential_back_off(
-            || async {
-                rpc_client
-                    .send_and_confirm_transaction(&transaction)
-                    .map_err(|e| anyhow::anyhow!("Failed to send and confirm transaction: {}", e))
-            },
-            "Send and confirm transaction",
-            5,
-            500,
-            Some(std::time::Duration::from_secs(60)),
+        let signature = self.execute_trigger_transaction(wallet, &created.transaction, created.request_id).await?;
+        info!("Placed limit order {}: {}", created.order, signature);
+        
+        Ok(LimitOrderResult {
+            order: created.order,
+            signature,
+        })
+    }
+    
+    pub async fn cancel_limit_order(&self, wallet: &crate::wallet::Wallet, order: &str) -> Result<String> {
+        let url = format!("{}/cancelOrder", self.trigger_api_url);
+        let request = CancelOrderRequest {
+            maker: wallet.pubkey().to_string(),
+            order: order.to_string(),
+            compute_unit_price: "auto".to_string(),
+        };
+        
+        let response = self.send(|| self.client.post(&url).json(&request), "Cancel order request")
+            .await
+            .map_err(|e| TradingError::SwapFailed(e.to_string()))?;
+        let cancel: CancelOrderResponse = trigger_response(response, "Cancel order").await?;
+        
+        let signature = self.execute_trigger_transaction(wallet, &cancel.transaction, cancel.request_id).await?;
+        info!("Cancelled limit order {}: {}", order, signature);
+        Ok(signature)
+    }
+    
+    // Order accounts of the wallet's open limit orders
+    pub async fn get_open_limit_orders(&self, user: &Pubkey) -> Result<Vec<String>> {
+        let url = format!("{}/getTriggerOrders", self.trigger_api_url);
+        let user = user.to_string();
+        
+        let response = self.send(
+            || self.client.get(&url).query(&[("user", user.as_str()), ("orderStatus", "active")]),
+            "Trigger orders request",
         )
-        .await?;
+        .await
+        .map_err(|e| TradingError::QuoteFailed(e.to_string()))?;
+        let orders: TriggerOrdersResponse = trigger_response(response, "Trigger orders").await?;
         
-        info!("Swap executed successfully: {}", signature);
+        Ok(orders.orders.into_iter().map(|order| order.order_key).collect())
+    }
+    
+    // Sign a trigger API transaction and submit it through execute, which sends and confirms it
+    async fn execute_trigger_transaction(
+        &self,
+        wallet: &crate::wallet::Wallet,
+        transaction: &str,
+        request_id: String,
+    ) -> Result<String> {
+        let tx_bytes = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, transaction)
+            .context("Failed to decode order transaction")?;
+        let mut versioned_tx: VersionedTransaction = bincode::deserialize(&tx_bytes)
+            .context("Failed to deserialize order transaction")?;
+        wallet.sign_versioned_transaction(&mut versioned_tx)?;
+        
+        let request = ExecuteRequest {
+            signed_transaction: base64::Engine::encode(
+                &base64::engine::general_purpose::STANDARD,
+                bincode::serialize(&versioned_tx).context("Failed to serialize order transaction")?,
+            ),
+            request_id,
+        };
+        let url = format!("{}/execute", self.trigger_api_url);
+        let response = self.send(|| self.client.post(&url).json(&request), "Execute order request")
+            .await
+            .map_err(|e| TradingError::SwapFailed(e.to_string()))?;
+        let executed: ExecuteResponse = trigger_response(response, "Execute order").await?;
         
-        Ok(signature.to_string())
+        match (executed.status.as_str(), executed.signature) {
+            ("Success", Some(signature)) => Ok(signature),
+            (status, _) => Err(TradingError::SwapFailed(format!(
+                "Order transaction {}: {}",
+                status.to_lowercase(),
+                executed.error.unwrap_or_default()
+            )).into()),
+        }
     }
 }
 
+// Parse a trigger API response, failing with the (redacted) body on an error status
+async fn trigger_response<T: for<'de> Deserialize<'de>>(response: reqwest::Response, operation_name: &str) -> Result<T> {
+    let status = response.status();
+    let response_text = response.text().await
+        .with_context(|| format!("Failed to read {} response body", operation_name.to_lowercase()))?;
+    if !status.is_success() {
+        error!("{} request failed with status {}: {}", operation_name, status, redact(&response_text));
+        return Err(TradingError::SwapFailed(format!("{} status {}: {}", operation_name, status, redact(&response_text))).into());
+    }
+    
+    serde_json::from_str(&response_text)
+        .map_err(|e| {
+            error!("Response text: {}", redact(&response_text));
+            TradingError::SwapFailed(format!("Failed to parse {} response: {}", operation_name.to_lowercase(), e)).into()
+        })
+}
+
+// What a send attempt does with the signed swap, which an earlier attempt may already have sent
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SendStep {
+    // An earlier attempt's transaction is confirmed, so sending again would swap twice
+    Landed,
+    // Send the transaction as signed, the network drops it if an earlier copy lands
+    Send,
+    // The blockhash expired without the transaction landing, so it can never land and is re-signed
+    Resign,
+}
+
+// The blockhash is checked before the signature status: once it has expired the earlier transaction
+// can no longer land, so a missing status is final and re-signing can't execute the swap twice
+fn prepare_send(rpc_client: &RpcClient, signature: &Signature, blockhash: &Hash) -> Result<SendStep> {
+    let blockhash_expired = needs_blockhash_refresh(&rpc_client.is_blockhash_valid(blockhash, rpc_client.commitment()));
+    let statuses = rpc_client.get_signature_statuses_with_history(&[*signature])
+        .map_err(|e| TradingError::RpcError(format!("Failed to get the status of {}: {}", signature, e)))?;
+    let status = statuses.value.into_iter().next().flatten();
+    
+    let step = send_step(blockhash_expired, status.as_ref(), rpc_client.commitment())?;
+    match step {
+        SendStep::Landed => info!("Transaction {} from an earlier attempt landed, not sending it again", signature),
+        SendStep::Resign => warn!("Blockhash {} expired before {} landed, re-signing", blockhash, signature),
+        SendStep::Send => {}
+    }
+    Ok(step)
+}
+
+fn send_step(blockhash_expired: bool, status: Option<&TransactionStatus>, commitment: CommitmentConfig) -> Result<SendStep> {
+    match status {
+        Some(TransactionStatus { err: Some(e), .. }) => {
+            Err(TradingError::SwapFailed(format!("Swap transaction failed on chain: {}", e)).into())
+        }
+        Some(status) if status.satisfies_commitment(commitment) => Ok(SendStep::Landed),
+        // Seen but not yet at the wanted commitment, so wait for it rather than send anything
+        Some(_) => anyhow::bail!("Swap transaction is not {:?} yet", commitment.commitment),
+        None if blockhash_expired => Ok(SendStep::Resign),
+        None => Ok(SendStep::Send),
+    }
+}
+
+fn latest_blockhash(rpc_client: &RpcClient) -> Result<Hash> {
+    Ok(rpc_client.get_latest_blockhash()
+        .map_err(|e| TradingError::RpcError(format!("Failed to refresh recent blockhash: {}", e)))?)
+}
+
+// Overwrite the SetComputeUnitLimit instruction Jupiter put in the swap transaction
+fn set_compute_unit_limit(account_keys: &[Pubkey], instructions: &mut [CompiledInstruction], limit: u32) {
+    let limit_data = ComputeBudgetInstruction::set_compute_unit_limit(limit).data;
+    let existing = instructions.iter_mut().find(|instruction| {
+        account_keys.get(instruction.program_id_index as usize) == Some(&compute_budget::id())
+            && instruction.data.first() == limit_data.first()
+    });
+    
+    match existing {
+        Some(instruction) => instruction.data = limit_data,
+        None => warn!("Swap transaction has no compute unit limit instruction, keeping Jupiter's limit"),
+    }
+}
+
+// Fails with the program logs when the node reports that the transaction would fail
+fn simulate_transaction(rpc_client: &RpcClient, transaction: &impl SerializableTransaction) -> Result<()> {
+    let simulation = rpc_client.simulate_transaction(transaction)
+        .map_err(|e| TradingError::RpcError(format!("Failed to simulate transaction: {}", e)))?
+        .value;
+    
+    let Some(error) = simulation.err else {
+        info!("Simulation succeeded, {} compute units consumed", simulation.units_consumed.unwrap_or_default());
+        return Ok(());
+    };
+    let logs = simulation.logs.unwrap_or_default();
+    error!("Simulation failed: {}", error);
+    Err(TradingError::SimulationFailed {
+        error: error.to_string(),
+        logs: simulation_logs(&logs),
+    }.into())
+}
+
+// The last MAX_SIMULATION_LOG_LINES lines of the program logs
+fn simulation_logs(logs: &[String]) -> String {
+    if logs.is_empty() {
+        return "(none)".to_string();
+    }
+    let skipped = logs.len().saturating_sub(MAX_SIMULATION_LOG_LINES);
+    let tail = logs[skipped..].join("\n");
+    if skipped > 0 {
+        format!("({} earlier lines omitted)\n{}", skipped, tail)
+    } else {
+        tail
+    }
+}
+
+// When validity can't be checked, send anyway and let the node reject an expired blockhash
+fn needs_blockhash_refresh(validity: &Result<bool, ClientError>) -> bool {
+    matches!(validity, Ok(false))
+}
+
+// Realized slippage in basis points, positive when less than quoted was received
+pub fn slippage_bps(quoted: Decimal, received: Decimal) -> Option<Decimal> {
+    if quoted <= Decimal::ZERO {
+        return None;
+    }
+    Some((quoted - received) / quoted * Decimal::from(10_000))
+}
+
+// A fill within margin_bps above the slippage minimum is what a sandwich squeezing out
+// all of the allowed slippage looks like
+pub fn is_suspicious_fill(min_out: Decimal, received: Decimal, margin_bps: u16) -> bool {
+    if min_out <= Decimal::ZERO {
+        return false;
+    }
+    received <= min_out * (Decimal::ONE + Decimal::from(margin_bps) / Decimal::from(10_000))
+}
+
+// Jupiter reports price impact as a plain or scientific-notation decimal string
+fn parse_price_impact_pct(value: &str) -> Result<Decimal> {
+    Decimal::from_str(value)
+        .or_else(|_| Decimal::from_scientific(value))
+        .with_context(|| format!("Invalid price impact: {}", value))
+}
+
+// Signature fee plus the priority fee for a typical swap, in SOL, for deciding whether a trade is worth making
+pub fn estimate_swap_fee(rpc_client: &RpcClient, config: &Config) -> Result<Decimal> {
+    let priority_fee = match config.priority_fee_micro_lamports {
+        Some(fee) => fee,
+        None => get_auto_priority_fee(rpc_client)?,
+    };
+    let compute_units = config.compute_unit_limit.map_or(ESTIMATED_SWAP_COMPUTE_UNITS, u64::from);
+    let lamports = BASE_SIGNATURE_FEE_LAMPORTS + priority_fee * compute_units / 1_000_000;
+    Ok(lamports_to_sol(lamports))
+}
+
+// Pick the 75th percentile of recent prioritization fees
+fn get_auto_priority_fee(rpc_client: &RpcClient) -> Result<u64> {
+    let mut fees: Vec<u64> = rpc_client
+        .get_recent_prioritization_fees(&[])
+        .context("Failed to get recent prioritization fees")?
+        .into_iter()
+        .map(|f| f.prioritization_fee)
+        .collect();
+    
+    if fees.is_empty() {
+        return Ok(0);
+    }
+    
+    fees.sort_unstable();
+    Ok(fees[(fees.len() - 1) * 75 / 100])
+}
+
+fn normalize_path(path: &str) -> String {
+    format!("/{}", path.trim_matches('/'))
+}
+
 pub async fn get_price(
     jupiter_client: &JupiterClient,
     from_mint: &str,
@@ -358,3 +1026,323 @@ pub async fn get_price(
     
     Ok(out_amount / in_amount)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jupiter_mock::{self, MockJupiter, SOL_MINT, USDC_MINT};
+    use rust_decimal_macros::dec;
+    use solana_sdk::transaction::TransactionError;
+    use solana_transaction_status::TransactionConfirmationStatus;
+    
+    // MAX_PRICE_IMPACT_PCT when unset
+    const DEFAULT_MAX_PRICE_IMPACT_PCT: Decimal = dec!(1.0);
+
+    fn status(confirmation_status: TransactionConfirmationStatus, err: Option<TransactionError>) -> TransactionStatus {
+        TransactionStatus {
+            slot: 1,
+            confirmations: Some(1),
+            status: err.clone().map_or(Ok(()), Err),
+            err,
+            confirmation_status: Some(confirmation_status),
+        }
+    }
+
+    #[test]
+    fn landed_transaction_is_never_sent_again() {
+        let confirmed = status(TransactionConfirmationStatus::Confirmed, None);
+        for blockhash_expired in [false, true] {
+            let step = send_step(blockhash_expired, Some(&confirmed), CommitmentConfig::confirmed()).unwrap();
+            assert_eq!(step, SendStep::Landed);
+        }
+    }
+
+    #[test]
+    fn unconfirmed_transaction_is_waited_for() {
+        let processed = status(TransactionConfirmationStatus::Processed, None);
+        for blockhash_expired in [false, true] {
+            assert!(send_step(blockhash_expired, Some(&processed), CommitmentConfig::confirmed()).is_err());
+        }
+    }
+
+    #[test]
+    fn failed_transaction_is_not_retried() {
+        let failed = status(TransactionConfirmationStatus::Confirmed, Some(TransactionError::InsufficientFundsForFee));
+        let err = send_step(true, Some(&failed), CommitmentConfig::confirmed()).unwrap_err();
+        assert!(matches!(err.downcast_ref::<TradingError>(), Some(TradingError::SwapFailed(_))));
+    }
+
+    #[test]
+    fn unseen_transaction_is_resigned_only_after_its_blockhash_expired() {
+        assert_eq!(send_step(false, None, CommitmentConfig::confirmed()).unwrap(), SendStep::Send);
+        assert_eq!(send_step(true, None, CommitmentConfig::confirmed()).unwrap(), SendStep::Resign);
+    }
+
+    #[test]
+    fn unknown_blockhash_validity_does_not_resign() {
+        let unreachable: Result<bool, ClientError> = Err(std::io::Error::other("rpc down").into());
+        assert!(!needs_blockhash_refresh(&unreachable));
+        assert!(needs_blockhash_refresh(&Ok(false)));
+        assert!(!needs_blockhash_refresh(&Ok(true)));
+    }
+
+    fn mock_client(mock: &MockJupiter) -> JupiterClient {
+        JupiterClient::new(mock.url()).with_rate_limit(100.0)
+    }
+
+    #[tokio::test]
+    async fn get_quote_parses_the_canned_quote() {
+        let mock = MockJupiter::start(jupiter_mock::normal_quote()).await;
+        let quote = mock_client(&mock).get_quote(SOL_MINT, USDC_MINT, 1_000_000_000, 50).await.unwrap();
+
+        assert_eq!(quote.out_amount, "150000000");
+        assert_eq!(quote.route_plan[0].swap_info.label.as_deref(), Some("Whirlpool"));
+        assert!(parse_price_impact_pct(&quote.price_impact_pct).unwrap() <= DEFAULT_MAX_PRICE_IMPACT_PCT);
+    }
+
+    #[tokio::test]
+    async fn high_impact_quote_exceeds_the_default_limit() {
+        let mock = MockJupiter::start(jupiter_mock::high_impact_quote()).await;
+        let quote = mock_client(&mock).get_quote(SOL_MINT, USDC_MINT, 1_000_000_000, 50).await.unwrap();
+
+        assert!(parse_price_impact_pct(&quote.price_impact_pct).unwrap() > DEFAULT_MAX_PRICE_IMPACT_PCT);
+    }
+
+    #[tokio::test]
+    async fn get_swap_transaction_posts_the_quote() {
+        let mock = MockJupiter::start(jupiter_mock::normal_quote()).await;
+        let client = mock_client(&mock).with_platform_fee("FeeAccount1111111111111111111111111111111111", 20);
+        let quote = client.get_quote(SOL_MINT, USDC_MINT, 1_000_000_000, 50).await.unwrap();
+        let user = Pubkey::new_unique();
+
+        let swap = client.get_swap_transaction(&user, quote, 10_000, Some(300_000)).await.unwrap();
+
+        assert_eq!(swap.swap_transaction, jupiter_mock::SWAP_TRANSACTION);
+        let request = mock.last_swap_request().unwrap();
+        assert_eq!(request["userPublicKey"], user.to_string());
+        assert_eq!(request["computeUnitPriceMicroLamports"], 10_000);
+        assert_eq!(request["dynamicComputeUnitLimit"], false);
+        assert_eq!(request["feeAccount"], "FeeAccount1111111111111111111111111111111111");
+        assert_eq!(request["quoteResponse"]["outAmount"], "150000000");
+    }
+
+    #[tokio::test]
+    async fn rate_limited_requests_are_retried() {
+        let mock = MockJupiter::start(jupiter_mock::normal_quote()).await;
+        let client = mock_client(&mock);
+
+        mock.rate_limit_next(2);
+        let quote = client.get_quote(SOL_MINT, USDC_MINT, 1_000_000_000, 50).await.unwrap();
+        assert_eq!(quote.out_amount, "150000000");
+        assert_eq!(mock.quote_requests(), 3);
+
+        mock.rate_limit_next(1);
+        let swap = client.get_swap_transaction(&Pubkey::new_unique(), quote, 0, None).await.unwrap();
+        assert_eq!(swap.swap_transaction, jupiter_mock::SWAP_TRANSACTION);
+        assert_eq!(mock.swap_requests(), 2);
+    }
+
+    #[tokio::test]
+    async fn persistent_rate_limit_fails_the_quote() {
+        let mock = MockJupiter::start(jupiter_mock::normal_quote()).await;
+        mock.rate_limit_next(u32::MAX);
+
+        let err = mock_client(&mock).get_quote(SOL_MINT, USDC_MINT, 1_000_000_000, 50).await.unwrap_err();
+
+        assert!(matches!(err.downcast_ref::<TradingError>(), Some(TradingError::QuoteFailed(_))));
+        assert_eq!(mock.quote_requests(), MAX_RATE_LIMIT_RETRIES + 1);
+    }
+    
+    #[tokio::test]
+    async fn quotes_are_reused_within_the_ttl() {
+        let mock = MockJupiter::start(jupiter_mock::normal_quote()).await;
+        let client = mock_client(&mock).with_quote_cache_ttl(Duration::from_millis(200));
+        
+        client.get_quote(SOL_MINT, USDC_MINT, 1_000_000_000, 50).await.unwrap();
+        client.get_quote(SOL_MINT, USDC_MINT, 1_000_000_000, 50).await.unwrap();
+        assert_eq!(mock.quote_requests(), 1);
+        
+        // A different amount is a different request
+        client.get_quote(SOL_MINT, USDC_MINT, 2_000_000_000, 50).await.unwrap();
+        assert_eq!(mock.quote_requests(), 2);
+        
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        client.get_quote(SOL_MINT, USDC_MINT, 1_000_000_000, 50).await.unwrap();
+        assert_eq!(mock.quote_requests(), 3);
+    }
+    
+    #[test]
+    fn price_impact_parses_plain_and_scientific_formats() {
+        assert_eq!(parse_price_impact_pct("0.01").unwrap(), dec!(0.01));
+        assert_eq!(parse_price_impact_pct("0.0").unwrap(), dec!(0));
+        assert_eq!(parse_price_impact_pct("1.5").unwrap(), dec!(1.5));
+        assert_eq!(parse_price_impact_pct("0").unwrap(), dec!(0));
+        assert_eq!(parse_price_impact_pct("1.2e-5").unwrap(), dec!(0.000012));
+        assert!(parse_price_impact_pct("").is_err());
+        assert!(parse_price_impact_pct("high").is_err());
+    }
+    
+    #[tokio::test]
+    async fn api_key_is_sent_only_when_configured() {
+        let mock = MockJupiter::start(jupiter_mock::normal_quote()).await;
+        
+        mock_client(&mock).get_quote(SOL_MINT, USDC_MINT, 1_000_000_000, 50).await.unwrap();
+        assert_eq!(mock.last_api_key(), None);
+        
+        mock_client(&mock).with_api_key("test-key")
+            .get_quote(SOL_MINT, USDC_MINT, 1_000_000_000, 50).await.unwrap();
+        assert_eq!(mock.last_api_key().as_deref(), Some("test-key"));
+    }
+    
+    #[test]
+    fn slippage_is_the_shortfall_from_the_quote_in_bps() {
+        assert_eq!(slippage_bps(dec!(150), dec!(149.25)), Some(dec!(50)));
+        assert_eq!(slippage_bps(dec!(150), dec!(150)), Some(dec!(0)));
+        // Receiving more than quoted is negative slippage
+        assert_eq!(slippage_bps(dec!(150), dec!(150.3)), Some(dec!(-20)));
+        assert_eq!(slippage_bps(dec!(0), dec!(1)), None);
+    }
+    
+    #[tokio::test]
+    async fn platform_fee_fields_are_only_sent_when_configured() {
+        let mock = MockJupiter::start(jupiter_mock::normal_quote()).await;
+        let client = mock_client(&mock);
+        let quote = client.get_quote(SOL_MINT, USDC_MINT, 1_000_000_000, 50).await.unwrap();
+        
+        client.get_swap_transaction(&Pubkey::new_unique(), quote, 0, None).await.unwrap();
+        assert!(mock.last_swap_request().unwrap().get("feeAccount").is_none());
+        
+        let quote_request = |platform_fee_bps| serde_json::to_value(QuoteRequest {
+            input_mint: SOL_MINT.to_string(),
+            output_mint: USDC_MINT.to_string(),
+            amount: "1000000000".to_string(),
+            slippage_bps: 50,
+            platform_fee_bps,
+        }).unwrap();
+        assert!(quote_request(None).get("platformFeeBps").is_none());
+        assert_eq!(quote_request(Some(20))["platformFeeBps"], 20);
+    }
+    
+    #[test]
+    fn fills_just_above_the_minimum_out_are_suspicious() {
+        // otherAmountThreshold of 149.25 USDC with a 10 bps margin flags anything up to 149.39925
+        let min_out = dec!(149.25);
+        let cases = [
+            (dec!(149.25), true),
+            (dec!(149.3), true),
+            (dec!(149.39925), true),
+            (dec!(149.4), false),
+            (dec!(150), false),
+        ];
+        for (received, suspicious) in cases {
+            assert_eq!(is_suspicious_fill(min_out, received, 10), suspicious, "received {}", received);
+        }
+        
+        assert!(!is_suspicious_fill(dec!(0), dec!(0), 10));
+        // With no margin only a fill at the minimum itself is flagged
+        assert!(is_suspicious_fill(min_out, min_out, 0));
+        assert!(!is_suspicious_fill(min_out, dec!(149.26), 0));
+    }
+    
+    #[test]
+    fn limit_order_requests_serialize_for_the_trigger_api() {
+        let maker = Pubkey::new_unique();
+        
+        // Sell 1 SOL for at least 160 USDC within the hour
+        let request = CreateOrderRequest::new(&maker, SOL_MINT, USDC_MINT, 1_000_000_000, 160_000_000, Some(1_714_525_200));
+        assert_eq!(serde_json::to_value(&request).unwrap(), serde_json::json!({
+            "inputMint": SOL_MINT,
+            "outputMint": USDC_MINT,
+            "maker": maker.to_string(),
+            "payer": maker.to_string(),
+            "params": { "makingAmount": "1000000000", "takingAmount": "160000000", "expiredAt": "1714525200" },
+            "computeUnitPrice": "auto",
+            "wrapAndUnwrapSol": true,
+        }));
+        
+        let request = CreateOrderRequest::new(&maker, SOL_MINT, USDC_MINT, 1_000_000_000, 160_000_000, None);
+        assert!(serde_json::to_value(&request).unwrap()["params"].get("expiredAt").is_none());
+        
+        let cancel = CancelOrderRequest { maker: maker.to_string(), order: "Order1".to_string(), compute_unit_price: "auto".to_string() };
+        assert_eq!(serde_json::to_value(&cancel).unwrap(), serde_json::json!({
+            "maker": maker.to_string(),
+            "order": "Order1",
+            "computeUnitPrice": "auto",
+        }));
+    }
+    
+    #[test]
+    fn simulation_logs_keep_the_last_lines() {
+        assert_eq!(simulation_logs(&[]), "(none)");
+        
+        let logs: Vec<String> = (1..=25).map(|line| format!("line {}", line)).collect();
+        let kept = simulation_logs(&logs);
+        assert!(kept.starts_with("(5 earlier lines omitted)\nline 6\n"), "{}", kept);
+        assert!(kept.ends_with("\nline 25"), "{}", kept);
+        assert_eq!(simulation_logs(&logs[..2]), "line 1\nline 2");
+    }
+    
+    #[tokio::test(flavor = "multi_thread")]
+    async fn failed_simulation_surfaces_the_program_logs() {
+        // The client asks for the node version before its first simulation
+        let app = axum::Router::new().route("/", axum::routing::post(|axum::Json(request): axum::Json<serde_json::Value>| async move {
+            let result = match request["method"].as_str() {
+                Some("getVersion") => serde_json::json!({ "solana-core": "1.18.26", "feature-set": 0 }),
+                _ => serde_json::json!({
+                    "context": { "slot": 1 },
+                    "value": {
+                        "err": { "InstructionError": [0, { "Custom": 6001 }] },
+                        "logs": ["Program JUP6 invoke [1]", "Program log: Error: SlippageToleranceExceeded"],
+                        "accounts": null,
+                        "unitsConsumed": 1000,
+                        "returnData": null
+                    }
+                }),
+            };
+            axum::Json(serde_json::json!({ "jsonrpc": "2.0", "result": result, "id": request["id"] }))
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        
+        let payer = solana_sdk::signature::Keypair::new();
+        let transaction = solana_sdk::system_transaction::transfer(&payer, &Pubkey::new_unique(), 1, Default::default());
+        let err = simulate_transaction(&RpcClient::new(url), &transaction).unwrap_err();
+        
+        match err.downcast_ref::<TradingError>() {
+            Some(TradingError::SimulationFailed { error, logs }) => {
+                assert!(error.contains("custom program error: 0x1771"), "{}", error);
+                assert_eq!(logs, "Program JUP6 invoke [1]\nProgram log: Error: SlippageToleranceExceeded");
+            }
+            _ => panic!("unexpected error: {:#}", err),
+        }
+    }
+    
+    #[tokio::test]
+    async fn dynamic_compute_unit_limit_is_only_requested_without_a_configured_limit() {
+        let mock = MockJupiter::start(jupiter_mock::normal_quote()).await;
+        let client = mock_client(&mock);
+        let quote = client.get_quote(SOL_MINT, USDC_MINT, 1_000_000_000, 50).await.unwrap();
+        
+        for (compute_unit_limit, dynamic) in [(None, true), (Some(300_000), false)] {
+            client.get_swap_transaction(&Pubkey::new_unique(), quote.clone(), 0, compute_unit_limit).await.unwrap();
+            assert_eq!(mock.last_swap_request().unwrap()["dynamicComputeUnitLimit"], dynamic, "limit {:?}", compute_unit_limit);
+        }
+    }
+    
+    #[test]
+    fn configured_limit_overwrites_the_compute_budget_instruction() {
+        let account_keys = [Pubkey::new_unique(), compute_budget::id()];
+        let instruction = |data: Vec<u8>| CompiledInstruction { program_id_index: 1, accounts: vec![], data };
+        let price = ComputeBudgetInstruction::set_compute_unit_price(10_000).data;
+        let mut instructions = [
+            instruction(ComputeBudgetInstruction::set_compute_unit_limit(1_400_000).data),
+            instruction(price.clone()),
+        ];
+        
+        set_compute_unit_limit(&account_keys, &mut instructions, 300_000);
+        
+        assert_eq!(instructions[0].data, ComputeBudgetInstruction::set_compute_unit_limit(300_000).data);
+        assert_eq!(instructions[1].data, price);
+    }
+}
@@ -0,0 +1,146 @@
+// Local stand-in for the Jupiter API, serving canned /quote and /swap responses to tests
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+pub const SOL_MINT: &str = "So11111111111111111111111111111111111111112";
+pub const USDC_MINT: &str = "EPjFWdd5AufqSSqeM2qJxdzDbmoMjFxnnVB7mKBGzNGs";
+
+// Any base64 payload will do, get_swap_transaction passes it through undecoded
+pub const SWAP_TRANSACTION: &str = "AQIDBA==";
+
+// 1 SOL -> 150 USDC through a single deep pool
+pub fn normal_quote() -> Value {
+    quote("150000000", "0.0012")
+}
+
+// The same swap through a thin pool, well past any sane max_price_impact_pct
+pub fn high_impact_quote() -> Value {
+    quote("120000000", "20.5")
+}
+
+fn quote(out_amount: &str, price_impact_pct: &str) -> Value {
+    json!({
+        "inputMint": SOL_MINT,
+        "inAmount": "1000000000",
+        "outputMint": USDC_MINT,
+        "outAmount": out_amount,
+        "otherAmountThreshold": out_amount,
+        "swapMode": "ExactIn",
+        "slippageBps": 50,
+        "priceImpactPct": price_impact_pct,
+        "routePlan": [{
+            "swapInfo": {
+                "ammKey": "HJPjoWUrhoZzkNfRpHuieeFk9WcZWjwy6PBjZ81ngndJ",
+                "label": "Whirlpool",
+                "inputMint": SOL_MINT,
+                "outputMint": USDC_MINT,
+                "inAmount": "1000000000",
+                "outAmount": out_amount,
+                "feeAmount": "2500",
+                "feeMint": SOL_MINT
+            },
+            "percent": 100
+        }]
+    })
+}
+
+struct MockState {
+    quote: Value,
+    // Requests still to be answered with 429 before the canned responses
+    rate_limited: AtomicU32,
+    quote_requests: AtomicU32,
+    swap_requests: AtomicU32,
+    last_swap_request: Mutex<Option<Value>>,
+    last_api_key: Mutex<Option<String>>,
+}
+
+pub struct MockJupiter {
+    url: String,
+    state: Arc<MockState>,
+}
+
+impl MockJupiter {
+    // Serve `quote` from /quote on a random local port until the test's runtime shuts down
+    pub async fn start(quote: Value) -> Self {
+        let state = Arc::new(MockState {
+            quote,
+            rate_limited: AtomicU32::new(0),
+            quote_requests: AtomicU32::new(0),
+            swap_requests: AtomicU32::new(0),
+            last_swap_request: Mutex::new(None),
+            last_api_key: Mutex::new(None),
+        });
+        let app = Router::new()
+            .route("/quote", get(handle_quote))
+            .route("/swap", post(handle_swap))
+            .with_state(state.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await
+            .expect("Failed to bind mock Jupiter server");
+        let url = format!("http://{}", listener.local_addr().expect("Mock server has no address"));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        Self { url, state }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    // Answer the next `count` requests with 429 and Retry-After: 0
+    pub fn rate_limit_next(&self, count: u32) {
+        self.state.rate_limited.store(count, Ordering::SeqCst);
+    }
+
+    pub fn quote_requests(&self) -> u32 {
+        self.state.quote_requests.load(Ordering::SeqCst)
+    }
+
+    pub fn swap_requests(&self) -> u32 {
+        self.state.swap_requests.load(Ordering::SeqCst)
+    }
+
+    pub fn last_swap_request(&self) -> Option<Value> {
+        self.state.last_swap_request.lock().unwrap().clone()
+    }
+
+    // x-api-key sent with the most recent /quote request
+    pub fn last_api_key(&self) -> Option<String> {
+        self.state.last_api_key.lock().unwrap().clone()
+    }
+}
+
+fn take_rate_limit(state: &MockState) -> Option<Response> {
+    state.rate_limited
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| left.checked_sub(1))
+        .ok()
+        .map(|_| (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, "0")], "Rate limit exceeded").into_response())
+}
+
+async fn handle_quote(State(state): State<Arc<MockState>>, headers: HeaderMap) -> Response {
+    state.quote_requests.fetch_add(1, Ordering::SeqCst);
+    *state.last_api_key.lock().unwrap() = headers.get("x-api-key")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    if let Some(response) = take_rate_limit(&state) {
+        return response;
+    }
+    Json(state.quote.clone()).into_response()
+}
+
+async fn handle_swap(State(state): State<Arc<MockState>>, Json(request): Json<Value>) -> Response {
+    state.swap_requests.fetch_add(1, Ordering::SeqCst);
+    if let Some(response) = take_rate_limit(&state) {
+        return response;
+    }
+    *state.last_swap_request.lock().unwrap() = Some(request);
+    Json(json!({ "swapTransaction": SWAP_TRANSACTION })).into_response()
+}
@@ -1,81 +1,275 @@
-use chrono::{FixedOffset, TimeZone};
-use chrono_tz::Asia::Tokyo;
 use rust_decimal::prelude::*;
 use rust_decimal_macros::dec;
+use crate::clock::format_local;
+use crate::config::TradingPair;
 use crate::firestore::FirestoreDb;
+use crate::error::notification_title;
+use crate::service::{redact, retry_as_exponential_back_off};
 use crate::trading::TradingState;
 
 use anyhow::{Result, Context};
-use serde::Serialize;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use serde_json::{json, Value as JsonValue};
 use std::sync::Arc;
+use std::time::Duration;
 use tracing::{info, error};
 
 #[derive(Debug, Serialize)]
-struct Message {
-    #[serde(rename = "type")]
-    message_type: String,
-    text: String,
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Message {
+    Text {
+        text: String,
+    },
+    Flex {
+        // Shown in notifications and chat lists, where the card itself isn't rendered
+        #[serde(rename = "altText")]
+        alt_text: String,
+        contents: JsonValue,
+    },
+}
+
+const LINE_API_BASE: &str = "https://api.line.me";
+const PUSH_PATH: &str = "/v2/bot/message/push";
+const MULTICAST_PATH: &str = "/v2/bot/message/multicast";
+const REPLY_PATH: &str = "/v2/bot/message/reply";
+
+// Most user IDs LINE accepts in one multicast request
+pub const MAX_MULTICAST_RECIPIENTS: usize = 500;
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum Recipients {
+    // A push takes a single user ID
+    One(String),
+    // A multicast takes a list of them
+    Many(Vec<String>),
 }
 
 #[derive(Debug, Serialize)]
 struct PushMessage {
-    to: String,
+    to: Recipients,
     messages: Vec<Message>,
 }
 
+// Push to a single user, multicast to several
+fn push_request(user_ids: &[String], message: Message) -> (&'static str, PushMessage) {
+    let (path, to) = match user_ids {
+        [user_id] => (PUSH_PATH, Recipients::One(user_id.clone())),
+        _ => (MULTICAST_PATH, Recipients::Many(user_ids.to_vec())),
+    };
+    (path, PushMessage { to, messages: vec![message] })
+}
+
+#[derive(Debug, Serialize)]
+struct ReplyMessage {
+    #[serde(rename = "replyToken")]
+    reply_token: String,
+    messages: Vec<Message>,
+}
+
+// Body of a webhook request, fields this bot doesn't use are ignored
+#[derive(Debug, Deserialize)]
+pub struct WebhookRequest {
+    pub events: Vec<WebhookEvent>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookEvent {
+    #[serde(rename = "type")]
+    pub event_type: String,
+    // Only message events and a few others carry a reply token
+    pub reply_token: Option<String>,
+    pub source: Option<EventSource>,
+    pub message: Option<EventMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventSource {
+    pub user_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventMessage {
+    #[serde(rename = "type")]
+    pub message_type: String,
+    pub text: Option<String>,
+}
+
+impl WebhookEvent {
+    // Text of a text message event, None for anything else
+    pub fn text(&self) -> Option<&str> {
+        match &self.message {
+            Some(message) if self.event_type == "message" && message.message_type == "text" => message.text.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+// Commands understood in chat, matched case-insensitively
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatCommand {
+    Status,
+    Performance,
+    Help,
+}
+
+impl ChatCommand {
+    // Anything unrecognised gets the help text
+    pub fn parse(text: &str) -> Self {
+        match text.trim().to_lowercase().as_str() {
+            "status" => Self::Status,
+            "performance" => Self::Performance,
+            _ => Self::Help,
+        }
+    }
+}
+
+pub const CHAT_HELP: &str = "Send \"status\" for the current position and P/L, or \"performance\" for the last 30 days";
+
+// Whether `signature` (the X-Line-Signature header) is the base64 HMAC-SHA256 of the body under the channel secret
+pub fn verify_signature(channel_secret: &str, body: &[u8], signature: &str) -> bool {
+    let Ok(signature) = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, signature) else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(channel_secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    // verify_slice compares in constant time
+    mac.verify_slice(&signature).is_ok()
+}
+
 pub struct LineClient {
     client: reqwest::Client,
+    api_base: String,
     channel_token: String,
-    user_id: String
+    user_ids: Vec<String>,
+    dry_run: bool,
 }
 
 impl LineClient {
-    pub fn new(channel_token: &str, user_id: &str) -> Self {
+    pub fn new(channel_token: &str, user_ids: &[String]) -> Self {
         Self {
-            client: reqwest::Client::new(),
+            client: Self::build_client(Duration::from_secs(10)),
+            api_base: LINE_API_BASE.to_string(),
             channel_token: channel_token.to_string(),
-            user_id: user_id.to_string(),
+            user_ids: user_ids.to_vec(),
+            dry_run: false,
         }
     }
     
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+    
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.client = Self::build_client(timeout);
+        self
+    }
+    
+    // Point the client at a local server instead of the LINE API
+    #[cfg(test)]
+    pub fn with_api_base(mut self, api_base: &str) -> Self {
+        self.api_base = api_base.trim_end_matches('/').to_string();
+        self
+    }
+    
+    fn build_client(timeout: Duration) -> reqwest::Client {
+        reqwest::Client::builder()
+            .timeout(timeout)
+            .connect_timeout(timeout)
+            .build()
+            .expect("Failed to build HTTP client")
+    }
+    
     pub async fn send_message(&self, text: &str) -> Result<()> {
-        let message = Message {
-            message_type: "text".to_string(),
-            text: text.to_string(),
-        };
+        self.push(Message::Text {
+            text: if self.dry_run { format!("[DRY RUN] {}", text) } else { text.to_string() },
+        }).await
+    }
+    
+    // `contents` is a Flex Message container such as the bubble from trade_flex_bubble
+    pub async fn send_flex_message(&self, alt_text: &str, contents: JsonValue) -> Result<()> {
+        self.push(Message::Flex {
+            alt_text: if self.dry_run { format!("[DRY RUN] {}", alt_text) } else { alt_text.to_string() },
+            contents,
+        }).await
+    }
+    
+    async fn push(&self, message: Message) -> Result<()> {
+        let (path, push_message) = push_request(&self.user_ids, message);
+        let url = format!("{}{}", self.api_base, path);
+        
+        retry_as_exponential_back_off(
+            || async {
+                let response = self.client
+                    .post(&url)
+                    .header("Content-Type", "application/json")
+                    .header("Authorization", format!("Bearer {}", self.channel_token))
+                    .json(&push_message)
+                    .send()
+                    .await
+                    .context("Failed to send LINE message")?;
+                
+                if !response.status().is_success() {
+                    let error_text = redact(&response.text().await?);
+                    error!("LINE API error: {}", error_text);
+                    anyhow::bail!("Failed to send LINE message: {}", error_text);
+                }
+                
+                Ok::<(), anyhow::Error>(())
+            },
+            "Send LINE message",
+            3,
+            500,
+            None,
+            None,
+        )
+        .await?;
         
-        let push_message = PushMessage {
-            to: self.user_id.to_string(),
-            messages: vec![message],
+        info!("LINE message sent successfully");
+        Ok(())
+    }
+
+    // Reply tokens are single-use, so unlike pushes a failed reply isn't retried
+    pub async fn reply_message(&self, reply_token: &str, text: &str) -> Result<()> {
+        let reply_message = ReplyMessage {
+            reply_token: reply_token.to_string(),
+            messages: vec![Message::Text { text: text.to_string() }],
         };
         
         let response = self.client
-            .post("https://api.line.me/v2/bot/message/push")
+            .post(format!("{}{}", self.api_base, REPLY_PATH))
             .header("Content-Type", "application/json")
             .header("Authorization", format!("Bearer {}", self.channel_token))
-            .json(&push_message)
+            .json(&reply_message)
             .send()
             .await
-            .context("Failed to send LINE message")?;
+            .context("Failed to send LINE reply")?;
         
         if !response.status().is_success() {
-            let error_text = response.text().await?;
+            let error_text = redact(&response.text().await?);
             error!("LINE API error: {}", error_text);
-            anyhow::bail!("Failed to send LINE message: {}", error_text);
+            anyhow::bail!("Failed to send LINE reply: {}", error_text);
         }
         
-        info!("LINE message sent successfully");
+        info!("LINE reply sent successfully");
         Ok(())
     }
 
-    pub async fn send_daily_high_and_low_sol_prices(
+    pub async fn send_daily_high_and_low_prices(
         &self,
-        state: &TradingState,
-        db: &Arc<FirestoreDb>) -> anyhow::Result<()> {
-        let price_history = db.get_price_history(24).await?;
+        db: &Arc<FirestoreDb>,
+        pair: &TradingPair) -> anyhow::Result<()> {
+        let price_history = db.get_price_history(&pair.name, 24).await?;
         
         if price_history.is_empty() {
-            info!("No price history available for the last 24 hours.");
+            info!("No {} price history available for the last 24 hours.", pair.name);
             return Ok(());
         }
         
@@ -89,20 +283,11 @@ impl LineClient {
             low_price = min;
         }
         
-        let last_trade_price = state.last_trade_price.unwrap_or(dec!(0));
+        let last_trade_price = db.get_latest_trading_session(&pair.name).await?
+            .map(|session| session.price_at_trade)
+            .unwrap_or(dec!(0));
 
-        let message = format!(
-            "📈 Daily SOL Price Update\n\n\
-            High: {:.4}\n\
-            Low: {:.4}\n\
-            Last Trade Price: {:.4}\n\
-            Time: {}",
-            high_price * dec!(1_000_000_000),
-            low_price * dec!(1_000_000_000),
-            last_trade_price * dec!(1_000_000_000),
-            Tokyo.from_utc_datetime(&chrono::Utc::now().naive_utc()).with_timezone(&FixedOffset::east_opt(9 * 3600).unwrap()).format("%Y-%m-%d %H:%M:%S JST")
-        );
-        
+        let message = daily_price_message(&pair.name, high_price, low_price, last_trade_price, &format_local(&chrono::Utc::now()));
         self.send_message(&message).await
     }
     
@@ -111,37 +296,325 @@ impl LineClient {
         state: &TradingState,
         profit: Decimal,
     ) -> anyhow::Result<()> {
-
-        let trade_price = state.last_trade_price.unwrap_or(dec!(0));
-        let message = format!(
-            "😎 Trade executed!\n\
-            Position: {}\n\
-            Trade Price: {:.4} USDC\n\
-            Profit: {:.4} USDC\n\
-            Time: {}",
-            state.position,
-            trade_price * dec!(1_000_000_000),
-            profit,
-            Tokyo.from_utc_datetime(&chrono::Utc::now().naive_utc()).with_timezone(&FixedOffset::east_opt(9 * 3600).unwrap()).format("%Y-%m-%d %H:%M:%S JST")
-        );
-        info!("{}", message);
-        self.send_message(&message).await
+        let time = format_local(&chrono::Utc::now());
+        let alt_text = trade_alt_text(state, profit);
+        info!("{}", alt_text);
+        self.send_flex_message(&alt_text, trade_flex_bubble(state, profit, &time)).await
     }
     
     pub async fn send_error_notification(
         &self,
         e: &anyhow::Error,
     ) -> Result<()> {
+        let title = notification_title(e);
         let message = format!(
-            "🥺 Trading error...\n\
+            "{}\n\
             {}\n\
             Time: {}",
+            title,
             e,
-            Tokyo.from_utc_datetime(&chrono::Utc::now().naive_utc()).with_timezone(&FixedOffset::east_opt(9 * 3600).unwrap()).format("%Y-%m-%d %H:%M:%S JST")
+            format_local(&chrono::Utc::now())
         );
         error!("Trading error: {}", e);
         self.send_message(&message).await
     }
     
     
-}
\ No newline at end of file
+}
+
+fn daily_price_message(pair: &str, high_price: Decimal, low_price: Decimal, last_trade_price: Decimal, time: &str) -> String {
+    format!(
+        "📈 Daily {} Price Update\n\n\
+        High: {:.4}\n\
+        Low: {:.4}\n\
+        Last Trade Price: {:.4}\n\
+        Time: {}",
+        pair,
+        high_price,
+        low_price,
+        last_trade_price,
+        time
+    )
+}
+
+// Shown in place of the trade bubble in notifications and chat lists
+fn trade_alt_text(state: &TradingState, profit: Decimal) -> String {
+    format!(
+        "😎 Trade executed! {} now in {}, profit {}",
+        state.pair.name,
+        state.position_symbol(),
+        state.format_profit(profit),
+    )
+}
+
+// Flex Message bubble for a completed trade: a green header for a profit (red for a loss),
+// the trade details, and a button to the transaction when there is one (not in dry run)
+fn trade_flex_bubble(state: &TradingState, profit: Decimal, time: &str) -> JsonValue {
+    let quote_symbol = state.pair.quote_symbol();
+    let header_color = if profit >= dec!(0) { "#1DB446" } else { "#E53935" };
+    let row = |label: &str, value: String| json!({
+        "type": "box",
+        "layout": "horizontal",
+        "contents": [
+            { "type": "text", "text": label, "size": "sm", "color": "#888888", "flex": 2 },
+            { "type": "text", "text": value, "size": "sm", "color": "#111111", "align": "end", "flex": 3, "wrap": true },
+        ],
+    });
+    
+    let mut bubble = json!({
+        "type": "bubble",
+        "header": {
+            "type": "box",
+            "layout": "vertical",
+            "backgroundColor": header_color,
+            "contents": [
+                { "type": "text", "text": "Trade executed", "weight": "bold", "color": "#FFFFFF", "size": "lg" },
+                { "type": "text", "text": state.pair.name.clone(), "color": "#FFFFFF", "size": "sm" },
+            ],
+        },
+        "body": {
+            "type": "box",
+            "layout": "vertical",
+            "spacing": "sm",
+            "contents": [
+                row("Position", state.position_symbol().to_string()),
+                row("Price", format!("{:.4} {}", state.last_trade_price.unwrap_or(dec!(0)), quote_symbol)),
+                row("Profit", state.format_profit(profit)),
+                row("Total", state.format_profit(state.total_profit)),
+                row("Time", time.to_string()),
+            ],
+        },
+    });
+    
+    if let Some(url) = &state.last_explorer_url {
+        bubble["footer"] = json!({
+            "type": "box",
+            "layout": "vertical",
+            "contents": [
+                {
+                    "type": "button",
+                    "style": "link",
+                    "action": { "type": "uri", "label": "View transaction", "uri": url },
+                },
+            ],
+        });
+    }
+    
+    bubble
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    
+    fn test_state() -> TradingState {
+        TradingState::new(test_config().pairs[0].clone())
+    }
+    
+    #[test]
+    fn trade_bubble_links_the_transaction_when_signed() {
+        let config = test_config();
+        let mut state = test_state();
+        state.last_signature = Some("5sig".to_string());
+        state.last_explorer_url = state.last_signature.as_deref().map(|signature| config.explorer_url(signature));
+        
+        let bubble = trade_flex_bubble(&state, dec!(1), "2024-01-01 09:00:00");
+        assert_eq!(bubble["footer"]["contents"][0]["action"]["uri"], "https://solscan.io/tx/5sig");
+        
+        // A dry run has no signature, so no button
+        let bubble = trade_flex_bubble(&test_state(), dec!(1), "2024-01-01 09:00:00");
+        assert!(bubble.get("footer").is_none());
+    }
+    
+    fn line_signature(channel_secret: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(channel_secret.as_bytes()).unwrap();
+        mac.update(body);
+        base64::Engine::encode(&base64::engine::general_purpose::STANDARD, mac.finalize().into_bytes())
+    }
+    
+    #[test]
+    fn webhook_signature_must_match_the_body_and_secret() {
+        let body = br#"{"events":[]}"#;
+        let signature = line_signature("channel-secret", body);
+        
+        assert!(verify_signature("channel-secret", body, &signature));
+        assert!(!verify_signature("channel-secret", br#"{"events":[{}]}"#, &signature));
+        assert!(!verify_signature("other-secret", body, &signature));
+        assert!(!verify_signature("channel-secret", body, "not base64!"));
+        assert!(!verify_signature("channel-secret", body, ""));
+    }
+    
+    #[test]
+    fn text_messages_dispatch_to_chat_commands() {
+        let request: WebhookRequest = serde_json::from_value(json!({
+            "destination": "U0",
+            "events": [
+                {
+                    "type": "message",
+                    "replyToken": "reply-1",
+                    "source": { "type": "user", "userId": "U1" },
+                    "message": { "type": "text", "id": "1", "text": "  Status " }
+                },
+                {
+                    "type": "message",
+                    "replyToken": "reply-2",
+                    "source": { "type": "user", "userId": "U1" },
+                    "message": { "type": "sticker", "id": "2" }
+                },
+                { "type": "follow", "replyToken": "reply-3", "source": { "type": "user", "userId": "U2" } }
+            ]
+        }))
+        .unwrap();
+        
+        let texts: Vec<Option<&str>> = request.events.iter().map(WebhookEvent::text).collect();
+        assert_eq!(texts, [Some("  Status "), None, None]);
+        assert_eq!(request.events[0].reply_token.as_deref(), Some("reply-1"));
+        assert_eq!(request.events[0].source.as_ref().and_then(|source| source.user_id.as_deref()), Some("U1"));
+        
+        assert_eq!(ChatCommand::parse("  Status "), ChatCommand::Status);
+        assert_eq!(ChatCommand::parse("PERFORMANCE"), ChatCommand::Performance);
+        assert_eq!(ChatCommand::parse("help"), ChatCommand::Help);
+        assert_eq!(ChatCommand::parse("buy everything"), ChatCommand::Help);
+    }
+    
+    #[test]
+    fn one_recipient_is_pushed_and_several_are_multicast() {
+        let text = || Message::Text { text: "hi".to_string() };
+        
+        let (path, request) = push_request(&["U1".to_string()], text());
+        assert_eq!(path, PUSH_PATH);
+        assert_eq!(serde_json::to_value(&request).unwrap(), json!({
+            "to": "U1",
+            "messages": [{ "type": "text", "text": "hi" }],
+        }));
+        
+        let (path, request) = push_request(&["U1".to_string(), "U2".to_string()], text());
+        assert_eq!(path, MULTICAST_PATH);
+        assert_eq!(serde_json::to_value(&request).unwrap(), json!({
+            "to": ["U1", "U2"],
+            "messages": [{ "type": "text", "text": "hi" }],
+        }));
+    }
+    
+    #[test]
+    fn trade_flex_message_matches_the_expected_json() {
+        let mut state = test_state();
+        state.position = crate::trading::Position::Base;
+        state.last_trade_price = Some(dec!(152.34));
+        state.total_profit = dec!(3.5);
+        state.last_explorer_url = Some("https://solscan.io/tx/5sig".to_string());
+        let message = Message::Flex {
+            alt_text: trade_alt_text(&state, dec!(1.25)),
+            contents: trade_flex_bubble(&state, dec!(1.25), "2026-01-01 09:00:00 JST"),
+        };
+        
+        let row = |label: &str, value: &str| json!({
+            "type": "box",
+            "layout": "horizontal",
+            "contents": [
+                { "type": "text", "text": label, "size": "sm", "color": "#888888", "flex": 2 },
+                { "type": "text", "text": value, "size": "sm", "color": "#111111", "align": "end", "flex": 3, "wrap": true },
+            ],
+        });
+        assert_eq!(serde_json::to_value(&message).unwrap(), json!({
+            "type": "flex",
+            "altText": "😎 Trade executed! SOL/USDC now in SOL, profit 1.2500 USDC",
+            "contents": {
+                "type": "bubble",
+                "header": {
+                    "type": "box",
+                    "layout": "vertical",
+                    "backgroundColor": "#1DB446",
+                    "contents": [
+                        { "type": "text", "text": "Trade executed", "weight": "bold", "color": "#FFFFFF", "size": "lg" },
+                        { "type": "text", "text": "SOL/USDC", "color": "#FFFFFF", "size": "sm" },
+                    ],
+                },
+                "body": {
+                    "type": "box",
+                    "layout": "vertical",
+                    "spacing": "sm",
+                    "contents": [
+                        row("Position", "SOL"),
+                        row("Price", "152.3400 USDC"),
+                        row("Profit", "1.2500 USDC"),
+                        row("Total", "3.5000 USDC"),
+                        row("Time", "2026-01-01 09:00:00 JST"),
+                    ],
+                },
+                "footer": {
+                    "type": "box",
+                    "layout": "vertical",
+                    "contents": [
+                        {
+                            "type": "button",
+                            "style": "link",
+                            "action": { "type": "uri", "label": "View transaction", "uri": "https://solscan.io/tx/5sig" },
+                        },
+                    ],
+                },
+            },
+        }));
+        
+        // A loss turns the header red
+        let bubble = trade_flex_bubble(&state, dec!(-1), "2026-01-01 09:00:00 JST");
+        assert_eq!(bubble["header"]["backgroundColor"], "#E53935");
+    }
+    
+    #[test]
+    fn daily_update_lists_high_low_and_last_trade_prices() {
+        let message = daily_price_message("SOL/USDC", dec!(160), dec!(140), dec!(150), "2026-01-01 00:00:00 JST");
+        assert_eq!(
+            message,
+            "📈 Daily SOL/USDC Price Update\n\n\
+            High: 160.0000\n\
+            Low: 140.0000\n\
+            Last Trade Price: 150.0000\n\
+            Time: 2026-01-01 00:00:00 JST"
+        );
+    }
+    
+    #[test]
+    fn prices_are_shown_as_stored_to_four_places() {
+        let message = daily_price_message("SOL/USDC", dec!(152.34), dec!(148.5), dec!(0.5), "");
+        assert!(message.contains("High: 152.3400\n"), "{}", message);
+        assert!(message.contains("Low: 148.5000\n"), "{}", message);
+        assert!(message.contains("Last Trade Price: 0.5000\n"), "{}", message);
+        
+        let mut state = test_state();
+        state.last_trade_price = Some(dec!(152.34));
+        let bubble = trade_flex_bubble(&state, dec!(0), "");
+        assert_eq!(bubble["body"]["contents"][1]["contents"][1]["text"], "152.3400 USDC");
+    }
+    
+    #[tokio::test]
+    async fn hung_line_api_times_out_as_an_error() {
+        // Accepts connections but never answers them
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                connections.push(socket);
+            }
+        });
+        let client = LineClient::new("token", &["U1".to_string()])
+            .with_api_base(&api_base)
+            .with_timeout(Duration::from_millis(50));
+        
+        let err = tokio::time::timeout(Duration::from_secs(10), client.send_message("hi")).await
+            .expect("send_message hung past its timeout")
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("Failed to send LINE message"), "{:#}", err);
+    }
+    
+    #[test]
+    fn trade_alt_text_uses_the_reporting_currency() {
+        let state = test_state();
+        assert_eq!(trade_alt_text(&state, dec!(1.5)), "😎 Trade executed! SOL/USDC now in USDC, profit 1.5000 USDC");
+        
+        let state = test_state().with_reporting_currency("SOL");
+        assert_eq!(trade_alt_text(&state, dec!(-0.01)), "😎 Trade executed! SOL/USDC now in USDC, profit -0.0100 SOL");
+    }
+}
@@ -1,105 +1,791 @@
+mod backtest;
+mod clock;
 mod config;
+mod discord;
+mod error;
+mod export;
 mod firestore;
+mod heartbeat;
+mod indicators;
 mod jupiter;
+#[cfg(test)]
+mod jupiter_mock;
 mod line_bot;
+mod metrics;
+mod notifier;
+mod portfolio;
+mod price_source;
+mod result_webhook;
+mod rpc;
 mod service;
+mod telegram;
 mod trading;
 mod wallet;
 
 use anyhow::Result;
-use axum::{extract::Query, response::IntoResponse, routing::get, Json, Router};
+use axum::{
+    body::Bytes,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::net::SocketAddr;
-use std::sync::Arc;
-use tracing::{info, error};
-use chrono::Timelike;
-use chrono_tz::Asia::Tokyo;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::Duration;
+use tokio::{
+    sync::{broadcast, Mutex},
+    task::JoinSet,
+};
+use tracing::{info, error, warn};
+use tracing_subscriber::util::SubscriberInitExt;
+use chrono::{DateTime, FixedOffset, Timelike};
+use clock::now_local;
+use notifier::NotificationEvent;
 
+// Keep the broadcast buffer small so a slow WebSocket client can't hold many price points
+const PRICE_BROADCAST_CAPACITY: usize = 16;
+
+// Per-dependency limit for /health/deep
+const DEPENDENCY_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// CoinGecko only returns hourly prices for ranges of up to 90 days
+const MAX_BACKFILL_DAYS: u32 = 90;
+
+// Candles are bucketed within a local day, so one day is the widest interval
+const MAX_CANDLE_INTERVAL_MINUTES: u32 = 24 * 60;
+
+// Cloud Run kills the container 10 seconds after SIGTERM
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(8);
+
+#[derive(Clone)]
+struct AppState {
+    price_tx: broadcast::Sender<firestore::PriceHistory>,
+    trigger_secret: Option<String>,
+    line_channel_secret: Option<String>,
+    // Set while a triggered trade is running, so retried triggers don't trade twice
+    trade_in_progress: Arc<AtomicBool>,
+    // Spawned trade tasks, awaited on shutdown so a swap isn't cut off before its session is stored
+    trade_tasks: Arc<Mutex<JoinSet<()>>>,
+    // Trading state of each pair kept between triggers, so a Firestore outage doesn't reset it
+    trading_states: Arc<Mutex<HashMap<String, trading::TradingState>>>,
+    heartbeat: heartbeat::Heartbeat,
+}
+
+// LOG_FORMAT=json emits one JSON object per event, which Cloud Logging parses into structured entries
+fn init_tracing() {
+    // Config::from_env loads .env too, but LOG_FORMAT is needed before the config is
+    dotenvy::dotenv().ok();
+    let env_filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| "solana_trading_bot=info".into());
+    
+    let log_format = std::env::var("LOG_FORMAT").unwrap_or_else(|_| "text".to_string());
+    build_subscriber(&log_format, env_filter).init();
+    
+    if log_format != "json" && log_format != "text" {
+        warn!("Unknown LOG_FORMAT {}, using text", log_format);
+    }
+}
+
+fn build_subscriber(log_format: &str, env_filter: tracing_subscriber::EnvFilter) -> Box<dyn tracing::Subscriber + Send + Sync> {
+    match log_format {
+        "json" => Box::new(tracing_subscriber::fmt()
+            .json()
+            .with_env_filter(env_filter)
+            .with_target(true)
+            .with_current_span(true)
+            .with_span_list(true)
+            .finish()),
+        _ => Box::new(tracing_subscriber::fmt()
+            .with_env_filter(env_filter)
+            .finish()),
+    }
+}
+
+// Clears the in-progress flag when the trade task ends, even if it panics
+struct TradeInProgressGuard(Arc<AtomicBool>);
+
+impl TradeInProgressGuard {
+    // None while another trade holds the flag
+    fn acquire(flag: &Arc<AtomicBool>) -> Option<Self> {
+        flag.compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire).ok()?;
+        Some(Self(flag.clone()))
+    }
+}
+
+impl Drop for TradeInProgressGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "solana_trading_bot=info".into()),
-        )
-        .init();
+    init_tracing();
 
     // Load configuration
     let config = config::Config::from_env()?;
+    clock::set_timezone(config.timezone);
     info!("Configuration loaded successfully");
+    info!("=== Solana cluster: {} ===", config.cluster);
+    if config.cluster == config::Cluster::Mainnet {
+        warn!("Trading on MAINNET with real funds{}", if config.dry_run { " (dry run)" } else { "" });
+    }
+
+    let (price_tx, _) = broadcast::channel(PRICE_BROADCAST_CAPACITY);
+    if config.trigger_secret.is_none() {
+        warn!("TRIGGER_SECRET is not set, /trigger and the /admin routes will reject every request");
+    }
+    let state = AppState {
+        price_tx,
+        trigger_secret: config.trigger_secret.clone(),
+        line_channel_secret: config.line_channel_secret.clone(),
+        trade_in_progress: Arc::new(AtomicBool::new(false)),
+        trade_tasks: Arc::new(Mutex::new(JoinSet::new())),
+        trading_states: Arc::new(Mutex::new(HashMap::new())),
+        heartbeat: heartbeat::Heartbeat::load(&config).await,
+    };
+    let trade_tasks = state.trade_tasks.clone();
+    
+    if let Some(timeout_secs) = config.heartbeat_timeout_secs {
+        tokio::spawn(state.heartbeat.clone().watch(config.clone(), Duration::from_secs(timeout_secs)));
+    }
 
     // Start HTTP server
     let app = Router::new()
         .route("/", get(health_check))
         .route("/health", get(health_check))
+        .route("/health/deep", get(deep_health_check))
         .route("/trigger", get(trigger_trade))
         .route("/api/performance", get(get_performance))
         .route("/api/price-history", get(get_price_history))
-        .route("/api/trading-sessions", get(get_trading_sessions));
+        .route("/api/trading-sessions", get(get_trading_sessions))
+        .route("/api/trade-history", get(get_trade_history))
+        .route("/api/export", get(export_trades))
+        .route("/api/backtest", get(get_backtest))
+        .route("/api/profit-series", get(get_profit_series))
+        .route("/api/portfolio", get(get_portfolio))
+        .route("/api/current-price", get(get_current_price))
+        .route("/api/trends", get(get_trends))
+        .route("/api/volatility", get(get_volatility))
+        .route("/api/candles", get(get_candles))
+        .route("/api/position", get(get_position))
+        .route("/api/preview-swap", get(preview_swap))
+        .route("/ws/prices", get(stream_prices))
+        .route("/metrics", get(get_metrics))
+        .route("/admin/reset-circuit", post(reset_circuit))
+        .route("/admin/force-trade", post(force_trade))
+        .route("/admin/backfill", post(backfill_prices))
+        .route("/webhook/line", post(line_webhook))
+        .with_state(state);
 
     let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
     info!("Starting server on {}", addr);
 
     let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, app).await?;
+    
+    if config.send_startup_notification {
+        let notifiers = notifier::build_notifiers(&config);
+        let summary = config.startup_summary();
+        tokio::spawn(async move {
+            for notifier in &notifiers {
+                if let Err(e) = notifier.send_message(&summary).await {
+                    error!("Failed to send startup notification: {}", e);
+                }
+            }
+        });
+    }
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    wait_for_trade_tasks(&trade_tasks).await;
 
     Ok(())
 }
 
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl+C: {}", e);
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    info!("Shutdown signal received, no longer accepting requests");
+}
+
+async fn wait_for_trade_tasks(trade_tasks: &Mutex<JoinSet<()>>) {
+    let mut tasks = trade_tasks.lock().await;
+    let mut awaited = 0;
+
+    let wait_all = async {
+        while tasks.join_next().await.is_some() {
+            awaited += 1;
+        }
+    };
+
+    let result = tokio::time::timeout(SHUTDOWN_TIMEOUT, wait_all).await;
+    match result {
+        Ok(()) => info!("Awaited {} trade tasks before shutdown", awaited),
+        Err(_) => warn!(
+            "Timed out after {:?} waiting for trade tasks, awaited {} and abandoned {}",
+            SHUTDOWN_TIMEOUT, awaited, tasks.len()
+        ),
+    }
+}
+
 async fn health_check() -> impl IntoResponse {
     "OK"
 }
 
-async fn trigger_trade() -> impl IntoResponse {
+#[derive(Serialize)]
+struct DependencyStatus {
+    name: &'static str,
+    healthy: bool,
+    latency_ms: u128,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DeepHealthResponse {
+    healthy: bool,
+    dependencies: Vec<DependencyStatus>,
+}
+
+// Readiness check: the bot can only trade when the RPC node, Jupiter and Firestore are all reachable
+async fn deep_health_check() -> impl IntoResponse {
+    let dependencies = match config::Config::from_env() {
+        Ok(config) => check_dependencies(&config).await,
+        Err(e) => vec![DependencyStatus {
+            name: "config",
+            healthy: false,
+            latency_ms: 0,
+            error: Some(e.to_string()),
+        }],
+    };
+    
+    let (status, response) = aggregate_health(dependencies);
+    (status, Json(response))
+}
+
+async fn check_dependencies(config: &config::Config) -> Vec<DependencyStatus> {
+    let rpc = check_dependency("rpc", async {
+        // Healthy when any endpoint is, since trades fail over to the others
+        rpc::connect_with_timeout(config, DEPENDENCY_CHECK_TIMEOUT)?;
+        Ok(())
+    });
+    let jupiter = check_dependency("jupiter", async {
+        let pair = &config.pairs[0];
+        jupiter::JupiterClient::shared(config)
+            .get_quote(&pair.base_mint, &pair.quote_mint, wallet::ui_to_raw_amount(Decimal::ONE, pair.base_decimals), 0)
+            .await?;
+        Ok(())
+    });
+    let firestore = check_dependency("firestore", async {
+        firestore::FirestoreDb::from_config(config).await?.ping().await
+    });
+    
+    let (rpc, jupiter, firestore) = tokio::join!(rpc, jupiter, firestore);
+    vec![rpc, jupiter, firestore]
+}
+
+async fn check_dependency<F>(name: &'static str, check: F) -> DependencyStatus
+where
+    F: std::future::Future<Output = Result<()>>,
+{
+    let started = std::time::Instant::now();
+    let result = match tokio::time::timeout(DEPENDENCY_CHECK_TIMEOUT, check).await {
+        Ok(result) => result,
+        Err(_) => Err(anyhow::anyhow!("timed out after {:?}", DEPENDENCY_CHECK_TIMEOUT)),
+    };
+    
+    if let Err(e) = &result {
+        warn!("Health check for {} failed: {}", name, e);
+    }
+    DependencyStatus {
+        name,
+        healthy: result.is_ok(),
+        latency_ms: started.elapsed().as_millis(),
+        error: result.err().map(|e| e.to_string()),
+    }
+}
+
+// Every dependency is needed to trade, so any failure makes the service unhealthy
+fn aggregate_health(dependencies: Vec<DependencyStatus>) -> (StatusCode, DeepHealthResponse) {
+    let healthy = dependencies.iter().all(|dependency| dependency.healthy);
+    let status = if healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, DeepHealthResponse { healthy, dependencies })
+}
+
+#[derive(Deserialize)]
+struct TriggerQuery {
+    token: Option<String>,
+}
+
+async fn trigger_trade(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<TriggerQuery>,
+) -> impl IntoResponse {
+    if !is_authorized(&state, &headers, params.token.as_deref()) {
+        warn!("Rejected trade trigger with missing or invalid token");
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    }
+    
+    // Count the trigger even if a trade is already running, the scheduler is still alive
+    state.heartbeat.record_trigger().await;
+    
+    let Some(guard) = TradeInProgressGuard::acquire(&state.trade_in_progress) else {
+        warn!("Rejected trade trigger while a trade is already in progress");
+        return (StatusCode::CONFLICT, "Trade already in progress").into_response();
+    };
+    
     info!("Trade trigger received");
     
-    // Spawn a task to handle the trade
-    tokio::spawn(async {
-        if let Err(e) = execute_single_trade().await {
+    // Spawn a task to handle the trade, dropping the results of tasks that already finished
+    let mut trade_tasks = state.trade_tasks.lock().await;
+    while trade_tasks.try_join_next().is_some() {}
+    trade_tasks.spawn(async move {
+        let _guard = guard;
+        if let Err(e) = execute_single_trade(state.price_tx, state.trading_states).await {
             error!("Trade execution error: {}", e);
         }
     });
     
-    "Trade triggered"
+    "Trade triggered".into_response()
+}
+
+// Check the shared secret from the X-Trigger-Token header or ?token= query. Every route behind it
+// trades or moves funds, so without TRIGGER_SECRET nothing gets through.
+fn is_authorized(state: &AppState, headers: &HeaderMap, query_token: Option<&str>) -> bool {
+    let Some(secret) = &state.trigger_secret else {
+        return false;
+    };
+    
+    let token = headers.get("X-Trigger-Token")
+        .and_then(|value| value.to_str().ok())
+        .or(query_token);
+    
+    token.is_some_and(|token| constant_time_eq(token.as_bytes(), secret.as_bytes()))
+}
+
+#[derive(Deserialize)]
+struct ResetCircuitQuery {
+    token: Option<String>,
+    // Resets every configured pair when omitted
+    pair: Option<String>,
+}
+
+async fn reset_circuit(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<ResetCircuitQuery>,
+) -> impl IntoResponse {
+    if !is_authorized(&state, &headers, params.token.as_deref()) {
+        warn!("Rejected circuit reset with missing or invalid token");
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    }
+    
+    match reset_circuit_internal(params.pair).await {
+        Ok(pairs) => {
+            info!("Circuit breaker reset for {}", pairs.join(", "));
+            format!("Circuit breaker reset for {}", pairs.join(", ")).into_response()
+        }
+        Err(e) => {
+            error!("Failed to reset circuit breaker: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Error: {}", e)).into_response()
+        }
+    }
+}
+
+async fn reset_circuit_internal(pair: Option<String>) -> Result<Vec<String>> {
+    let config = config::Config::from_env()?;
+    let pairs: Vec<String> = match pair {
+        Some(pair) if config.pairs.iter().any(|p| p.name == pair) => vec![pair],
+        Some(pair) => anyhow::bail!("Unknown pair: {}", pair),
+        None => config.pairs.iter().map(|p| p.name.clone()).collect(),
+    };
+    
+    let db = firestore::FirestoreDb::from_config(&config).await?;
+    let now = now_local();
+    for pair in &pairs {
+        db.store_circuit_breaker(&firestore::CircuitBreakerState {
+            pair: pair.clone(),
+            consecutive_losses: 0,
+            circuit_broken: false,
+            updated_at: now,
+        }).await?;
+    }
+    
+    Ok(pairs)
+}
+
+#[derive(Deserialize)]
+struct ForceTradeQuery {
+    token: Option<String>,
+    // "buy" or "sell"
+    action: String,
+    // The first configured pair when omitted
+    pair: Option<String>,
+}
+
+async fn force_trade(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<ForceTradeQuery>,
+) -> impl IntoResponse {
+    if !is_authorized(&state, &headers, params.token.as_deref()) {
+        warn!("Rejected forced trade with missing or invalid token");
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    }
+    
+    let action: trading::ForcedAction = match params.action.parse() {
+        Ok(action) => action,
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("Error: {}", e)).into_response(),
+    };
+    
+    let Some(_guard) = TradeInProgressGuard::acquire(&state.trade_in_progress) else {
+        warn!("Rejected forced trade while a trade is already in progress");
+        return (StatusCode::CONFLICT, "Trade already in progress").into_response();
+    };
+    
+    info!("Forced {:?} requested", action);
+    match force_trade_internal(state.price_tx, state.trading_states, params.pair, action).await {
+        Ok((pair, profit)) => format!(
+            "Forced {:?} of {} executed{}",
+            action,
+            pair,
+            profit.map(|profit| format!(", profit {}", profit)).unwrap_or_default(),
+        ).into_response(),
+        Err(e) => {
+            error!("Forced trade failed: {}", e);
+            let status = match e.downcast_ref::<error::TradingError>() {
+                Some(error::TradingError::PositionMismatch { .. }) => StatusCode::CONFLICT,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            (status, format!("Error: {}", e)).into_response()
+        }
+    }
 }
 
-async fn execute_single_trade() -> Result<()> {
+async fn force_trade_internal(
+    price_tx: broadcast::Sender<firestore::PriceHistory>,
+    trading_states: Arc<Mutex<HashMap<String, trading::TradingState>>>,
+    pair: Option<String>,
+    action: trading::ForcedAction,
+) -> Result<(String, Option<Decimal>)> {
     let config = config::Config::from_env()?;
+    let pair = resolve_pair(&config, pair)?;
     let wallet = wallet::Wallet::new(&config.private_key)?;
-    let line_client = line_bot::LineClient::new(&config.line_channel_token, &config.line_user_id);
+    let notifiers = notifier::build_notifiers(&config);
+    let firestore = connect_firestore(&config, price_tx).await;
+    
+    let mut trading_states = trading_states.lock().await;
+    let mut state = load_trading_state(&config, &wallet, &pair, firestore, &mut trading_states).await;
+    let result = trading::force_trade(&wallet, &config, &mut state, action).await;
+    post_trade_result(&config, &mut state).await;
+    let state = trading_states.entry(pair.name.clone()).or_insert(state);
+    notify_trade_result(&config, &notifiers, state, &result).await;
+    
+    let profit = result?;
+    info!(
+        pair = %pair.name,
+        position = state.position_symbol(),
+        signature = state.last_signature.as_deref(),
+        "Forced trade executed"
+    );
+    Ok((pair.name, profit))
+}
+
+#[derive(Deserialize)]
+struct BackfillQuery {
+    token: Option<String>,
+    days: Option<u32>,
+    // The first configured pair when omitted
+    pair: Option<String>,
+}
+
+async fn backfill_prices(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<BackfillQuery>,
+) -> impl IntoResponse {
+    if !is_authorized(&state, &headers, params.token.as_deref()) {
+        warn!("Rejected price backfill with missing or invalid token");
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    }
     
-    // Initialize Firestore if configured
-    let firestore = match firestore::FirestoreDb::new(config.gcp_project_id.clone()).await {
-        Ok(db) => Some(Arc::new(db)),
+    let days = params.days.unwrap_or(7);
+    if days == 0 || days > MAX_BACKFILL_DAYS {
+        return (StatusCode::BAD_REQUEST, format!("Error: days must be between 1 and {}", MAX_BACKFILL_DAYS)).into_response();
+    }
+    
+    match backfill_prices_internal(params.pair, days).await {
+        Ok((pair, written)) => {
+            info!("Backfilled {} {} prices over the last {} days", written, pair, days);
+            format!("Backfilled {} {} prices over the last {} days", written, pair, days).into_response()
+        }
         Err(e) => {
-            error!("Failed to initialize Firestore: {}", e);
-            None
+            error!("Failed to backfill prices: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Error: {}", e)).into_response()
         }
+    }
+}
+
+async fn backfill_prices_internal(pair: Option<String>, days: u32) -> Result<(String, usize)> {
+    let config = config::Config::from_env()?;
+    let pair = resolve_pair(&config, pair)?;
+    let db = firestore::FirestoreDb::from_config(&config).await?;
+    let source = price_source::CoinGeckoClient::from_config(&config);
+    
+    let to = chrono::Utc::now();
+    let from = to - chrono::Duration::days(days as i64);
+    let written = db.backfill_prices(&source, &pair, from, to, config.price_decimals).await?;
+    Ok((pair.name, written))
+}
+
+async fn line_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> impl IntoResponse {
+    let Some(channel_secret) = &state.line_channel_secret else {
+        return (StatusCode::NOT_FOUND, "LINE webhook is not configured").into_response();
     };
     
-    // Initialize trading state with persistent storage
-    let mut state = trading::TradingState::new();
-    if let Some(db) = firestore.clone() {
-        state = state.with_firestore(db.clone());
-        if let Err(e) = state.load_from_firestore().await {
-            error!("Failed to load trading state from Firestore: {}", e);
+    let signature = headers.get("X-Line-Signature").and_then(|value| value.to_str().ok());
+    if !signature.is_some_and(|signature| line_bot::verify_signature(channel_secret, &body, signature)) {
+        warn!("Rejected LINE webhook with missing or invalid signature");
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    }
+    
+    let request: line_bot::WebhookRequest = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("Error: {}", e)).into_response(),
+    };
+    
+    // LINE wants a quick 200, so the replies are sent in the background
+    tokio::spawn(async move {
+        for event in request.events {
+            if let Err(e) = handle_line_event(event).await {
+                error!("Failed to handle LINE event: {}", e);
+            }
         }
-        
+    });
+    
+    StatusCode::OK.into_response()
+}
+
+// Replies to a text message from one of LINE_USER_IDS, other events and senders are ignored
+async fn handle_line_event(event: line_bot::WebhookEvent) -> Result<()> {
+    let (Some(text), Some(reply_token)) = (event.text(), event.reply_token.as_deref()) else {
+        return Ok(());
+    };
+    
+    let config = config::Config::from_env()?;
+    let Some(channel_token) = config.line_channel_token.as_ref().filter(|_| !config.line_user_ids.is_empty()) else {
+        anyhow::bail!("LINE_CHANNEL_TOKEN and LINE_USER_IDS are needed to reply");
+    };
+    let sender = event.source.as_ref().and_then(|source| source.user_id.as_deref());
+    if !sender.is_some_and(|sender| config.line_user_ids.iter().any(|user_id| user_id == sender)) {
+        warn!("Ignoring LINE message from {}", sender.unwrap_or("an unknown sender"));
+        return Ok(());
+    }
+    
+    let command = line_bot::ChatCommand::parse(text);
+    info!("LINE command received: {:?}", command);
+    let reply = chat_reply(&config, command).await.unwrap_or_else(|e| {
+        error!("Failed to answer LINE {:?} command: {}", command, e);
+        format!("Error: {}", e)
+    });
+    
+    line_bot::LineClient::new(channel_token, &config.line_user_ids)
+        .with_timeout(Duration::from_secs(config.line_timeout_secs))
+        .reply_message(reply_token, &reply)
+        .await
+}
+
+async fn chat_reply(config: &config::Config, command: line_bot::ChatCommand) -> Result<String> {
+    match command {
+        line_bot::ChatCommand::Status => {
+            let mut positions = Vec::with_capacity(config.pairs.len());
+            for pair in &config.pairs {
+                positions.push(format_position(&get_position_internal(Some(pair.name.clone())).await?));
+            }
+            Ok(positions.join("\n\n"))
+        }
+        line_bot::ChatCommand::Performance => {
+            Ok(format_performance(&get_trading_performance_internal(30).await?))
+        }
+        line_bot::ChatCommand::Help => Ok(line_bot::CHAT_HELP.to_string()),
+    }
+}
+
+fn format_position(position: &PositionResponse) -> String {
+    let optional = |value: Option<Decimal>| value.map_or_else(|| "-".to_string(), |value| format!("{:.4}", value));
+    let mut lines = vec![
+        format!("📊 {}", position.pair),
+        format!("Position: {}", position.position),
+    ];
+    if position.open {
+        lines.push(format!("Size: {:.4}", position.position_size));
+        lines.push(format!("Entry: {}", optional(position.average_entry_price)));
+        lines.push(format!("Break-even: {}", optional(position.break_even_price)));
+        lines.push(format!("Unrealized P/L: {}", optional(position.unrealized_pnl)));
+        if let Some(secs) = position.time_in_position_secs {
+            lines.push(format!("Held: {}h {}m", secs / 3600, secs % 3600 / 60));
+        }
+    } else {
+        lines.push(format!("Last trade: {}", optional(position.last_trade_price)));
+    }
+    lines.push(format!("Live price: {}", optional(position.live_price)));
+    if position.circuit_broken {
+        lines.push("🛑 Circuit breaker tripped".to_string());
+    }
+    lines.join("\n")
+}
+
+fn format_performance(performance: &firestore::TradingPerformance) -> String {
+    format!(
+        "📈 Last {} days\n\
+        Trades: {} ({} won, {} lost)\n\
+        Win rate: {:.2}%\n\
+        P/L: {:.4} {}\n\
+        Max drawdown: {:.4} {}\n\
+        Gas fees: {:.6} SOL\n\
+        Current streak: {}",
+        performance.period_days,
+        performance.total_trades,
+        performance.winning_trades,
+        performance.losing_trades,
+        performance.win_rate,
+        performance.total_profit_loss,
+        performance.currency,
+        performance.max_drawdown,
+        performance.currency,
+        performance.total_gas_fees,
+        performance.current_streak,
+    )
+}
+
+// Posts the session of a trade made by this run to RESULT_WEBHOOK_URL, failures are only logged
+async fn post_trade_result(config: &config::Config, state: &mut trading::TradingState) {
+    let Some(session) = state.last_session.take() else {
+        return;
+    };
+    let Some(webhook) = result_webhook::ResultWebhookClient::from_config(config) else {
+        return;
+    };
+    if let Err(e) = webhook.send_session(&session).await {
+        error!("Failed to post {} trade result: {}", state.pair.name, e);
+    }
+}
+
+// Compare without short-circuiting so the response time doesn't leak how much of the token matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn stream_prices(ws: WebSocketUpgrade, State(state): State<AppState>) -> impl IntoResponse {
+    let price_rx = state.price_tx.subscribe();
+    ws.on_upgrade(move |socket| send_prices(socket, price_rx))
+}
+
+async fn send_prices(mut socket: WebSocket, mut price_rx: broadcast::Receiver<firestore::PriceHistory>) {
+    loop {
+        tokio::select! {
+            price = price_rx.recv() => match price {
+                Ok(price) => {
+                    let text = match serde_json::to_string(&price) {
+                        Ok(text) => text,
+                        Err(e) => {
+                            error!("Failed to serialize price point: {}", e);
+                            continue;
+                        }
+                    };
+                    if socket.send(Message::Text(text)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("WebSocket client lagged behind, skipped {} price points", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    
+    info!("WebSocket price stream closed");
+}
+
+async fn execute_single_trade(
+    price_tx: broadcast::Sender<firestore::PriceHistory>,
+    trading_states: Arc<Mutex<HashMap<String, trading::TradingState>>>,
+) -> Result<()> {
+    let config = config::Config::from_env()?;
+    let wallet = wallet::Wallet::new(&config.private_key)?;
+    let notifiers = notifier::build_notifiers(&config);
+    
+    let firestore = connect_firestore(&config, price_tx).await;
+    
+    if let Some(db) = &firestore {
         // Cleanup old data (this replaces the periodic cleanup task)
         if let Err(e) = db.cleanup_old_data(config.data_retention_days).await {
             error!("Failed to cleanup old data: {}", e);
         }
     }
 
-    // Send daily high/low price update at midnight JST
-    let now_jst = chrono::Utc::now().with_timezone(&Tokyo);
-    if now_jst.hour() == 0 {
-        // Send daily price update at midnight JST
-        if let Some(db) = firestore {
-            if let Err(e) = line_client.send_daily_high_and_low_sol_prices(&state, &db).await {
-                error!("Failed to send daily price update: {}", e);
+    let notification_level = config.notification_level;
+    
+    // Send daily high/low price update at local midnight
+    if now_local().hour() == 0 && notification_level.notifies(NotificationEvent::DailySummary) {
+        if let (Some(db), Some(token)) = (&firestore, config.line_channel_token.as_ref().filter(|_| !config.line_user_ids.is_empty())) {
+            let line_client = line_bot::LineClient::new(token, &config.line_user_ids)
+                .with_dry_run(config.dry_run)
+                .with_timeout(Duration::from_secs(config.line_timeout_secs));
+            for pair in &config.pairs {
+                if let Err(e) = line_client.send_daily_high_and_low_prices(db, pair).await {
+                    error!("Failed to send daily {} price update: {}", pair.name, e);
+                }
             }
         }
     }
@@ -120,23 +806,151 @@ async fn execute_single_trade() -> Result<()> {
     }
     */
     
-    // Execute the trade
-    match trading::check_and_trade(&wallet, &config, &mut state).await {
-        Ok(Some(profit)) => {
-            line_client.send_success_notification(&state, profit).await?;
-        }
-        Ok(None) => {
-            info!("No trading opportunity found");
+    // Each pair trades independently, so a failure on one pair doesn't stop the others
+    let mut first_error = None;
+    let mut trading_states = trading_states.lock().await;
+    for pair in &config.pairs {
+        let mut state = load_trading_state(&config, &wallet, pair, firestore.clone(), &mut trading_states).await;
+        
+        // Execute the trade
+        let result = trading::check_and_trade(&wallet, &config, &mut state).await;
+        post_trade_result(&config, &mut state).await;
+        let state = trading_states.entry(pair.name.clone()).or_insert(state);
+        notify_trade_result(&config, &notifiers, state, &result).await;
+        match result {
+            Ok(Some(profit)) => {
+                info!(
+                    pair = %pair.name,
+                    profit = %profit,
+                    position = state.position_symbol(),
+                    signature = state.last_signature.as_deref(),
+                    explorer_url = state.last_explorer_url.as_deref(),
+                    "Trade executed"
+                );
+            }
+            Ok(None) => {
+                info!("No trading opportunity found for {}", pair.name);
+                if notification_level.notifies(NotificationEvent::NoTrade) {
+                    let message = no_trade_message(state);
+                    for notifier in &notifiers {
+                        if let Err(e) = notifier.send_message(&message).await {
+                            error!("Failed to send no-trade notification: {}", e);
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Trade execution error for {}: {}", pair.name, e);
+                first_error.get_or_insert(e);
+            }
         }
+    }
+    
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+async fn connect_firestore(
+    config: &config::Config,
+    price_tx: broadcast::Sender<firestore::PriceHistory>,
+) -> Option<Arc<firestore::FirestoreDb>> {
+    match firestore::FirestoreDb::from_config(config).await {
+        Ok(db) => Some(Arc::new(db.with_price_broadcast(price_tx))),
         Err(e) => {
-            line_client.send_error_notification(&e).await?;
-            return Err(e);
+            error!("Failed to initialize Firestore: {}", e);
+            None
+        }
+    }
+}
+
+// Trading state of `pair` for the next trade, taken out of `trading_states`.
+// Triggered and forced trades both go through here, so neither runs on a state the other has moved past.
+async fn load_trading_state(
+    config: &config::Config,
+    wallet: &wallet::Wallet,
+    pair: &config::TradingPair,
+    firestore: Option<Arc<firestore::FirestoreDb>>,
+    trading_states: &mut HashMap<String, trading::TradingState>,
+) -> trading::TradingState {
+    // Initialize trading state with persistent storage
+    let mut state = trading::TradingState::new(pair.clone())
+        .with_reporting_currency(&config.reporting_currency);
+    if let Some(db) = firestore {
+        state = state.with_firestore(db);
+        if let Err(e) = state.load_from_firestore().await {
+            error!("Failed to load {} trading state from Firestore: {}", pair.name, e);
         }
     }
     
-    Ok(())
+    // The state from the previous trade wins when Firestore is down, or is behind
+    // because the last trade was made while it was down
+    if let Some(mut cached) = trading_states.remove(&pair.name) {
+        if state.firestore.is_none() || cached.last_trade_timestamp > state.last_trade_timestamp {
+            warn!("Continuing {} from the trading state kept in memory, Firestore is unavailable or out of date", pair.name);
+            cached.pair = pair.clone();
+            cached.firestore = state.firestore.take();
+            state = cached;
+        }
+    }
+    
+    if let Err(e) = trading::reconcile_position(wallet, config, &mut state).await {
+        warn!("Failed to reconcile {} position with wallet balances: {}", pair.name, e);
+    }
+    state
+}
+
+// Trade and error notifications, as far as NOTIFICATION_LEVEL asks for them
+async fn notify_trade_result(
+    config: &config::Config,
+    notifiers: &[Box<dyn notifier::Notifier>],
+    state: &trading::TradingState,
+    result: &Result<Option<Decimal>>,
+) {
+    match result {
+        Ok(Some(profit)) if config.notification_level.notifies(NotificationEvent::Trade) => {
+            for notifier in notifiers {
+                if let Err(e) = notifier.send_success_notification(state, *profit).await {
+                    error!("Failed to send success notification: {}", e);
+                }
+            }
+        }
+        Err(e) if config.notification_level.notifies(NotificationEvent::Error) => {
+            for notifier in notifiers {
+                if let Err(notify_err) = notifier.send_error_notification(e).await {
+                    error!("Failed to send error notification: {}", notify_err);
+                }
+            }
+        }
+        _ => {}
+    }
 }
 
+// "Still alive" ping for NOTIFICATION_LEVEL=verbose
+fn no_trade_message(state: &trading::TradingState) -> String {
+    format!(
+        "💤 No trade\n\
+        Pair: {}\n\
+        Position: {}\n\
+        Price: {}\n\
+        Time: {}",
+        state.pair.name,
+        state.position_symbol(),
+        state.last_observed_price.map_or_else(|| "unknown".to_string(), |price| format!("{:.4} {}", price, state.pair.quote_symbol())),
+        clock::format_local(&chrono::Utc::now()),
+    )
+}
+
+async fn get_metrics() -> impl IntoResponse {
+    match metrics::metrics().render() {
+        Ok(body) => ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response(),
+        Err(e) => {
+            error!("Failed to render metrics: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Error: {}", e)).into_response()
+        }
+    }
+}
 
 #[derive(Deserialize)]
 struct PerformanceQuery {
@@ -149,8 +963,17 @@ struct PerformanceResponse {
     winning_trades: i64,
     losing_trades: i64,
     total_profit_loss: String,
+    // Symbol of the reporting currency total_profit_loss is in
+    currency: String,
     total_gas_fees: String,
     win_rate: String,
+    max_drawdown: String,
+    // Mean time the base token was held per sell, None until a sell records one
+    average_holding_secs: Option<i64>,
+    longest_winning_streak: u32,
+    longest_losing_streak: u32,
+    // Positive for a winning streak, negative for a losing one
+    current_streak: i64,
     period_days: u32,
 }
 
@@ -163,8 +986,14 @@ async fn get_performance(Query(params): Query<PerformanceQuery>) -> impl IntoRes
             winning_trades: performance.winning_trades,
             losing_trades: performance.losing_trades,
             total_profit_loss: performance.total_profit_loss.to_string(),
+            currency: performance.currency,
             total_gas_fees: performance.total_gas_fees.to_string(),
             win_rate: format!("{:.2}%", performance.win_rate),
+            max_drawdown: performance.max_drawdown.to_string(),
+            average_holding_secs: performance.average_holding_secs,
+            longest_winning_streak: performance.longest_winning_streak,
+            longest_losing_streak: performance.longest_losing_streak,
+            current_streak: performance.current_streak,
             period_days: performance.period_days,
         }).into_response(),
         Err(e) => {
@@ -177,12 +1006,14 @@ async fn get_performance(Query(params): Query<PerformanceQuery>) -> impl IntoRes
 #[derive(Deserialize)]
 struct PriceHistoryQuery {
     hours: Option<u32>,
+    // Defaults to the first configured pair
+    pair: Option<String>,
 }
 
 async fn get_price_history(Query(params): Query<PriceHistoryQuery>) -> impl IntoResponse {
     let hours = params.hours.unwrap_or(24);
     
-    match get_price_history_internal(hours).await {
+    match get_price_history_internal(params.pair, hours).await {
         Ok(prices) => Json(prices).into_response(),
         Err(e) => {
             error!("Failed to get price history: {}", e);
@@ -208,60 +1039,891 @@ async fn get_trading_sessions(Query(params): Query<TradingSessionsQuery>) -> imp
     }
 }
 
+#[derive(Deserialize)]
+struct TradeHistoryQuery {
+    limit: Option<u32>,
+    offset: Option<u32>,
+    since: Option<DateTime<FixedOffset>>,
+}
+
+async fn get_trade_history(Query(params): Query<TradeHistoryQuery>) -> impl IntoResponse {
+    let limit = params.limit.unwrap_or(50);
+    let offset = params.offset.unwrap_or(0);
+    
+    match get_trade_history_internal(limit, offset, params.since).await {
+        Ok(sessions) => Json(sessions).into_response(),
+        Err(e) => {
+            error!("Failed to get trade history: {}", e);
+            format!("Error: {}", e).into_response()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    // "csv" (default) or "json"
+    format: Option<String>,
+    days: Option<u32>,
+}
+
+async fn export_trades(Query(params): Query<ExportQuery>) -> impl IntoResponse {
+    let days = params.days.unwrap_or(365);
+    let (content_type, extension) = match params.format.as_deref().unwrap_or("csv") {
+        "csv" => ("text/csv; charset=utf-8", "csv"),
+        "json" => ("application/json", "json"),
+        format => return (StatusCode::BAD_REQUEST, format!("Error: Unknown format: {}", format)).into_response(),
+    };
+    
+    let sessions = match get_export_sessions_internal(days).await {
+        Ok(sessions) => sessions,
+        Err(e) => {
+            error!("Failed to export trades: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Error: {}", e)).into_response();
+        }
+    };
+    
+    let body = if extension == "csv" {
+        export::trading_sessions_to_csv(&sessions)
+    } else {
+        match serde_json::to_string(&sessions) {
+            Ok(json) => json,
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("Error: {}", e)).into_response(),
+        }
+    };
+    
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"trades-{}d.{}\"", days, extension)),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+#[derive(Deserialize)]
+struct CurrentPriceQuery {
+    // Defaults to the first configured pair
+    pair: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CurrentPriceResponse {
+    pair: String,
+    sol_price_usdc: Decimal,
+    usdc_price_sol: Decimal,
+    timestamp: DateTime<FixedOffset>,
+    // Price impact reported by Jupiter for a one-token quote
+    price_impact_pct: Option<String>,
+    // Set when the live quote failed and the latest stored price is returned instead
+    stale: bool,
+    note: Option<String>,
+}
+
+async fn get_current_price(Query(params): Query<CurrentPriceQuery>) -> impl IntoResponse {
+    match get_current_price_internal(params.pair).await {
+        Ok(price) => Json(price).into_response(),
+        Err(e) => {
+            error!("Failed to get current price: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Error: {}", e)).into_response()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TrendsQuery {
+    // Defaults to the first configured pair
+    pair: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TrendsResponse {
+    pair: String,
+    current_price: Decimal,
+    // Trend and volatility fields are null when there isn't enough price history yet
+    #[serde(flatten)]
+    trend: firestore::PriceTrend,
+}
+
+async fn get_trends(Query(params): Query<TrendsQuery>) -> impl IntoResponse {
+    match get_trends_internal(params.pair).await {
+        Ok(trends) => Json(trends).into_response(),
+        Err(e) => {
+            error!("Failed to get price trends: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Error: {}", e)).into_response()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct VolatilityQuery {
+    hours: Option<u32>,
+    // Defaults to the first configured pair
+    pair: Option<String>,
+}
+
+#[derive(Serialize)]
+struct VolatilityResponse {
+    pair: String,
+    #[serde(flatten)]
+    volatility: firestore::Volatility,
+}
+
+async fn get_volatility(Query(params): Query<VolatilityQuery>) -> impl IntoResponse {
+    let hours = params.hours.unwrap_or(24);
+    
+    match get_volatility_internal(params.pair, hours).await {
+        Ok(volatility) => Json(volatility).into_response(),
+        Err(e) => {
+            error!("Failed to get volatility: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Error: {}", e)).into_response()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CandlesQuery {
+    hours: Option<u32>,
+    interval_minutes: Option<u32>,
+    // Defaults to the first configured pair
+    pair: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CandlesResponse {
+    pair: String,
+    interval_minutes: u32,
+    candles: Vec<firestore::Candle>,
+}
+
+async fn get_candles(Query(params): Query<CandlesQuery>) -> impl IntoResponse {
+    let hours = params.hours.unwrap_or(24);
+    let interval_minutes = params.interval_minutes.unwrap_or(60);
+    if interval_minutes == 0 || interval_minutes > MAX_CANDLE_INTERVAL_MINUTES {
+        return (StatusCode::BAD_REQUEST, format!("Error: interval_minutes must be between 1 and {}", MAX_CANDLE_INTERVAL_MINUTES)).into_response();
+    }
+    
+    match get_candles_internal(params.pair, hours, interval_minutes).await {
+        Ok(candles) => Json(candles).into_response(),
+        Err(e) => {
+            error!("Failed to get candles: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Error: {}", e)).into_response()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PositionQuery {
+    // Defaults to the first configured pair
+    pair: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PositionResponse {
+    pair: String,
+    // Symbol of the token held
+    position: String,
+    // False while holding the quote token, when the base-token fields below are null
+    open: bool,
+    // Null while holding the quote token or when the entry wasn't recorded
+    average_entry_price: Option<Decimal>,
+    position_size: Decimal,
+    // Average entry plus the entry fees per base token
+    break_even_price: Option<Decimal>,
+    last_trade_price: Option<Decimal>,
+    // Null when the Jupiter quote failed
+    live_price: Option<Decimal>,
+    // In quote tokens, against the average entry price
+    unrealized_pnl: Option<Decimal>,
+    time_in_position_secs: Option<i64>,
+    circuit_broken: bool,
+}
+
+async fn get_position(Query(params): Query<PositionQuery>) -> impl IntoResponse {
+    match get_position_internal(params.pair).await {
+        Ok(position) => Json(position).into_response(),
+        Err(e) => {
+            error!("Failed to get position: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Error: {}", e)).into_response()
+        }
+    }
+}
+
+async fn get_position_internal(pair: Option<String>) -> Result<PositionResponse> {
+    let config = config::Config::from_env()?;
+    let pair = resolve_pair(&config, pair)?;
+    let db = firestore::FirestoreDb::from_config(&config).await?;
+    
+    let mut state = trading::TradingState::new(pair.clone()).with_firestore(Arc::new(db));
+    state.load_from_firestore().await?;
+    
+    let jupiter_client = jupiter::JupiterClient::shared(&config);
+    let live_price = match trading::get_current_prices(jupiter_client, &pair).await {
+        Ok((base_price, _)) => Some(trading::round_price(base_price, &config)),
+        Err(e) => {
+            warn!("Failed to get the live {} price for the position: {}", pair.name, e);
+            None
+        }
+    };
+    
+    Ok(position_response(&state, live_price))
+}
+
+fn position_response(state: &trading::TradingState, live_price: Option<Decimal>) -> PositionResponse {
+    let open = state.position == trading::Position::Base;
+    let entry_price = state.average_entry_price.or(state.last_trade_price);
+    let unrealized_pnl = match (open, live_price, entry_price) {
+        (true, Some(live_price), Some(entry_price)) if state.position_size > Decimal::ZERO => {
+            Some((live_price - entry_price) * state.position_size)
+        }
+        _ => None,
+    };
+    let now = now_local();
+    
+    PositionResponse {
+        pair: state.pair.name.clone(),
+        position: state.position_symbol().to_string(),
+        open,
+        average_entry_price: state.average_entry_price.filter(|_| open),
+        position_size: if open { state.position_size } else { Decimal::ZERO },
+        break_even_price: state.break_even_price().filter(|_| open),
+        last_trade_price: state.last_trade_price,
+        live_price,
+        unrealized_pnl,
+        time_in_position_secs: state.entered_at.filter(|_| open).map(|entered_at| (now - entered_at).num_seconds()),
+        circuit_broken: state.circuit_broken,
+    }
+}
+
+#[derive(Deserialize)]
+struct PreviewSwapQuery {
+    // Symbol ("SOL") or mint of a token in a configured pair
+    from: String,
+    to: String,
+    // In whole `from` tokens
+    amount: Decimal,
+}
+
+#[derive(Serialize)]
+struct PreviewSwapResponse {
+    input_mint: String,
+    output_mint: String,
+    in_amount: Decimal,
+    expected_out_amount: Decimal,
+    // Lowest output accepted at the configured slippage
+    minimum_received: Decimal,
+    slippage_bps: u16,
+    price_impact_pct: String,
+    route_labels: Vec<String>,
+}
+
+// Quote-only preview of a swap, nothing is signed or sent
+async fn preview_swap(Query(params): Query<PreviewSwapQuery>) -> impl IntoResponse {
+    match preview_swap_internal(params).await {
+        Ok(preview) => Json(preview).into_response(),
+        Err(e) => {
+            error!("Failed to preview swap: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Error: {}", e)).into_response()
+        }
+    }
+}
+
+async fn get_portfolio() -> impl IntoResponse {
+    match get_portfolio_internal().await {
+        Ok(portfolio) => Json(portfolio).into_response(),
+        Err(e) => {
+            error!("Failed to get portfolio: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Error: {}", e)).into_response()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ProfitSeriesQuery {
+    days: Option<u32>,
+    // "day" or "week"
+    bucket: Option<String>,
+}
+
+async fn get_profit_series(Query(params): Query<ProfitSeriesQuery>) -> impl IntoResponse {
+    let days = params.days.unwrap_or(30);
+    let bucket = params.bucket.as_deref().unwrap_or("day");
+    
+    match get_profit_series_internal(days, bucket).await {
+        Ok(buckets) => Json(buckets).into_response(),
+        Err(e) => {
+            error!("Failed to get profit series: {}", e);
+            format!("Error: {}", e).into_response()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BacktestQuery {
+    days: Option<u32>,
+    // Defaults to the configured STRATEGY
+    strategy: Option<String>,
+    // Defaults to the first configured pair
+    pair: Option<String>,
+    fee_pct: Option<Decimal>,
+    slippage_bps: Option<u16>,
+    initial_quote: Option<Decimal>,
+}
+
+async fn get_backtest(Query(params): Query<BacktestQuery>) -> impl IntoResponse {
+    match get_backtest_internal(params).await {
+        Ok(result) => Json(result).into_response(),
+        Err(e) => {
+            error!("Failed to run backtest: {}", e);
+            format!("Error: {}", e).into_response()
+        }
+    }
+}
+
 async fn get_trading_performance_internal(days: u32) -> Result<firestore::TradingPerformance> {
     let config = config::Config::from_env()?;
-    let db = firestore::FirestoreDb::new(config.gcp_project_id).await?;
-    db.get_trading_performance(days).await
+    let db = firestore::FirestoreDb::from_config(&config).await?;
+    db.get_trading_performance(days, &config.reporting_currency).await
+}
+
+async fn get_price_history_internal(pair: Option<String>, hours: u32) -> Result<Vec<firestore::PriceHistory>> {
+    let config = config::Config::from_env()?;
+    let pair = pair.unwrap_or_else(|| config.pairs[0].name.clone());
+    let db = firestore::FirestoreDb::from_config(&config).await?;
+    db.get_price_history(&pair, hours).await
+}
+
+async fn get_current_price_internal(pair: Option<String>) -> Result<CurrentPriceResponse> {
+    let config = config::Config::from_env()?;
+    let pair = resolve_pair(&config, pair)?;
+    let jupiter_client = jupiter::JupiterClient::shared(&config);
+    
+    match live_current_price(jupiter_client, &pair).await {
+        Ok(price) => Ok(price),
+        Err(e) => {
+            warn!("Live quote failed, falling back to the latest stored price: {}", e);
+            let db = firestore::FirestoreDb::from_config(&config).await?;
+            let latest = db.get_latest_price(&pair.name).await?
+                .ok_or_else(|| anyhow::anyhow!("Live quote failed and no stored price is available: {}", e))?;
+            
+            Ok(CurrentPriceResponse {
+                pair: pair.name,
+                sol_price_usdc: latest.sol_price_usdc,
+                usdc_price_sol: latest.usdc_price_sol,
+                timestamp: latest.timestamp,
+                price_impact_pct: None,
+                stale: true,
+                note: Some(format!("Live quote failed ({}), showing the latest stored price", e)),
+            })
+        }
+    }
+}
+
+async fn live_current_price(jupiter_client: &jupiter::JupiterClient, pair: &config::TradingPair) -> Result<CurrentPriceResponse> {
+    // The one-token quote is cached, so get_current_prices reuses it for the base price
+    let quote = jupiter_client.get_quote(
+        &pair.base_mint,
+        &pair.quote_mint,
+        wallet::ui_to_raw_amount(Decimal::ONE, pair.base_decimals),
+        0,
+    ).await?;
+    let (base_price, quote_price) = trading::get_current_prices(jupiter_client, pair).await?;
+    
+    Ok(CurrentPriceResponse {
+        pair: pair.name.clone(),
+        sol_price_usdc: base_price,
+        usdc_price_sol: quote_price,
+        timestamp: now_local(),
+        price_impact_pct: Some(quote.price_impact_pct),
+        stale: false,
+        note: None,
+    })
+}
+
+async fn get_trends_internal(pair: Option<String>) -> Result<TrendsResponse> {
+    let config = config::Config::from_env()?;
+    let pair = resolve_pair(&config, pair)?;
+    let jupiter_client = jupiter::JupiterClient::shared(&config);
+    let (current_price, _) = trading::get_current_prices(jupiter_client, &pair).await?;
+    
+    let db = firestore::FirestoreDb::from_config(&config).await?;
+    let trend = db.get_price_trend(&pair.name, current_price).await?;
+    
+    Ok(TrendsResponse {
+        pair: pair.name,
+        current_price,
+        trend,
+    })
+}
+
+async fn get_volatility_internal(pair: Option<String>, hours: u32) -> Result<VolatilityResponse> {
+    let config = config::Config::from_env()?;
+    let pair = resolve_pair(&config, pair)?;
+    let db = firestore::FirestoreDb::from_config(&config).await?;
+    let volatility = db.get_volatility(&pair.name, hours).await?;
+    
+    Ok(VolatilityResponse {
+        pair: pair.name,
+        volatility,
+    })
+}
+
+async fn get_candles_internal(pair: Option<String>, hours: u32, interval_minutes: u32) -> Result<CandlesResponse> {
+    let config = config::Config::from_env()?;
+    let pair = resolve_pair(&config, pair)?;
+    let db = firestore::FirestoreDb::from_config(&config).await?;
+    let candles = db.get_ohlc(&pair.name, hours, interval_minutes).await?;
+    
+    Ok(CandlesResponse {
+        pair: pair.name,
+        interval_minutes,
+        candles,
+    })
+}
+
+async fn preview_swap_internal(params: PreviewSwapQuery) -> Result<PreviewSwapResponse> {
+    let config = config::Config::from_env()?;
+    let (input_mint, input_decimals) = config.find_token(&params.from)
+        .ok_or_else(|| anyhow::anyhow!("Unknown token: {}, expected a symbol or mint from a configured pair", params.from))?;
+    let (output_mint, output_decimals) = config.find_token(&params.to)
+        .ok_or_else(|| anyhow::anyhow!("Unknown token: {}, expected a symbol or mint from a configured pair", params.to))?;
+    if input_mint == output_mint {
+        anyhow::bail!("from and to must be different tokens");
+    }
+    if params.amount <= Decimal::ZERO {
+        anyhow::bail!("amount must be greater than 0, got {}", params.amount);
+    }
+    
+    let jupiter_client = jupiter::JupiterClient::shared(&config);
+    let quote = jupiter_client.get_quote(
+        &input_mint,
+        &output_mint,
+        wallet::to_base_units(params.amount, input_decimals)?,
+        config.slippage_bps,
+    ).await?;
+    
+    Ok(swap_preview(&quote, input_decimals, output_decimals))
+}
+
+fn swap_preview(quote: &jupiter::QuoteResponse, input_decimals: u8, output_decimals: u8) -> PreviewSwapResponse {
+    let ui_amount = |raw: &str, decimals| wallet::raw_to_ui_amount(raw.parse().unwrap_or(0), decimals);
+    
+    PreviewSwapResponse {
+        input_mint: quote.input_mint.clone(),
+        output_mint: quote.output_mint.clone(),
+        in_amount: ui_amount(&quote.in_amount, input_decimals),
+        expected_out_amount: ui_amount(&quote.out_amount, output_decimals),
+        minimum_received: ui_amount(&quote.other_amount_threshold, output_decimals),
+        slippage_bps: quote.slippage_bps,
+        price_impact_pct: quote.price_impact_pct.clone(),
+        route_labels: quote.route_plan.iter()
+            .map(|step| step.swap_info.label.clone().unwrap_or_else(|| step.swap_info.amm_key.clone()))
+            .collect(),
+    }
+}
+
+// The named pair, or the first configured pair when none is given
+fn resolve_pair(config: &config::Config, pair: Option<String>) -> Result<config::TradingPair> {
+    match pair {
+        Some(name) => config.pairs.iter()
+            .find(|pair| pair.name == name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Unknown pair: {}", name)),
+        None => Ok(config.pairs[0].clone()),
+    }
+}
+
+async fn get_portfolio_internal() -> Result<portfolio::Portfolio> {
+    let config = config::Config::from_env()?;
+    let wallet = wallet::Wallet::new(&config.private_key)?;
+    let db = firestore::FirestoreDb::from_config(&config).await?;
+    portfolio::snapshot(&wallet, &config, &db).await
+}
+
+async fn get_profit_series_internal(days: u32, bucket: &str) -> Result<Vec<firestore::ProfitBucket>> {
+    let bucket: firestore::Bucket = bucket.parse()?;
+    let config = config::Config::from_env()?;
+    let db = firestore::FirestoreDb::from_config(&config).await?;
+    db.get_profit_by_period(days, bucket).await
+}
+
+async fn get_backtest_internal(params: BacktestQuery) -> Result<backtest::BacktestResult> {
+    let config = config::Config::from_env()?;
+    let strategy = match params.strategy {
+        Some(strategy) => strategy.parse()?,
+        None => config.strategy.clone(),
+    };
+    let pair = resolve_pair(&config, params.pair)?;
+    
+    let db = firestore::FirestoreDb::from_config(&config).await?;
+    let prices = db.get_price_history(&pair.name, params.days.unwrap_or(30) * 24).await?;
+    
+    let backtest_config = backtest::BacktestConfig {
+        slippage_bps: params.slippage_bps.unwrap_or(config.slippage_bps),
+        trading: config,
+        pair,
+        fee_pct: params.fee_pct.unwrap_or(Decimal::ZERO),
+        initial_quote: params.initial_quote.unwrap_or(Decimal::ONE_HUNDRED),
+    };
+    
+    Ok(backtest::backtest(&prices, &strategy, &backtest_config))
 }
 
-async fn get_price_history_internal(hours: u32) -> Result<Vec<firestore::PriceHistory>> {
+// Every trading session of the last `days` days, oldest first
+async fn get_export_sessions_internal(days: u32) -> Result<Vec<firestore::TradingSession>> {
     let config = config::Config::from_env()?;
-    let db = firestore::FirestoreDb::new(config.gcp_project_id).await?;
-    db.get_price_history(hours).await
+    let db = firestore::FirestoreDb::from_config(&config).await?;
+    let since = now_local() - chrono::Duration::days(days as i64);
+    let mut sessions = db.get_trading_sessions(u32::MAX, 0, Some(since)).await?;
+    sessions.reverse();
+    Ok(sessions)
 }
 
 async fn get_trading_sessions_internal(limit: u32) -> Result<Vec<firestore::TradingSession>> {
     let config = config::Config::from_env()?;
-    let db = firestore::FirestoreDb::new(config.gcp_project_id).await?;
+    let db = firestore::FirestoreDb::from_config(&config).await?;
+    db.get_trading_sessions(limit, 0, None).await
+}
+
+async fn get_trade_history_internal(
+    limit: u32,
+    offset: u32,
+    since: Option<DateTime<FixedOffset>>,
+) -> Result<Vec<firestore::TradingSession>> {
+    let config = config::Config::from_env()?;
+    let db = firestore::FirestoreDb::from_config(&config).await?;
+    db.get_trading_sessions(limit, offset, since).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
     
-    // Get all trading sessions from the database
-    let _all_sessions = db.get_trading_performance(3650).await?; // Get up to 10 years of data
+    fn app_state(trigger_secret: Option<&str>) -> AppState {
+        AppState {
+            price_tx: broadcast::channel(PRICE_BROADCAST_CAPACITY).0,
+            trigger_secret: trigger_secret.map(str::to_string),
+            line_channel_secret: None,
+            trade_in_progress: Arc::new(AtomicBool::new(false)),
+            trade_tasks: Arc::new(Mutex::new(JoinSet::new())),
+            trading_states: Arc::new(Mutex::new(HashMap::new())),
+            heartbeat: heartbeat::Heartbeat::in_memory(),
+        }
+    }
     
-    // Get the actual sessions data by making another call
-    // For now, we'll use the price history method as a template
-    let url = format!(
-        "https://firestore.googleapis.com/v1/projects/{}/databases/{}/documents/trading_sessions?orderBy=timestamp%20desc",
-        db.project_id, db.database_id
-    );
+    fn token_header(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Trigger-Token", token.parse().unwrap());
+        headers
+    }
     
-    let auth_token = db.get_auth_token().await?;
+    #[test]
+    fn trigger_accepts_the_secret_from_the_header_or_query() {
+        let state = app_state(Some("s3cret"));
+        assert!(is_authorized(&state, &token_header("s3cret"), None));
+        assert!(is_authorized(&state, &HeaderMap::new(), Some("s3cret")));
+    }
     
-    let response = reqwest::Client::new()
-        .get(&url)
-        .header("Authorization", auth_token)
-        .send()
-        .await?
-        .error_for_status()?;
+    #[test]
+    fn trigger_rejects_a_missing_or_wrong_token() {
+        let state = app_state(Some("s3cret"));
+        assert!(!is_authorized(&state, &HeaderMap::new(), None));
+        assert!(!is_authorized(&state, &token_header("wrong"), None));
+        assert!(!is_authorized(&state, &HeaderMap::new(), Some("s3cre")));
+        // The header wins over the query, so a bad header isn't rescued by a good query token
+        assert!(!is_authorized(&state, &token_header("wrong"), Some("s3cret")));
+    }
     
-    #[derive(serde::Deserialize)]
-    struct ListResponse {
-        documents: Option<Vec<firestore::FirestoreDocument>>,
+    #[test]
+    fn trigger_is_closed_without_a_secret() {
+        let state = app_state(None);
+        assert!(!is_authorized(&state, &HeaderMap::new(), None));
+        assert!(!is_authorized(&state, &token_header(""), Some("")));
     }
     
-    let result: ListResponse = response.json().await?;
-    let mut sessions = Vec::new();
+    #[tokio::test]
+    async fn trigger_handler_returns_401_for_a_wrong_token() {
+        let query = TriggerQuery { token: Some("wrong".to_string()) };
+        let response = trigger_trade(State(app_state(Some("s3cret"))), HeaderMap::new(), Query(query)).await.into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
     
-    if let Some(documents) = result.documents {
-        for doc in documents.into_iter().take(limit as usize) {
-            match db.firestore_document_to_json(doc) {
-                Ok(session) => sessions.push(session),
-                Err(e) => {
-                    error!("Error reading trading session document: {}", e);
-                    continue;
-                }
-            }
+    #[test]
+    fn constant_time_eq_compares_whole_values() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+    }
+    
+    #[tokio::test]
+    async fn only_one_of_two_concurrent_triggers_trades() {
+        let state = app_state(Some("s3cret"));
+        let trigger = || trigger_trade(State(state.clone()), token_header("s3cret"), Query(TriggerQuery { token: None }));
+        
+        let (first, second) = tokio::join!(trigger(), trigger());
+        let mut statuses = [first.into_response().status(), second.into_response().status()];
+        statuses.sort_by_key(|status| status.as_u16());
+        
+        assert_eq!(statuses, [StatusCode::OK, StatusCode::CONFLICT]);
+        let mut trade_tasks = state.trade_tasks.lock().await;
+        assert_eq!(trade_tasks.len(), 1);
+        
+        // Stop the trade before it reaches the network; the flag is released with its task
+        trade_tasks.abort_all();
+        while trade_tasks.join_next().await.is_some() {}
+        assert!(TradeInProgressGuard::acquire(&state.trade_in_progress).is_some());
+    }
+    
+    #[tokio::test]
+    async fn trigger_handler_returns_409_while_a_trade_runs() {
+        let state = app_state(Some("s3cret"));
+        let _running = TradeInProgressGuard::acquire(&state.trade_in_progress).unwrap();
+        
+        let response = trigger_trade(State(state.clone()), token_header("s3cret"), Query(TriggerQuery { token: None })).await.into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert!(state.trade_tasks.lock().await.is_empty());
+    }
+    
+    // The RPC client blocks in place, which needs the multi-threaded runtime
+    #[tokio::test(flavor = "multi_thread")]
+    async fn trading_state_survives_between_triggers_without_firestore() {
+        // Nothing listens on port 1, so every RPC call fails straight away
+        let config = config::Config { rpc_urls: vec!["http://127.0.0.1:1".to_string()], ..config::test_config() };
+        let wallet = wallet::Wallet::new(&config.private_key).unwrap();
+        let pair = &config.pairs[0];
+        let entered_at = DateTime::parse_from_rfc3339("2026-01-01T12:00:00+09:00").unwrap();
+        let mut trading_states = HashMap::new();
+        
+        // First trigger: the trade moves the state, then the run hands it back like execute_single_trade
+        let mut state = load_trading_state(&config, &wallet, pair, None, &mut trading_states).await;
+        assert_eq!(state.position, trading::Position::Quote);
+        assert!(trading::check_and_trade(&wallet, &config, &mut state).await.is_err());
+        state.enter_position(Decimal::from(150), Decimal::from(2), Decimal::ZERO, entered_at);
+        trading_states.entry(pair.name.clone()).or_insert(state);
+        
+        // Second trigger picks up where the first left off
+        let mut state = load_trading_state(&config, &wallet, pair, None, &mut trading_states).await;
+        assert!(trading_states.is_empty());
+        assert!(trading::check_and_trade(&wallet, &config, &mut state).await.is_err());
+        assert_eq!(state.position, trading::Position::Base);
+        assert_eq!(state.last_trade_price, Some(Decimal::from(150)));
+        assert_eq!(state.last_trade_timestamp, Some(entered_at));
+        assert_eq!(state.position_size, Decimal::from(2));
+    }
+    
+    #[tokio::test]
+    async fn line_webhook_checks_the_signature() {
+        use hmac::Mac;
+        
+        let state = AppState { line_channel_secret: Some("channel-secret".to_string()), ..app_state(None) };
+        let body = Bytes::from_static(br#"{"events":[]}"#);
+        let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(b"channel-secret").unwrap();
+        mac.update(&body);
+        let signature = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, mac.finalize().into_bytes());
+        let signed = |signature: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("X-Line-Signature", signature.parse().unwrap());
+            headers
+        };
+        
+        let response = line_webhook(State(state.clone()), signed(&signature), body.clone()).await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = line_webhook(State(state.clone()), signed(&signature), Bytes::from_static(br#"{"events":[{}]}"#)).await.into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = line_webhook(State(state.clone()), HeaderMap::new(), body.clone()).await.into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        
+        // Without a channel secret the route doesn't exist
+        let response = line_webhook(State(app_state(None)), signed(&signature), body).await.into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+    
+    #[tokio::test]
+    async fn unknown_chat_commands_get_the_help_text() {
+        let reply = chat_reply(&config::test_config(), line_bot::ChatCommand::parse("hello")).await.unwrap();
+        assert_eq!(reply, line_bot::CHAT_HELP);
+    }
+    
+    #[tokio::test]
+    async fn shutdown_waits_for_the_in_flight_trade() {
+        let trade_tasks = Mutex::new(JoinSet::new());
+        let finished = Arc::new(AtomicBool::new(false));
+        let task_finished = finished.clone();
+        trade_tasks.lock().await.spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            task_finished.store(true, Ordering::SeqCst);
+        });
+        
+        wait_for_trade_tasks(&trade_tasks).await;
+        
+        assert!(finished.load(Ordering::SeqCst));
+        assert!(trade_tasks.lock().await.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn current_price_is_served_as_json_from_the_live_quote() {
+        let mock = jupiter_mock::MockJupiter::start(jupiter_mock::normal_quote()).await;
+        let client = jupiter::JupiterClient::new(mock.url()).with_rate_limit(100.0);
+        let pair = config::test_config().pairs[0].clone();
+        
+        let price = live_current_price(&client, &pair).await.unwrap();
+        let json = serde_json::to_value(&price).unwrap();
+        
+        assert_eq!(json["pair"], pair.name);
+        assert_eq!(json["sol_price_usdc"].as_str().unwrap().parse::<Decimal>().unwrap(), Decimal::from(150));
+        assert_eq!(json["price_impact_pct"], "0.0012");
+        assert_eq!(json["stale"], false);
+        assert!(json["note"].is_null());
+        assert!(chrono::DateTime::parse_from_rfc3339(json["timestamp"].as_str().unwrap()).is_ok());
+    }
+    
+    #[test]
+    fn subscriber_builds_in_both_log_formats() {
+        for log_format in ["text", "json"] {
+            let subscriber = build_subscriber(log_format, "solana_trading_bot=info".into());
+            tracing::subscriber::with_default(subscriber, || {
+                info!(profit = "1.5", position = "SOL", signature = "sig", "Smoke test in {}", log_format);
+            });
         }
     }
     
-    Ok(sessions)
-}
\ No newline at end of file
+    fn dependency(name: &'static str, healthy: bool) -> DependencyStatus {
+        DependencyStatus { name, healthy, latency_ms: 5, error: (!healthy).then(|| format!("{} unreachable", name)) }
+    }
+    
+    #[test]
+    fn deep_health_is_unavailable_when_any_dependency_is_down() {
+        let cases = [
+            ([true, true, true], StatusCode::OK),
+            ([false, true, true], StatusCode::SERVICE_UNAVAILABLE),
+            ([true, true, false], StatusCode::SERVICE_UNAVAILABLE),
+            ([false, false, false], StatusCode::SERVICE_UNAVAILABLE),
+        ];
+        for (up, expected) in cases {
+            let dependencies = vec![dependency("rpc", up[0]), dependency("jupiter", up[1]), dependency("firestore", up[2])];
+            let (status, response) = aggregate_health(dependencies);
+            assert_eq!(status, expected, "dependencies up: {:?}", up);
+            assert_eq!(response.healthy, status == StatusCode::OK);
+            assert_eq!(response.dependencies.len(), 3);
+        }
+    }
+    
+    #[test]
+    fn deep_health_reports_each_dependency() {
+        let (_, response) = aggregate_health(vec![dependency("rpc", true), dependency("jupiter", false)]);
+        assert_eq!(serde_json::to_value(&response).unwrap(), serde_json::json!({
+            "healthy": false,
+            "dependencies": [
+                { "name": "rpc", "healthy": true, "latency_ms": 5, "error": null },
+                { "name": "jupiter", "healthy": false, "latency_ms": 5, "error": "jupiter unreachable" },
+            ]
+        }));
+    }
+    
+    #[test]
+    fn trends_serialize_flat_with_null_for_missing_history() {
+        let trend = firestore::PriceTrend {
+            timestamp: DateTime::parse_from_rfc3339("2024-05-01T09:00:00+09:00").unwrap(),
+            price_1h_ago: Some(Decimal::from(148)),
+            price_24h_ago: Some(Decimal::from(140)),
+            // Less than a week of history
+            price_7d_ago: None,
+            trend_1h: Some("up".to_string()),
+            trend_24h: Some("up".to_string()),
+            trend_7d: None,
+            volatility_1h: Some(Decimal::new(12, 1)),
+            volatility_24h: Some(Decimal::new(35, 1)),
+        };
+        let response = TrendsResponse { pair: "SOL/USDC".to_string(), current_price: Decimal::from(150), trend };
+        
+        assert_eq!(serde_json::to_value(&response).unwrap(), serde_json::json!({
+            "pair": "SOL/USDC",
+            "current_price": "150",
+            "timestamp": "2024-05-01T09:00:00+09:00",
+            "price_1h_ago": "148",
+            "price_24h_ago": "140",
+            "price_7d_ago": null,
+            "trend_1h": "up",
+            "trend_24h": "up",
+            "trend_7d": null,
+            "volatility_1h": "1.2",
+            "volatility_24h": "3.5",
+        }));
+    }
+    
+    #[tokio::test]
+    async fn swap_preview_lists_the_route_labels_of_the_quote() {
+        let mock = jupiter_mock::MockJupiter::start(jupiter_mock::normal_quote()).await;
+        let client = jupiter::JupiterClient::new(mock.url()).with_rate_limit(100.0);
+        let quote = client.get_quote(jupiter_mock::SOL_MINT, jupiter_mock::USDC_MINT, 1_000_000_000, 50).await.unwrap();
+        
+        let preview = swap_preview(&quote, 9, 6);
+        
+        assert_eq!(preview.route_labels, ["Whirlpool"]);
+        assert_eq!(preview.in_amount, Decimal::ONE);
+        assert_eq!(preview.expected_out_amount, Decimal::from(150));
+        assert_eq!(preview.minimum_received, Decimal::from(150));
+        assert_eq!(preview.price_impact_pct, "0.0012");
+    }
+    
+    #[test]
+    fn open_position_serializes_with_unrealized_pnl() {
+        let mut state = trading::TradingState::new(config::test_config().pairs[0].clone());
+        state.enter_position(Decimal::from(150), Decimal::from(2), Decimal::ONE, now_local() - chrono::Duration::hours(1));
+        
+        let json = serde_json::to_value(position_response(&state, Some(Decimal::from(160)))).unwrap();
+        let time_in_position = json["time_in_position_secs"].as_i64().unwrap();
+        assert!((3600..3660).contains(&time_in_position), "{}s in position", time_in_position);
+        assert_eq!(json, serde_json::json!({
+            "pair": "SOL/USDC",
+            "position": "SOL",
+            "open": true,
+            "average_entry_price": "150",
+            "position_size": "2",
+            "break_even_price": "150.50",
+            "last_trade_price": "150",
+            "live_price": "160",
+            "unrealized_pnl": "20",
+            "time_in_position_secs": time_in_position,
+            "circuit_broken": false,
+        }));
+    }
+    
+    #[test]
+    fn closed_position_leaves_the_base_token_fields_null() {
+        let mut state = trading::TradingState::new(config::test_config().pairs[0].clone());
+        state.enter_position(Decimal::from(150), Decimal::from(2), Decimal::ONE, now_local());
+        state.exit_position(Decimal::from(160));
+        
+        let json = serde_json::to_value(position_response(&state, Some(Decimal::from(158)))).unwrap();
+        assert_eq!(json["position"], "USDC");
+        assert_eq!(json["open"], false);
+        assert_eq!(json["last_trade_price"], "160");
+        for field in ["average_entry_price", "break_even_price", "unrealized_pnl", "time_in_position_secs"] {
+            assert!(json[field].is_null(), "{} is {}", field, json[field]);
+        }
+    }
+    
+    #[test]
+    fn volatility_is_served_with_its_sample_count_and_window() {
+        let prices = [148, 150, 152, 150].map(Decimal::from);
+        let response = VolatilityResponse { pair: "SOL/USDC".to_string(), volatility: firestore::volatility(&prices, 24) };
+        
+        // Population standard deviation of 148, 150, 152, 150
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["volatility"].as_str().unwrap().parse::<Decimal>().unwrap().round_dp(6), Decimal::new(1414214, 6));
+        assert_eq!(json["sample_count"], 4);
+        assert_eq!(json["hours"], 24);
+        assert_eq!(json["insufficient_data"], false);
+        assert_eq!(json["pair"], "SOL/USDC");
+        
+        let response = VolatilityResponse { pair: "SOL/USDC".to_string(), volatility: firestore::volatility(&prices[..1], 1) };
+        assert_eq!(serde_json::to_value(&response).unwrap(), serde_json::json!({
+            "pair": "SOL/USDC",
+            "volatility": "0",
+            "sample_count": 1,
+            "hours": 1,
+            "insufficient_data": true,
+        }));
+    }
+}
@@ -0,0 +1,71 @@
+use prometheus::{Encoder, GaugeVec, Histogram, HistogramOpts, IntCounter, Opts, Registry, TextEncoder};
+use std::sync::OnceLock;
+
+pub struct Metrics {
+    registry: Registry,
+    pub total_trades: IntCounter,
+    pub winning_trades: IntCounter,
+    pub losing_trades: IntCounter,
+    // Labelled by pair, in units of the pair's quote token
+    pub cumulative_profit: GaugeVec,
+    pub last_price: GaugeVec,
+    pub quote_latency_seconds: Histogram,
+    pub rpc_errors: IntCounter,
+    pub suspicious_fills: IntCounter,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        
+        let total_trades = IntCounter::new("trading_bot_trades_total", "Total executed trades").unwrap();
+        let winning_trades = IntCounter::new("trading_bot_winning_trades_total", "Trades with positive profit").unwrap();
+        let losing_trades = IntCounter::new("trading_bot_losing_trades_total", "Trades with negative profit").unwrap();
+        let cumulative_profit = GaugeVec::new(
+            Opts::new("trading_bot_cumulative_profit", "Cumulative profit in the quote token"),
+            &["pair"],
+        ).unwrap();
+        let last_price = GaugeVec::new(
+            Opts::new("trading_bot_last_price", "Last observed base token price in the quote token"),
+            &["pair"],
+        ).unwrap();
+        let quote_latency_seconds = Histogram::with_opts(
+            HistogramOpts::new("trading_bot_jupiter_quote_latency_seconds", "Jupiter quote request latency"),
+        ).unwrap();
+        let rpc_errors = IntCounter::new("trading_bot_rpc_errors_total", "Failed Solana RPC calls").unwrap();
+        let suspicious_fills = IntCounter::new("trading_bot_suspicious_fills_total", "Swaps filled close to the slippage minimum").unwrap();
+        
+        registry.register(Box::new(total_trades.clone())).unwrap();
+        registry.register(Box::new(winning_trades.clone())).unwrap();
+        registry.register(Box::new(losing_trades.clone())).unwrap();
+        registry.register(Box::new(cumulative_profit.clone())).unwrap();
+        registry.register(Box::new(last_price.clone())).unwrap();
+        registry.register(Box::new(quote_latency_seconds.clone())).unwrap();
+        registry.register(Box::new(rpc_errors.clone())).unwrap();
+        registry.register(Box::new(suspicious_fills.clone())).unwrap();
+        
+        Self {
+            registry,
+            total_trades,
+            winning_trades,
+            losing_trades,
+            cumulative_profit,
+            last_price,
+            quote_latency_seconds,
+            rpc_errors,
+            suspicious_fills,
+        }
+    }
+    
+    // Render all metrics in the Prometheus text exposition format
+    pub fn render(&self) -> anyhow::Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
@@ -0,0 +1,137 @@
+use anyhow::Result;
+use futures::future::BoxFuture;
+use rust_decimal::Decimal;
+use std::time::Duration;
+use tracing::{info, error};
+
+use crate::discord::DiscordClient;
+use crate::line_bot::LineClient;
+use crate::telegram::TelegramClient;
+use crate::trading::TradingState;
+
+pub trait Notifier: Send + Sync {
+    fn send_message<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<()>>;
+
+    fn send_success_notification<'a>(
+        &'a self,
+        state: &'a TradingState,
+        profit: Decimal,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    fn send_error_notification<'a>(&'a self, e: &'a anyhow::Error) -> BoxFuture<'a, Result<()>>;
+}
+
+impl Notifier for LineClient {
+    fn send_message<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(LineClient::send_message(self, text))
+    }
+
+    fn send_success_notification<'a>(
+        &'a self,
+        state: &'a TradingState,
+        profit: Decimal,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(LineClient::send_success_notification(self, state, profit))
+    }
+
+    fn send_error_notification<'a>(&'a self, e: &'a anyhow::Error) -> BoxFuture<'a, Result<()>> {
+        Box::pin(LineClient::send_error_notification(self, e))
+    }
+}
+
+impl Notifier for TelegramClient {
+    fn send_message<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(TelegramClient::send_message(self, text))
+    }
+
+    fn send_success_notification<'a>(
+        &'a self,
+        state: &'a TradingState,
+        profit: Decimal,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(TelegramClient::send_success_notification(self, state, profit))
+    }
+
+    fn send_error_notification<'a>(&'a self, e: &'a anyhow::Error) -> BoxFuture<'a, Result<()>> {
+        Box::pin(TelegramClient::send_error_notification(self, e))
+    }
+}
+
+impl Notifier for DiscordClient {
+    fn send_message<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DiscordClient::send_message(self, text))
+    }
+
+    fn send_success_notification<'a>(
+        &'a self,
+        state: &'a TradingState,
+        profit: Decimal,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DiscordClient::send_success_notification(self, state, profit))
+    }
+
+    fn send_error_notification<'a>(&'a self, e: &'a anyhow::Error) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DiscordClient::send_error_notification(self, e))
+    }
+}
+
+// Only logs, used when no notification channel is configured
+pub struct NullNotifier;
+
+impl Notifier for NullNotifier {
+    fn send_message<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<()>> {
+        info!("Notification (not sent): {}", text);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn send_success_notification<'a>(
+        &'a self,
+        state: &'a TradingState,
+        profit: Decimal,
+    ) -> BoxFuture<'a, Result<()>> {
+        info!("Trade executed (not sent): {} position {}, profit {}",
+            state.pair.name, state.position_symbol(), state.format_profit(profit));
+        Box::pin(async { Ok(()) })
+    }
+
+    fn send_error_notification<'a>(&'a self, e: &'a anyhow::Error) -> BoxFuture<'a, Result<()>> {
+        error!("Trading error (not sent): {}", e);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+// What a trigger can notify about, filtered by NOTIFICATION_LEVEL
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotificationEvent {
+    Error,
+    Trade,
+    // A trigger that found no trading opportunity
+    NoTrade,
+    // Daily high/low prices, sent at local midnight
+    DailySummary,
+}
+
+pub fn build_notifiers(config: &crate::config::Config) -> Vec<Box<dyn Notifier>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+
+    if let Some(token) = config.line_channel_token.as_ref().filter(|_| !config.line_user_ids.is_empty()) {
+        notifiers.push(Box::new(
+            LineClient::new(token, &config.line_user_ids)
+                .with_dry_run(config.dry_run)
+                .with_timeout(Duration::from_secs(config.line_timeout_secs)),
+        ));
+    }
+    if let (Some(token), Some(chat_id)) = (&config.telegram_bot_token, &config.telegram_chat_id) {
+        notifiers.push(Box::new(TelegramClient::new(token, chat_id).with_dry_run(config.dry_run)));
+    }
+    if let Some(webhook_url) = &config.discord_webhook_url {
+        notifiers.push(Box::new(DiscordClient::new(webhook_url).with_dry_run(config.dry_run)));
+    }
+
+    if notifiers.is_empty() {
+        info!("No notification channel configured, notifications will only be logged");
+        notifiers.push(Box::new(NullNotifier));
+    }
+
+    notifiers
+}
@@ -0,0 +1,139 @@
+use anyhow::Result;
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    config::Config,
+    firestore::FirestoreDb,
+    jupiter::{self, JupiterClient},
+    rpc,
+    wallet::{raw_price_to_ui, ui_to_raw_amount, Wallet, SOL_DECIMALS},
+};
+
+#[derive(Debug, Serialize)]
+pub struct PortfolioAsset {
+    pub mint: String,
+    pub symbol: Option<String>,
+    pub balance: Decimal,
+    // None when the token isn't part of a configured pair, so its decimals are unknown
+    pub value: Option<Decimal>,
+    // Against the last trade price, for base tokens currently held by a pair's position
+    pub unrealized_pnl: Option<Decimal>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Portfolio {
+    // Values are in this token, the quote token of the first configured pair
+    pub valued_in: String,
+    pub assets: Vec<PortfolioAsset>,
+    pub total_value: Decimal,
+    pub unrealized_pnl: Decimal,
+}
+
+// Current wallet holdings (native SOL and every non-empty token account), valued via Jupiter quotes
+pub async fn snapshot(wallet: &Wallet, config: &Config, db: &FirestoreDb) -> Result<Portfolio> {
+    let rpc_client = rpc::connect(config)?;
+    let jupiter_client = JupiterClient::shared(config);
+    let valuation_pair = &config.pairs[0];
+
+    let mut holdings = vec![(spl_token::native_mint::id(), wallet.get_effective_sol_balance(&rpc_client).await?)];
+    for (mint, balance) in wallet.get_all_token_balances(&rpc_client).await? {
+        // Skip empty accounts and wrapped SOL, which is already counted with the native balance
+        if balance > 0.0 && mint != spl_token::native_mint::id() {
+            holdings.push((mint, balance));
+        }
+    }
+
+    let mut assets = Vec::new();
+    for (mint, balance) in holdings {
+        let mint_str = mint.to_string();
+        let balance = Decimal::from_f64(balance).unwrap_or(dec!(0));
+
+        let value = match token_decimals(config, &mint) {
+            Some(_) if balance == dec!(0) => Some(dec!(0)),
+            Some(decimals) => {
+                let price = price_in(jupiter_client, &mint_str, decimals, &valuation_pair.quote_mint, valuation_pair.quote_decimals).await?;
+                Some(balance * price)
+            }
+            None => None,
+        };
+
+        // Unrealized P/L for pairs that hold this token as their base, priced in the valuation token
+        let mut unrealized_pnl = None;
+        for pair in config.pairs.iter().filter(|p| p.base_mint == mint_str && p.quote_mint == valuation_pair.quote_mint) {
+            if let Some(session) = db.get_latest_trading_session(&pair.name).await? {
+                if session.position_after == pair.base_symbol() && balance > dec!(0) {
+                    let current_price = value.unwrap_or(dec!(0)) / balance;
+                    unrealized_pnl = Some((current_price - session.price_at_trade) * balance);
+                }
+            }
+        }
+
+        assets.push(PortfolioAsset {
+            symbol: symbol_for(config, &mint),
+            mint: mint_str,
+            balance,
+            value,
+            unrealized_pnl,
+        });
+    }
+
+    Ok(Portfolio {
+        valued_in: valuation_pair.quote_symbol().to_string(),
+        total_value: assets.iter().filter_map(|a| a.value).sum(),
+        unrealized_pnl: assets.iter().filter_map(|a| a.unrealized_pnl).sum(),
+        assets,
+    })
+}
+
+// Price of one whole `mint` token in `valuation_mint`
+async fn price_in(
+    jupiter_client: &JupiterClient,
+    mint: &str,
+    decimals: u8,
+    valuation_mint: &str,
+    valuation_decimals: u8,
+) -> Result<Decimal> {
+    if mint == valuation_mint {
+        return Ok(dec!(1));
+    }
+
+    let raw_price = jupiter::get_price(jupiter_client, mint, valuation_mint, ui_to_raw_amount(dec!(1), decimals)).await?;
+    Ok(raw_price_to_ui(Decimal::from_f64(raw_price).unwrap_or(dec!(0)), decimals, valuation_decimals))
+}
+
+fn token_decimals(config: &Config, mint: &Pubkey) -> Option<u8> {
+    if *mint == spl_token::native_mint::id() {
+        return Some(SOL_DECIMALS);
+    }
+
+    let mint = mint.to_string();
+    config.pairs.iter().find_map(|pair| {
+        if pair.base_mint == mint {
+            Some(pair.base_decimals)
+        } else if pair.quote_mint == mint {
+            Some(pair.quote_decimals)
+        } else {
+            None
+        }
+    })
+}
+
+fn symbol_for(config: &Config, mint: &Pubkey) -> Option<String> {
+    if *mint == spl_token::native_mint::id() {
+        return Some("SOL".to_string());
+    }
+
+    let mint = mint.to_string();
+    config.pairs.iter().find_map(|pair| {
+        if pair.base_mint == mint {
+            Some(pair.base_symbol().to_string())
+        } else if pair.quote_mint == mint {
+            Some(pair.quote_symbol().to_string())
+        } else {
+            None
+        }
+    })
+}
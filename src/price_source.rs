@@ -0,0 +1,224 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use serde::Deserialize;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::config::{Config, TradingPair};
+use crate::jupiter::{self, JupiterClient};
+use crate::metrics::metrics;
+use crate::service::{redact, retry_as_exponential_back_off};
+use crate::wallet::{is_native_mint, raw_price_to_ui, ui_to_raw_amount};
+
+// Pyth prices older than this are not trusted as a reference
+const PYTH_MAX_PRICE_AGE_SECS: i64 = 60;
+
+pub trait PriceSource: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // Price of one whole base token in the pair's quote token
+    fn get_price<'a>(&'a self, pair: &'a TradingPair) -> BoxFuture<'a, Result<Decimal>>;
+}
+
+// Executable price from a one-token Jupiter quote
+impl PriceSource for JupiterClient {
+    fn name(&self) -> &'static str {
+        "Jupiter"
+    }
+
+    fn get_price<'a>(&'a self, pair: &'a TradingPair) -> BoxFuture<'a, Result<Decimal>> {
+        Box::pin(async move {
+            let raw_price = jupiter::get_price(
+                self,
+                &pair.base_mint,
+                &pair.quote_mint,
+                ui_to_raw_amount(dec!(1), pair.base_decimals),
+            ).await?;
+            Ok(raw_price_to_ui(
+                Decimal::from_f64(raw_price).unwrap_or(dec!(0)),
+                pair.base_decimals,
+                pair.quote_decimals,
+            ))
+        })
+    }
+}
+
+// SOL/USD from a Pyth price feed account, treating the quote token as worth one US dollar
+pub struct PythPriceSource<'a> {
+    rpc_client: &'a RpcClient,
+    price_account: Pubkey,
+}
+
+impl<'a> PythPriceSource<'a> {
+    pub fn new(rpc_client: &'a RpcClient, price_account: &str) -> Result<Self> {
+        Ok(Self {
+            rpc_client,
+            price_account: Pubkey::from_str(price_account).context("Invalid Pyth price account")?,
+        })
+    }
+
+    // Only SOL is covered by the configured feed
+    pub fn supports(pair: &TradingPair) -> bool {
+        Pubkey::from_str(&pair.base_mint).map(|mint| is_native_mint(&mint)).unwrap_or(false)
+    }
+}
+
+impl PriceSource for PythPriceSource<'_> {
+    fn name(&self) -> &'static str {
+        "Pyth"
+    }
+
+    fn get_price<'a>(&'a self, pair: &'a TradingPair) -> BoxFuture<'a, Result<Decimal>> {
+        Box::pin(async move {
+            if !Self::supports(pair) {
+                anyhow::bail!("Pyth price feed only covers SOL, not {}", pair.name);
+            }
+
+            let data = self.rpc_client.get_account_data(&self.price_account)
+                .map_err(|e| {
+                    metrics().rpc_errors.inc();
+                    anyhow::anyhow!("Failed to read Pyth price account: {}", e)
+                })?;
+            let update = parse_price_update(&data)?;
+
+            let age = chrono::Utc::now().timestamp() - update.publish_time;
+            if age > PYTH_MAX_PRICE_AGE_SECS {
+                anyhow::bail!("Pyth price is {}s old, older than {}s", age, PYTH_MAX_PRICE_AGE_SECS);
+            }
+            update.price()
+        })
+    }
+}
+
+// Historical SOL/USD prices from CoinGecko, treating the quote token as worth one US dollar
+pub struct CoinGeckoClient {
+    client: reqwest::Client,
+    api_url: String,
+    api_key: Option<String>,
+}
+
+// Response of /coins/{id}/market_chart/range, each price is [unix milliseconds, price]
+#[derive(Debug, Deserialize)]
+struct MarketChartResponse {
+    prices: Vec<[f64; 2]>,
+}
+
+impl CoinGeckoClient {
+    pub fn new(api_url: &str, api_key: Option<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_url: api_url.trim_end_matches('/').to_string(),
+            api_key,
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(&config.coingecko_api_url, config.coingecko_api_key.clone())
+    }
+
+    // Prices between `from` and `to`, oldest first. CoinGecko returns hourly points for ranges of up to 90 days
+    pub async fn get_price_range(
+        &self,
+        pair: &TradingPair,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<(DateTime<Utc>, Decimal)>> {
+        // Only SOL is looked up, like the Pyth feed
+        if !PythPriceSource::supports(pair) {
+            anyhow::bail!("CoinGecko prices only cover SOL, not {}", pair.name);
+        }
+
+        let url = format!("{}/coins/solana/market_chart/range", self.api_url);
+        let query = [
+            ("vs_currency", "usd".to_string()),
+            ("from", from.timestamp().to_string()),
+            ("to", to.timestamp().to_string()),
+        ];
+        let response = retry_as_exponential_back_off(
+            || async {
+                let mut request = self.client.get(&url).query(&query);
+                if let Some(api_key) = &self.api_key {
+                    request = request.header("x-cg-demo-api-key", api_key);
+                }
+                let response = request.send().await?;
+                if !response.status().is_success() {
+                    let status = response.status();
+                    anyhow::bail!("status {}: {}", status, redact(&response.text().await?));
+                }
+                Ok::<_, anyhow::Error>(response.json::<MarketChartResponse>().await?)
+            },
+            "Get CoinGecko prices",
+            3,
+            1000,
+            Some(Duration::from_secs(30)),
+            None,
+        )
+        .await?;
+
+        let mut prices = Vec::with_capacity(response.prices.len());
+        for [timestamp_ms, price] in response.prices {
+            let timestamp = DateTime::from_timestamp_millis(timestamp_ms as i64)
+                .with_context(|| format!("Invalid CoinGecko timestamp: {}", timestamp_ms))?;
+            match Decimal::from_f64(price) {
+                Some(price) if price > dec!(0) => prices.push((timestamp, price)),
+                _ => anyhow::bail!("Invalid CoinGecko price at {}: {}", timestamp, price),
+            }
+        }
+        prices.sort_by_key(|(timestamp, _)| *timestamp);
+        Ok(prices)
+    }
+}
+
+struct PriceUpdate {
+    price: i64,
+    exponent: i32,
+    publish_time: i64,
+}
+
+impl PriceUpdate {
+    fn price(&self) -> Result<Decimal> {
+        let price = if self.exponent <= 0 {
+            Decimal::try_new(self.price, self.exponent.unsigned_abs())?
+        } else {
+            Decimal::from(self.price) * Decimal::from(10u64.pow(self.exponent as u32))
+        };
+        if price <= dec!(0) {
+            anyhow::bail!("Pyth price is not positive: {}", price);
+        }
+        Ok(price)
+    }
+}
+
+// PriceUpdateV2 account: 8-byte discriminator, write authority, Borsh verification level
+// (Partial { num_signatures: u8 } or Full), then the price message:
+// feed_id [u8; 32], price i64, conf u64, exponent i32, publish_time i64, ...
+fn parse_price_update(data: &[u8]) -> Result<PriceUpdate> {
+    let message_start = match data.get(40) {
+        Some(0) => 42,
+        Some(1) => 41,
+        _ => anyhow::bail!("Unexpected Pyth price account data"),
+    };
+    let field = |offset: usize, len: usize| {
+        data.get(message_start + offset..message_start + offset + len)
+            .context("Pyth price account data is too short")
+    };
+
+    Ok(PriceUpdate {
+        price: i64::from_le_bytes(field(32, 8)?.try_into()?),
+        exponent: i32::from_le_bytes(field(48, 4)?.try_into()?),
+        publish_time: i64::from_le_bytes(field(52, 8)?.try_into()?),
+    })
+}
+
+// How far `price` is from `reference`, as a fraction of the reference
+pub fn price_divergence(price: Decimal, reference: Decimal) -> Option<Decimal> {
+    if reference <= dec!(0) {
+        return None;
+    }
+    Some((price - reference).abs() / reference)
+}
@@ -0,0 +1,152 @@
+use anyhow::Result;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::Duration;
+use tracing::{error, info};
+
+use crate::config::Config;
+use crate::firestore::TradingSession;
+use crate::service::{redact, retry_as_exponential_back_off};
+
+// Base64 HMAC-SHA256 of the raw body, present when RESULT_WEBHOOK_SECRET is set
+const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+// Posts each completed trade's session as JSON to RESULT_WEBHOOK_URL
+pub struct ResultWebhookClient {
+    client: reqwest::Client,
+    url: String,
+    secret: Option<String>,
+}
+
+impl ResultWebhookClient {
+    pub fn new(url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.to_string(),
+            secret: None,
+        }
+    }
+
+    pub fn with_secret(mut self, secret: Option<String>) -> Self {
+        self.secret = secret;
+        self
+    }
+
+    // None when RESULT_WEBHOOK_URL isn't set
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let url = config.result_webhook_url.as_deref()?;
+        Some(Self::new(url).with_secret(config.result_webhook_secret.clone()))
+    }
+
+    pub async fn send_session(&self, session: &TradingSession) -> Result<()> {
+        let body = session_payload(session)?;
+        let signature = self.secret.as_deref().map(|secret| sign(secret, &body));
+
+        retry_as_exponential_back_off(
+            || async {
+                let mut request = self.client
+                    .post(&self.url)
+                    .header("Content-Type", "application/json")
+                    .body(body.clone());
+                if let Some(signature) = &signature {
+                    request = request.header(SIGNATURE_HEADER, signature);
+                }
+                let response = request.send().await?;
+
+                if !response.status().is_success() {
+                    let error_text = redact(&response.text().await?);
+                    error!("Trade result webhook error: {}", error_text);
+                    anyhow::bail!("Failed to post trade result: {}", error_text);
+                }
+
+                Ok::<(), anyhow::Error>(())
+            },
+            "Post trade result",
+            3,
+            500,
+            Some(Duration::from_secs(10)),
+            None,
+        )
+        .await?;
+
+        info!("Trade result {} posted to webhook", session.id);
+        Ok(())
+    }
+}
+
+// The body is the session exactly as stored in Firestore, signature included
+pub fn session_payload(session: &TradingSession) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(session)?)
+}
+
+// Same scheme as LINE's X-Line-Signature, so receivers can verify it with any HMAC library
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    base64::Engine::encode(&base64::engine::general_purpose::STANDARD, mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Bytes, extract::State, http::HeaderMap, routing::post, Router};
+    use rust_decimal_macros::dec;
+    use std::sync::{Arc, Mutex};
+
+    type Received = Arc<Mutex<Option<(Option<String>, Bytes)>>>;
+
+    fn session() -> TradingSession {
+        TradingSession {
+            id: "session-1".to_string(),
+            pair: "SOL/USDC".to_string(),
+            timestamp: chrono::DateTime::parse_from_rfc3339("2024-05-01T09:00:00+09:00").unwrap(),
+            position_before: "SOL".to_string(),
+            position_after: "USDC".to_string(),
+            action: "SELL".to_string(),
+            sol_balance_before: dec!(1),
+            usdc_balance_before: dec!(0),
+            sol_balance_after: dec!(0),
+            usdc_balance_after: dec!(152),
+            price_at_trade: dec!(152),
+            slippage: Some(dec!(3)),
+            gas_fee: Some(dec!(0.000005)),
+            signature: Some("5sig".to_string()),
+            profit_loss: Some(dec!(2)),
+            cumulative_profit: Some(dec!(2)),
+            suspicious_fill: false,
+            holding_duration_secs: Some(3600),
+        }
+    }
+
+    #[tokio::test]
+    async fn webhook_posts_the_signed_session() {
+        let received: Received = Arc::default();
+        let app = Router::new()
+            .route("/", post(|State(received): State<Received>, headers: HeaderMap, body: Bytes| async move {
+                let signature = headers.get(SIGNATURE_HEADER).and_then(|value| value.to_str().ok()).map(str::to_string);
+                *received.lock().unwrap() = Some((signature, body));
+            }))
+            .with_state(received.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let session = session();
+        ResultWebhookClient::new(&url).with_secret(Some("s3cret".to_string()))
+            .send_session(&session).await.unwrap();
+
+        let (signature, body) = received.lock().unwrap().take().unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&body).unwrap(), serde_json::to_value(&session).unwrap());
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&body).unwrap()["signature"], "5sig");
+        assert_eq!(signature.as_deref(), Some(sign("s3cret", &body).as_str()));
+    }
+
+    #[test]
+    fn signature_changes_with_the_body_and_secret() {
+        let body = session_payload(&session()).unwrap();
+        assert_eq!(sign("s3cret", &body), sign("s3cret", &body));
+        assert_ne!(sign("s3cret", &body), sign("other", &body));
+        assert_ne!(sign("s3cret", &body), sign("s3cret", b"{}"));
+    }
+}
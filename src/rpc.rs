@@ -0,0 +1,99 @@
+use anyhow::{Context, Result};
+use solana_client::rpc_client::RpcClient;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::metrics::metrics;
+
+// Same as RpcClient::new
+const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Index into Config::rpc_urls of the endpoint that last answered, tried first next time
+static PREFERRED_ENDPOINT: AtomicUsize = AtomicUsize::new(0);
+
+pub fn connect(config: &Config) -> Result<RpcClient> {
+    connect_with_timeout(config, DEFAULT_RPC_TIMEOUT)
+}
+
+// Client for the first endpoint that passes a health check, starting from the one that worked last.
+// Endpoints are only rotated here, so a client that starts failing mid-trade is replaced on the next run.
+pub fn connect_with_timeout(config: &Config, timeout: Duration) -> Result<RpcClient> {
+    let urls = &config.rpc_urls;
+    let start = PREFERRED_ENDPOINT.load(Ordering::Relaxed);
+    
+    let (index, rpc_client) = first_healthy(urls.len(), start, |index| {
+        let rpc_client = RpcClient::new_with_timeout(urls[index].clone(), timeout);
+        rpc_client.get_health()
+            .map_err(|e| {
+                metrics().rpc_errors.inc();
+                anyhow::anyhow!("RPC endpoint {} is unhealthy: {}", index, e)
+            })?;
+        Ok(rpc_client)
+    })
+    .with_context(|| format!("All {} RPC endpoints failed", urls.len()))?;
+    
+    if index != start {
+        info!("Switched to RPC endpoint {} of {}", index + 1, urls.len());
+        PREFERRED_ENDPOINT.store(index, Ordering::Relaxed);
+    }
+    Ok(rpc_client)
+}
+
+// Try each of `count` endpoints in rotation order until one connects, returning its index.
+// Fails with the last error when none do.
+pub(crate) fn first_healthy<T>(
+    count: usize,
+    start: usize,
+    mut connect: impl FnMut(usize) -> Result<T>,
+) -> Result<(usize, T)> {
+    let mut last_error = anyhow::anyhow!("No RPC endpoints configured");
+    for index in rotation_order(count, start) {
+        match connect(index) {
+            Ok(client) => return Ok((index, client)),
+            Err(e) => {
+                // Endpoint URLs often embed an API key, so only the index is logged
+                warn!("{}, trying the next endpoint", e);
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
+// Every index once, starting at `start` and wrapping around
+pub(crate) fn rotation_order(count: usize, start: usize) -> impl Iterator<Item = usize> {
+    (0..count).map(move |offset| (start + offset) % count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn rotation_starts_at_the_preferred_endpoint_and_wraps() {
+        assert_eq!(rotation_order(3, 0).collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(rotation_order(3, 2).collect::<Vec<_>>(), [2, 0, 1]);
+        assert_eq!(rotation_order(0, 0).count(), 0);
+    }
+    
+    #[test]
+    fn failing_endpoints_are_skipped_in_rotation_order() {
+        let mut tried = Vec::new();
+        let (index, client) = first_healthy(4, 1, |index| {
+            tried.push(index);
+            // Only the endpoint after the wrap-around is up
+            if index == 0 { Ok("endpoint 0") } else { anyhow::bail!("endpoint {} is down", index) }
+        }).unwrap();
+        
+        assert_eq!(tried, [1, 2, 3, 0]);
+        assert_eq!((index, client), (0, "endpoint 0"));
+    }
+    
+    #[test]
+    fn all_endpoints_failing_returns_the_last_error() {
+        let err = first_healthy::<()>(2, 0, |index| anyhow::bail!("endpoint {} is down", index)).unwrap_err();
+        assert_eq!(err.to_string(), "endpoint 1 is down");
+    }
+}
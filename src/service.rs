@@ -1,86 +1,339 @@
 use anyhow::Result;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
 use std::future::Future;
 use std::time::Duration;
-use tokio::time::{sleep, timeout};
+use tokio::sync::Mutex;
+use tokio::time::{sleep, timeout, Instant};
 use tracing::{debug, error, warn};
 
+// Longest external response body written to logs and error messages
+const MAX_LOGGED_BODY_CHARS: usize = 500;
+
+// Make an external response body safe to log: bearer tokens and anything shaped like a
+// base58 private key are masked, and long bodies (e.g. base64 transactions) are truncated
+pub fn redact(text: &str) -> String {
+    let mut redacted = String::with_capacity(text.len().min(MAX_LOGGED_BODY_CHARS));
+    let mut after_bearer = false;
+    let mut rest = text;
+    
+    while let Some(c) = rest.chars().next() {
+        let token_len = rest.find(|c: char| !is_token_char(c)).unwrap_or(rest.len());
+        if token_len == 0 {
+            redacted.push(c);
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        
+        let token = &rest[..token_len];
+        if after_bearer || looks_like_private_key(token) {
+            redacted.push_str("[REDACTED]");
+        } else {
+            redacted.push_str(token);
+        }
+        after_bearer = token.eq_ignore_ascii_case("bearer");
+        rest = &rest[token_len..];
+    }
+    
+    let total_chars = redacted.chars().count();
+    if total_chars > MAX_LOGGED_BODY_CHARS {
+        let truncated: String = redacted.chars().take(MAX_LOGGED_BODY_CHARS).collect();
+        return format!("{}... ({} more characters)", truncated, total_chars - MAX_LOGGED_BODY_CHARS);
+    }
+    redacted
+}
+
+// Characters of a bearer token (RFC 6750 b64token)
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-._~+/=".contains(c)
+}
+
+// A 64-byte keypair is 87 or 88 base58 characters
+fn looks_like_private_key(token: &str) -> bool {
+    token.len() >= 80 && bs58::decode(token).into_vec().is_ok_and(|bytes| bytes.len() == 64)
+}
+
+// `max_elapsed` bounds the total time spent across attempts: once it has passed,
+// or the next backoff would run past it, the last error is returned even if attempts remain
 pub async fn retry_as_exponential_back_off<F, Fut, T, E>(
+    operation: F,
+    operation_name: &str,
+    max_retries: u32,
+    initial_delay_ms: u64,
+    timeout_duration: Option<Duration>,
+    max_elapsed: Option<Duration>,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: std::fmt::Display + Send + Sync + 'static,
+{
+    retry_as_exponential_back_off_with_jitter(
+        operation,
+        operation_name,
+        max_retries,
+        initial_delay_ms,
+        timeout_duration,
+        max_elapsed,
+        true,
+    )
+    .await
+}
+
+// Same as retry_as_exponential_back_off, but full jitter can be turned off
+// so that each sleep is exactly the doubled delay
+pub async fn retry_as_exponential_back_off_with_jitter<F, Fut, T, E>(
     mut operation: F,
     operation_name: &str,
     max_retries: u32,
     initial_delay_ms: u64,
     timeout_duration: Option<Duration>,
+    max_elapsed: Option<Duration>,
+    jitter: bool,
 ) -> Result<T>
 where
     F: FnMut() -> Fut,
     Fut: Future<Output = Result<T, E>>,
     E: std::fmt::Display + Send + Sync + 'static,
 {
+    let started = Instant::now();
     let mut retry_delay = Duration::from_millis(initial_delay_ms);
     
     for attempt in 0..max_retries {
         let result = if let Some(timeout_dur) = timeout_duration {
-            match timeout(timeout_dur, operation()).await {
-                Ok(result) => result,
-                Err(_) => {
-                    let error_msg = format!("{} timeout", operation_name);
-                    if attempt < max_retries - 1 {
-                        warn!(
-                            "{} (attempt {}/{}). Retrying in {:?}...",
-                            error_msg,
-                            attempt + 1,
-                            max_retries,
-                            retry_delay
-                        );
-                        sleep(retry_delay).await;
-                        retry_delay *= 2;
-                        continue;
-                    } else {
-                        error!("{} after {} attempts", error_msg, max_retries);
-                        return Err(anyhow::anyhow!(
-                            "{} after {} attempts",
-                            error_msg,
-                            max_retries
-                        ));
-                    }
-                }
-            }
+            timeout(timeout_dur, operation()).await
         } else {
-            operation().await
+            Ok(operation().await)
         };
         
-        match result {
-            Ok(value) => {
+        // The cause is None when the attempt timed out
+        let (error_msg, cause) = match result {
+            Ok(Ok(value)) => {
                 if attempt > 0 {
                     debug!("Successfully completed {} on attempt {}", operation_name, attempt + 1);
                 }
                 return Ok(value);
             }
-            Err(e) => {
-                let error_msg = format!("{} error: {}", operation_name, e);
-                
-                if attempt < max_retries - 1 {
-                    warn!(
-                        "{} (attempt {}/{}). Retrying in {:?}...",
-                        error_msg,
-                        attempt + 1,
-                        max_retries,
-                        retry_delay
-                    );
-                    sleep(retry_delay).await;
-                    retry_delay *= 2;
-                } else {
-                    error!("{} failed after {} attempts: {}", operation_name, max_retries, e);
-                    return Err(anyhow::anyhow!(
-                        "{} failed after {} attempts: {}",
-                        operation_name,
-                        max_retries,
-                        e
-                    ));
-                }
+            Ok(Err(e)) => (format!("{} error: {}", operation_name, e), Some(e.to_string())),
+            Err(_) => (format!("{} timeout", operation_name), None),
+        };
+        let give_up = |attempts: u32| match &cause {
+            Some(e) => format!("{} failed after {} attempts: {}", operation_name, attempts, e),
+            None => format!("{} after {} attempts", error_msg, attempts),
+        };
+        
+        if attempt + 1 >= max_retries {
+            let message = give_up(max_retries);
+            error!("{}", message);
+            return Err(anyhow::anyhow!(message));
+        }
+        
+        let delay = if jitter { full_jitter(retry_delay) } else { retry_delay };
+        if let Some(max_elapsed) = max_elapsed {
+            if is_retry_budget_spent(started.elapsed(), delay, max_elapsed) {
+                let message = format!("{} (retry budget of {:?} spent)", give_up(attempt + 1), max_elapsed);
+                error!("{}", message);
+                return Err(anyhow::anyhow!(message));
             }
         }
+        
+        warn!(
+            "{} (attempt {}/{}). Retrying in {:?}...",
+            error_msg,
+            attempt + 1,
+            max_retries,
+            delay
+        );
+        sleep(delay).await;
+        retry_delay *= 2;
     }
     
     unreachable!("Should have returned from the retry loop")
-}
\ No newline at end of file
+}
+
+// Whether sleeping `delay` after `elapsed` would run past the overall budget
+fn is_retry_budget_spent(elapsed: Duration, delay: Duration, max_elapsed: Duration) -> bool {
+    elapsed.saturating_add(delay) >= max_elapsed
+}
+
+// Pick a random delay in [0, delay] so concurrent instances don't retry in lockstep.
+// RandomState is seeded randomly per instance, which is enough for spreading retries.
+fn full_jitter(delay: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    let max_ms = delay.as_millis() as u64;
+    Duration::from_millis(random % (max_ms + 1))
+}
+
+// Token bucket allowing `requests_per_second` on average, with bursts of up to one second's worth
+pub struct RateLimiter {
+    requests_per_second: f64,
+    capacity: f64,
+    // (available tokens, last refill)
+    bucket: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: f64) -> Self {
+        let capacity = requests_per_second.ceil().max(1.0);
+        Self {
+            requests_per_second,
+            capacity,
+            bucket: Mutex::new((capacity, Instant::now())),
+        }
+    }
+    
+    // Wait until a request may be sent
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().await;
+                let (tokens, last_refill) = &mut *bucket;
+                let now = Instant::now();
+                *tokens = (*tokens + now.duration_since(*last_refill).as_secs_f64() * self.requests_per_second).min(self.capacity);
+                *last_refill = now;
+                
+                if *tokens >= 1.0 {
+                    *tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - *tokens) / self.requests_per_second)
+            };
+            debug!("Rate limited, waiting {:?}", wait);
+            sleep(wait).await;
+        }
+    }
+}
+
+// Retry-After is either a number of seconds or an HTTP date
+pub fn parse_retry_after(value: &str, now: chrono::DateTime<chrono::Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    
+    let retry_at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((retry_at.with_timezone(&chrono::Utc) - now).to_std().unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[tokio::test]
+    async fn rate_limiter_spaces_out_requests_after_the_burst() {
+        let limiter = RateLimiter::new(10.0);
+        let start = Instant::now();
+        
+        for _ in 0..10 {
+            limiter.acquire().await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(50), "the first second's worth should not wait");
+        
+        for _ in 0..3 {
+            limiter.acquire().await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(280), "took {:?}", start.elapsed());
+    }
+    
+    #[tokio::test]
+    async fn rate_limiter_allows_one_request_below_one_per_second() {
+        let limiter = RateLimiter::new(0.5);
+        let start = Instant::now();
+        limiter.acquire().await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+    
+    #[test]
+    fn full_jitter_stays_within_the_delay() {
+        let delay = Duration::from_millis(400);
+        let samples: Vec<Duration> = (0..200).map(|_| full_jitter(delay)).collect();
+        
+        assert!(samples.iter().all(|sample| *sample <= delay));
+        // Spread out rather than stuck at one value, as retries in lockstep would be
+        assert!(samples.iter().any(|sample| *sample != samples[0]));
+        assert_eq!(full_jitter(Duration::ZERO), Duration::ZERO);
+    }
+    
+    #[tokio::test]
+    async fn jittered_retries_sleep_at_most_the_doubled_delays() {
+        let start = Instant::now();
+        let mut attempts = 0;
+        let result: Result<()> = retry_as_exponential_back_off_with_jitter(
+            || {
+                attempts += 1;
+                async { Err::<(), _>("unavailable") }
+            },
+            "Test operation",
+            4,
+            20,
+            None,
+            None,
+            true,
+        )
+        .await;
+        
+        assert!(result.is_err());
+        assert_eq!(attempts, 4);
+        // Sleeps of at most 20 + 40 + 80 ms between the four attempts
+        assert!(start.elapsed() < Duration::from_millis(140 + 100), "took {:?}", start.elapsed());
+    }
+    
+    #[test]
+    fn retry_after_accepts_seconds_and_http_dates() {
+        let now = chrono::DateTime::parse_from_rfc3339("2026-01-01T12:00:00Z").unwrap().with_timezone(&chrono::Utc);
+        
+        assert_eq!(parse_retry_after("7", now), Some(Duration::from_secs(7)));
+        assert_eq!(parse_retry_after(" 0 ", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("Thu, 01 Jan 2026 12:00:30 GMT", now), Some(Duration::from_secs(30)));
+        // A date already passed means retry now
+        assert_eq!(parse_retry_after("Thu, 01 Jan 2026 11:59:00 GMT", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon", now), None);
+        assert_eq!(parse_retry_after("-5", now), None);
+    }
+    
+    #[test]
+    fn redact_masks_credentials_and_truncates() {
+        assert_eq!(redact("Authorization: Bearer abc123"), "Authorization: Bearer [REDACTED]");
+        assert_eq!(redact(r#"{"error":"bad token bearer x.y-z"}"#), r#"{"error":"bad token bearer [REDACTED]"}"#);
+        
+        let private_key = bs58::encode([7u8; 64]).into_string();
+        assert_eq!(redact(&format!(r#"{{"key":"{}"}}"#, private_key)), r#"{"key":"[REDACTED]"}"#);
+        assert_eq!(redact("Slippage tolerance exceeded: 6001"), "Slippage tolerance exceeded: 6001");
+        
+        let long = "a ".repeat(400);
+        assert_eq!(redact(&long), format!("{}... (300 more characters)", &long[..MAX_LOGGED_BODY_CHARS]));
+    }
+    
+    #[test]
+    fn retry_budget_is_spent_when_the_next_sleep_would_overrun_it() {
+        let budget = Duration::from_secs(10);
+        assert!(!is_retry_budget_spent(Duration::from_secs(2), Duration::from_secs(4), budget));
+        assert!(is_retry_budget_spent(Duration::from_secs(6), Duration::from_secs(4), budget));
+        assert!(is_retry_budget_spent(Duration::from_secs(9), Duration::from_secs(4), budget));
+        assert!(is_retry_budget_spent(Duration::from_secs(11), Duration::ZERO, budget));
+    }
+    
+    #[tokio::test]
+    async fn retry_budget_stops_further_attempts() {
+        let mut attempts = 0;
+        let result: Result<()> = retry_as_exponential_back_off_with_jitter(
+            || {
+                attempts += 1;
+                async { Err::<(), _>("unavailable") }
+            },
+            "Test operation",
+            10,
+            40,
+            None,
+            Some(Duration::from_millis(100)),
+            false,
+        )
+        .await;
+        
+        // Sleeps of 40 then 80 ms would pass the 100 ms budget, so the second failure is the last
+        assert_eq!(attempts, 2);
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("failed after 2 attempts: unavailable"), "{}", err);
+        assert!(err.contains("retry budget of 100ms spent"), "{}", err);
+    }
+}
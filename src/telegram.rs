@@ -0,0 +1,104 @@
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use crate::clock::format_local;
+use crate::error::notification_title;
+use crate::service::redact;
+use crate::trading::TradingState;
+
+use anyhow::{Result, Context};
+use serde::Serialize;
+use tracing::{info, error};
+
+#[derive(Debug, Serialize)]
+struct SendMessage {
+    chat_id: String,
+    text: String,
+}
+
+pub struct TelegramClient {
+    client: reqwest::Client,
+    bot_token: String,
+    chat_id: String,
+    dry_run: bool,
+}
+
+impl TelegramClient {
+    pub fn new(bot_token: &str, chat_id: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            bot_token: bot_token.to_string(),
+            chat_id: chat_id.to_string(),
+            dry_run: false,
+        }
+    }
+    
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+    
+    pub async fn send_message(&self, text: &str) -> Result<()> {
+        let send_message = SendMessage {
+            chat_id: self.chat_id.to_string(),
+            text: if self.dry_run { format!("[DRY RUN] {}", text) } else { text.to_string() },
+        };
+        
+        let response = self.client
+            .post(format!("https://api.telegram.org/bot{}/sendMessage", self.bot_token))
+            .json(&send_message)
+            .send()
+            .await
+            // The URL contains the bot token
+            .map_err(|e| e.without_url())
+            .context("Failed to send Telegram message")?;
+        
+        if !response.status().is_success() {
+            let error_text = redact(&response.text().await?);
+            error!("Telegram API error: {}", error_text);
+            anyhow::bail!("Failed to send Telegram message: {}", error_text);
+        }
+        
+        info!("Telegram message sent successfully");
+        Ok(())
+    }
+    
+    pub async fn send_success_notification(
+        &self,
+        state: &TradingState,
+        profit: Decimal,
+    ) -> Result<()> {
+        let trade_price = state.last_trade_price.unwrap_or(dec!(0));
+        let message = format!(
+            "😎 Trade executed!\n\
+            Pair: {}\n\
+            Position: {}\n\
+            Trade Price: {:.4} {}\n\
+            Profit: {}\n\
+            {}Time: {}",
+            state.pair.name,
+            state.position_symbol(),
+            trade_price,
+            state.pair.quote_symbol(),
+            state.format_profit(profit),
+            state.last_explorer_url.as_ref().map(|url| format!("Tx: {}\n", url)).unwrap_or_default(),
+            format_local(&chrono::Utc::now())
+        );
+        self.send_message(&message).await
+    }
+    
+    pub async fn send_error_notification(
+        &self,
+        e: &anyhow::Error,
+    ) -> Result<()> {
+        let title = notification_title(e);
+        let message = format!(
+            "{}\n\
+            {}\n\
+            Time: {}",
+            title,
+            e,
+            format_local(&chrono::Utc::now())
+        );
+        self.send_message(&message).await
+    }
+}
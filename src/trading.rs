@@ -1,107 +1,326 @@
 use anyhow::Result;
-use chrono::{FixedOffset, TimeZone, Duration};
-use chrono_tz::Asia::Tokyo;
+use chrono::{DateTime, FixedOffset, Duration};
 use rust_decimal::prelude::*;
 use rust_decimal_macros::dec;
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::pubkey::Pubkey;
 use std::str::FromStr;
 use std::sync::Arc;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use crate::{
-    config::Config,
-    firestore::{FirestoreDb, PriceHistory, TradingSession, ProfitTracking, generate_session_id, validate_price_data},
-    jupiter::JupiterClient,
-    wallet::Wallet,
+    clock::now_local,
+    config::{Config, LadderRung, OrderMode, Strategy, TradingPair},
+    error::TradingError,
+    firestore::{CircuitBreakerState, FirestoreDb, LimitOrderState, OpenLimitOrder, PriceHistory, TradingSession, TradingStateSnapshot, ProfitTracking, TrailingStopState, generate_session_id, validate_price_data},
+    indicators,
+    jupiter::{JupiterClient, SwapResult, estimate_swap_fee, is_suspicious_fill, slippage_bps},
+    metrics::metrics,
+    price_source::{price_divergence, PriceSource, PythPriceSource},
+    rpc,
+    wallet::{checked_ui_amount, is_native_mint, raw_price_to_ui, raw_to_ui_amount, to_base_units, ui_to_raw_amount, Wallet, SOL_DECIMALS},
 };
 
 fn f64_to_decimal(value: f64, default: u32) -> Decimal {
     Decimal::from_f64(value).unwrap_or(Decimal::from(default))
 }
 
+// Which side of the pair the wallet is holding
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum Position {
-    SOL,
-    USDC,
+    Base,
+    Quote,
 }
 
-impl std::fmt::Display for Position {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Position::SOL => write!(f, "SOL"),
-            Position::USDC => write!(f, "USDC"),
+// Manual trade requested through /admin/force-trade, bypassing the strategy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ForcedAction {
+    Buy,
+    Sell,
+}
+
+impl FromStr for ForcedAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "buy" => Ok(ForcedAction::Buy),
+            "sell" => Ok(ForcedAction::Sell),
+            _ => anyhow::bail!("Unknown action: {}", s),
         }
     }
 }
 
 pub struct TradingState {
+    pub pair: TradingPair,
     pub position: Position,
-    pub last_sol_price: Option<Decimal>,
-    pub last_usdc_price: Option<Decimal>,
+    // Base price at the last trade, reloaded from the latest trading session
+    pub last_base_price: Option<Decimal>,
+    // Most recently observed prices, which profit calculations must not use
+    pub last_observed_price: Option<Decimal>,
+    pub last_quote_price: Option<Decimal>,
+    // Price the profit calculation is measured against (same as last_base_price)
     pub last_trade_price: Option<Decimal>,
     pub last_trade_timestamp: Option<chrono::DateTime<FixedOffset>>,
-    pub total_profit_usdc: Decimal,
+    // Highest base price since entering the base position, for the trailing stop
+    pub peak_price: Option<Decimal>,
+    // Take profit ladder rungs already sold since entering the base position
+    pub ladder_rungs_fired: usize,
+    // When the base position was entered, None while holding the quote token or if unknown
+    pub entered_at: Option<chrono::DateTime<FixedOffset>>,
+    // Volume-weighted price of the base token bought into the current position, the cost basis for sells
+    pub average_entry_price: Option<Decimal>,
+    // Base tokens bought into the current position and not yet sold
+    pub position_size: Decimal,
+    // Transaction fees paid buying into the current position, in quote tokens
+    pub entry_fees: Decimal,
+    // In the reporting currency
+    pub total_profit: Decimal,
     pub total_trades: i64,
     pub winning_trades: i64,
     pub losing_trades: i64,
+    pub consecutive_losses: u32,
+    // Set once consecutive_losses reaches max_consecutive_losses, cleared only by a manual reset
+    pub circuit_broken: bool,
     pub gas_fee: Option<Decimal>,
+    // Signature and explorer link of the last swap, None in dry run
+    pub last_signature: Option<String>,
+    pub last_explorer_url: Option<String>,
+    // Session of the latest trade, taken once it has been posted to RESULT_WEBHOOK_URL
+    pub last_session: Option<TradingSession>,
+    // Symbol profits are converted to and reported in
+    pub reporting_currency: String,
     pub firestore: Option<Arc<FirestoreDb>>,
 }
 
 impl TradingState {
-    pub fn new() -> Self {
+    pub fn new(pair: TradingPair) -> Self {
+        let reporting_currency = pair.quote_symbol().to_string();
         Self {
-            position: Position::USDC,
-            last_sol_price: None,
-            last_usdc_price: None,
+            pair,
+            position: Position::Quote,
+            last_base_price: None,
+            last_observed_price: None,
+            last_quote_price: None,
             last_trade_price: None,
             last_trade_timestamp: None,
-            total_profit_usdc: dec!(0),
+            peak_price: None,
+            ladder_rungs_fired: 0,
+            entered_at: None,
+            average_entry_price: None,
+            position_size: dec!(0),
+            entry_fees: dec!(0),
+            total_profit: dec!(0),
             total_trades: 0,
             winning_trades: 0,
             losing_trades: 0,
+            consecutive_losses: 0,
+            circuit_broken: false,
             gas_fee: None,
+            last_signature: None,
+            last_explorer_url: None,
+            last_session: None,
+            reporting_currency,
             firestore: None,
         }
     }
     
+    pub fn with_reporting_currency(mut self, reporting_currency: &str) -> Self {
+        self.reporting_currency = reporting_currency.to_string();
+        self
+    }
+    
+    // An amount in the reporting currency, as shown in notifications
+    pub fn format_profit(&self, amount: Decimal) -> String {
+        format!("{:.4} {}", amount, self.reporting_currency)
+    }
+    
     pub fn with_firestore(mut self, firestore: Arc<FirestoreDb>) -> Self {
         self.firestore = Some(firestore);
         self
     }
     
+    // Price the base token has to be sold at to cover the entry cost and its fees
+    pub fn break_even_price(&self) -> Option<Decimal> {
+        let average_entry_price = self.average_entry_price?;
+        if self.position_size <= dec!(0) {
+            return Some(average_entry_price);
+        }
+        Some(average_entry_price + self.entry_fees / self.position_size)
+    }
+    
+    // Add a buy of `amount` base tokens at `price` to the average entry price
+    pub fn record_entry(&mut self, price: Decimal, amount: Decimal, fee: Decimal) {
+        self.average_entry_price = Some(average_entry_price(self.average_entry_price, self.position_size, price, amount));
+        self.position_size += amount;
+        self.entry_fees += fee;
+    }
+    
+    pub fn clear_entry(&mut self) {
+        self.average_entry_price = None;
+        self.position_size = dec!(0);
+        self.entry_fees = dec!(0);
+    }
+    
+    // Hold the base token after buying `amount` of it at `price`, with the stops measured from there
+    pub fn enter_position(&mut self, price: Decimal, amount: Decimal, fee: Decimal, now: DateTime<FixedOffset>) {
+        self.last_base_price = Some(price);
+        self.last_trade_price = Some(price);
+        self.last_trade_timestamp = Some(now);
+        self.position = Position::Base;
+        self.peak_price = Some(price);
+        self.ladder_rungs_fired = 0;
+        self.entered_at = Some(now);
+        self.record_entry(price, amount, fee);
+    }
+    
+    // Hold the quote token after selling the whole position at `price`.
+    // The caller sets last_trade_timestamp, which a partial ladder sell also moves.
+    pub fn exit_position(&mut self, price: Decimal) {
+        self.last_base_price = Some(price);
+        self.last_trade_price = Some(price);
+        self.position = Position::Quote;
+        self.peak_price = None;
+        self.ladder_rungs_fired = 0;
+        self.entered_at = None;
+        self.clear_entry();
+    }
+    
+    // Symbol of the token currently held, as stored in trading sessions
+    pub fn position_symbol(&self) -> &str {
+        match self.position {
+            Position::Base => self.pair.base_symbol(),
+            Position::Quote => self.pair.quote_symbol(),
+        }
+    }
+    
+    pub fn snapshot(&self) -> TradingStateSnapshot {
+        TradingStateSnapshot {
+            pair: self.pair.name.clone(),
+            position: self.position.clone(),
+            last_base_price: self.last_base_price,
+            last_observed_price: self.last_observed_price,
+            last_quote_price: self.last_quote_price,
+            last_trade_price: self.last_trade_price,
+            last_trade_timestamp: self.last_trade_timestamp,
+            peak_price: self.peak_price,
+            ladder_rungs_fired: self.ladder_rungs_fired,
+            entered_at: self.entered_at,
+            average_entry_price: self.average_entry_price,
+            position_size: self.position_size,
+            entry_fees: self.entry_fees,
+            total_profit: self.total_profit,
+            total_trades: self.total_trades,
+            winning_trades: self.winning_trades,
+            losing_trades: self.losing_trades,
+            consecutive_losses: self.consecutive_losses,
+            circuit_broken: self.circuit_broken,
+            gas_fee: self.gas_fee,
+            updated_at: now_local(),
+        }
+    }
+    
+    pub fn restore(&mut self, snapshot: TradingStateSnapshot) {
+        self.position = snapshot.position;
+        self.last_base_price = snapshot.last_base_price;
+        self.last_observed_price = snapshot.last_observed_price;
+        self.last_quote_price = snapshot.last_quote_price;
+        self.last_trade_price = snapshot.last_trade_price;
+        self.last_trade_timestamp = snapshot.last_trade_timestamp;
+        self.peak_price = snapshot.peak_price;
+        self.ladder_rungs_fired = snapshot.ladder_rungs_fired;
+        self.entered_at = snapshot.entered_at;
+        self.average_entry_price = snapshot.average_entry_price;
+        self.position_size = snapshot.position_size;
+        self.entry_fees = snapshot.entry_fees;
+        self.total_profit = snapshot.total_profit;
+        self.total_trades = snapshot.total_trades;
+        self.winning_trades = snapshot.winning_trades;
+        self.losing_trades = snapshot.losing_trades;
+        self.consecutive_losses = snapshot.consecutive_losses;
+        self.circuit_broken = snapshot.circuit_broken;
+        self.gas_fee = snapshot.gas_fee;
+    }
+    
+    // Prefer the trading_state snapshot, falling back to rebuilding the state from sessions,
+    // profit tracking and prices for pairs that have never saved one
     pub async fn load_from_firestore(&mut self) -> Result<()> {
-        if let Some(db) = &self.firestore {
+        if let Some(db) = self.firestore.clone() {
+            match db.load_trading_state(&self.pair.name).await {
+                Ok(Some(snapshot)) => {
+                    self.restore(snapshot);
+                    info!("Loaded {} trading state snapshot: {}, {} trades, {} {} profit",
+                        self.pair.name, self.position_symbol(), self.total_trades, self.total_profit, self.reporting_currency);
+                    
+                    // /admin/reset-circuit only updates the circuit breaker document
+                    if let Ok(Some(circuit_breaker)) = db.get_circuit_breaker(&self.pair.name).await {
+                        self.consecutive_losses = circuit_breaker.consecutive_losses;
+                        self.circuit_broken = circuit_breaker.circuit_broken;
+                    }
+                    return Ok(());
+                }
+                Ok(None) => info!("No {} trading state snapshot, rebuilding from history", self.pair.name),
+                Err(e) => warn!("Failed to load {} trading state snapshot, rebuilding from history: {}", self.pair.name, e),
+            }
+        }
+        
+        if let Some(db) = self.firestore.clone() {
             // Load position from latest trading session
-            if let Ok(Some(latest_session)) = db.get_latest_trading_session().await {
-                self.position = match latest_session.position_after.as_str() {
-                    "SOL" => Position::SOL,
-                    "USDC" => Position::USDC,
-                    _ => Position::USDC, // Default to USDC if unknown
-                };
-                self.last_trade_price = Some(latest_session.price_at_trade);
-                self.last_trade_timestamp = Some(latest_session.timestamp);
-                self.gas_fee = latest_session.gas_fee;
-                info!("Loaded position from latest trading session: {}, price: {}", self.position, latest_session.price_at_trade);
+            if let Ok(Some(latest_session)) = db.get_latest_trading_session(&self.pair.name).await {
+                self.apply_latest_session(&latest_session);
+                info!("Loaded {} position from latest trading session: {}, price: {}",
+                    self.pair.name, self.position_symbol(), latest_session.price_at_trade);
             }
             
-            if let Ok(Some(latest_profit)) = db.get_latest_profit_tracking().await {
-                self.total_profit_usdc = latest_profit.cumulative_profit_usdc;
+            if let Ok(Some(latest_profit)) = db.get_latest_profit_tracking(&self.pair.name).await {
+                self.total_profit = latest_profit.cumulative_profit_usdc;
                 self.total_trades = latest_profit.total_trades;
                 self.winning_trades = latest_profit.winning_trades;
                 self.losing_trades = latest_profit.losing_trades;
-                info!("Loaded trading state from Firestore: {} trades, {} USDC profit", 
-                    self.total_trades, self.total_profit_usdc);
+                info!("Loaded {} trading state from Firestore: {} trades, {} {} profit",
+                    self.pair.name, self.total_trades, self.total_profit, latest_profit.currency);
+                if latest_profit.currency != self.reporting_currency {
+                    warn!("{} profit was tracked in {} but is now reported in {}, the total mixes both",
+                        self.pair.name, latest_profit.currency, self.reporting_currency);
+                }
+            }
+            
+            if let Ok(Some(circuit_breaker)) = db.get_circuit_breaker(&self.pair.name).await {
+                self.consecutive_losses = circuit_breaker.consecutive_losses;
+                self.circuit_broken = circuit_breaker.circuit_broken;
             }
             
-            if let Ok(Some(latest_price)) = db.get_latest_price().await {
-                self.last_sol_price = Some(latest_price.sol_price_usdc);
-                self.last_usdc_price = Some(latest_price.usdc_price_sol);
+            if self.position == Position::Base {
+                if let Ok(Some(trailing_stop)) = db.get_trailing_stop(&self.pair.name).await {
+                    self.peak_price = trailing_stop.peak_price;
+                }
+            }
+            
+            if let Ok(Some(latest_price)) = db.get_latest_price(&self.pair.name).await {
+                self.apply_latest_price(&latest_price);
             }
         }
         Ok(())
     }
+    
+    // Profit is measured from the price of the last trade, never from the latest tick
+    fn apply_latest_session(&mut self, session: &TradingSession) {
+        // Default to the quote token if the symbol is unknown
+        self.position = if session.position_after == self.pair.base_symbol() {
+            Position::Base
+        } else {
+            Position::Quote
+        };
+        self.last_base_price = Some(session.price_at_trade);
+        self.last_trade_price = Some(session.price_at_trade);
+        self.last_trade_timestamp = Some(session.timestamp);
+        self.gas_fee = session.gas_fee;
+    }
+    
+    fn apply_latest_price(&mut self, price: &PriceHistory) {
+        self.last_observed_price = Some(price.sol_price_usdc);
+        self.last_quote_price = Some(price.usdc_price_sol);
+    }
 }
 
 /*
@@ -129,7 +348,7 @@ pub async fn perform_initial_swap(wallet: &Wallet, config: &Config) -> Result<()
         &config.sol_mint,
         &config.usdc_mint,
         amount_lamports,
-        config.slippage_bps,
+        config,
     ).await?;
     
     info!("Initial swap completed: {}", signature);
@@ -138,61 +357,199 @@ pub async fn perform_initial_swap(wallet: &Wallet, config: &Config) -> Result<()
 }
 */
 
+// Evaluate the strategy for the state's pair and trade when it signals.
+// The resulting state is saved to Firestore whether or not a trade was made.
 pub async fn check_and_trade(
     wallet: &Wallet,
     config: &Config,
     state: &mut TradingState,
 ) -> Result<Option<Decimal>> {
-    let rpc_client = RpcClient::new(&config.rpc_url);
-    let jupiter_client = JupiterClient::new(&config.jupiter_api_url);
+    let result = evaluate_and_trade(wallet, config, state, None).await;
+    save_trading_state(state).await;
+    result
+}
+
+// Buy or sell now regardless of the strategy, cooldown and minimum profit. The action has to
+// match the position, since the bot always swaps from the token it is holding.
+pub async fn force_trade(
+    wallet: &Wallet,
+    config: &Config,
+    state: &mut TradingState,
+    action: ForcedAction,
+) -> Result<Option<Decimal>> {
+    check_forced_action(action, state)?;
+    
+    let result = evaluate_and_trade(wallet, config, state, Some(action)).await;
+    save_trading_state(state).await;
+    result
+}
+
+pub(crate) fn check_forced_action(action: ForcedAction, state: &TradingState) -> Result<(), TradingError> {
+    let (allowed, verb) = match action {
+        ForcedAction::Buy => (state.position == Position::Quote, "buy"),
+        ForcedAction::Sell => (state.position == Position::Base, "sell"),
+    };
+    if allowed {
+        return Ok(());
+    }
+    Err(TradingError::PositionMismatch {
+        pair: state.pair.name.clone(),
+        action: verb.to_string(),
+        position: state.position_symbol().to_string(),
+    })
+}
+
+async fn save_trading_state(state: &TradingState) {
+    if let Some(db) = &state.firestore {
+        if let Err(e) = db.save_trading_state(&state.snapshot()).await {
+            error!("Failed to save {} trading state: {}", state.pair.name, e);
+        }
+    }
+}
+
+async fn evaluate_and_trade(
+    wallet: &Wallet,
+    config: &Config,
+    state: &mut TradingState,
+    forced: Option<ForcedAction>,
+) -> Result<Option<Decimal>> {
+    let rpc_client = rpc::connect(config)
+        .map_err(|e| TradingError::RpcError(e.to_string()))?;
+    let jupiter_client = JupiterClient::shared(config);
+    let pair = state.pair.clone();
+    // Correlation ID shared by the price point, the trading session it triggers and its profit record
+    let trading_session_id = generate_session_id();
+    
+    // A manual trade is the operator's call, so the circuit breaker doesn't block it
+    if state.circuit_broken && forced.is_none() {
+        info!("Circuit breaker is tripped for {}, skipping trade", pair.name);
+        return Ok(None);
+    }
     
-    // Get current prices
-    let (sol_price_in_usdc, usdc_price_in_sol) = get_current_prices(&jupiter_client, config).await?;
+    if let (None, Some(max_consecutive_losses)) = (forced, config.max_consecutive_losses) {
+        if state.consecutive_losses >= max_consecutive_losses {
+            // Persist the flag so later runs skip silently, and report the trip once through the error notification
+            state.circuit_broken = true;
+            if let Some(db) = &state.firestore {
+                if let Err(e) = store_circuit_breaker(db, state).await {
+                    error!("Failed to store circuit breaker state: {}", e);
+                }
+            }
+            return Err(TradingError::CircuitBroken { consecutive_losses: state.consecutive_losses }.into());
+        }
+    }
     
-    info!("Current prices - SOL/USDC: {}, USDC/SOL: {}", sol_price_in_usdc, usdc_price_in_sol);
+    let base_mint = Pubkey::from_str(&pair.base_mint)?;
+    let quote_mint = Pubkey::from_str(&pair.quote_mint)?;
+    
+    // Get current prices, rounded once so the stored price is the one the thresholds were checked against
+    let (base_price, quote_price) = get_current_prices(jupiter_client, &pair).await?;
+    // Measured before rounding, which would swamp the spread of a small inverse price
+    let spread = implied_spread(base_price, quote_price);
+    let (base_price, quote_price) = (round_price(base_price, config), round_price(quote_price, config));
+    
+    info!("Current prices - {}: {}, inverse: {}", pair.name, base_price, quote_price);
     
     // Validate prices
-    validate_price_data(sol_price_in_usdc)?;
-    validate_price_data(usdc_price_in_sol)?;
+    validate_price_data(base_price)?;
+    validate_price_data(quote_price)?;
+    
+    state.last_observed_price = Some(base_price);
+    metrics().last_price.with_label_values(&[&pair.name]).set(base_price.to_f64().unwrap_or(0.0));
+    state.last_quote_price = Some(quote_price);
+    
+    if state.position == Position::Base {
+        update_peak_price(state, base_price).await;
+    }
     
     // Store price history in Firestore
     if let Some(db) = &state.firestore {
-        let price_history = PriceHistory {
-            id: generate_session_id(),
-            timestamp: Tokyo.from_utc_datetime(&chrono::Utc::now().naive_utc()).with_timezone(&FixedOffset::east_opt(9 * 3600).unwrap()),
-            sol_price_usdc: sol_price_in_usdc,
-            usdc_price_sol: usdc_price_in_sol,
-            data_source: "Jupiter".to_string(),
-            trading_session_id: generate_session_id(),
-        };
-        
+        let price_history = price_history_record(&pair, trading_session_id.clone(), base_price, quote_price, spread);
         if let Err(e) = db.store_price_history(&price_history).await {
             error!("Failed to store price history: {}", e);
         }
     }
     
-    let mut profit: Option<Decimal> = None;
-    let trading_session_id = generate_session_id();
+    if let (None, Some(spread), Some(max_spread)) = (forced, spread, config.max_spread_pct) {
+        if is_spread_too_wide(spread, max_spread) {
+            warn!("{} quotes are {}% apart, more than the maximum spread of {}%, skipping trade",
+                pair.name, (spread * dec!(100)).round_dp(4), max_spread * dec!(100));
+            return Ok(None);
+        }
+    }
+    
+    // Limit orders are placed relative to the last trade price, so the first trade is always a market swap
+    if config.order_mode == OrderMode::Limit && forced.is_none() && state.last_trade_price.is_some() && state.firestore.is_some() {
+        match manage_limit_order(wallet, config, state, jupiter_client, &rpc_client, base_price, trading_session_id.clone()).await? {
+            LimitOrderOutcome::Handled(profit) => return Ok(profit),
+            LimitOrderOutcome::Market => info!("Falling back to a market swap for {}", pair.name),
+        }
+    }
+    
+    let profit: Option<Decimal>;
+    
+    // Unwrap leftover wSOL first so that it's part of the native balance the swap is sized from
+    if config.close_wrapped_sol && !config.dry_run && (is_native_mint(&base_mint) || is_native_mint(&quote_mint)) {
+        if let Err(e) = wallet.close_wrapped_sol_account(&rpc_client).await {
+            warn!("Failed to close the wSOL account: {}", e);
+        }
+    }
     
     // Get current balances before trade
-    let sol_balance_before = wallet.get_sol_balance(&rpc_client).await?;
-    let usdc_mint = Pubkey::from_str(&config.usdc_mint)?;
-    let usdc_balance_before = wallet.get_token_balance(&rpc_client, &usdc_mint).await?;
-    
-    // Fetch price trend data
-    let should_trade = if let Some(db) = &state.firestore {
-        match db.get_price_trend(sol_price_in_usdc).await {
-            Ok(trend) => {
-                info!("Price trend - 1h: {:?}, 24h: {:?}, 7d: {:?}", 
-                    trend.trend_1h, trend.trend_24h, trend.trend_7d);
-                
-                // Enhanced trading logic based on price trends
-                should_make_trade(&state.position, &trend, sol_price_in_usdc, usdc_price_in_sol, state)
-            }
-            Err(e) => {
-                error!("Failed to get price trend: {}", e);
-                return Ok(None)
-            }
+    let base_balance_before = wallet.get_balance(&rpc_client, &base_mint).await
+        .map_err(|e| TradingError::RpcError(e.to_string()))?;
+    let quote_balance_before = wallet.get_balance(&rpc_client, &quote_mint).await
+        .map_err(|e| TradingError::RpcError(e.to_string()))?;
+    
+    // A time exit sells the whole position, so it takes precedence over a ladder rung
+    let time_exit = forced.is_none() && is_time_exit(state, config, now_local());
+    let ladder_rung = match (forced, &state.position) {
+        (None, Position::Base) if !time_exit => next_ladder_rung(state, base_price, config),
+        _ => None,
+    };
+    
+    // Fetch price data for the configured strategy
+    let should_trade = if let Some(action) = forced {
+        info!("Forcing a {:?} of {}, skipping the strategy", action, pair.name);
+        true
+    } else if time_exit {
+        info!("{} position held since {:?}, over the maximum of {}h, selling at {} regardless of price",
+            pair.name, state.entered_at, config.max_hold_hours.unwrap_or_default(), base_price);
+        true
+    } else if let Some(rung) = ladder_rung {
+        info!("{} reached take profit ladder rung {} at {}", pair.name, rung + 1, base_price);
+        true
+    } else if let Some(db) = &state.firestore {
+        match config.strategy {
+            Strategy::ThresholdPct => match db.get_price_trend(&pair.name, base_price).await {
+                Ok(trend) => {
+                    info!("Price trend - 1h: {:?}, 24h: {:?}, 7d: {:?}", 
+                        trend.trend_1h, trend.trend_24h, trend.trend_7d);
+                    
+                    // Enhanced trading logic based on price trends
+                    let now = now_local();
+                    should_make_trade(&state.position, &trend, base_price, quote_price, now, state, config)
+                }
+                Err(e) => {
+                    error!("Failed to get price trend: {}", e);
+                    return Ok(None)
+                }
+            },
+            Strategy::SmaCrossover => match db.get_price_history(&pair.name, config.sma_long_hours).await {
+                Ok(prices) => should_trade_sma_crossover(&state.position, &prices, base_price, state, config),
+                Err(e) => {
+                    error!("Failed to get price history: {}", e);
+                    return Ok(None)
+                }
+            },
+            // Assumes roughly hourly prices, with extra history to warm up Wilder's smoothing
+            Strategy::Rsi => match db.get_price_history(&pair.name, (config.rsi_period * 3) as u32).await {
+                Ok(prices) => should_trade_rsi(&state.position, &prices, base_price, state, config),
+                Err(e) => {
+                    error!("Failed to get price history: {}", e);
+                    return Ok(None)
+                }
+            },
         }
     } else {
         return Ok(None)
@@ -202,184 +559,302 @@ pub async fn check_and_trade(
         return Ok(None);
     }
     
+    let now = now_local();
+    if let (None, Some(remaining)) = (forced, cooldown_remaining(state, config, now)) {
+        info!("Trade signal for {} ignored, cooldown active, {} seconds remaining", pair.name, remaining.num_seconds());
+        return Ok(None);
+    }
+    
+    // Quotes and swaps below use the effective slippage
+    let effective_slippage = effective_slippage_bps(state.firestore.as_deref(), &pair.name, base_price, config).await;
+    let config = &Config { slippage_bps: effective_slippage, ..config.clone() };
+    
+    // Profit is measured in the quote token, then converted at the current rate
+    let reporting_rate = reporting_rate(jupiter_client, &pair, base_price, config).await?;
+    
+    if let Some(max_divergence) = config.max_price_divergence_pct {
+        check_reference_price(&rpc_client, &pair, base_price, max_divergence, config).await?;
+    }
+    
     match state.position {
-        Position::USDC => {
-            info!("Executing swap USDC -> SOL");
+        Position::Quote => {
+            info!(pair = %pair.name, position = state.position_symbol(), "Executing swap {} -> {}", pair.quote_symbol(), pair.base_symbol());
+            let action = if forced.is_some() {
+                "MANUAL_BUY".to_string()
+            } else {
+                format!("BUY_{}", pair.base_symbol())
+            };
             
-            if usdc_balance_before > 0.0 {
-                // Convert USDC amount to smallest unit (6 decimals for USDC)
-                let amount = (usdc_balance_before * 1_000_000.0) as u64;
+            let quote_available = spendable_balance(checked_ui_amount(quote_balance_before)?, &quote_mint, config)?;
+            let quote_to_swap = size_trade(quote_available, dec!(1), config)
+                .ok_or_else(|| TradingError::InsufficientBalance {
+                    token: pair.quote_symbol().to_string(),
+                    available: quote_available,
+                })?;
+            
+            let amount = to_base_units(quote_to_swap, pair.quote_decimals)?;
+            
+            if forced.is_none() && !meets_min_profit(jupiter_client, &rpc_client, &pair, state, amount, base_price, config).await? {
+                return Ok(None);
+            }
+            
+            let (gas_fee, slippage, suspicious_fill, signature, base_balance_after, quote_balance_after) = if config.dry_run {
+                // Estimate balances after trade from the quote instead of swapping
+                let quote = jupiter_client.get_quote(&pair.quote_mint, &pair.base_mint, amount, config.slippage_bps).await?;
+                let base_out = raw_to_ui_amount(quote.out_amount.parse()?, pair.base_decimals);
+                info!("[DRY RUN] Would swap {} {} for {} {}", quote_to_swap, pair.quote_symbol(), base_out, pair.base_symbol());
                 
-                let signature = jupiter_client.execute_swap(
+                (
+                    None,
+                    None,
+                    false,
+                    None,
+                    base_balance_before + base_out.to_f64().unwrap_or(0.0),
+                    quote_balance_before - raw_to_ui_amount(amount, pair.quote_decimals).to_f64().unwrap_or(0.0),
+                )
+            } else {
+                if !is_native_mint(&base_mint) {
+                    wallet.ensure_token_account(&rpc_client, &base_mint).await?;
+                }
+                
+                let swap = jupiter_client.execute_swap(
                     &rpc_client,
                     wallet,
-                    &config.usdc_mint,
-                    &config.sol_mint,
+                    &pair.quote_mint,
+                    &pair.base_mint,
                     amount,
-                    config.slippage_bps,
+                    config,
                 ).await?;
                 
-                info!("Swap completed: {}", signature);
-
-                let gas_fee = wallet.get_gas_fee(&rpc_client, signature.clone()).await.unwrap_or(0.0);
+                info!(pair = %pair.name, signature = %swap.signature, "Swap completed");
                 
                 // Get balances after trade
-                let sol_balance_after = wallet.get_sol_balance(&rpc_client).await?;
-                let usdc_balance_after = wallet.get_token_balance(&rpc_client, &usdc_mint).await?;
+                let base_balance_after = wallet.get_balance(&rpc_client, &base_mint).await?;
+                let quote_balance_after = wallet.get_balance(&rpc_client, &quote_mint).await?;
                 
-                // Calculate profit/loss
-                let sol_gained = sol_balance_after - sol_balance_before;
-                let usdc_spent = usdc_balance_before - usdc_balance_after;
-                let effective_price = if sol_gained > 0.0 { usdc_spent / sol_gained } else { 0.0 };
-
-                let profit_loss = if let Some(last_trade_price) = state.last_trade_price {
-                    let price_difference = sol_price_in_usdc - last_trade_price;
-                    let profit = price_difference * (f64_to_decimal(sol_gained, 0) * dec!(1_000_000_000)) - f64_to_decimal(gas_fee, 0);
+                // The transaction fee is paid in SOL, so add it back when SOL is the token received
+                let mut base_received = f64_to_decimal(base_balance_after - base_balance_before, 0);
+                if is_native_mint(&base_mint) {
+                    base_received += swap.gas_fee.unwrap_or(dec!(0));
+                }
+                let slippage = slippage_bps(raw_to_ui_amount(swap.quoted_out_amount, pair.base_decimals), base_received);
+                let suspicious_fill = check_fill(&pair, &swap, base_received, pair.base_decimals, config);
+                
+                (swap.gas_fee, slippage, suspicious_fill, Some(swap.signature), base_balance_after, quote_balance_after)
+            };
+            state.last_signature = signature.clone();
+            state.last_explorer_url = signature.as_deref().map(|signature| config.explorer_url(signature));
+            
+            // Calculate profit/loss
+            let base_gained = base_balance_after - base_balance_before;
 
-                    state.total_profit_usdc += profit;
-                    
-                    match profit.cmp(&dec!(0)) {
-                        std::cmp::Ordering::Greater => state.winning_trades += 1,
-                        std::cmp::Ordering::Less => state.losing_trades += 1,
-                        std::cmp::Ordering::Equal => {}
-                    }
-                    
-                    Some(profit)
-                } else {
-                    None
-                };
+            let profit_loss = state.last_trade_price.map(|last_trade_price| {
+                let price_difference = base_price - last_trade_price;
+                (price_difference * f64_to_decimal(base_gained, 0) - fee_in_quote(gas_fee, &pair, base_price)) * reporting_rate
+            });
 
-                profit = profit_loss;
+            profit = profit_loss;
+            record_trade(state, profit_loss);
+            // No update_consecutive_losses here: a dip buy is measured against the higher sell price
+            // before it, so it would always count as a loss
+            
+            let session = TradingSession {
+                id: trading_session_id.clone(),
+                pair: pair.name.clone(),
+                timestamp: now_local(),
+                position_before: pair.quote_symbol().to_string(),
+                position_after: pair.base_symbol().to_string(),
+                action: session_action(&action, config),
+                sol_balance_before: f64_to_decimal(base_balance_before, 0),
+                usdc_balance_before: f64_to_decimal(quote_balance_before, 0),
+                sol_balance_after: f64_to_decimal(base_balance_after, 0),
+                usdc_balance_after: f64_to_decimal(quote_balance_after, 0),
+                price_at_trade: base_price,
+                slippage,
+                gas_fee,
+                signature,
+                profit_loss,
+                cumulative_profit: Some(state.total_profit),
+                suspicious_fill,
+                holding_duration_secs: None,
+            };
+            
+            // Store trading session
+            if let Some(db) = &state.firestore {
+                if let Err(e) = db.store_trading_session(&session).await {
+                    error!("Failed to store trading session: {}", e);
+                }
                 
-                // Store trading session
-                if let Some(db) = &state.firestore {
-                    let session = TradingSession {
-                        id: trading_session_id.clone(),
-                        timestamp: Tokyo.from_utc_datetime(&chrono::Utc::now().naive_utc()).with_timezone(&FixedOffset::east_opt(9 * 3600).unwrap()),
-                        position_before: "USDC".to_string(),
-                        position_after: "SOL".to_string(),
-                        action: "BUY_SOL".to_string(),
-                        sol_balance_before: f64_to_decimal(sol_balance_before, 0),
-                        usdc_balance_before: f64_to_decimal(usdc_balance_before, 0),
-                        sol_balance_after: f64_to_decimal(sol_balance_after, 0),
-                        usdc_balance_after: f64_to_decimal(usdc_balance_after, 0),
-                        price_at_trade: sol_price_in_usdc,
-                        slippage: Some(f64_to_decimal(effective_price, 0) - sol_price_in_usdc),
-                        gas_fee: Some(f64_to_decimal(gas_fee, 0)),
-                        profit_loss,
-                        cumulative_profit: Some(state.total_profit_usdc),
-                    };
-                    
-                    if let Err(e) = db.store_trading_session(&session).await {
-                        error!("Failed to store trading session: {}", e);
+                // Update profit tracking
+                if let Some(profit_loss) = profit_loss {
+                    store_profit_tracking(db, state, trading_session_id, profit_loss, f64_to_decimal(quote_balance_before, 0) * reporting_rate).await;
+                    if let Err(e) = store_circuit_breaker(db, state).await {
+                        error!("Failed to store circuit breaker state: {}", e);
                     }
                 }
-                
-                // Update last trade price and position
-                state.last_trade_price = Some(sol_price_in_usdc);
-                state.position = Position::SOL;
+            }
+            state.last_session = Some(session);
+            
+            // Update last trade price and position
+            state.enter_position(base_price, f64_to_decimal(base_gained, 0), fee_in_quote(gas_fee, &pair, base_price), now);
+            if let Some(db) = &state.firestore {
+                if let Err(e) = store_trailing_stop(db, state).await {
+                    error!("Failed to store trailing stop state: {}", e);
+                }
             }
         }
-        Position::SOL => {
-            info!("Executing swap SOL -> USDC");
+        Position::Base => {
+            info!(pair = %pair.name, position = state.position_symbol(), "Executing swap {} -> {}", pair.base_symbol(), pair.quote_symbol());
+            // A stop exits the whole position even when a ladder rung was also reached
+            let (action, ladder_rung) = if forced.is_some() {
+                ("MANUAL_SELL".to_string(), None)
+            } else if is_stop_loss(base_price, state, config) {
+                ("STOP_LOSS".to_string(), None)
+            } else if is_trailing_stop(base_price, state, config) {
+                ("TRAILING_STOP".to_string(), None)
+            } else if time_exit {
+                ("TIME_EXIT".to_string(), None)
+            } else if let Some(rung) = ladder_rung {
+                (format!("LADDER_SELL_{}", rung + 1), Some(rung))
+            } else {
+                (format!("SELL_{}", pair.base_symbol()), None)
+            };
+            
+            let base_available = spendable_balance(checked_ui_amount(base_balance_before)?, &base_mint, config)?;
+            // A rung sells its fraction of the position as entered, not of what trade_fraction would size
+            let base_to_swap = match ladder_rung {
+                Some(rung) => {
+                    let position_size = if state.position_size > dec!(0) { state.position_size } else { base_available };
+                    Some(ladder_sell_amount(position_size, &config.take_profit_ladder, rung).min(base_available))
+                        .filter(|amount| *amount > dec!(0))
+                }
+                None => size_trade(base_available, base_price, config),
+            }
+            .ok_or_else(|| TradingError::InsufficientBalance {
+                token: pair.base_symbol().to_string(),
+                available: base_available,
+            })?;
             
-            // Keep some SOL for fees
-            let sol_to_swap = sol_balance_before - 0.01;
-            if sol_to_swap > 0.0 {
-                let amount_lamports = (sol_to_swap * 1_000_000_000.0) as u64;
+            let amount = to_base_units(base_to_swap, pair.base_decimals)?;
+            
+            // Stops, time exits and manual sells exit regardless of the expected profit
+            if !matches!(action.as_str(), "STOP_LOSS" | "TRAILING_STOP" | "TIME_EXIT" | "MANUAL_SELL")
+                && !meets_min_profit(jupiter_client, &rpc_client, &pair, state, amount, base_price, config).await?
+            {
+                return Ok(None);
+            }
+            
+            let (gas_fee, slippage, suspicious_fill, signature, base_balance_after, quote_balance_after) = if config.dry_run {
+                // Estimate balances after trade from the quote instead of swapping
+                let quote = jupiter_client.get_quote(&pair.base_mint, &pair.quote_mint, amount, config.slippage_bps).await?;
+                let quote_out = raw_to_ui_amount(quote.out_amount.parse()?, pair.quote_decimals);
+                info!("[DRY RUN] Would swap {} {} for {} {}", base_to_swap, pair.base_symbol(), quote_out, pair.quote_symbol());
+                
+                (
+                    None,
+                    None,
+                    false,
+                    None,
+                    base_balance_before - raw_to_ui_amount(amount, pair.base_decimals).to_f64().unwrap_or(0.0),
+                    quote_balance_before + quote_out.to_f64().unwrap_or(0.0),
+                )
+            } else {
+                if !is_native_mint(&quote_mint) {
+                    wallet.ensure_token_account(&rpc_client, &quote_mint).await?;
+                }
                 
-                let signature = jupiter_client.execute_swap(
+                let swap = jupiter_client.execute_swap(
                     &rpc_client,
                     wallet,
-                    &config.sol_mint,
-                    &config.usdc_mint,
-                    amount_lamports,
-                    config.slippage_bps,
+                    &pair.base_mint,
+                    &pair.quote_mint,
+                    amount,
+                    config,
                 ).await?;
                 
-                info!("Swap completed: {}", signature);
-
-                let gas_fee = wallet.get_gas_fee(&rpc_client, signature.clone()).await.unwrap_or(0.0);
+                info!(pair = %pair.name, signature = %swap.signature, "Swap completed");
                 
                 // Get balances after trade
-                let sol_balance_after = wallet.get_sol_balance(&rpc_client).await?;
-                let usdc_balance_after = wallet.get_token_balance(&rpc_client, &usdc_mint).await?;
-                
-                // Calculate profit/loss
-                let usdc_gained = usdc_balance_after - usdc_balance_before;
-                let sol_spent = sol_balance_before - sol_balance_after;
-                let effective_price = if sol_spent > 0.0 { usdc_gained / sol_spent } else { 0.0 };
+                let base_balance_after = wallet.get_balance(&rpc_client, &base_mint).await?;
+                let quote_balance_after = wallet.get_balance(&rpc_client, &quote_mint).await?;
                 
-                // Calculate profit if we have a previous price
-                let profit_loss = if let Some(last_price) = state.last_usdc_price {
-                    let price_difference = sol_price_in_usdc - last_price;
-                    let profit = price_difference * (f64_to_decimal(sol_spent, 0) * dec!(1_000_000_000)) - f64_to_decimal(gas_fee, 0);
-                    state.total_profit_usdc += profit;
-                    
-
-                    match profit.cmp(&dec!(0)) {
-                        std::cmp::Ordering::Greater => state.winning_trades += 1,
-                        std::cmp::Ordering::Less => state.losing_trades += 1,
-                        std::cmp::Ordering::Equal => {}
-                    }
-                    
-                    Some(profit)
-                } else {
-                    None
-                };
+                // The transaction fee is paid in SOL, so add it back when SOL is the token received
+                let mut quote_received = f64_to_decimal(quote_balance_after - quote_balance_before, 0);
+                if is_native_mint(&quote_mint) {
+                    quote_received += swap.gas_fee.unwrap_or(dec!(0));
+                }
+                let slippage = slippage_bps(raw_to_ui_amount(swap.quoted_out_amount, pair.quote_decimals), quote_received);
+                let suspicious_fill = check_fill(&pair, &swap, quote_received, pair.quote_decimals, config);
                 
-                profit = profit_loss;
+                (swap.gas_fee, slippage, suspicious_fill, Some(swap.signature), base_balance_after, quote_balance_after)
+            };
+            state.last_signature = signature.clone();
+            state.last_explorer_url = signature.as_deref().map(|signature| config.explorer_url(signature));
+            
+            // Calculate profit/loss
+            let base_spent = base_balance_before - base_balance_after;
+            
+            // Profit is measured against the average entry price, or the last trade price if it isn't known
+            let profit_loss = state.average_entry_price.or(state.last_trade_price).map(|entry_price| {
+                let price_difference = base_price - entry_price;
+                (price_difference * f64_to_decimal(base_spent, 0) - fee_in_quote(gas_fee, &pair, base_price)) * reporting_rate
+            });
+            
+            profit = profit_loss;
+            record_trade(state, profit_loss);
+            update_consecutive_losses(state, profit_loss);
+            
+            let session = TradingSession {
+                id: trading_session_id.clone(),
+                pair: pair.name.clone(),
+                timestamp: now_local(),
+                position_before: pair.base_symbol().to_string(),
+                position_after: if ladder_rung.is_some() { pair.base_symbol() } else { pair.quote_symbol() }.to_string(),
+                action: session_action(&action, config),
+                sol_balance_before: f64_to_decimal(base_balance_before, 0),
+                usdc_balance_before: f64_to_decimal(quote_balance_before, 0),
+                sol_balance_after: f64_to_decimal(base_balance_after, 0),
+                usdc_balance_after: f64_to_decimal(quote_balance_after, 0),
+                price_at_trade: base_price,
+                slippage,
+                gas_fee,
+                signature,
+                profit_loss,
+                cumulative_profit: Some(state.total_profit),
+                suspicious_fill,
+                holding_duration_secs: holding_duration(state.entered_at, now).map(|duration| duration.num_seconds()),
+            };
+            
+            // Store trading session
+            if let Some(db) = &state.firestore {
+                if let Err(e) = db.store_trading_session(&session).await {
+                    error!("Failed to store trading session: {}", e);
+                }
                 
-                // Store trading session
-                if let Some(db) = &state.firestore {
-                    let session = TradingSession {
-                        id: trading_session_id.clone(),
-                        timestamp: Tokyo.from_utc_datetime(&chrono::Utc::now().naive_utc()).with_timezone(&FixedOffset::east_opt(9 * 3600).unwrap()),
-                        position_before: "SOL".to_string(),
-                        position_after: "USDC".to_string(),
-                        action: "SELL_SOL".to_string(),
-                        sol_balance_before: f64_to_decimal(sol_balance_before, 0),
-                        usdc_balance_before: f64_to_decimal(usdc_balance_before, 0),
-                        sol_balance_after: f64_to_decimal(sol_balance_after, 0),
-                        usdc_balance_after: f64_to_decimal(usdc_balance_after, 0),
-                        price_at_trade: sol_price_in_usdc,
-                        slippage: Some(f64_to_decimal(effective_price, 0) - sol_price_in_usdc),
-                        gas_fee: Some(f64_to_decimal(gas_fee, 0)),
-                        profit_loss,
-                        cumulative_profit: Some(state.total_profit_usdc),
-                    };
-                    
-                    if let Err(e) = db.store_trading_session(&session).await {
-                        error!("Failed to store trading session: {}", e);
-                    }
-                    
-                    // Update profit tracking
-                    if profit_loss.is_some() {
-                        let profit_tracking = ProfitTracking {
-                            id: generate_session_id(),
-                            timestamp: Tokyo.from_utc_datetime(&chrono::Utc::now().naive_utc()).with_timezone(&FixedOffset::east_opt(9 * 3600).unwrap()),
-                            trading_session_id,
-                            profit_loss_usdc: profit_loss.unwrap_or(dec!(0)),
-                            cumulative_profit_usdc: state.total_profit_usdc,
-                            roi_percentage: if usdc_balance_before > 0.0 {
-                                state.total_profit_usdc / f64_to_decimal(usdc_balance_before, 0) * dec!(100)
-                            } else {
-                                dec!(0)
-                            },
-                            total_trades: state.total_trades + 1,
-                            winning_trades: state.winning_trades,
-                            losing_trades: state.losing_trades,
-                        };
-                        
-                        if let Err(e) = db.store_profit_tracking(&profit_tracking).await {
-                            error!("Failed to store profit tracking: {}", e);
-                        }
+                // Update profit tracking
+                if let Some(profit_loss) = profit_loss {
+                    store_profit_tracking(db, state, trading_session_id, profit_loss, f64_to_decimal(quote_balance_before, 0) * reporting_rate).await;
+                    if let Err(e) = store_circuit_breaker(db, state).await {
+                        error!("Failed to store circuit breaker state: {}", e);
                     }
                 }
-                
-                // Update last trade price and position
-                state.last_trade_price = Some(sol_price_in_usdc);
-                state.position = Position::USDC;
+            }
+            state.last_session = Some(session);
+            
+            // A ladder sell keeps the position and entry price, so later rungs and the stops still measure from entry
+            state.last_trade_timestamp = Some(now);
+            if ladder_rung.is_some() {
+                state.ladder_rungs_fired += 1;
+                state.position_size = (state.position_size - f64_to_decimal(base_spent, 0)).max(dec!(0));
+                return Ok(profit);
+            }
+            
+            // Update last trade price and position
+            state.exit_position(base_price);
+            if let Some(db) = &state.firestore {
+                if let Err(e) = store_trailing_stop(db, state).await {
+                    error!("Failed to store trailing stop state: {}", e);
+                }
             }
         }
     }
@@ -387,78 +862,1690 @@ pub async fn check_and_trade(
     Ok(profit)
 }
 
-async fn get_current_prices(
-    jupiter_client: &JupiterClient,
+enum LimitOrderOutcome {
+    // Nothing more to do this run, with the profit of an order that filled
+    Handled(Option<Decimal>),
+    // A stop was hit, so the resting order was cancelled and the market path should sell
+    Market,
+}
+
+// Keep one limit order resting at the next threshold price: record it once it has filled,
+// replace it once it has expired, and cancel it when a stop loss or time exit needs a market exit
+async fn manage_limit_order(
+    wallet: &Wallet,
     config: &Config,
-) -> Result<(Decimal, Decimal)> {
-    // Get SOL price in USDC (1 SOL = ? USDC)
-    let sol_price = crate::jupiter::get_price(
-        jupiter_client,
-        &config.sol_mint,
-        &config.usdc_mint,
-        1_000_000_000, // 1 SOL in lamports
-    ).await?;
+    state: &mut TradingState,
+    jupiter_client: &JupiterClient,
+    rpc_client: &RpcClient,
+    base_price: Decimal,
+    trading_session_id: String,
+) -> Result<LimitOrderOutcome> {
+    let Some(db) = state.firestore.clone() else {
+        return Ok(LimitOrderOutcome::Market);
+    };
+    let pair = state.pair.clone();
+    let stop_hit = state.position == Position::Base
+        && (is_stop_loss(base_price, state, config) || is_trailing_stop(base_price, state, config)
+            || is_time_exit(state, config, now_local()));
     
-    // Get USDC price in SOL (1 USDC = ? SOL)
-    let usdc_price = crate::jupiter::get_price(
-        jupiter_client,
-        &config.usdc_mint,
-        &config.sol_mint,
-        1_000_000, // 1 USDC in smallest unit
-    ).await?;
+    if let Some(open_order) = db.get_limit_order(&pair.name).await? {
+        let open_orders = jupiter_client.get_open_limit_orders(wallet.pubkey()).await?;
+        if open_orders.contains(&open_order.order) {
+            if !stop_hit {
+                info!("{} limit order {} at {} is still open", pair.name, open_order.order, open_order.target_price);
+                return Ok(LimitOrderOutcome::Handled(None));
+            }
+            
+            warn!("{} stop hit at {}, cancelling limit order {}", pair.name, base_price, open_order.order);
+            jupiter_client.cancel_limit_order(wallet, &open_order.order).await?;
+            clear_limit_order(&db, &pair).await;
+            return Ok(LimitOrderOutcome::Market);
+        }
+        
+        // The order is gone: either it filled, or it expired and the tokens were refunded
+        clear_limit_order(&db, &pair).await;
+        let base_mint = Pubkey::from_str(&pair.base_mint)?;
+        let quote_mint = Pubkey::from_str(&pair.quote_mint)?;
+        let base_balance = wallet.get_balance(rpc_client, &base_mint).await?;
+        let quote_balance = wallet.get_balance(rpc_client, &quote_mint).await?;
+        let held = reconciled_position(
+            &open_order.position,
+            tradable_balance(f64_to_decimal(base_balance, 0), &base_mint, config) * base_price,
+            tradable_balance(f64_to_decimal(quote_balance, 0), &quote_mint, config),
+            config.position_reconcile_threshold_usdc,
+        );
+        if held != open_order.position {
+            let profit = record_limit_fill(state, config, jupiter_client, &open_order, base_balance, quote_balance, trading_session_id).await?;
+            return Ok(LimitOrderOutcome::Handled(profit));
+        }
+        info!("{} limit order {} expired unfilled", pair.name, open_order.order);
+    }
     
-    // Adjust for decimal places
-    let sol_price_adjusted = sol_price / 1_000_000.0; // USDC has 6 decimals
-    let usdc_price_adjusted = usdc_price / 1_000_000_000.0; // SOL has 9 decimals
+    if stop_hit {
+        return Ok(LimitOrderOutcome::Market);
+    }
     
-    Ok((
-        f64_to_decimal(sol_price_adjusted, 0),
-        f64_to_decimal(usdc_price_adjusted, 0),
-    ))
+    place_limit_order(wallet, config, state, &db, jupiter_client, rpc_client).await?;
+    Ok(LimitOrderOutcome::Handled(None))
 }
 
-// Enhanced trading logic with trend analysis
-fn should_make_trade(
-    position: &Position,
-    trend: &crate::firestore::PriceTrend,
-    sol_price: Decimal,
-    _usdc_price: Decimal,
+// Buy below the last trade price by buy_dip_pct, or sell above it by take_profit_pct
+async fn place_limit_order(
+    wallet: &Wallet,
+    config: &Config,
     state: &TradingState,
-) -> bool {
+    db: &FirestoreDb,
+    jupiter_client: &JupiterClient,
+    rpc_client: &RpcClient,
+) -> Result<()> {
+    let pair = &state.pair;
+    let Some(last_trade_price) = state.last_trade_price else {
+        return Ok(());
+    };
+    let base_mint = Pubkey::from_str(&pair.base_mint)?;
+    let quote_mint = Pubkey::from_str(&pair.quote_mint)?;
+    
+    let (input_mint, output_mint, target_price, base_amount, making_amount, taking_amount) = match state.position {
+        Position::Quote => {
+            let target_price = last_trade_price * (dec!(1) - config.buy_dip_pct);
+            let quote_available = spendable_balance(checked_ui_amount(wallet.get_balance(rpc_client, &quote_mint).await?)?, &quote_mint, config)?;
+            let quote_amount = size_trade(quote_available, dec!(1), config)
+                .ok_or_else(|| TradingError::InsufficientBalance {
+                    token: pair.quote_symbol().to_string(),
+                    available: quote_available,
+                })?;
+            let base_amount = quote_amount / target_price;
+            (&pair.quote_mint, &pair.base_mint, target_price, base_amount,
+                to_base_units(quote_amount, pair.quote_decimals)?, to_base_units(base_amount, pair.base_decimals)?)
+        }
+        Position::Base => {
+            let target_price = last_trade_price * (dec!(1) + config.take_profit_pct);
+            let base_available = spendable_balance(checked_ui_amount(wallet.get_balance(rpc_client, &base_mint).await?)?, &base_mint, config)?;
+            let base_amount = size_trade(base_available, target_price, config)
+                .ok_or_else(|| TradingError::InsufficientBalance {
+                    token: pair.base_symbol().to_string(),
+                    available: base_available,
+                })?;
+            (&pair.base_mint, &pair.quote_mint, target_price, base_amount,
+                to_base_units(base_amount, pair.base_decimals)?, to_base_units(base_amount * target_price, pair.quote_decimals)?)
+        }
+    };
+    
+    if config.dry_run {
+        info!("[DRY RUN] Would place a {} limit order for {} {} at {}", pair.name, base_amount, pair.base_symbol(), target_price);
+        return Ok(());
+    }
+    
+    let output = Pubkey::from_str(output_mint)?;
+    if !is_native_mint(&output) {
+        wallet.ensure_token_account(rpc_client, &output).await?;
+    }
+    
+    let expired_at = config.limit_order_expiry_secs.map(|secs| chrono::Utc::now().timestamp() + secs as i64);
+    let placed = jupiter_client.place_limit_order(wallet, input_mint, output_mint, making_amount, taking_amount, expired_at).await?;
+    info!(pair = %pair.name, signature = %placed.signature, "Placed limit order {} for {} {} at {}", placed.order, base_amount, pair.base_symbol(), target_price);
+    
+    db.store_limit_order(&LimitOrderState {
+        pair: pair.name.clone(),
+        open_order: Some(OpenLimitOrder {
+            order: placed.order,
+            position: state.position.clone(),
+            base_amount,
+            target_price,
+            signature: placed.signature,
+            placed_at: now_local(),
+        }),
+        updated_at: now_local(),
+    }).await
+}
 
-    let now_at_jst = Tokyo.from_utc_datetime(&chrono::Utc::now().naive_utc()).with_timezone(&FixedOffset::east_opt(9 * 3600).unwrap());
-    let duration_24h = Duration::days(1);
+async fn clear_limit_order(db: &FirestoreDb, pair: &TradingPair) {
+    let limit_order = LimitOrderState {
+        pair: pair.name.clone(),
+        open_order: None,
+        updated_at: now_local(),
+    };
+    if let Err(e) = db.store_limit_order(&limit_order).await {
+        error!("Failed to clear {} limit order: {}", pair.name, e);
+    }
+}
 
-    if let Some(last_trade_time) = state.last_trade_timestamp {
-        if now_at_jst - last_trade_time > duration_24h {
-            info!("More than 24 hours since last trade, considering new trade");
-            let price_1h_ago: Decimal = match trend.price_1h_ago {
-                Some(p) => p,
-                None => dec!(0),
-            };
-            info!("price_1h_ago is {}, sol_price is {}", price_1h_ago, sol_price);
-            return match position {
-                Position::USDC => sol_price < price_1h_ago,
-                Position::SOL => state.last_trade_price
-                .map(|last_price| sol_price > last_price)
-                .unwrap_or(false)
-            };
-        }
+// Account for a filled limit order like a market trade at the order's target price
+async fn record_limit_fill(
+    state: &mut TradingState,
+    config: &Config,
+    jupiter_client: &JupiterClient,
+    order: &OpenLimitOrder,
+    base_balance_after: f64,
+    quote_balance_after: f64,
+    trading_session_id: String,
+) -> Result<Option<Decimal>> {
+    let Some(db) = state.firestore.clone() else {
+        return Ok(None);
+    };
+    let pair = state.pair.clone();
+    info!(pair = %pair.name, "Limit order {} filled at {}", order.order, order.target_price);
+    
+    let reporting_rate = reporting_rate(jupiter_client, &pair, order.target_price, config).await?;
+    let profit_loss = state.last_trade_price.map(|last_trade_price| {
+        (order.target_price - last_trade_price) * order.base_amount * reporting_rate
+    });
+    record_trade(state, profit_loss);
+    if order.position == Position::Base {
+        update_consecutive_losses(state, profit_loss);
     }
-    info!("last_trade_price is {}, sol_price is {}", state.last_trade_price.unwrap_or(dec!(0)), sol_price);
-    match position {
-        Position::USDC => {
-            // Buy SOL if the price has decreased by 1% or more compared to the price from the last trade
-            state.last_trade_price
-                .map(|last_price| sol_price < last_price)
-                .unwrap_or(false)
-        }
-        Position::SOL => {
-            // Sell SOL if the price has increased by 1% or more compared to the price from the last trade
-            state.last_trade_price
-                .map(|last_price| sol_price > last_price)
-                .unwrap_or(false)
+    
+    let (position_after, action) = match order.position {
+        Position::Quote => (Position::Base, format!("LIMIT_BUY_{}", pair.base_symbol())),
+        Position::Base => (Position::Quote, format!("LIMIT_SELL_{}", pair.base_symbol())),
+    };
+    let now = now_local();
+    
+    // Balances before the fill aren't known, the order already held the tokens it spent
+    let session = TradingSession {
+        id: trading_session_id.clone(),
+        pair: pair.name.clone(),
+        timestamp: now,
+        position_before: state.position_symbol().to_string(),
+        position_after: match position_after {
+            Position::Base => pair.base_symbol().to_string(),
+            Position::Quote => pair.quote_symbol().to_string(),
+        },
+        action: session_action(&action, config),
+        sol_balance_before: f64_to_decimal(base_balance_after, 0),
+        usdc_balance_before: f64_to_decimal(quote_balance_after, 0),
+        sol_balance_after: f64_to_decimal(base_balance_after, 0),
+        usdc_balance_after: f64_to_decimal(quote_balance_after, 0),
+        price_at_trade: order.target_price,
+        slippage: None,
+        gas_fee: None,
+        signature: None,
+        profit_loss,
+        cumulative_profit: Some(state.total_profit),
+        suspicious_fill: false,
+        holding_duration_secs: match order.position {
+            Position::Base => holding_duration(state.entered_at, now).map(|duration| duration.num_seconds()),
+            Position::Quote => None,
+        },
+    };
+    if let Err(e) = db.store_trading_session(&session).await {
+        error!("Failed to store trading session: {}", e);
+    }
+    if let Some(profit_loss) = profit_loss {
+        let quote_balance = f64_to_decimal(quote_balance_after, 0) * reporting_rate;
+        store_profit_tracking(&db, state, trading_session_id, profit_loss, quote_balance).await;
+        if let Err(e) = store_circuit_breaker(&db, state).await {
+            error!("Failed to store circuit breaker state: {}", e);
         }
     }
+    state.last_session = Some(session);
+    
+    state.last_base_price = Some(order.target_price);
+    state.last_trade_price = Some(order.target_price);
+    state.last_trade_timestamp = Some(now);
+    state.last_signature = None;
+    state.last_explorer_url = None;
+    state.peak_price = (position_after == Position::Base).then_some(order.target_price);
+    state.ladder_rungs_fired = 0;
+    state.entered_at = (position_after == Position::Base).then_some(now);
+    state.clear_entry();
+    if position_after == Position::Base {
+        state.record_entry(order.target_price, order.base_amount, dec!(0));
+    }
+    state.position = position_after;
+    if let Err(e) = store_trailing_stop(&db, state).await {
+        error!("Failed to store trailing stop state: {}", e);
+    }
+    
+    Ok(profit_loss)
+}
+
+// Whether the swap is expected to clear min_profit_usdc once fees are paid, measured against the
+// last trade price like the realized profit. Always true without a threshold or a previous trade.
+async fn meets_min_profit(
+    jupiter_client: &JupiterClient,
+    rpc_client: &RpcClient,
+    pair: &TradingPair,
+    state: &TradingState,
+    amount: u64,
+    base_price: Decimal,
+    config: &Config,
+) -> Result<bool> {
+    let (Some(min_profit), Some(last_trade_price)) = (config.min_profit_usdc, state.last_trade_price) else {
+        return Ok(true);
+    };
+    
+    let (input_mint, output_mint) = match state.position {
+        Position::Quote => (&pair.quote_mint, &pair.base_mint),
+        Position::Base => (&pair.base_mint, &pair.quote_mint),
+    };
+    // A cached quote is close enough for the estimate, the swap itself always fetches a fresh one
+    let quote = jupiter_client.get_quote(input_mint, output_mint, amount, config.slippage_bps).await?;
+    let out_amount: u64 = quote.out_amount.parse()?;
+    
+    // Spread and price impact are already reflected in the quoted output
+    let gross_profit = match state.position {
+        Position::Quote => raw_to_ui_amount(out_amount, pair.base_decimals) * last_trade_price
+            - raw_to_ui_amount(amount, pair.quote_decimals),
+        Position::Base => raw_to_ui_amount(out_amount, pair.quote_decimals)
+            - raw_to_ui_amount(amount, pair.base_decimals) * last_trade_price,
+    };
+    let fee = estimate_swap_fee(rpc_client, config)? * sol_price_in_quote(jupiter_client, pair, base_price).await?;
+    let net_profit = gross_profit - fee;
+    
+    if net_profit < min_profit {
+        info!(
+            "Skipping {} trade: estimated net profit {:.6} {} (gross {:.6}, fees {:.6}) is below MIN_PROFIT_USDC {}",
+            pair.name, net_profit, pair.quote_symbol(), gross_profit, fee, min_profit
+        );
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+// Price of one SOL in the pair's quote token, for converting transaction fees
+async fn sol_price_in_quote(jupiter_client: &JupiterClient, pair: &TradingPair, base_price: Decimal) -> Result<Decimal> {
+    let sol_mint = spl_token::native_mint::id().to_string();
+    if pair.quote_mint == sol_mint {
+        return Ok(dec!(1));
+    }
+    if pair.base_mint == sol_mint {
+        return Ok(base_price);
+    }
+    
+    let raw_price = crate::jupiter::get_price(jupiter_client, &sol_mint, &pair.quote_mint, ui_to_raw_amount(dec!(1), SOL_DECIMALS)).await?;
+    Ok(raw_price_to_ui(f64_to_decimal(raw_price, 0), SOL_DECIMALS, pair.quote_decimals))
 }
 
+// Correct the position when the wallet clearly holds the other side of the pair,
+// e.g. after Firestore was wiped or a trade was made outside the bot
+pub async fn reconcile_position(wallet: &Wallet, config: &Config, state: &mut TradingState) -> Result<()> {
+    let rpc_client = rpc::connect(config)?;
+    let jupiter_client = JupiterClient::shared(config);
+    let pair = state.pair.clone();
+    
+    let base_mint = Pubkey::from_str(&pair.base_mint)?;
+    let quote_mint = Pubkey::from_str(&pair.quote_mint)?;
+    let base_balance = tradable_balance(f64_to_decimal(wallet.get_effective_balance(&rpc_client, &base_mint).await?, 0), &base_mint, config);
+    let quote_balance = tradable_balance(f64_to_decimal(wallet.get_effective_balance(&rpc_client, &quote_mint).await?, 0), &quote_mint, config);
+    let (base_price, _) = get_current_prices(jupiter_client, &pair).await?;
+    
+    let position = reconciled_position(&state.position, base_balance * base_price, quote_balance, config.position_reconcile_threshold_usdc);
+    if position != state.position {
+        warn!(
+            "Stored {} position is {} but the wallet holds {} {} and {} {}, switching to {}",
+            pair.name, state.position_symbol(), base_balance, pair.base_symbol(), quote_balance, pair.quote_symbol(),
+            match position {
+                Position::Base => pair.base_symbol(),
+                Position::Quote => pair.quote_symbol(),
+            }
+        );
+        state.position = position;
+        state.ladder_rungs_fired = 0;
+        // The wallet doesn't say when or at what price the position was entered
+        state.entered_at = None;
+        state.clear_entry();
+    }
+    Ok(())
+}
+
+// The SOL fee reserve is never traded, so it doesn't count as holding SOL
+fn tradable_balance(balance: Decimal, mint: &Pubkey, config: &Config) -> Decimal {
+    if is_native_mint(mint) {
+        (balance - config.sol_fee_reserve).max(dec!(0))
+    } else {
+        balance
+    }
+}
+
+// Position matching the wallet, given both holdings valued in the quote token. Only switches when
+// exactly one side is worth at least `threshold`, so dust or a split balance keeps the stored position.
+pub(crate) fn reconciled_position(position: &Position, base_value: Decimal, quote_value: Decimal, threshold: Decimal) -> Position {
+    match (base_value >= threshold, quote_value >= threshold) {
+        (true, false) => Position::Base,
+        (false, true) => Position::Quote,
+        _ => position.clone(),
+    }
+}
+
+// Time left before another trade is allowed, None once trade_cooldown_secs has passed since the last trade
+fn cooldown_remaining(state: &TradingState, config: &Config, now: DateTime<FixedOffset>) -> Option<Duration> {
+    let last_trade_time = state.last_trade_timestamp?;
+    let remaining = last_trade_time + Duration::seconds(config.trade_cooldown_secs as i64) - now;
+    (remaining > Duration::zero()).then_some(remaining)
+}
+
+// slippage_bps, or with adaptive slippage the bps for the pair's current 24h volatility
+async fn effective_slippage_bps(db: Option<&FirestoreDb>, pair: &str, price: Decimal, config: &Config) -> u16 {
+    if !config.adaptive_slippage {
+        return config.slippage_bps;
+    }
+    
+    let volatility = match db {
+        Some(db) => db.get_price_trend(pair, price).await.map(|trend| trend.volatility_24h),
+        None => Ok(None),
+    };
+    match volatility {
+        Ok(Some(volatility_24h)) => {
+            let slippage_bps = adaptive_slippage_bps(volatility_24h, price, config);
+            info!(pair = %pair, volatility_24h = %volatility_24h, slippage_bps, "Using adaptive slippage");
+            slippage_bps
+        }
+        Ok(None) => {
+            warn!("No 24h volatility for {}, using the maximum slippage of {} bps", pair, config.max_slippage_bps);
+            config.max_slippage_bps
+        }
+        Err(e) => {
+            warn!("Failed to get 24h volatility for {}, using the maximum slippage of {} bps: {}", pair, config.max_slippage_bps, e);
+            config.max_slippage_bps
+        }
+    }
+}
+
+// Scale linearly from min_slippage_bps with no volatility to max_slippage_bps once the
+// standard deviation reaches adaptive_slippage_volatility_pct of the price
+pub(crate) fn adaptive_slippage_bps(volatility_24h: Decimal, price: Decimal, config: &Config) -> u16 {
+    if price <= Decimal::ZERO {
+        return config.max_slippage_bps;
+    }
+    
+    let ratio = (volatility_24h / price / config.adaptive_slippage_volatility_pct).clamp(Decimal::ZERO, Decimal::ONE);
+    let range = Decimal::from(config.max_slippage_bps - config.min_slippage_bps);
+    let slippage_bps = Decimal::from(config.min_slippage_bps) + (range * ratio).round();
+    slippage_bps.to_u16().unwrap_or(config.max_slippage_bps).clamp(config.min_slippage_bps, config.max_slippage_bps)
+}
+
+// Flag a fill that landed just above the quote's minimum output, logging and counting it
+fn check_fill(pair: &TradingPair, swap: &SwapResult, received: Decimal, decimals: u8, config: &Config) -> bool {
+    let min_out = raw_to_ui_amount(swap.min_out_amount, decimals);
+    if !is_suspicious_fill(min_out, received, config.suspicious_fill_margin_bps) {
+        return false;
+    }
+    
+    metrics().suspicious_fills.inc();
+    warn!(
+        pair = %pair.name,
+        signature = %swap.signature,
+        "Suspicious fill: received {} against a minimum of {} (quoted {}), possibly sandwiched",
+        received, min_out, raw_to_ui_amount(swap.quoted_out_amount, decimals)
+    );
+    true
+}
+
+// Keep sol_fee_reserve back for fees when the token being spent is native SOL
+fn spendable_balance(balance: Decimal, mint: &Pubkey, config: &Config) -> Result<Decimal, TradingError> {
+    if !is_native_mint(mint) {
+        return Ok(balance);
+    }
+    
+    let spendable = balance - config.sol_fee_reserve;
+    if spendable <= dec!(0) {
+        return Err(TradingError::InsufficientSolAfterReserve {
+            available: balance,
+            reserve: config.sol_fee_reserve,
+        });
+    }
+    Ok(spendable)
+}
+
+// Size a trade as trade_fraction of the available balance, clamped to min/max_trade_usdc
+// (which are applied in units of the pair's quote token).
+// Returns the amount in units of the input token, or None if the trade would be below the minimum.
+fn size_trade(available: Decimal, price_in_usdc: Decimal, config: &Config) -> Option<Decimal> {
+    if available <= dec!(0) || price_in_usdc <= dec!(0) {
+        return None;
+    }
+    
+    let available_usdc = available * price_in_usdc;
+    let mut size_usdc = available_usdc * config.trade_fraction;
+    if let Some(max_trade_usdc) = config.max_trade_usdc {
+        size_usdc = size_usdc.min(max_trade_usdc);
+    }
+    if let Some(min_trade_usdc) = config.min_trade_usdc {
+        size_usdc = size_usdc.max(min_trade_usdc).min(available_usdc);
+        if size_usdc < min_trade_usdc {
+            info!("Available balance {} in quote tokens is below min_trade_usdc {}", available_usdc, min_trade_usdc);
+            return None;
+        }
+    }
+    
+    Some(size_usdc / price_in_usdc)
+}
+
+// Add a trade to the running totals, which accumulate across trades
+fn record_trade(state: &mut TradingState, profit_loss: Option<Decimal>) {
+    if let Some(profit) = profit_loss {
+        state.total_profit += profit;
+        match profit.cmp(&dec!(0)) {
+            std::cmp::Ordering::Greater => state.winning_trades += 1,
+            std::cmp::Ordering::Less => state.losing_trades += 1,
+            std::cmp::Ordering::Equal => {}
+        }
+    }
+    state.total_trades += 1;
+    record_trade_metrics(state, profit_loss);
+}
+
+fn record_trade_metrics(state: &TradingState, profit_loss: Option<Decimal>) {
+    let metrics = metrics();
+    metrics.total_trades.inc();
+    match profit_loss.map(|p| p.cmp(&dec!(0))) {
+        Some(std::cmp::Ordering::Greater) => metrics.winning_trades.inc(),
+        Some(std::cmp::Ordering::Less) => metrics.losing_trades.inc(),
+        _ => {}
+    }
+    metrics.cumulative_profit
+        .with_label_values(&[&state.pair.name])
+        .set(state.total_profit.to_f64().unwrap_or(0.0));
+}
+
+// A win resets the loss streak, a loss extends it, and a break-even trade leaves it alone.
+// Only sells are counted, since a buy's profit isn't realized
+fn update_consecutive_losses(state: &mut TradingState, profit_loss: Option<Decimal>) {
+    match profit_loss.map(|p| p.cmp(&dec!(0))) {
+        Some(std::cmp::Ordering::Greater) => state.consecutive_losses = 0,
+        Some(std::cmp::Ordering::Less) => state.consecutive_losses += 1,
+        _ => {}
+    }
+}
+
+async fn store_circuit_breaker(db: &FirestoreDb, state: &TradingState) -> Result<()> {
+    db.store_circuit_breaker(&CircuitBreakerState {
+        pair: state.pair.name.clone(),
+        consecutive_losses: state.consecutive_losses,
+        circuit_broken: state.circuit_broken,
+        updated_at: now_local(),
+    }).await
+}
+
+async fn store_trailing_stop(db: &FirestoreDb, state: &TradingState) -> Result<()> {
+    db.store_trailing_stop(&TrailingStopState {
+        pair: state.pair.name.clone(),
+        peak_price: state.peak_price,
+        updated_at: now_local(),
+    }).await
+}
+
+// Raise the peak while holding the base token, starting from the entry price
+async fn update_peak_price(state: &mut TradingState, price: Decimal) {
+    let peak_price = state.peak_price.or(state.last_trade_price).map_or(price, |peak| peak.max(price));
+    if state.peak_price == Some(peak_price) {
+        return;
+    }
+    
+    state.peak_price = Some(peak_price);
+    if let Some(db) = &state.firestore {
+        if let Err(e) = store_trailing_stop(db, state).await {
+            error!("Failed to store trailing stop state: {}", e);
+        }
+    }
+}
+
+fn session_action(action: &str, config: &Config) -> String {
+    if config.dry_run {
+        format!("{}_SIMULATED", action)
+    } else {
+        action.to_string()
+    }
+}
+
+async fn store_profit_tracking(
+    db: &FirestoreDb,
+    state: &TradingState,
+    trading_session_id: String,
+    profit_loss: Decimal,
+    // Quote balance before the trade, in the reporting currency
+    balance_before: Decimal,
+) {
+    let profit_tracking = profit_tracking_record(state, trading_session_id, profit_loss, balance_before);
+    if let Err(e) = db.store_profit_tracking(&profit_tracking).await {
+        error!("Failed to store profit tracking: {}", e);
+    }
+}
+
+// The price point, trading session and profit tracking of one run share trading_session_id,
+// which is also the session's own id, so the collections can be joined on it
+fn price_history_record(
+    pair: &TradingPair,
+    trading_session_id: String,
+    base_price: Decimal,
+    quote_price: Decimal,
+    spread: Option<Decimal>,
+) -> PriceHistory {
+    PriceHistory {
+        id: generate_session_id(),
+        pair: pair.name.clone(),
+        timestamp: now_local(),
+        sol_price_usdc: base_price,
+        usdc_price_sol: quote_price,
+        data_source: "Jupiter".to_string(),
+        trading_session_id,
+        spread,
+    }
+}
+
+fn profit_tracking_record(
+    state: &TradingState,
+    trading_session_id: String,
+    profit_loss: Decimal,
+    balance_before: Decimal,
+) -> ProfitTracking {
+    ProfitTracking {
+        id: generate_session_id(),
+        pair: state.pair.name.clone(),
+        timestamp: now_local(),
+        trading_session_id,
+        profit_loss_usdc: profit_loss,
+        cumulative_profit_usdc: state.total_profit,
+        currency: state.reporting_currency.clone(),
+        roi_percentage: if balance_before > dec!(0) {
+            state.total_profit / balance_before * dec!(100)
+        } else {
+            dec!(0)
+        },
+        total_trades: state.total_trades,
+        winning_trades: state.winning_trades,
+        losing_trades: state.losing_trades,
+    }
+}
+
+// Refuse to trade when the Jupiter price is too far from Pyth's. Trading continues when the
+// Pyth price can't be read, since it is only a sanity check.
+async fn check_reference_price(
+    rpc_client: &RpcClient,
+    pair: &TradingPair,
+    price: Decimal,
+    max_divergence: Decimal,
+    config: &Config,
+) -> Result<()> {
+    if !PythPriceSource::supports(pair) {
+        return Ok(());
+    }
+    
+    let pyth = PythPriceSource::new(rpc_client, &config.pyth_price_account)?;
+    let reference = match pyth.get_price(pair).await {
+        Ok(reference) => reference,
+        Err(e) => {
+            warn!("Skipping the {} price check against {}: {}", pair.name, pyth.name(), e);
+            return Ok(());
+        }
+    };
+    
+    check_divergence(pair, price, reference, max_divergence)?;
+    info!("{} price {} is within {}% of the {} price {}", pair.name, price, max_divergence * dec!(100), pyth.name(), reference);
+    Ok(())
+}
+
+// Fails when `price` is more than `max_divergence` (a fraction) away from the reference price
+fn check_divergence(pair: &TradingPair, price: Decimal, reference: Decimal, max_divergence: Decimal) -> Result<(), TradingError> {
+    match price_divergence(price, reference) {
+        Some(divergence) if divergence > max_divergence => Err(TradingError::PriceDivergence {
+            pair: pair.name.clone(),
+            price,
+            reference,
+            divergence_pct: (divergence * dec!(100)).round_dp(2),
+            max_pct: max_divergence * dec!(100),
+        }),
+        _ => Ok(()),
+    }
+}
+
+// Value of one of the pair's quote tokens in the reporting currency
+async fn reporting_rate(
+    jupiter_client: &JupiterClient,
+    pair: &TradingPair,
+    base_price: Decimal,
+    config: &Config,
+) -> Result<Decimal> {
+    if pair.quote_symbol() == config.reporting_currency {
+        return Ok(dec!(1));
+    }
+    if pair.base_symbol() == config.reporting_currency {
+        if base_price <= dec!(0) {
+            anyhow::bail!("Cannot convert {} profit to {} at price {}", pair.quote_symbol(), config.reporting_currency, base_price);
+        }
+        return Ok(dec!(1) / base_price);
+    }
+    
+    let (reporting_mint, reporting_decimals) = config.find_token(&config.reporting_currency)
+        .ok_or_else(|| anyhow::anyhow!("Unknown reporting currency: {}", config.reporting_currency))?;
+    let raw_price = crate::jupiter::get_price(jupiter_client, &pair.quote_mint, &reporting_mint, ui_to_raw_amount(dec!(1), pair.quote_decimals)).await?;
+    Ok(raw_price_to_ui(f64_to_decimal(raw_price, 0), pair.quote_decimals, reporting_decimals))
+}
+
+pub(crate) async fn get_current_prices(
+    jupiter_client: &JupiterClient,
+    pair: &TradingPair,
+) -> Result<(Decimal, Decimal)> {
+    // Get base price in quote (1 base = ? quote)
+    let base_price = PriceSource::get_price(jupiter_client, pair).await?;
+    
+    // Get quote price in base (1 quote = ? base)
+    let quote_price = crate::jupiter::get_price(
+        jupiter_client,
+        &pair.quote_mint,
+        &pair.base_mint,
+        ui_to_raw_amount(dec!(1), pair.quote_decimals),
+    ).await?;
+    
+    // The quote price is per raw unit, so adjust it to be per whole token
+    Ok((
+        base_price,
+        raw_price_to_ui(f64_to_decimal(quote_price, 0), pair.quote_decimals, pair.base_decimals),
+    ))
+}
+
+// How far buying one base token (1 / quote_price) costs more than selling one (base_price) yields,
+// as a fraction of base_price. None when either price isn't positive
+pub(crate) fn implied_spread(base_price: Decimal, quote_price: Decimal) -> Option<Decimal> {
+    if base_price <= Decimal::ZERO || quote_price <= Decimal::ZERO {
+        return None;
+    }
+    Some((Decimal::ONE / quote_price - base_price) / base_price)
+}
+
+// A negative spread (selling yields more than buying costs) means a stale quote just as much
+pub(crate) fn is_spread_too_wide(spread: Decimal, max_spread: Decimal) -> bool {
+    spread.abs() > max_spread
+}
+
+pub(crate) fn round_price(price: Decimal, config: &Config) -> Decimal {
+    price.round_dp(config.price_decimals)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TradeAction {
+    Buy,
+    Sell,
+    StopLoss,
+    TrailingStop,
+    Hold,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradeDecision {
+    pub action: TradeAction,
+    // Human-readable explanation, for logs
+    pub reason: String,
+}
+
+impl TradeDecision {
+    fn new(action: TradeAction, reason: String) -> Self {
+        Self { action, reason }
+    }
+    
+    pub fn should_trade(&self) -> bool {
+        self.action != TradeAction::Hold
+    }
+}
+
+// Percentages the threshold strategy trades on, as fractions (0.05 = 5%)
+#[derive(Debug, Clone, Copy)]
+pub struct TradeThresholds {
+    pub stop_loss_pct: Decimal,
+    pub take_profit_pct: Decimal,
+    pub buy_dip_pct: Decimal,
+    pub trailing_stop_pct: Option<Decimal>,
+}
+
+impl TradeThresholds {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            stop_loss_pct: config.stop_loss_pct,
+            take_profit_pct: config.take_profit_pct,
+            buy_dip_pct: config.buy_dip_pct,
+            trailing_stop_pct: config.trailing_stop_pct,
+        }
+    }
+}
+
+// What the strategy knows about the trade that opened the current position
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PositionContext {
+    pub last_trade_price: Option<Decimal>,
+    pub last_trade_time: Option<DateTime<FixedOffset>>,
+    // Highest price since entering the base position
+    pub peak_price: Option<Decimal>,
+}
+
+impl PositionContext {
+    pub fn from_state(state: &TradingState) -> Self {
+        Self {
+            last_trade_price: state.last_trade_price,
+            last_trade_time: state.last_trade_timestamp,
+            peak_price: state.peak_price,
+        }
+    }
+}
+
+// Threshold strategy decision from plain inputs, without any I/O:
+// - holding the base token, sell on a stop loss, once the price is trailing_stop_pct below the peak,
+//   or once it is take_profit_pct above the last trade
+// - holding the quote token, buy once the price is buy_dip_pct below the last trade
+// - more than 24 hours after the last trade, buy on any drop over the last hour or sell on any gain
+pub(crate) fn decide_trade(
+    position: &Position,
+    price: Decimal,
+    context: &PositionContext,
+    now: DateTime<FixedOffset>,
+    trend: &crate::firestore::PriceTrend,
+    thresholds: &TradeThresholds,
+) -> TradeDecision {
+    let PositionContext { last_trade_price, last_trade_time, peak_price } = *context;
+    
+    if *position == Position::Base {
+        if let Some(last_price) = last_trade_price.filter(|last_price| price <= *last_price * (dec!(1) - thresholds.stop_loss_pct)) {
+            return TradeDecision::new(
+                TradeAction::StopLoss,
+                format!("stop-loss: price {} is at or below {}% under last trade price {}", price, thresholds.stop_loss_pct * dec!(100), last_price),
+            );
+        }
+        
+        if let (Some(peak), Some(trailing_stop_pct)) = (peak_price, thresholds.trailing_stop_pct) {
+            if trailing_stop_hit(price, peak, trailing_stop_pct) {
+                return TradeDecision::new(
+                    TradeAction::TrailingStop,
+                    format!("trailing stop: price {} is at or below {}% under peak price {}", price, trailing_stop_pct * dec!(100), peak),
+                );
+            }
+        }
+    }
+    
+    if let Some(last_trade_time) = last_trade_time {
+        if now - last_trade_time > Duration::days(1) {
+            let price_1h_ago = trend.price_1h_ago.unwrap_or(dec!(0));
+            return match (position, last_trade_price) {
+                (Position::Quote, _) if price < price_1h_ago => TradeDecision::new(
+                    TradeAction::Buy,
+                    format!("over 24h since last trade and price {} is below the price 1h ago {}", price, price_1h_ago),
+                ),
+                (Position::Base, Some(last_price)) if price > last_price => TradeDecision::new(
+                    TradeAction::Sell,
+                    format!("over 24h since last trade and price {} is above last trade price {}", price, last_price),
+                ),
+                _ => TradeDecision::new(
+                    TradeAction::Hold,
+                    format!("over 24h since last trade, price {} has not moved in the trade direction", price),
+                ),
+            };
+        }
+    }
+    
+    let Some(last_price) = last_trade_price else {
+        return TradeDecision::new(TradeAction::Hold, "no previous trade price".to_string());
+    };
+    
+    match position {
+        Position::Quote if price <= last_price * (dec!(1) - thresholds.buy_dip_pct) => TradeDecision::new(
+            TradeAction::Buy,
+            format!("price {} is at least {}% below last trade price {}", price, thresholds.buy_dip_pct * dec!(100), last_price),
+        ),
+        Position::Base if price >= last_price * (dec!(1) + thresholds.take_profit_pct) => TradeDecision::new(
+            TradeAction::Sell,
+            format!("price {} is at least {}% above last trade price {}", price, thresholds.take_profit_pct * dec!(100), last_price),
+        ),
+        _ => TradeDecision::new(
+            TradeAction::Hold,
+            format!("price {} is within thresholds of last trade price {}", price, last_price),
+        ),
+    }
+}
+
+// Threshold strategy against the current trading state.
+// `now` is passed in so backtests can replay past prices.
+pub(crate) fn should_make_trade(
+    position: &Position,
+    trend: &crate::firestore::PriceTrend,
+    price: Decimal,
+    _quote_price: Decimal,
+    now: DateTime<FixedOffset>,
+    state: &TradingState,
+    config: &Config,
+) -> bool {
+    let decision = decide_trade(
+        position,
+        price,
+        &PositionContext::from_state(state),
+        now,
+        trend,
+        &TradeThresholds::from_config(config),
+    );
+    info!("Trade decision: {:?} ({})", decision.action, decision.reason);
+    decision.should_trade()
+}
+
+// Buy the base token when the short SMA crosses above the long SMA, sell when it crosses below.
+// Prices are ordered newest first, as returned by get_price_history.
+pub(crate) fn should_trade_sma_crossover(
+    position: &Position,
+    prices: &[PriceHistory],
+    price: Decimal,
+    state: &TradingState,
+    config: &Config,
+) -> bool {
+    if *position == Position::Base && is_stop_loss(price, state, config) {
+        info!("Stop-loss triggered: price {} is at or below {}% under last_trade_price", price, config.stop_loss_pct * dec!(100));
+        return true;
+    }
+    if *position == Position::Base && is_trailing_stop(price, state, config) {
+        info!("Trailing stop triggered: price {} is at or below {}% under peak price {:?}", price, config.trailing_stop_pct.unwrap_or_default() * dec!(100), state.peak_price);
+        return true;
+    }
+    
+    if prices.len() < 2 {
+        info!("Not enough price history for SMA crossover");
+        return false;
+    }
+    
+    let short_window = Duration::hours(config.sma_short_hours as i64);
+    let long_window = Duration::hours(config.sma_long_hours as i64);
+    
+    // Compare the SMAs at the latest tick against the SMAs at the tick before it
+    let current = (sma(prices, short_window), sma(prices, long_window));
+    let previous = (sma(&prices[1..], short_window), sma(&prices[1..], long_window));
+    
+    let (Some(short_sma), Some(long_sma)) = current else { return false };
+    let (Some(prev_short_sma), Some(prev_long_sma)) = previous else { return false };
+    info!("SMA short: {} (prev {}), long: {} (prev {})", short_sma, prev_short_sma, long_sma, prev_long_sma);
+    
+    match position {
+        Position::Quote => prev_short_sma <= prev_long_sma && short_sma > long_sma,
+        Position::Base => prev_short_sma >= prev_long_sma && short_sma < long_sma,
+    }
+}
+
+// Buy the base token when RSI is at or below rsi_oversold, sell when it is at or above rsi_overbought.
+// Prices are ordered newest first, as returned by get_price_history.
+pub(crate) fn should_trade_rsi(
+    position: &Position,
+    prices: &[PriceHistory],
+    price: Decimal,
+    state: &TradingState,
+    config: &Config,
+) -> bool {
+    if *position == Position::Base && is_stop_loss(price, state, config) {
+        info!("Stop-loss triggered: price {} is at or below {}% under last_trade_price", price, config.stop_loss_pct * dec!(100));
+        return true;
+    }
+    if *position == Position::Base && is_trailing_stop(price, state, config) {
+        info!("Trailing stop triggered: price {} is at or below {}% under peak price {:?}", price, config.trailing_stop_pct.unwrap_or_default() * dec!(100), state.peak_price);
+        return true;
+    }
+    
+    let oldest_first: Vec<Decimal> = prices.iter().rev().map(|p| p.sol_price_usdc).collect();
+    let Some(rsi) = indicators::rsi(&oldest_first, config.rsi_period) else {
+        info!("Not enough price history for RSI");
+        return false;
+    };
+    info!("RSI({}): {}", config.rsi_period, rsi);
+    
+    match position {
+        Position::Quote => rsi <= config.rsi_oversold,
+        Position::Base => rsi >= config.rsi_overbought,
+    }
+}
+
+// Simple moving average over the prices within `window` of the newest price
+fn sma(prices: &[PriceHistory], window: Duration) -> Option<Decimal> {
+    let newest = prices.first()?.timestamp;
+    let window_prices: Vec<Decimal> = prices.iter()
+        .take_while(|p| newest - p.timestamp < window)
+        .map(|p| p.sol_price_usdc)
+        .collect();
+    
+    if window_prices.is_empty() {
+        return None;
+    }
+    Some(window_prices.iter().sum::<Decimal>() / Decimal::from(window_prices.len()))
+}
+
+// Sell the base token once the price has fallen trailing_stop_pct or more below the peak since entry
+fn is_trailing_stop(price: Decimal, state: &TradingState, config: &Config) -> bool {
+    match (state.peak_price, config.trailing_stop_pct) {
+        (Some(peak), Some(trailing_stop_pct)) => trailing_stop_hit(price, peak, trailing_stop_pct),
+        _ => false,
+    }
+}
+
+fn trailing_stop_hit(price: Decimal, peak: Decimal, trailing_stop_pct: Decimal) -> bool {
+    price <= peak * (dec!(1) - trailing_stop_pct)
+}
+
+// Volume-weighted average of the current entry price over `size` base tokens and a buy of `amount` at `price`
+pub(crate) fn average_entry_price(current: Option<Decimal>, size: Decimal, price: Decimal, amount: Decimal) -> Decimal {
+    match current {
+        Some(current) if size + amount > dec!(0) => (current * size + price * amount) / (size + amount),
+        _ => price,
+    }
+}
+
+// A transaction fee (paid in SOL) in quote tokens, zero when neither side of the pair is SOL
+fn fee_in_quote(gas_fee: Option<Decimal>, pair: &TradingPair, base_price: Decimal) -> Decimal {
+    let gas_fee = gas_fee.unwrap_or(dec!(0));
+    let is_native = |mint: &str| Pubkey::from_str(mint).map(|mint| is_native_mint(&mint)).unwrap_or(false);
+    if is_native(&pair.base_mint) {
+        gas_fee * base_price
+    } else if is_native(&pair.quote_mint) {
+        gas_fee
+    } else {
+        dec!(0)
+    }
+}
+
+// Time between entering and leaving the base position, None when the entry time is unknown
+pub(crate) fn holding_duration(entered_at: Option<DateTime<FixedOffset>>, exited_at: DateTime<FixedOffset>) -> Option<Duration> {
+    entered_at.map(|entered_at| exited_at - entered_at)
+}
+
+// Sell the base token once it has been held longer than max_hold_hours, whatever the price
+fn is_time_exit(state: &TradingState, config: &Config, now: DateTime<FixedOffset>) -> bool {
+    match (&state.position, config.max_hold_hours) {
+        (Position::Base, Some(max_hold_hours)) => max_hold_exceeded(state.entered_at, now, max_hold_hours),
+        _ => false,
+    }
+}
+
+// A position with an unknown entry time (entered before it was tracked) is never aged out
+pub(crate) fn max_hold_exceeded(entered_at: Option<DateTime<FixedOffset>>, now: DateTime<FixedOffset>, max_hold_hours: u32) -> bool {
+    holding_duration(entered_at, now)
+        .map(|held| held > Duration::hours(max_hold_hours as i64))
+        .unwrap_or(false)
+}
+
+// Index of the next unfired ladder rung once the price has reached its target above the entry price.
+// Rungs fire one per run, in order, so a jump past several targets sells them on successive runs.
+pub(crate) fn next_ladder_rung(state: &TradingState, price: Decimal, config: &Config) -> Option<usize> {
+    let entry_price = state.last_trade_price?;
+    let rung = config.take_profit_ladder.get(state.ladder_rungs_fired)?;
+    (price >= entry_price * (dec!(1) + rung.target_pct)).then_some(state.ladder_rungs_fired)
+}
+
+// Base tokens ladder rung `rung` sells: its fraction of the position size at entry. Earlier rungs each
+// sold their fraction of that, so the entry size is `position_size` scaled back up by what is left.
+pub(crate) fn ladder_sell_amount(position_size: Decimal, ladder: &[LadderRung], rung: usize) -> Decimal {
+    let remaining = dec!(1) - ladder[..rung].iter().map(|rung| rung.fraction).sum::<Decimal>();
+    if remaining <= dec!(0) {
+        return position_size;
+    }
+    (position_size / remaining * ladder[rung].fraction).min(position_size)
+}
+
+// Sell the base token if the price has fallen stop_loss_pct or more below the price from the last trade
+fn is_stop_loss(price: Decimal, state: &TradingState, config: &Config) -> bool {
+    state.last_trade_price
+        .map(|last_price| price <= last_price * (dec!(1) - config.stop_loss_pct))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use crate::jupiter_mock::{self, MockJupiter};
+    
+    fn test_pair() -> TradingPair {
+        test_config().pairs[0].clone()
+    }
+    
+    fn ladder() -> Vec<LadderRung> {
+        vec![
+            LadderRung { target_pct: dec!(0.02), fraction: dec!(0.25) },
+            LadderRung { target_pct: dec!(0.05), fraction: dec!(0.5) },
+        ]
+    }
+    
+    #[test]
+    fn ladder_rungs_fire_once_each_in_order() {
+        let config = Config { take_profit_ladder: ladder(), ..test_config() };
+        let mut state = TradingState::new(test_pair());
+        state.position = Position::Base;
+        state.last_trade_price = Some(dec!(100));
+        state.position_size = dec!(8);
+        
+        let mut sold = Vec::new();
+        for price in [dec!(101), dec!(102), dec!(103), dec!(106), dec!(110), dec!(120)] {
+            if let Some(rung) = next_ladder_rung(&state, price, &config) {
+                let amount = ladder_sell_amount(state.position_size, &config.take_profit_ladder, rung);
+                sold.push((price, rung, amount));
+                state.position_size -= amount;
+                state.ladder_rungs_fired += 1;
+            }
+        }
+        
+        // A quarter then half of the 8 tokens entered, leaving the last quarter
+        assert_eq!(sold, vec![(dec!(102), 0, dec!(2)), (dec!(106), 1, dec!(4))]);
+        assert_eq!(state.position_size, dec!(2));
+    }
+    
+    #[test]
+    fn price_jumping_past_several_targets_fires_one_rung_per_run() {
+        let config = Config { take_profit_ladder: ladder(), ..test_config() };
+        let mut state = TradingState::new(test_pair());
+        state.position = Position::Base;
+        state.last_trade_price = Some(dec!(100));
+        
+        assert_eq!(next_ladder_rung(&state, dec!(110), &config), Some(0));
+        state.ladder_rungs_fired = 1;
+        assert_eq!(next_ladder_rung(&state, dec!(110), &config), Some(1));
+        state.ladder_rungs_fired = 2;
+        assert_eq!(next_ladder_rung(&state, dec!(110), &config), None);
+    }
+    
+    #[test]
+    fn consecutive_losses_count_losing_sells_until_a_win() {
+        let mut state = TradingState::new(test_pair());
+        
+        update_consecutive_losses(&mut state, Some(dec!(-1)));
+        update_consecutive_losses(&mut state, Some(dec!(-2)));
+        assert_eq!(state.consecutive_losses, 2);
+        
+        // Break-even sells and sells without a previous price leave the count alone
+        update_consecutive_losses(&mut state, Some(dec!(0)));
+        update_consecutive_losses(&mut state, None);
+        assert_eq!(state.consecutive_losses, 2);
+        
+        update_consecutive_losses(&mut state, Some(dec!(0.5)));
+        assert_eq!(state.consecutive_losses, 0);
+        update_consecutive_losses(&mut state, Some(dec!(-1)));
+        assert_eq!(state.consecutive_losses, 1);
+    }
+    
+    #[test]
+    fn fees_paid_in_sol_are_converted_to_the_quote_token() {
+        // SOL/USDC: the SOL fee is worth fee * SOL price in USDC
+        assert_eq!(fee_in_quote(Some(dec!(0.00001)), &test_pair(), dec!(150)), dec!(0.0015));
+        assert_eq!(fee_in_quote(None, &test_pair(), dec!(150)), dec!(0));
+    }
+    
+    #[test]
+    fn ladder_sell_amount_never_exceeds_the_position() {
+        let ladder = vec![
+            LadderRung { target_pct: dec!(0.02), fraction: dec!(0.75) },
+            LadderRung { target_pct: dec!(0.05), fraction: dec!(0.75) },
+        ];
+        
+        assert_eq!(ladder_sell_amount(dec!(4), &ladder, 0), dec!(3));
+        assert_eq!(ladder_sell_amount(dec!(1), &ladder, 1), dec!(1));
+    }
+    
+    fn trend(now: DateTime<FixedOffset>, price_1h_ago: Option<Decimal>) -> crate::firestore::PriceTrend {
+        crate::firestore::PriceTrend {
+            timestamp: now,
+            price_1h_ago,
+            price_24h_ago: None,
+            price_7d_ago: None,
+            trend_1h: None,
+            trend_24h: None,
+            trend_7d: None,
+            volatility_1h: None,
+            volatility_24h: None,
+        }
+    }
+    
+    #[test]
+    fn decide_trade_follows_the_price_for_both_positions() {
+        let thresholds = TradeThresholds {
+            stop_loss_pct: dec!(0.05),
+            take_profit_pct: dec!(0.03),
+            buy_dip_pct: dec!(0.02),
+            trailing_stop_pct: Some(dec!(0.04)),
+        };
+        let now = DateTime::parse_from_rfc3339("2026-01-01T12:00:00+00:00").unwrap();
+        let trend = trend(now, Some(dec!(101)));
+        
+        // (position, price, peak, hours since the last trade at 100, expected)
+        let cases = [
+            (Position::Base, dec!(105), None, 1, TradeAction::Sell),
+            (Position::Base, dec!(100), None, 1, TradeAction::Hold),
+            (Position::Base, dec!(98), None, 1, TradeAction::Hold),
+            (Position::Base, dec!(94), None, 1, TradeAction::StopLoss),
+            (Position::Base, dec!(105), Some(dec!(110)), 1, TradeAction::TrailingStop),
+            (Position::Base, dec!(105), Some(dec!(106)), 1, TradeAction::Sell),
+            (Position::Quote, dec!(97), None, 1, TradeAction::Buy),
+            (Position::Quote, dec!(99), None, 1, TradeAction::Hold),
+            (Position::Quote, dec!(100), None, 1, TradeAction::Hold),
+            (Position::Quote, dec!(105), None, 1, TradeAction::Hold),
+            // Stale positions trade on any move in their direction
+            (Position::Base, dec!(100.5), None, 30, TradeAction::Sell),
+            (Position::Base, dec!(100), None, 30, TradeAction::Hold),
+            (Position::Base, dec!(99.5), None, 30, TradeAction::Hold),
+            (Position::Base, dec!(90), None, 30, TradeAction::StopLoss),
+            (Position::Quote, dec!(100.5), None, 30, TradeAction::Buy),
+            (Position::Quote, dec!(101), None, 30, TradeAction::Hold),
+            (Position::Quote, dec!(105), None, 30, TradeAction::Hold),
+        ];
+        
+        for (position, price, peak_price, hours, expected) in cases {
+            let context = PositionContext {
+                last_trade_price: Some(dec!(100)),
+                last_trade_time: Some(now - Duration::hours(hours)),
+                peak_price,
+            };
+            let decision = decide_trade(&position, price, &context, now, &trend, &thresholds);
+            assert_eq!(
+                decision.action, expected,
+                "{:?} at {} (peak {:?}, {}h since last trade): {}", position, price, peak_price, hours, decision.reason
+            );
+        }
+    }
+    
+    #[test]
+    fn decide_trade_holds_without_a_previous_trade() {
+        let now = DateTime::parse_from_rfc3339("2026-01-01T12:00:00+00:00").unwrap();
+        let trend = trend(now, None);
+        let thresholds = TradeThresholds::from_config(&test_config());
+        
+        for position in [Position::Base, Position::Quote] {
+            for price in [dec!(50), dec!(100), dec!(200)] {
+                let decision = decide_trade(&position, price, &PositionContext::default(), now, &trend, &thresholds);
+                assert_eq!(decision.action, TradeAction::Hold);
+            }
+        }
+    }
+    
+    #[test]
+    fn profit_accumulates_across_winning_trades() {
+        let mut state = TradingState::new(test_pair());
+        
+        record_trade(&mut state, Some(dec!(1.5)));
+        record_trade(&mut state, Some(dec!(2.25)));
+        
+        assert_eq!(state.total_profit, dec!(3.75));
+        assert_eq!(state.total_trades, 2);
+        assert_eq!(state.winning_trades, 2);
+        assert_eq!(state.losing_trades, 0);
+        
+        // A first buy has no previous price to measure against, but still counts as a trade
+        record_trade(&mut state, None);
+        record_trade(&mut state, Some(dec!(-0.75)));
+        assert_eq!(state.total_profit, dec!(3));
+        assert_eq!(state.total_trades, 4);
+        assert_eq!(state.losing_trades, 1);
+    }
+    
+    #[test]
+    fn stop_loss_triggers_exactly_at_the_threshold() {
+        let config = Config { stop_loss_pct: dec!(0.05), trailing_stop_pct: None, ..test_config() };
+        let mut state = TradingState::new(test_pair());
+        state.position = Position::Base;
+        state.last_trade_price = Some(dec!(100));
+        let now = DateTime::parse_from_rfc3339("2026-01-01T12:00:00+00:00").unwrap();
+        let context = PositionContext { last_trade_time: Some(now), ..PositionContext::from_state(&state) };
+        let thresholds = TradeThresholds::from_config(&config);
+        
+        for (price, expected) in [(dec!(95.01), false), (dec!(95), true), (dec!(94.99), true)] {
+            assert_eq!(is_stop_loss(price, &state, &config), expected, "price {}", price);
+            let decision = decide_trade(&Position::Base, price, &context, now, &trend(now, None), &thresholds);
+            assert_eq!(decision.action == TradeAction::StopLoss, expected, "price {}: {}", price, decision.reason);
+        }
+        
+        // Holding the quote token there is nothing to stop out of
+        let decision = decide_trade(&Position::Quote, dec!(95), &context, now, &trend(now, None), &thresholds);
+        assert_ne!(decision.action, TradeAction::StopLoss);
+    }
+    
+    #[test]
+    fn buy_and_sell_arms_trade_in_opposite_directions() {
+        let thresholds = TradeThresholds {
+            stop_loss_pct: dec!(0.5),
+            take_profit_pct: dec!(0.03),
+            buy_dip_pct: dec!(0.02),
+            trailing_stop_pct: None,
+        };
+        let now = DateTime::parse_from_rfc3339("2026-01-01T12:00:00+00:00").unwrap();
+        let context = PositionContext { last_trade_price: Some(dec!(100)), last_trade_time: Some(now), peak_price: None };
+        let decide = |position: Position, price: Decimal| decide_trade(&position, price, &context, now, &trend(now, None), &thresholds).action;
+        
+        // Holding the base token sells into a rise of take_profit_pct, never into a drop
+        assert_eq!(decide(Position::Base, dec!(103)), TradeAction::Sell);
+        assert_eq!(decide(Position::Base, dec!(102.99)), TradeAction::Hold);
+        assert_eq!(decide(Position::Base, dec!(98)), TradeAction::Hold);
+        
+        // Holding the quote token buys a drop of buy_dip_pct, never a rise
+        assert_eq!(decide(Position::Quote, dec!(98)), TradeAction::Buy);
+        assert_eq!(decide(Position::Quote, dec!(98.01)), TradeAction::Hold);
+        assert_eq!(decide(Position::Quote, dec!(103)), TradeAction::Hold);
+    }
+    
+    #[test]
+    fn trades_are_sized_as_a_fraction_of_the_balance() {
+        let config = Config { trade_fraction: dec!(0.25), max_trade_usdc: None, min_trade_usdc: None, ..test_config() };
+        // 4 SOL at 150 is 600 in quote tokens, a quarter of which is 1 SOL
+        assert_eq!(size_trade(dec!(4), dec!(150), &config), Some(dec!(1)));
+        assert_eq!(size_trade(dec!(0), dec!(150), &config), None);
+        assert_eq!(size_trade(dec!(4), dec!(0), &config), None);
+        
+        let all_in = Config { trade_fraction: dec!(1), ..config };
+        assert_eq!(size_trade(dec!(4), dec!(150), &all_in), Some(dec!(4)));
+    }
+    
+    #[test]
+    fn trade_size_is_clamped_to_the_min_and_max() {
+        let config = Config {
+            trade_fraction: dec!(0.5),
+            max_trade_usdc: Some(dec!(150)),
+            min_trade_usdc: Some(dec!(30)),
+            ..test_config()
+        };
+        
+        // Half of 600 is capped at 150
+        assert_eq!(size_trade(dec!(4), dec!(150), &config), Some(dec!(1)));
+        // Half of 90 is within the bounds
+        assert_eq!(size_trade(dec!(0.6), dec!(150), &config), Some(dec!(0.3)));
+        // Half of 40 is raised to the 30 minimum
+        assert_eq!(size_trade(dec!(40), dec!(1), &config), Some(dec!(30)));
+        // 20 available can't reach the minimum at all
+        assert_eq!(size_trade(dec!(20), dec!(1), &config), None);
+    }
+    
+    // Hourly prices given oldest first, returned newest first like get_price_history
+    fn price_history(prices: &[Decimal]) -> Vec<PriceHistory> {
+        let start = DateTime::parse_from_rfc3339("2026-01-01T00:00:00+00:00").unwrap();
+        let mut history: Vec<PriceHistory> = prices.iter().enumerate().map(|(i, price)| PriceHistory {
+            id: i.to_string(),
+            pair: "SOL/USDC".to_string(),
+            timestamp: start + Duration::hours(i as i64),
+            sol_price_usdc: *price,
+            usdc_price_sol: dec!(1) / *price,
+            data_source: "test".to_string(),
+            trading_session_id: String::new(),
+            spread: None,
+        }).collect();
+        history.reverse();
+        history
+    }
+    
+    #[test]
+    fn sma_crossover_signals_on_the_tick_the_averages_cross() {
+        let config = Config { sma_short_hours: 2, sma_long_hours: 4, trailing_stop_pct: None, ..test_config() };
+        let state = TradingState::new(test_pair());
+        let signals = |position: Position, prices: &[Decimal]| {
+            let history = price_history(prices);
+            should_trade_sma_crossover(&position, &history, history[0].sol_price_usdc, &state, &config)
+        };
+        
+        // Short SMA goes from 8 (under the long 8.75) to 10 (over the long 9.25)
+        let cross_up = [dec!(10), dec!(10), dec!(10), dec!(10), dec!(9), dec!(8), dec!(8), dec!(12)];
+        assert!(signals(Position::Quote, &cross_up));
+        assert!(!signals(Position::Base, &cross_up));
+        
+        // Short SMA goes from 12 (over the long 11.25) to 10 (under the long 10.75)
+        let cross_down = [dec!(10), dec!(10), dec!(10), dec!(10), dec!(11), dec!(12), dec!(12), dec!(8)];
+        assert!(signals(Position::Base, &cross_down));
+        assert!(!signals(Position::Quote, &cross_down));
+        
+        // A steady rise keeps the short SMA above the long one without crossing
+        let rising: Vec<Decimal> = (1..=8).map(Decimal::from).collect();
+        assert!(!signals(Position::Quote, &rising));
+        assert!(!signals(Position::Base, &rising));
+    }
+    
+    #[test]
+    fn forced_trades_skip_the_strategy_and_move_the_state() {
+        let now = DateTime::parse_from_rfc3339("2026-01-01T12:00:00+00:00").unwrap();
+        let thresholds = TradeThresholds::from_config(&test_config());
+        let mut state = TradingState::new(test_pair());
+        state.last_trade_price = Some(dec!(100));
+        state.last_trade_timestamp = Some(now);
+        
+        // Flat price, so the strategy alone would hold either way
+        let hold = |state: &TradingState| {
+            decide_trade(&state.position, dec!(100), &PositionContext::from_state(state), now, &trend(now, None), &thresholds).action
+        };
+        
+        assert_eq!(hold(&state), TradeAction::Hold);
+        assert!(matches!(check_forced_action(ForcedAction::Sell, &state), Err(TradingError::PositionMismatch { .. })));
+        check_forced_action(ForcedAction::Buy, &state).unwrap();
+        state.enter_position(dec!(100), dec!(2), dec!(0.01), now);
+        
+        assert_eq!(state.position, Position::Base);
+        assert_eq!(state.last_trade_price, Some(dec!(100)));
+        assert_eq!(state.peak_price, Some(dec!(100)));
+        assert_eq!(state.entered_at, Some(now));
+        assert_eq!(state.position_size, dec!(2));
+        assert_eq!(state.break_even_price(), Some(dec!(100.005)));
+        
+        assert_eq!(hold(&state), TradeAction::Hold);
+        assert!(matches!(check_forced_action(ForcedAction::Buy, &state), Err(TradingError::PositionMismatch { .. })));
+        check_forced_action(ForcedAction::Sell, &state).unwrap();
+        state.exit_position(dec!(100));
+        
+        assert_eq!(state.position, Position::Quote);
+        assert_eq!(state.peak_price, None);
+        assert_eq!(state.entered_at, None);
+        assert_eq!(state.average_entry_price, None);
+        assert_eq!(state.position_size, dec!(0));
+    }
+    
+    #[test]
+    fn position_follows_the_side_holding_value() {
+        use Position::{Base, Quote};
+        
+        // (base value, quote value, stored position, expected) with a threshold of 1
+        let cases = [
+            (dec!(150), dec!(0.5), Quote, Base),
+            (dec!(150), dec!(0.5), Base, Base),
+            (dec!(0.2), dec!(100), Base, Quote),
+            (dec!(0.2), dec!(100), Quote, Quote),
+            (dec!(1), dec!(0.99), Quote, Base),
+            // A split balance or dust on both sides keeps the stored position
+            (dec!(50), dec!(50), Base, Base),
+            (dec!(50), dec!(50), Quote, Quote),
+            (dec!(0.1), dec!(0.1), Base, Base),
+            (dec!(0), dec!(0), Quote, Quote),
+        ];
+        for (base_value, quote_value, position, expected) in cases {
+            assert_eq!(
+                reconciled_position(&position, base_value, quote_value, dec!(1)), expected,
+                "{} base / {} quote, stored {:?}", base_value, quote_value, position
+            );
+        }
+    }
+    
+    #[test]
+    fn adaptive_slippage_scales_with_volatility() {
+        let config = Config {
+            min_slippage_bps: 30,
+            max_slippage_bps: 300,
+            adaptive_slippage_volatility_pct: dec!(0.05),
+            ..test_config()
+        };
+        
+        // Volatility against a price of 100, so 5 is the 5% that maxes out slippage
+        let cases = [
+            (dec!(0), 30),
+            (dec!(-1), 30),
+            (dec!(0.5), 57),
+            (dec!(2.5), 165),
+            (dec!(5), 300),
+            (dec!(20), 300),
+        ];
+        for (volatility, expected) in cases {
+            assert_eq!(adaptive_slippage_bps(volatility, dec!(100), &config), expected, "volatility {}", volatility);
+        }
+        
+        // Without a usable price there's nothing to scale against
+        assert_eq!(adaptive_slippage_bps(dec!(1), dec!(0), &config), 300);
+    }
+    
+    #[test]
+    fn spread_compares_the_buy_and_sell_prices() {
+        // Buying one base token costs 1 / quote_price
+        assert_eq!(implied_spread(dec!(100), dec!(0.01)), Some(dec!(0)));
+        assert_eq!(implied_spread(dec!(100), dec!(0.008)), Some(dec!(0.25)));
+        assert_eq!(implied_spread(dec!(100), dec!(0.0125)), Some(dec!(-0.2)));
+        assert_eq!(implied_spread(dec!(0), dec!(0.01)), None);
+        assert_eq!(implied_spread(dec!(100), dec!(0)), None);
+        
+        assert!(!is_spread_too_wide(dec!(0.005), dec!(0.01)));
+        assert!(!is_spread_too_wide(dec!(0.01), dec!(0.01)));
+        assert!(is_spread_too_wide(dec!(0.25), dec!(0.01)));
+        assert!(is_spread_too_wide(dec!(-0.2), dec!(0.01)));
+    }
+    
+    #[test]
+    fn only_positions_held_past_the_maximum_are_aged_out() {
+        let now = DateTime::parse_from_rfc3339("2026-01-02T12:00:00+09:00").unwrap();
+        
+        assert!(max_hold_exceeded(Some(now - Duration::hours(25)), now, 24));
+        assert!(!max_hold_exceeded(Some(now - Duration::hours(24)), now, 24));
+        assert!(!max_hold_exceeded(Some(now - Duration::hours(1)), now, 24));
+        assert!(!max_hold_exceeded(None, now, 24));
+    }
+    
+    #[tokio::test]
+    async fn trailing_stop_follows_the_peak_and_exits_on_a_pullback() {
+        let config = Config { trailing_stop_pct: Some(dec!(0.05)), stop_loss_pct: dec!(0.5), ..test_config() };
+        let thresholds = TradeThresholds::from_config(&config);
+        let now = DateTime::parse_from_rfc3339("2026-01-01T12:00:00+00:00").unwrap();
+        let mut state = TradingState::new(test_pair());
+        state.enter_position(dec!(100), dec!(1), dec!(0), now);
+        
+        // Each tick raises the peak, and 5% under 110 is 104.5
+        for (price, peak) in [(dec!(102), dec!(102)), (dec!(106), dec!(106)), (dec!(110), dec!(110)), (dec!(107), dec!(110))] {
+            update_peak_price(&mut state, price).await;
+            assert_eq!(state.peak_price, Some(peak));
+            assert!(!is_trailing_stop(price, &state, &config), "price {}", price);
+        }
+        
+        update_peak_price(&mut state, dec!(104)).await;
+        assert!(is_trailing_stop(dec!(104), &state, &config));
+        let decision = decide_trade(&state.position, dec!(104), &PositionContext::from_state(&state), now, &trend(now, None), &thresholds);
+        assert_eq!(decision.action, TradeAction::TrailingStop, "{}", decision.reason);
+        
+        // Selling clears the peak, and the next entry starts from its own price
+        state.exit_position(dec!(104));
+        assert_eq!(state.peak_price, None);
+        state.enter_position(dec!(90), dec!(1), dec!(0), now);
+        assert_eq!(state.peak_price, Some(dec!(90)));
+    }
+    
+    #[test]
+    fn entry_price_is_volume_weighted_across_buys() {
+        let mut state = TradingState::new(test_pair());
+        
+        state.record_entry(dec!(100), dec!(2), dec!(0.02));
+        assert_eq!(state.average_entry_price, Some(dec!(100)));
+        
+        // 2 at 100 and 1 at 130 average 110, not the 115 midpoint
+        state.record_entry(dec!(130), dec!(1), dec!(0.01));
+        assert_eq!(state.average_entry_price, Some(dec!(110)));
+        assert_eq!(state.position_size, dec!(3));
+        assert_eq!(state.break_even_price(), Some(dec!(110.01)));
+        
+        assert_eq!(average_entry_price(Some(dec!(110)), dec!(3), dec!(90), dec!(1)), dec!(105));
+        assert_eq!(average_entry_price(None, dec!(0), dec!(90), dec!(1)), dec!(90));
+    }
+    
+    #[test]
+    fn trades_wait_out_the_cooldown() {
+        let config = Config { trade_cooldown_secs: 600, ..test_config() };
+        let last_trade = DateTime::parse_from_rfc3339("2026-01-01T12:00:00+09:00").unwrap();
+        let mut state = TradingState::new(test_pair());
+        assert_eq!(cooldown_remaining(&state, &config, last_trade), None);
+        
+        state.last_trade_timestamp = Some(last_trade);
+        assert_eq!(cooldown_remaining(&state, &config, last_trade + Duration::seconds(1)), Some(Duration::seconds(599)));
+        assert_eq!(cooldown_remaining(&state, &config, last_trade + Duration::minutes(9)), Some(Duration::minutes(1)));
+        assert_eq!(cooldown_remaining(&state, &config, last_trade + Duration::minutes(10)), None);
+        assert_eq!(cooldown_remaining(&state, &config, last_trade + Duration::hours(1)), None);
+        
+        let no_cooldown = Config { trade_cooldown_secs: 0, ..config };
+        assert_eq!(cooldown_remaining(&state, &no_cooldown, last_trade), None);
+    }
+    
+    #[test]
+    fn prices_are_rounded_to_the_configured_decimals() {
+        let config = Config { price_decimals: 6, ..test_config() };
+        assert_eq!(round_price(dec!(148.123456789), &config), dec!(148.123457));
+        assert_eq!(round_price(dec!(0.0067567567567), &config), dec!(0.006757));
+        assert_eq!(round_price(dec!(150), &config), dec!(150));
+        
+        let config = Config { price_decimals: 2, ..config };
+        assert_eq!(round_price(dec!(148.126), &config), dec!(148.13));
+        assert_eq!(round_price(dec!(148.124), &config), dec!(148.12));
+    }
+    
+    #[test]
+    fn holding_duration_spans_the_entry_and_exit_sessions() {
+        // Entered late in the JST evening and sold after midnight
+        let entered_at = DateTime::parse_from_rfc3339("2026-01-01T23:30:00+09:00").unwrap();
+        let exited_at = DateTime::parse_from_rfc3339("2026-01-02T02:15:30+09:00").unwrap();
+        
+        let held = holding_duration(Some(entered_at), exited_at).unwrap();
+        assert_eq!(held, Duration::hours(2) + Duration::minutes(45) + Duration::seconds(30));
+        assert_eq!(held.num_seconds(), 9930);
+        assert_eq!(holding_duration(None, exited_at), None);
+    }
+    
+    #[test]
+    fn reload_measures_profit_from_the_trade_price() {
+        let pair = test_pair();
+        let timestamp = now_local();
+        let session = TradingSession {
+            id: "session".to_string(),
+            pair: pair.name.clone(),
+            timestamp,
+            position_before: pair.quote_symbol().to_string(),
+            position_after: pair.base_symbol().to_string(),
+            action: "BUY".to_string(),
+            sol_balance_before: dec!(0),
+            usdc_balance_before: dec!(150),
+            sol_balance_after: dec!(1),
+            usdc_balance_after: dec!(0),
+            price_at_trade: dec!(150),
+            slippage: None,
+            gas_fee: None,
+            signature: None,
+            profit_loss: None,
+            cumulative_profit: None,
+            suspicious_fill: false,
+            holding_duration_secs: None,
+        };
+        let tick = PriceHistory {
+            id: "price".to_string(),
+            pair: pair.name.clone(),
+            timestamp,
+            sol_price_usdc: dec!(162),
+            usdc_price_sol: dec!(0.0062),
+            data_source: "jupiter".to_string(),
+            trading_session_id: "session".to_string(),
+            spread: None,
+        };
+        let mut state = TradingState::new(pair);
+        
+        state.apply_latest_session(&session);
+        state.apply_latest_price(&tick);
+        
+        assert_eq!(state.position, Position::Base);
+        assert_eq!(state.last_base_price, Some(dec!(150)));
+        assert_eq!(state.last_trade_price, Some(dec!(150)));
+        assert_eq!(state.last_observed_price, Some(dec!(162)));
+    }
+    
+    // Value of an unlabelled metric, or one labelled with the given pair, in the /metrics text
+    fn scraped(name: &str, pair: Option<&str>) -> f64 {
+        let series = match pair {
+            Some(pair) => format!("{}{{pair=\"{}\"}} ", name, pair),
+            None => format!("{} ", name),
+        };
+        metrics().render().unwrap().lines()
+            .find_map(|line| line.strip_prefix(series.as_str()))
+            .map_or(0.0, |value| value.parse().unwrap())
+    }
+    
+    #[test]
+    fn recorded_trades_move_the_metrics() {
+        // Other tests share the registry, so counters are only checked to have moved
+        let trades = scraped("trading_bot_trades_total", None);
+        let wins = scraped("trading_bot_winning_trades_total", None);
+        let losses = scraped("trading_bot_losing_trades_total", None);
+        let mut state = TradingState::new(TradingPair { name: "METRICS/TEST".to_string(), ..test_pair() });
+        
+        record_trade(&mut state, Some(dec!(5)));
+        record_trade(&mut state, Some(dec!(-2)));
+        
+        assert!(scraped("trading_bot_trades_total", None) >= trades + 2.0);
+        assert!(scraped("trading_bot_winning_trades_total", None) > wins);
+        assert!(scraped("trading_bot_losing_trades_total", None) > losses);
+        assert_eq!(scraped("trading_bot_cumulative_profit", Some("METRICS/TEST")), 3.0);
+    }
+    
+    #[test]
+    fn sol_fee_reserve_is_held_back_from_trades() {
+        let config = Config { sol_fee_reserve: dec!(0.05), ..test_config() };
+        let sol = spl_token::native_mint::id();
+        let usdc = Pubkey::new_unique();
+        
+        assert_eq!(spendable_balance(dec!(1), &sol, &config).unwrap(), dec!(0.95));
+        assert_eq!(tradable_balance(dec!(1), &sol, &config), dec!(0.95));
+        // Only SOL pays fees
+        assert_eq!(spendable_balance(dec!(1), &usdc, &config).unwrap(), dec!(1));
+        assert_eq!(tradable_balance(dec!(1), &usdc, &config), dec!(1));
+    }
+    
+    #[test]
+    fn balance_within_the_reserve_is_insufficient() {
+        let config = Config { sol_fee_reserve: dec!(0.05), ..test_config() };
+        let sol = spl_token::native_mint::id();
+        
+        for balance in [dec!(0.05), dec!(0.01), dec!(0)] {
+            let err = spendable_balance(balance, &sol, &config).unwrap_err();
+            assert!(matches!(err, TradingError::InsufficientSolAfterReserve { available, reserve }
+                if available == balance && reserve == dec!(0.05)), "{} was spendable", balance);
+            assert_eq!(tradable_balance(balance, &sol, &config), dec!(0));
+        }
+    }
+    
+    #[tokio::test]
+    async fn marginally_profitable_trade_is_skipped() {
+        let mock = MockJupiter::start(jupiter_mock::normal_quote()).await;
+        let jupiter_client = JupiterClient::new(mock.url()).with_rate_limit(100.0);
+        // A fixed priority fee keeps the fee estimate off the network
+        let rpc_client = RpcClient::new("http://127.0.0.1:1".to_string());
+        let pair = test_pair();
+        let mut state = TradingState::new(pair.clone());
+        state.position = Position::Base;
+        state.last_trade_price = Some(dec!(149));
+        let one_sol = ui_to_raw_amount(dec!(1), pair.base_decimals);
+        
+        // Selling 1 SOL bought at 149 for the quoted 150 clears 1 USDC before fees
+        for (min_profit, expected) in [(dec!(0.5), true), (dec!(1), false)] {
+            let config = Config { min_profit_usdc: Some(min_profit), priority_fee_micro_lamports: Some(10_000), ..test_config() };
+            let meets = meets_min_profit(&jupiter_client, &rpc_client, &pair, &state, one_sol, dec!(150), &config).await.unwrap();
+            assert_eq!(meets, expected, "min profit {}", min_profit);
+        }
+    }
+    
+    #[test]
+    fn price_and_profit_records_carry_the_session_id() {
+        let pair = test_pair();
+        let mut state = TradingState::new(pair.clone());
+        record_trade(&mut state, Some(dec!(2)));
+        let trading_session_id = generate_session_id();
+        
+        let price = price_history_record(&pair, trading_session_id.clone(), dec!(150), dec!(0.0066), None);
+        let profit = profit_tracking_record(&state, trading_session_id.clone(), dec!(2), dec!(100));
+        
+        assert_eq!(price.trading_session_id, trading_session_id);
+        assert_eq!(profit.trading_session_id, trading_session_id);
+        // Each record still has its own document id
+        assert_ne!(price.id, trading_session_id);
+        assert_ne!(profit.id, trading_session_id);
+        assert_eq!(profit.roi_percentage, dec!(2));
+    }
+    
+    #[tokio::test]
+    async fn profit_is_converted_into_the_reporting_currency() {
+        // Neither case needs a quote, so the client is never called
+        let jupiter_client = JupiterClient::new("http://127.0.0.1:1");
+        let pair = test_pair();
+        
+        let usdc = Config { reporting_currency: "USDC".to_string(), ..test_config() };
+        assert_eq!(reporting_rate(&jupiter_client, &pair, dec!(150), &usdc).await.unwrap(), dec!(1));
+        assert_eq!(TradingState::new(pair.clone()).format_profit(dec!(3)), "3.0000 USDC");
+        
+        let sol = Config { reporting_currency: "SOL".to_string(), ..test_config() };
+        let rate = reporting_rate(&jupiter_client, &pair, dec!(150), &sol).await.unwrap();
+        assert_eq!((dec!(3) * rate).round_dp(4), dec!(0.02));
+        assert_eq!(TradingState::new(pair).with_reporting_currency("SOL").format_profit(dec!(3) * rate), "0.0200 SOL");
+        assert!(reporting_rate(&jupiter_client, &test_pair(), dec!(0), &sol).await.is_err());
+    }
+    
+    #[test]
+    fn prices_diverging_from_the_reference_are_refused() {
+        let pair = test_pair();
+        // Within 2% of the Pyth price of 150 either way
+        for price in [dec!(150), dec!(147), dec!(153)] {
+            assert!(check_divergence(&pair, price, dec!(150), dec!(0.02)).is_ok(), "{} was refused", price);
+        }
+        
+        let err = check_divergence(&pair, dec!(156), dec!(150), dec!(0.02)).unwrap_err();
+        assert!(matches!(err, TradingError::PriceDivergence { divergence_pct, max_pct, .. }
+            if divergence_pct == dec!(4) && max_pct == dec!(2)));
+        assert!(check_divergence(&pair, dec!(140), dec!(150), dec!(0.02)).is_err());
+        
+        // A reference price that can't be compared against is no reason to stop trading
+        assert!(check_divergence(&pair, dec!(150), dec!(0), dec!(0.02)).is_ok());
+    }
+}
@@ -1,13 +1,17 @@
 use anyhow::{Result, Context};
+use rust_decimal::prelude::*;
 use std::str::FromStr;
 use solana_sdk::{
     signature::Signature,
     pubkey::Pubkey,
     signature::{Keypair, Signer},
-    transaction::Transaction,
+    transaction::{Transaction, VersionedTransaction},
 };
-use solana_client::rpc_client::RpcClient;
+use solana_client::{client_error::ClientError, rpc_client::RpcClient, rpc_request::TokenAccountsFilter};
 use std::time::Duration;
+use tracing::info;
+use crate::error::TradingError;
+use crate::metrics::metrics;
 use crate::service::retry_as_exponential_back_off;
 
 pub struct Wallet {
@@ -16,10 +20,21 @@ pub struct Wallet {
 }
 
 impl Wallet {
+    // Accepts a base58 string or a JSON byte array as written by solana-keygen
     pub fn new(private_key: &str) -> Result<Self> {
-        let decoded = bs58::decode(private_key)
-            .into_vec()
-            .context("Failed to decode private key")?;
+        let private_key = private_key.trim();
+        let decoded: Vec<u8> = if private_key.starts_with('[') {
+            serde_json::from_str(private_key)
+                .context("Failed to parse private key as a JSON byte array")?
+        } else {
+            bs58::decode(private_key)
+                .into_vec()
+                .context("Failed to decode private key")?
+        };
+        
+        if decoded.len() != 64 {
+            anyhow::bail!("private key must decode to 64 bytes, got {}", decoded.len());
+        }
         
         let keypair = Keypair::from_bytes(&decoded)
             .context("Failed to create keypair from private key")?;
@@ -37,6 +52,12 @@ impl Wallet {
         transaction.sign(&[&self.keypair], transaction.message.recent_blockhash);
         Ok(())
     }
+
+    pub fn sign_versioned_transaction(&self, transaction: &mut VersionedTransaction) -> Result<()> {
+        *transaction = VersionedTransaction::try_new(transaction.message.clone(), &[&self.keypair])
+            .context("Failed to sign versioned transaction")?;
+        Ok(())
+    }
     
     pub async fn get_sol_balance(&self, client: &RpcClient) -> Result<f64> {
         let pubkey = self.pubkey;
@@ -44,16 +65,20 @@ impl Wallet {
         let balance = retry_as_exponential_back_off(
             || async move {
                 client.get_balance(&pubkey)
-                    .map_err(|e| anyhow::anyhow!("RPC error: {}", e))
+                    .map_err(|e| {
+                        metrics().rpc_errors.inc();
+                        anyhow::anyhow!("RPC error: {}", e)
+                    })
             },
             "Get SOL balance",
             3,
             500,
             Some(Duration::from_secs(10)),
+            None,
         )
         .await?;
         
-        Ok(balance as f64 / 1_000_000_000.0) // Convert lamports to SOL
+        Ok(lamports_to_sol(balance).to_f64().unwrap_or(0.0))
     }
     
     pub async fn get_token_balance(
@@ -67,24 +92,202 @@ impl Wallet {
         
         let account_info = retry_as_exponential_back_off(
             || async {
-                client.get_token_account_balance(&token_account)
-                    .map_err(|e| anyhow::anyhow!("RPC error: {}", e))
+                match client.get_token_account_balance(&token_account) {
+                    Ok(balance) => Ok(Some(balance)),
+                    // The associated token account has not been created yet, so there is nothing to retry
+                    Err(e) if is_account_not_found(&e) => Ok(None),
+                    Err(e) => {
+                        metrics().rpc_errors.inc();
+                        Err(anyhow::anyhow!("RPC error: {}", e))
+                    }
+                }
             },
             "Get token balance",
             3,
             500,
             Some(Duration::from_secs(10)),
+            None,
+        )
+        .await?;
+        
+        match account_info {
+            Some(account_info) => Ok(account_info.ui_amount.unwrap_or(0.0)),
+            None => {
+                info!("Token account {} does not exist yet, treating balance as 0", token_account);
+                Ok(0.0)
+            }
+        }
+    }
+
+    // Mint and UI amount of every SPL token account the wallet owns, empty if it has none
+    pub async fn get_all_token_balances(&self, client: &RpcClient) -> Result<Vec<(Pubkey, f64)>> {
+        let pubkey = self.pubkey;
+        
+        let accounts = retry_as_exponential_back_off(
+            || async move {
+                client.get_token_accounts_by_owner(&pubkey, TokenAccountsFilter::ProgramId(spl_token::id()))
+                    .map_err(|e| {
+                        metrics().rpc_errors.inc();
+                        anyhow::anyhow!("RPC error: {}", e)
+                    })
+            },
+            "Get token accounts",
+            3,
+            500,
+            Some(Duration::from_secs(10)),
+            None,
+        )
+        .await?;
+        
+        let mut balances = Vec::new();
+        for keyed_account in accounts {
+            let data = serde_json::to_value(&keyed_account.account.data)?;
+            match parse_token_account_balance(&data) {
+                Some(balance) => balances.push(balance),
+                None => info!("Skipping token account {} with unexpected data", keyed_account.pubkey),
+            }
+        }
+        
+        Ok(balances)
+    }
+
+    // Native SOL is held in the wallet itself rather than in a token account
+    pub async fn get_balance(&self, client: &RpcClient, mint: &Pubkey) -> Result<f64> {
+        if is_native_mint(mint) {
+            self.get_sol_balance(client).await
+        } else {
+            self.get_token_balance(client, mint).await
+        }
+    }
+
+    // Wrapped SOL left in the wallet's wSOL associated token account, e.g. after an interrupted swap
+    pub async fn get_wrapped_sol_balance(&self, client: &RpcClient) -> Result<f64> {
+        self.get_token_balance(client, &spl_token::native_mint::id()).await
+    }
+
+    // Native plus wrapped SOL. Swaps only spend the native balance, so this is for checking
+    // what the wallet holds rather than for sizing trades
+    pub async fn get_effective_sol_balance(&self, client: &RpcClient) -> Result<f64> {
+        let native_balance = self.get_sol_balance(client).await?;
+        let wrapped_balance = self.get_wrapped_sol_balance(client).await?;
+        Ok(native_balance + wrapped_balance)
+    }
+
+    // Like get_balance, but native SOL includes wrapped SOL
+    pub async fn get_effective_balance(&self, client: &RpcClient, mint: &Pubkey) -> Result<f64> {
+        if is_native_mint(mint) {
+            self.get_effective_sol_balance(client).await
+        } else {
+            self.get_token_balance(client, mint).await
+        }
+    }
+
+    // Close the wSOL account, which unwraps its balance and returns its rent to the wallet.
+    // Returns the signature, or None when there is no account to close
+    pub async fn close_wrapped_sol_account(&self, client: &RpcClient) -> Result<Option<String>> {
+        use spl_associated_token_account::get_associated_token_address;
+        
+        let token_account = get_associated_token_address(&self.pubkey, &spl_token::native_mint::id());
+        
+        let exists = client.get_account_with_commitment(&token_account, client.commitment())
+            .context("Failed to check wSOL account")?
+            .value
+            .is_some();
+        if !exists {
+            return Ok(None);
+        }
+        
+        let instruction = spl_token::instruction::close_account(
+            &spl_token::id(),
+            &token_account,
+            &self.pubkey,
+            &self.pubkey,
+            &[],
+        )
+        .context("Failed to build close account instruction")?;
+        
+        let recent_blockhash = client.get_latest_blockhash()
+            .context("Failed to get recent blockhash")?;
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.pubkey),
+            &[&self.keypair],
+            recent_blockhash,
+        );
+        
+        let signature = retry_as_exponential_back_off(
+            || async {
+                client.send_and_confirm_transaction(&transaction)
+                    .map_err(|e| anyhow::anyhow!("Failed to close wSOL account: {}", e))
+            },
+            "Close wSOL account",
+            3,
+            500,
+            Some(Duration::from_secs(60)),
+            None,
         )
         .await?;
         
-        Ok(account_info.ui_amount.unwrap_or(0.0))
+        info!("Closed wSOL account {}: {}", token_account, signature);
+        Ok(Some(signature.to_string()))
+    }
+
+    pub async fn ensure_token_account(
+        &self,
+        client: &RpcClient,
+        token_mint: &Pubkey,
+    ) -> Result<Pubkey> {
+        use spl_associated_token_account::{get_associated_token_address, instruction::create_associated_token_account_idempotent};
+        
+        let token_account = get_associated_token_address(&self.pubkey, token_mint);
+        
+        let exists = client.get_account_with_commitment(&token_account, client.commitment())
+            .context("Failed to check token account")?
+            .value
+            .is_some();
+        if exists {
+            return Ok(token_account);
+        }
+        
+        info!("Creating associated token account {} for mint {}", token_account, token_mint);
+        
+        let instruction = create_associated_token_account_idempotent(
+            &self.pubkey,
+            &self.pubkey,
+            token_mint,
+            &spl_token::id(),
+        );
+        let recent_blockhash = client.get_latest_blockhash()
+            .context("Failed to get recent blockhash")?;
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.pubkey),
+            &[&self.keypair],
+            recent_blockhash,
+        );
+        
+        let signature = retry_as_exponential_back_off(
+            || async {
+                client.send_and_confirm_transaction(&transaction)
+                    .map_err(|e| anyhow::anyhow!("Failed to create token account: {}", e))
+            },
+            "Create token account",
+            3,
+            500,
+            Some(Duration::from_secs(60)),
+            None,
+        )
+        .await?;
+        
+        info!("Created associated token account {}: {}", token_account, signature);
+        Ok(token_account)
     }
 
     pub async fn get_gas_fee(
         &self,
         client: &RpcClient,
         signature: String,
-    ) -> Result<f64> {
+    ) -> Result<Decimal> {
 
         let signature = Signature::from_str(&signature)
             .map_err(|e| anyhow::anyhow!("Invalid signature: {}", e))?;
@@ -92,20 +295,254 @@ impl Wallet {
         let tx = retry_as_exponential_back_off(
             || async {
                 client.get_transaction(&signature, solana_transaction_status::UiTransactionEncoding::Json)
-                    .map_err(|e| anyhow::anyhow!("RPC error: {}", e))
+                    .map_err(|e| {
+                        metrics().rpc_errors.inc();
+                        anyhow::anyhow!("RPC error: {}", e)
+                    })
             },
             "Get gas fee",
             3,
             500,
             Some(Duration::from_secs(10)),
+            None,
         )
         .await?;
 
         if let Some(meta) = tx.transaction.meta {
-            let fee = meta.fee as f64 / 1_000_000_000.0; // Convert lamports to SOL
-            return Ok(fee);
+            return Ok(lamports_to_sol(meta.fee));
         }
 
         Err(anyhow::anyhow!("Transaction meta not found"))
     }
-}
\ No newline at end of file
+}
+
+// Read the mint and UI amount from jsonParsed token account data:
+// {"parsed": {"info": {"mint": "...", "tokenAmount": {"uiAmount": 1.5, ...}}}, ...}
+fn parse_token_account_balance(data: &serde_json::Value) -> Option<(Pubkey, f64)> {
+    let info = data.get("parsed")?.get("info")?;
+    let mint = Pubkey::from_str(info.get("mint")?.as_str()?).ok()?;
+    let token_amount = info.get("tokenAmount")?;
+    // uiAmount is null for amounts that don't fit in an f64, so fall back to uiAmountString
+    let ui_amount = token_amount.get("uiAmount")
+        .and_then(|amount| amount.as_f64())
+        .or_else(|| token_amount.get("uiAmountString")?.as_str()?.parse().ok())?;
+    Some((mint, ui_amount))
+}
+
+fn is_account_not_found(error: &ClientError) -> bool {
+    let message = error.to_string();
+    message.contains("could not find account") || message.contains("AccountNotFound")
+}
+
+pub const SOL_DECIMALS: u8 = 9;
+
+// Conversions between whole-token amounts and a mint's smallest unit all go through these helpers
+fn unit_factor(decimals: u8) -> Decimal {
+    Decimal::from(10u64.pow(decimals as u32))
+}
+
+pub fn ui_to_raw_amount(ui_amount: Decimal, decimals: u8) -> u64 {
+    (ui_amount * unit_factor(decimals)).trunc().to_u64().unwrap_or(0)
+}
+
+// Checked conversion for swap amounts, which must fail rather than truncate to 0 or saturate
+pub fn to_base_units(ui_amount: Decimal, decimals: u8) -> Result<u64, TradingError> {
+    if ui_amount.is_sign_negative() && !ui_amount.is_zero() {
+        return Err(TradingError::InvalidAmount(format!("{} is negative", ui_amount)));
+    }
+    ui_amount.checked_mul(unit_factor(decimals))
+        .and_then(|raw| raw.trunc().to_u64())
+        .ok_or_else(|| TradingError::InvalidAmount(format!("{} with {} decimals does not fit in u64", ui_amount, decimals)))
+}
+
+// Balances are read over RPC as f64, so a bad read can be NaN, infinite or negative
+pub fn checked_ui_amount(ui_amount: f64) -> Result<Decimal, TradingError> {
+    if !ui_amount.is_finite() || ui_amount < 0.0 {
+        return Err(TradingError::InvalidAmount(format!("balance {} is not a finite non-negative number", ui_amount)));
+    }
+    Decimal::from_f64(ui_amount)
+        .ok_or_else(|| TradingError::InvalidAmount(format!("balance {} is out of range", ui_amount)))
+}
+
+pub fn raw_to_ui_amount(raw_amount: u64, decimals: u8) -> Decimal {
+    Decimal::from(raw_amount) / unit_factor(decimals)
+}
+
+// Convert a price in smallest units (raw output per raw input) to a price per whole token
+pub fn raw_price_to_ui(raw_price: Decimal, input_decimals: u8, output_decimals: u8) -> Decimal {
+    raw_price * unit_factor(input_decimals) / unit_factor(output_decimals)
+}
+
+pub fn lamports_to_sol(lamports: u64) -> Decimal {
+    raw_to_ui_amount(lamports, SOL_DECIMALS)
+}
+
+pub fn is_native_mint(mint: &Pubkey) -> bool {
+    *mint == spl_token::native_mint::id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use solana_client::rpc_request::{RpcError, RpcResponseErrorData};
+    
+    #[test]
+    fn wallet_loads_a_bs58_key() {
+        let keypair = Keypair::new();
+        let wallet = Wallet::new(&bs58::encode(keypair.to_bytes()).into_string()).unwrap();
+        assert_eq!(*wallet.pubkey(), keypair.pubkey());
+    }
+    
+    #[test]
+    fn wallet_loads_a_json_byte_array_key() {
+        let keypair = Keypair::new();
+        // solana-keygen writes the key as a JSON array, possibly with a trailing newline
+        let json = format!("{}\n", serde_json::to_string(&keypair.to_bytes().to_vec()).unwrap());
+        let wallet = Wallet::new(&json).unwrap();
+        assert_eq!(*wallet.pubkey(), keypair.pubkey());
+    }
+    
+    #[test]
+    fn wallet_rejects_a_key_of_the_wrong_length() {
+        let short = bs58::encode([7u8; 32]).into_string();
+        let err = Wallet::new(&short).err().unwrap();
+        assert_eq!(err.to_string(), "private key must decode to 64 bytes, got 32");
+        
+        let err = Wallet::new("[1, 2, 3]").err().unwrap();
+        assert_eq!(err.to_string(), "private key must decode to 64 bytes, got 3");
+        
+        assert!(Wallet::new("not-base58-0OIl").is_err());
+    }
+    
+    #[test]
+    fn lamports_convert_to_sol() {
+        assert_eq!(lamports_to_sol(0), dec!(0));
+        assert_eq!(lamports_to_sol(5_000), dec!(0.000005));
+        assert_eq!(lamports_to_sol(1_000_000_000), dec!(1));
+        assert_eq!(lamports_to_sol(1_234_567_891), dec!(1.234567891));
+        // Round trips through the raw amount without losing a lamport
+        assert_eq!(ui_to_raw_amount(lamports_to_sol(u64::MAX), SOL_DECIMALS), u64::MAX);
+    }
+    
+    #[test]
+    fn balances_must_be_finite_and_non_negative() {
+        assert_eq!(checked_ui_amount(1.5).unwrap(), dec!(1.5));
+        assert_eq!(checked_ui_amount(0.0).unwrap(), dec!(0));
+        for bad in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, -0.01] {
+            assert!(matches!(checked_ui_amount(bad), Err(TradingError::InvalidAmount(_))), "{} was accepted", bad);
+        }
+    }
+    
+    #[test]
+    fn base_units_fail_rather_than_wrap() {
+        assert_eq!(to_base_units(dec!(1.5), SOL_DECIMALS).unwrap(), 1_500_000_000);
+        assert_eq!(to_base_units(dec!(0), 6).unwrap(), 0);
+        // Sub-unit dust truncates rather than rounding up past the balance
+        assert_eq!(to_base_units(dec!(0.0000019), 6).unwrap(), 1);
+        
+        assert!(matches!(to_base_units(dec!(-1), 6), Err(TradingError::InvalidAmount(_))));
+        // u64::MAX lamports is about 18.4 billion SOL
+        assert!(matches!(to_base_units(dec!(20_000_000_000), SOL_DECIMALS), Err(TradingError::InvalidAmount(_))));
+        assert!(matches!(to_base_units(Decimal::MAX, SOL_DECIMALS), Err(TradingError::InvalidAmount(_))));
+    }
+    
+    fn rpc_error(code: i64, message: &str) -> ClientError {
+        RpcError::RpcResponseError { code, message: message.to_string(), data: RpcResponseErrorData::Empty }.into()
+    }
+    
+    #[test]
+    fn only_a_missing_account_reads_as_not_found() {
+        assert!(is_account_not_found(&rpc_error(-32602, "Invalid param: could not find account")));
+        assert!(!is_account_not_found(&rpc_error(-32005, "Node is behind by 42 slots")));
+        assert!(!is_account_not_found(&RpcError::RpcRequestError("connection refused".to_string()).into()));
+    }
+    
+    // JSON-RPC node answering each method with the result from `respond`, or with `error` when it returns Err.
+    // getVersion is always answered, since the client asks for it before some calls.
+    async fn mock_rpc(respond: fn(&str) -> Result<serde_json::Value, serde_json::Value>) -> RpcClient {
+        let app = axum::Router::new().route("/", axum::routing::post(move |axum::Json(request): axum::Json<serde_json::Value>| async move {
+            let reply = match request["method"].as_str().unwrap_or_default() {
+                "getVersion" => Ok(serde_json::json!({ "solana-core": "1.18.26", "feature-set": 0 })),
+                method => respond(method),
+            };
+            let reply = match reply {
+                Ok(result) => serde_json::json!({ "jsonrpc": "2.0", "result": result, "id": request["id"] }),
+                Err(error) => serde_json::json!({ "jsonrpc": "2.0", "error": error, "id": request["id"] }),
+            };
+            axum::Json(reply)
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        RpcClient::new(url)
+    }
+    
+    fn test_wallet() -> Wallet {
+        Wallet::new(&bs58::encode(Keypair::new().to_bytes()).into_string()).unwrap()
+    }
+    
+    #[tokio::test(flavor = "multi_thread")]
+    async fn missing_token_account_is_a_zero_balance() {
+        // The reply a validator gives getTokenAccountBalance for an account that was never created
+        let client = mock_rpc(|_| Err(serde_json::json!({ "code": -32602, "message": "Invalid param: could not find account" }))).await;
+        
+        let balance = test_wallet().get_token_balance(&client, &Pubkey::new_unique()).await.unwrap();
+        assert_eq!(balance, 0.0);
+    }
+    
+    #[test]
+    fn amounts_convert_with_six_and_nine_decimals() {
+        // USDC has 6 decimals and SOL 9
+        assert_eq!(raw_to_ui_amount(150_000_000, 6), dec!(150));
+        assert_eq!(raw_to_ui_amount(1_500_000_000, 9), dec!(1.5));
+        assert_eq!(ui_to_raw_amount(dec!(150), 6), 150_000_000);
+        assert_eq!(ui_to_raw_amount(dec!(1.5), 9), 1_500_000_000);
+        
+        // 150 USDC per SOL is 0.15 raw USDC units per lamport
+        assert_eq!(raw_price_to_ui(dec!(0.15), 9, 6), dec!(150));
+        // and 5 lamports per raw USDC unit is 0.005 SOL per USDC
+        assert_eq!(raw_price_to_ui(dec!(5), 6, 9), dec!(0.005));
+    }
+    
+    #[test]
+    fn token_account_balances_are_parsed_from_json_parsed_data() {
+        let mint = Pubkey::new_unique();
+        let account = |token_amount: serde_json::Value| serde_json::json!({
+            "program": "spl-token",
+            "parsed": {
+                "type": "account",
+                "info": { "mint": mint.to_string(), "owner": Pubkey::new_unique().to_string(), "tokenAmount": token_amount }
+            },
+            "space": 165
+        });
+        
+        let data = account(serde_json::json!({ "amount": "1500000", "decimals": 6, "uiAmount": 1.5, "uiAmountString": "1.5" }));
+        assert_eq!(parse_token_account_balance(&data), Some((mint, 1.5)));
+        
+        let data = account(serde_json::json!({ "amount": "2500000", "decimals": 6, "uiAmount": null, "uiAmountString": "2.5" }));
+        assert_eq!(parse_token_account_balance(&data), Some((mint, 2.5)));
+        
+        assert_eq!(parse_token_account_balance(&account(serde_json::json!({ "amount": "1" }))), None);
+        assert_eq!(parse_token_account_balance(&serde_json::json!(["AQID", "base64"])), None);
+    }
+    
+    #[tokio::test(flavor = "multi_thread")]
+    async fn effective_sol_balance_includes_wrapped_sol() {
+        // 1.5 SOL native and 0.25 SOL left wrapped in the wSOL account
+        let client = mock_rpc(|method| match method {
+            "getBalance" => Ok(serde_json::json!({ "context": { "slot": 1 }, "value": 1_500_000_000u64 })),
+            "getTokenAccountBalance" => Ok(serde_json::json!({
+                "context": { "slot": 1 },
+                "value": { "amount": "250000000", "decimals": 9, "uiAmount": 0.25, "uiAmountString": "0.25" }
+            })),
+            _ => Err(serde_json::json!({ "code": -32601, "message": "Method not found" })),
+        }).await;
+        let wallet = test_wallet();
+        let sol = spl_token::native_mint::id();
+        
+        assert_eq!(wallet.get_sol_balance(&client).await.unwrap(), 1.5);
+        assert_eq!(wallet.get_effective_sol_balance(&client).await.unwrap(), 1.75);
+        assert_eq!(wallet.get_effective_balance(&client, &sol).await.unwrap(), 1.75);
+    }
+}
@@ -168,6 +168,7 @@ module "cloud_run" {
 
   env_vars = {
     SOLANA_RPC_URL      = var.solana_rpc_url
+    CONFIRM_MAINNET     = tostring(var.confirm_mainnet)
     JUPITER_API_URL     = var.jupiter_api_url
     SLIPPAGE_BPS        = tostring(var.slippage_bps)
     GCP_PROJECT_ID      = var.project_id
@@ -41,6 +41,12 @@ variable "solana_rpc_url" {
   sensitive   = true
 }
 
+variable "confirm_mainnet" {
+  description = "Allow the bot to start against mainnet (it refuses to otherwise)"
+  type        = bool
+  default     = false
+}
+
 variable "jupiter_api_url" {
   description = "Jupiter DEX API endpoint URL"
   type        = string